@@ -55,19 +55,41 @@
 //! | bench2 | 1,204 | +754   |
 //! | bench3 | 2,811 | +2,361 |
 //! ```
+//!
+//! Benches can also be profiled, to see where their compute units are spent.
+//! With `profile` set, each bench is processed with a per-function compute
+//! unit profiler (see `mollusk_svm::profile`), and two files are written to a
+//! `profiles` subdirectory of `out_dir`:
+//!
+//! * `<name>.folded`: the bench's call stacks in the folded format, which can
+//!   be rendered as a flamegraph with tools like `inferno-flamegraph`.
+//! * `<name>.md`: a table of the functions with the most compute units.
+//!
+//! ```rust,ignore
+//! MolluskComputeUnitBencher::new(mollusk)
+//!     .bench(("bench0", &instruction0, &accounts0))
+//!     .profile(true)
+//!     .execute();
+//! ```
 
 pub mod result;
 
 use {
     chrono::Utc,
-    mollusk_svm::{result::ProgramResult, Mollusk},
+    mollusk_svm::{profile::Profile, result::ProgramResult, Mollusk},
     result::{write_results, MolluskComputeUnitBenchResult},
     solana_account::Account,
     solana_instruction::Instruction,
     solana_pubkey::Pubkey,
-    std::{path::PathBuf, process::Command},
+    std::{
+        path::{Path, PathBuf},
+        process::Command,
+    },
 };
 
+/// The number of functions listed in a bench's profile table.
+const PROFILE_TOP_FUNCTIONS: usize = 20;
+
 /// A bench is a tuple of a name, an instruction, and a list of accounts.
 pub type Bench<'a> = (&'a str, &'a Instruction, &'a [(Pubkey, Account)]);
 
@@ -79,6 +101,7 @@ pub struct MolluskComputeUnitBencher<'a> {
     mollusk: Mollusk,
     must_pass: bool,
     out_dir: PathBuf,
+    profile: bool,
 }
 
 impl<'a> MolluskComputeUnitBencher<'a> {
@@ -91,6 +114,7 @@ impl<'a> MolluskComputeUnitBencher<'a> {
             mollusk,
             must_pass: false,
             out_dir,
+            profile: false,
         }
     }
 
@@ -112,6 +136,14 @@ impl<'a> MolluskComputeUnitBencher<'a> {
         self
     }
 
+    /// Set whether the bencher should profile the compute units spent in
+    /// each function of the benches, writing the profiles to a `profiles`
+    /// subdirectory of the output directory.
+    pub fn profile(mut self, profile: bool) -> Self {
+        self.profile = profile;
+        self
+    }
+
     /// Execute the benches.
     pub fn execute(&mut self) {
         let table_header = Utc::now().to_string();
@@ -119,7 +151,13 @@ impl<'a> MolluskComputeUnitBencher<'a> {
         let bench_results = std::mem::take(&mut self.benches)
             .into_iter()
             .map(|(name, instruction, accounts)| {
-                let result = self.mollusk.process_instruction(instruction, accounts);
+                let result = if self.profile {
+                    let (result, profile) = self.mollusk.profile_instruction(instruction, accounts);
+                    write_profile(&self.out_dir, name, &profile);
+                    result
+                } else {
+                    self.mollusk.process_instruction(instruction, accounts)
+                };
                 match result.program_result {
                     ProgramResult::Success => (),
                     _ => {
@@ -138,6 +176,19 @@ impl<'a> MolluskComputeUnitBencher<'a> {
     }
 }
 
+fn write_profile(out_dir: &Path, name: &str, profile: &Profile) {
+    let dir = out_dir.join("profiles");
+    std::fs::create_dir_all(&dir).unwrap();
+    profile
+        .write_folded(dir.join(format!("{}.folded", name)))
+        .unwrap();
+    std::fs::write(
+        dir.join(format!("{}.md", name)),
+        profile.top(PROFILE_TOP_FUNCTIONS),
+    )
+    .unwrap();
+}
+
 pub fn get_solana_version() -> String {
     match Command::new("solana").arg("--version").output() {
         Ok(output) if output.status.success() => {
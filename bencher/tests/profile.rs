@@ -0,0 +1,36 @@
+use {
+    mollusk_svm::{program::loader_keys, Mollusk},
+    mollusk_svm_bencher::MolluskComputeUnitBencher,
+    solana_instruction::Instruction,
+    solana_pubkey::Pubkey,
+};
+
+const MEMO_ID: Pubkey = solana_pubkey::pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+const MEMO_ELF: &[u8] = include_bytes!("../../programs/memo/src/elf/memo.so");
+
+#[test]
+fn test_profile() {
+    solana_logger::setup_with("");
+
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_with_elf_and_loader(&MEMO_ID, MEMO_ELF, &loader_keys::LOADER_V2);
+
+    let instruction = Instruction::new_with_bytes(MEMO_ID, b"profile me", vec![]);
+    let accounts = vec![];
+
+    let out_dir = "../target/benches/profile";
+    MolluskComputeUnitBencher::new(mollusk)
+        .bench(("memo", &instruction, &accounts))
+        .must_pass(true)
+        .profile(true)
+        .out_dir(out_dir)
+        .execute();
+
+    let profiles = std::path::Path::new(out_dir).join("profiles");
+    let folded = std::fs::read_to_string(profiles.join("memo.folded")).unwrap();
+    assert!(folded
+        .lines()
+        .all(|line| line.starts_with(&format!("{};", MEMO_ID))));
+    let table = std::fs::read_to_string(profiles.join("memo.md")).unwrap();
+    assert!(table.starts_with("| Function | Program |"));
+}
@@ -0,0 +1,171 @@
+//! Differential execution of fixtures between Mollusk and another SVM backend.
+
+use {
+    crate::runner::ProtoLayout,
+    mollusk_svm::{
+        fuzz::{
+            backend::{ExecutionBackend, ProcessBackend},
+            convert::mollusk_to_firedancer,
+        },
+        program::{
+            create_program_account_loader_v3, create_program_data_account_loader_v3, loader_keys,
+        },
+        result::{Compare, Config},
+        Mollusk,
+    },
+    mollusk_svm_fuzz_fixture_firedancer::context::Context,
+    solana_pubkey::Pubkey,
+};
+
+pub struct DiffBackends {
+    pub backend: ProcessBackend,
+    pub checks: Vec<Compare>,
+    /// The ELF to deploy at each fixture's program ID, if any.
+    pub elf: Option<Vec<u8>>,
+    pub program_logs: bool,
+    pub proto: ProtoLayout,
+    pub verbose: bool,
+}
+
+/// The outcome of executing one fixture on both backends.
+enum Outcome {
+    Pass,
+    Diverged,
+    Error,
+}
+
+impl DiffBackends {
+    /// Load a fixture's context in the Firedancer layout, which is what
+    /// backends execute.
+    fn load_context(&self, fixture_path: &str) -> Context {
+        match self.proto {
+            ProtoLayout::Mollusk => {
+                let fixture = mollusk_svm_fuzz_fixture::Fixture::load_from_blob_file(fixture_path);
+                let converted = mollusk_to_firedancer(&fixture);
+                if self.verbose {
+                    for field in &converted.unrepresentable {
+                        println!("[LOST]: {}", field);
+                    }
+                }
+                converted.fixture.input
+            }
+            ProtoLayout::Firedancer => {
+                mollusk_svm_fuzz_fixture_firedancer::Fixture::load_from_blob_file(fixture_path)
+                    .input
+            }
+        }
+    }
+
+    /// Deploy the ELF at the context's program ID, replacing any program
+    /// account the context already has, so both backends execute it.
+    fn deploy_elf(context: &mut Context, elf: &[u8]) {
+        let program_id = context.program_id;
+        let programdata_address =
+            Pubkey::find_program_address(&[program_id.as_ref()], &loader_keys::LOADER_V3).0;
+        for (key, account) in [
+            (program_id, create_program_account_loader_v3(&program_id)),
+            (
+                programdata_address,
+                create_program_data_account_loader_v3(elf),
+            ),
+        ] {
+            match context.accounts.iter_mut().find(|(k, _, _)| *k == key) {
+                Some((_, existing, _)) => *existing = account,
+                None => context.accounts.push((key, account, None)),
+            }
+        }
+    }
+
+    fn run(&mut self, fixture_path: &str) -> Outcome {
+        if self.verbose {
+            println!("----------------------------------------");
+            println!("[DIFF]: FIX: {}", fixture_path);
+        }
+
+        let mut context = self.load_context(fixture_path);
+        if let Some(elf) = &self.elf {
+            Self::deploy_elf(&mut context, elf);
+        }
+
+        // Each fixture gets a fresh Mollusk, so programs loaded from one
+        // fixture's accounts aren't used for another's.
+        let mut mollusk = Mollusk::default();
+
+        // Disable stdout logging of program logs if not specified.
+        if self.program_logs {
+            println!("[MOLLUSK]: Program logs:");
+            println!();
+        } else {
+            solana_logger::setup_with("");
+        }
+
+        let mollusk_result = mollusk.execute(&context);
+        let backend_result = self.backend.execute(&context);
+
+        let (mollusk_result, backend_result) = match (mollusk_result, backend_result) {
+            (Ok(mollusk_result), Ok(backend_result)) => (mollusk_result, backend_result),
+            (mollusk_result, backend_result) => {
+                if let Err(err) = mollusk_result {
+                    println!("ERROR: {}: [MOLLUSK]: {}", fixture_path, err);
+                }
+                if let Err(err) = backend_result {
+                    println!("ERROR: {}: [BACKEND]: {}", fixture_path, err);
+                }
+                return Outcome::Error;
+            }
+        };
+
+        if self.verbose {
+            println!("[MOLLUSK]: Result:");
+            println!();
+            println!("{:?}", &mollusk_result);
+            println!();
+            println!("[BACKEND]: Result:");
+            println!();
+            println!("{:?}", &backend_result);
+            println!();
+        }
+
+        // Divergences are always reported, with Mollusk's values as the
+        // expected ones.
+        let pass = mollusk_result.compare_with_config(
+            &backend_result,
+            &self.checks,
+            &Config {
+                panic: false,
+                verbose: true,
+            },
+        );
+
+        if pass {
+            println!("PASS: {}", fixture_path);
+            Outcome::Pass
+        } else {
+            println!("DIVERGED: {}", fixture_path);
+            Outcome::Diverged
+        }
+    }
+
+    pub fn run_all(&mut self, fixtures: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut divergences = 0;
+        let mut errors = 0;
+        for fixture_path in fixtures {
+            match self.run(fixture_path) {
+                Outcome::Pass => {}
+                Outcome::Diverged => divergences += 1,
+                Outcome::Error => errors += 1,
+            }
+        }
+
+        println!();
+        println!(
+            "[DONE][DIFF RESULT]: {} divergences, {} errors",
+            divergences, errors
+        );
+
+        if divergences > 0 || errors > 0 {
+            std::process::exit(1);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+//! Patching of fixture fields, for `mollusk fixture edit`.
+
+use {
+    mollusk_svm_fuzz_fixture::Fixture, solana_account::Account, solana_pubkey::Pubkey,
+    std::str::FromStr,
+};
+
+/// The fields `mollusk fixture edit` can set, for its help text.
+pub const FIELDS: &str = "\
+Fields:
+  program-id=<pubkey>
+  instruction-data=<hex>
+  compute-unit-limit=<u64>
+  slot=<u64>
+  instruction-account.<index>.pubkey=<pubkey>
+  instruction-account.<index>.signer=<bool>
+  instruction-account.<index>.writable=<bool>
+  account.<index|pubkey>.lamports=<u64>
+  account.<index|pubkey>.data=<hex>
+  account.<index|pubkey>.owner=<pubkey>
+  account.<index|pubkey>.executable=<bool>
+  account.<index|pubkey>.rent-epoch=<u64>
+  resulting-account.<index|pubkey>.<field>=<value>  (as for `account`)
+  program-result=<u64>
+  compute-units-consumed=<u64>
+  return-data=<hex>";
+
+pub fn parse_hex(value: &str) -> Result<Vec<u8>, String> {
+    let value = value.strip_prefix("0x").unwrap_or(value);
+    if value.len() % 2 != 0 {
+        return Err(format!("Odd number of hex digits: {}", value));
+    }
+    // Parse over bytes, since slicing the string could split a non-ASCII
+    // character.
+    value
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("Invalid hex: {}", value))
+        })
+        .collect()
+}
+
+fn parse<T: FromStr>(field: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", field, value))
+}
+
+fn parse_pubkey(field: &str, value: &str) -> Result<Pubkey, String> {
+    Pubkey::from_str(value).map_err(|_| format!("Invalid pubkey for {}: {}", field, value))
+}
+
+fn find_account<'a>(
+    accounts: &'a mut [(Pubkey, Account)],
+    selector: &str,
+) -> Result<&'a mut Account, String> {
+    let position = match selector.parse::<usize>() {
+        Ok(index) => (index < accounts.len()).then_some(index),
+        Err(_) => {
+            let pubkey = parse_pubkey("account", selector)?;
+            accounts.iter().position(|(key, _)| key == &pubkey)
+        }
+    };
+    position
+        .map(|index| &mut accounts[index].1)
+        .ok_or_else(|| format!("No such account: {}", selector))
+}
+
+fn set_account_field(account: &mut Account, field: &str, value: &str) -> Result<(), String> {
+    match field {
+        "lamports" => account.lamports = parse(field, value)?,
+        "data" => account.data = parse_hex(value)?,
+        "owner" => account.owner = parse_pubkey(field, value)?,
+        "executable" => account.executable = parse(field, value)?,
+        "rent-epoch" => account.rent_epoch = parse(field, value)?,
+        _ => return Err(format!("Unknown account field: {}", field)),
+    }
+    Ok(())
+}
+
+/// Apply an edit of the form `field=value` to a fixture.
+pub fn apply(fixture: &mut Fixture, edit: &str) -> Result<(), String> {
+    let (path, value) = edit
+        .split_once('=')
+        .ok_or_else(|| format!("Expected `field=value`, got: {}", edit))?;
+    let input = &mut fixture.input;
+    let output = &mut fixture.output;
+
+    let parts = path.split('.').collect::<Vec<_>>();
+    match parts.as_slice() {
+        ["program-id"] => input.program_id = parse_pubkey(path, value)?,
+        ["instruction-data"] => input.instruction_data = parse_hex(value)?,
+        ["compute-unit-limit"] => input.compute_budget.compute_unit_limit = parse(path, value)?,
+        ["slot"] => input.sysvars.clock.slot = parse(path, value)?,
+        ["instruction-account", index, field] => {
+            let index: usize = parse(path, index)?;
+            let meta = input
+                .instruction_accounts
+                .get_mut(index)
+                .ok_or_else(|| format!("No such instruction account: {}", index))?;
+            match *field {
+                "pubkey" => {
+                    // Instruction accounts are encoded as indices into the
+                    // fixture's accounts.
+                    let pubkey = parse_pubkey(path, value)?;
+                    if !input.accounts.iter().any(|(key, _)| key == &pubkey) {
+                        return Err(format!("No such account: {}", pubkey));
+                    }
+                    meta.pubkey = pubkey;
+                }
+                "signer" => meta.is_signer = parse(path, value)?,
+                "writable" => meta.is_writable = parse(path, value)?,
+                _ => return Err(format!("Unknown instruction account field: {}", field)),
+            }
+        }
+        ["account", selector, field] => {
+            let account = find_account(&mut input.accounts, selector)?;
+            set_account_field(account, field, value)?;
+        }
+        ["resulting-account", selector, field] => {
+            let account = find_account(&mut output.resulting_accounts, selector)?;
+            set_account_field(account, field, value)?;
+        }
+        ["program-result"] => output.program_result = parse(path, value)?,
+        ["compute-units-consumed"] => output.compute_units_consumed = parse(path, value)?,
+        ["return-data"] => output.return_data = parse_hex(value)?,
+        _ => return Err(format!("Unknown field: {}", path)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_instruction::AccountMeta};
+
+    fn fixture() -> (Fixture, Pubkey, Pubkey) {
+        let key = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let mut fixture = Fixture::default();
+        fixture.input.instruction_accounts = vec![AccountMeta::new(key, false)];
+        fixture.input.accounts = vec![(key, Account::default()), (other, Account::default())];
+        fixture.output.resulting_accounts = fixture.input.accounts.clone();
+        (fixture, key, other)
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex(""), Ok(vec![]));
+        assert_eq!(parse_hex("00ff1A"), Ok(vec![0x00, 0xff, 0x1a]));
+        assert_eq!(parse_hex("0xdead"), Ok(vec![0xde, 0xad]));
+
+        assert_eq!(
+            parse_hex("abc"),
+            Err("Odd number of hex digits: abc".to_string())
+        );
+        assert_eq!(parse_hex("zz"), Err("Invalid hex: zz".to_string()));
+        assert_eq!(parse_hex("+1"), Err("Invalid hex: +1".to_string()));
+
+        // Multibyte characters are rejected, even when a pair of digits
+        // would split one.
+        assert_eq!(parse_hex("aéb"), Err("Invalid hex: aéb".to_string()));
+        assert_eq!(parse_hex("éé"), Err("Invalid hex: éé".to_string()));
+    }
+
+    #[test]
+    fn test_apply() {
+        let (mut fixture, key, other) = fixture();
+        let owner = Pubkey::new_unique();
+
+        for edit in [
+            "instruction-data=0102".to_string(),
+            "compute-unit-limit=5000".to_string(),
+            "slot=7".to_string(),
+            format!("instruction-account.0.pubkey={}", other),
+            "instruction-account.0.signer=true".to_string(),
+            "account.0.lamports=42".to_string(),
+            format!("account.{}.data=abcd", other),
+            format!("account.1.owner={}", owner),
+            "resulting-account.1.executable=true".to_string(),
+            "program-result=3".to_string(),
+            "return-data=ff".to_string(),
+        ] {
+            apply(&mut fixture, &edit).unwrap();
+        }
+
+        let input = &fixture.input;
+        assert_eq!(input.instruction_data, vec![1, 2]);
+        assert_eq!(input.compute_budget.compute_unit_limit, 5000);
+        assert_eq!(input.sysvars.clock.slot, 7);
+        assert_eq!(input.instruction_accounts[0].pubkey, other);
+        assert!(input.instruction_accounts[0].is_signer);
+        assert_eq!(
+            input.accounts[0],
+            (key, Account::new(42, 0, &Pubkey::default()))
+        );
+        assert_eq!(input.accounts[1].1.data, vec![0xab, 0xcd]);
+        assert_eq!(input.accounts[1].1.owner, owner);
+        assert!(fixture.output.resulting_accounts[1].1.executable);
+        assert!(!input.accounts[1].1.executable);
+        assert_eq!(fixture.output.program_result, 3);
+        assert_eq!(fixture.output.return_data, vec![0xff]);
+    }
+
+    #[test]
+    fn test_apply_invalid() {
+        let (mut fixture, _, _) = fixture();
+        let unchanged = fixture.clone();
+
+        for (edit, error) in [
+            ("slot", "Expected `field=value`, got: slot"),
+            ("unknown=1", "Unknown field: unknown"),
+            ("slot=-1", "Invalid value for slot: -1"),
+            ("instruction-data=abc", "Odd number of hex digits: abc"),
+            ("return-data=xy", "Invalid hex: xy"),
+            ("account.0.data=éé", "Invalid hex: éé"),
+            ("account.2.lamports=1", "No such account: 2"),
+            ("account.0.size=1", "Unknown account field: size"),
+            (
+                "instruction-account.1.signer=true",
+                "No such instruction account: 1",
+            ),
+        ] {
+            assert_eq!(apply(&mut fixture, edit), Err(error.to_string()));
+        }
+
+        // Instruction accounts can only refer to the fixture's accounts.
+        let missing = Pubkey::new_unique();
+        assert_eq!(
+            apply(
+                &mut fixture,
+                &format!("instruction-account.0.pubkey={}", missing)
+            ),
+            Err(format!("No such account: {}", missing)),
+        );
+
+        assert_eq!(fixture, unchanged);
+    }
+}
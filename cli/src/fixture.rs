@@ -0,0 +1,406 @@
+//! Fixture maintenance commands.
+
+use {
+    crate::{
+        add_elf_to_mollusk, edit,
+        runner::ProtoLayout,
+        show::{self, ContextView},
+    },
+    clap::{Subcommand, ValueEnum},
+    mollusk_svm::{
+        fuzz::{self, convert},
+        Mollusk,
+    },
+    mollusk_svm_fuzz_fixture::{
+        index::{FixtureIndex, DISCRIMINATOR_LEN},
+        Fixture,
+    },
+    mollusk_svm_fuzz_fixture_firedancer::Fixture as FdFixture,
+    mollusk_svm_fuzz_fs::{IntoSerializableFixture, SerializableFixture},
+    solana_pubkey::Pubkey,
+    std::{fs, path::Path, str::FromStr},
+};
+
+/// The encoding or layout to convert a fixture to.
+#[derive(Clone, Debug, ValueEnum)]
+pub enum ConvertTarget {
+    /// JSON encoding, keeping the layout.
+    Json,
+    /// Protobuf blob encoding, keeping the layout.
+    Blob,
+    /// Firedancer protobuf layout, keeping the encoding.
+    Firedancer,
+    /// Mollusk protobuf layout, keeping the encoding.
+    Mollusk,
+}
+
+#[derive(Subcommand)]
+pub enum FixtureCommand {
+    /// Print a fixture's context and effects, decoding sysvars and known
+    /// account types.
+    Show {
+        /// Path to an instruction fixture (`.fix` or `.json` file).
+        #[arg(required = true)]
+        fixture: String,
+
+        /// Print all instruction and account data, rather than only its
+        /// first bytes.
+        #[arg(long)]
+        full: bool,
+        /// Protobuf layout of the fixture.
+        #[arg(long, default_value = "mollusk")]
+        proto: ProtoLayout,
+    },
+    /// Convert a fixture between JSON and blob encodings, or between Mollusk
+    /// and Firedancer layouts.
+    Convert {
+        /// Path to an instruction fixture (`.fix` or `.json` file).
+        #[arg(required = true)]
+        fixture: String,
+        /// The encoding or layout to convert to.
+        #[arg(long, required = true)]
+        to: ConvertTarget,
+
+        /// Path to write the converted fixture to. Defaults to the fixture's
+        /// path, with the new extension, or with a `.firedancer` or
+        /// `.mollusk` suffix for layout conversions.
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Protobuf layout of the fixture.
+        #[arg(long, default_value = "mollusk")]
+        proto: ProtoLayout,
+    },
+    /// Patch fields of a Mollusk fixture, such as its instruction data or an
+    /// account's lamports.
+    #[command(after_help = edit::FIELDS)]
+    Edit {
+        /// Path to an instruction fixture (`.fix` or `.json` file).
+        #[arg(required = true)]
+        fixture: String,
+        /// Edits to apply, in order, each of the form `field=value`.
+        #[arg(required = true)]
+        edits: Vec<String>,
+
+        /// Path to write the edited fixture to. Defaults to overwriting the
+        /// fixture.
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Shrink a fixture which fails, keeping the program result it fails
+    /// with.
+    ///
+    /// Accounts are dropped, and instruction and account data removed or
+    /// zeroed, for as long as the failure still reproduces.
+    Minimize {
+        /// The path to the ELF file.
+        #[arg(required = true)]
+        elf_path: String,
+        /// Path to an instruction fixture (`.fix` file).
+        #[arg(required = true)]
+        fixture: String,
+        /// The ID to use for the program.
+        #[arg(value_parser = Pubkey::from_str)]
+        program_id: Pubkey,
+
+        /// Path to write the minimized fixture to. Defaults to the fixture's
+        /// path, with a `.min.fix` extension.
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Enable emission of program logs to stdout. Disabled by default.
+        #[arg(long)]
+        program_logs: bool,
+    },
+    /// Remove near-identical fixtures from a directory, keeping the smallest
+    /// fixture of each cluster.
+    ///
+    /// Fixtures are clustered by program, instruction discriminator, program
+    /// result and the shape of their instruction accounts, as recorded in the
+    /// directory's index. The index is brought up to date first.
+    Dedupe {
+        /// The fixture directory.
+        #[arg(required = true)]
+        dir: String,
+
+        /// The number of leading instruction data bytes identifying an
+        /// instruction.
+        #[arg(long, default_value_t = DISCRIMINATOR_LEN)]
+        discriminator_len: usize,
+        /// Report the clusters without removing any fixture.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// A fixture of either layout.
+enum AnyFixture {
+    Mollusk(Box<Fixture>),
+    Firedancer(Box<FdFixture>),
+}
+
+fn is_json(path: &str) -> bool {
+    path.ends_with(".json")
+}
+
+fn load_mollusk(path: &str) -> Fixture {
+    if is_json(path) {
+        Fixture::load_from_json_file(path)
+    } else {
+        Fixture::load_from_blob_file(path)
+    }
+}
+
+fn load(path: &str, proto: &ProtoLayout) -> AnyFixture {
+    match proto {
+        ProtoLayout::Mollusk => AnyFixture::Mollusk(Box::new(load_mollusk(path))),
+        ProtoLayout::Firedancer if is_json(path) => {
+            AnyFixture::Firedancer(Box::new(FdFixture::load_from_json_file(path)))
+        }
+        ProtoLayout::Firedancer => {
+            AnyFixture::Firedancer(Box::new(FdFixture::load_from_blob_file(path)))
+        }
+    }
+}
+
+fn encode<T: IntoSerializableFixture>(fixture: T, json: bool) -> Vec<u8> {
+    let fixture = IntoSerializableFixture::into(fixture);
+    if json {
+        serde_json::to_string_pretty(&fixture)
+            .expect("Failed to serialize fixture to JSON")
+            .into_bytes()
+    } else {
+        SerializableFixture::encode(&fixture)
+    }
+}
+
+fn save(fixture: AnyFixture, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = match fixture {
+        AnyFixture::Mollusk(fixture) => encode(*fixture, is_json(path)),
+        AnyFixture::Firedancer(fixture) => encode(*fixture, is_json(path)),
+    };
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+fn show_fixture(fixture: &AnyFixture, full: bool) {
+    match fixture {
+        AnyFixture::Mollusk(fixture) => {
+            let (parsed, result) = fuzz::mollusk::load_fixture(fixture);
+            let context = ContextView {
+                instruction: &parsed.instruction,
+                accounts: &parsed.accounts,
+                compute_unit_limit: parsed.compute_budget.compute_unit_limit,
+                active_features: parsed.feature_set.active().len(),
+                sysvars: Ok(&parsed.sysvars),
+            };
+            show::print_fixture(&context, &result, full);
+        }
+        AnyFixture::Firedancer(fixture) => {
+            let (parsed, result) = fuzz::firedancer::load_firedancer_fixture(fixture);
+            let context = ContextView {
+                instruction: &parsed.instruction,
+                accounts: &parsed.accounts,
+                compute_unit_limit: parsed.compute_budget.compute_unit_limit,
+                active_features: parsed.feature_set.active().len(),
+                sysvars: Err(parsed.slot),
+            };
+            show::print_fixture(&context, &result, full);
+        }
+    }
+}
+
+fn data_len(fixture: &Fixture) -> usize {
+    fixture.input.instruction_data.len()
+        + fixture
+            .input
+            .accounts
+            .iter()
+            .map(|(_, account)| account.data.len())
+            .sum::<usize>()
+}
+
+pub fn run(command: FixtureCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        FixtureCommand::Show {
+            fixture,
+            full,
+            proto,
+        } => {
+            show_fixture(&load(&fixture, &proto), full);
+        }
+        FixtureCommand::Convert {
+            fixture: fixture_path,
+            to,
+            output,
+            proto,
+        } => {
+            let fixture = load(&fixture_path, &proto);
+            let (stem, extension) = fixture_path
+                .rsplit_once('.')
+                .unwrap_or((&fixture_path, "fix"));
+            let mut unrepresentable = Vec::new();
+            let (converted, default_output) = match (to, fixture) {
+                (ConvertTarget::Json, fixture) if extension != "json" => {
+                    (fixture, format!("{}.json", stem))
+                }
+                (ConvertTarget::Blob, fixture) if extension == "json" => {
+                    (fixture, format!("{}.fix", stem))
+                }
+                (ConvertTarget::Firedancer, AnyFixture::Mollusk(fixture)) => {
+                    let converted = convert::mollusk_to_firedancer(&fixture);
+                    unrepresentable = converted.unrepresentable;
+                    (
+                        AnyFixture::Firedancer(Box::new(converted.fixture)),
+                        format!("{}.firedancer.{}", stem, extension),
+                    )
+                }
+                (ConvertTarget::Mollusk, AnyFixture::Firedancer(fixture)) => {
+                    let converted = convert::firedancer_to_mollusk(&fixture);
+                    unrepresentable = converted.unrepresentable;
+                    (
+                        AnyFixture::Mollusk(Box::new(converted.fixture)),
+                        format!("{}.mollusk.{}", stem, extension),
+                    )
+                }
+                (to, _) => {
+                    return Err(format!("Fixture is already {:?}: {}", to, fixture_path).into())
+                }
+            };
+            let output = output.unwrap_or(default_output);
+            save(converted, &output)?;
+            for field in &unrepresentable {
+                println!("[LOST]: {}", field);
+            }
+            println!("Converted fixture written to {}", output);
+        }
+        FixtureCommand::Edit {
+            fixture: fixture_path,
+            edits,
+            output,
+        } => {
+            let mut fixture = load_mollusk(&fixture_path);
+            for edit in &edits {
+                edit::apply(&mut fixture, edit)?;
+            }
+            let output = output.unwrap_or(fixture_path);
+            save(AnyFixture::Mollusk(Box::new(fixture)), &output)?;
+            println!("Edited fixture written to {}", output);
+        }
+        FixtureCommand::Minimize {
+            elf_path,
+            fixture: fixture_path,
+            program_id,
+            output,
+            program_logs,
+        } => {
+            if !program_logs {
+                solana_logger::setup_with("");
+            }
+
+            let mut mollusk = Mollusk::default();
+            add_elf_to_mollusk(&mut mollusk, &elf_path, &program_id);
+
+            let fixture = Fixture::load_from_blob_file(&fixture_path);
+            let Some(minimized) = mollusk_svm_fuzz::minimize::minimize(&mut mollusk, &fixture)
+            else {
+                return Err(format!("Fixture does not fail: {}", fixture_path).into());
+            };
+
+            let output = output.unwrap_or_else(|| {
+                let stem = fixture_path.strip_suffix(".fix").unwrap_or(&fixture_path);
+                format!("{}.min.fix", stem)
+            });
+            let blob = SerializableFixture::encode(&IntoSerializableFixture::into(
+                minimized.fixture.clone(),
+            ));
+            fs::write(&output, blob)?;
+
+            match &minimized.program_result {
+                Some(program_result) => println!("Result: {:?}", program_result),
+                None => println!("Result: panicked"),
+            }
+            println!(
+                "Accounts: {} -> {}",
+                fixture.input.accounts.len(),
+                minimized.fixture.input.accounts.len(),
+            );
+            println!(
+                "Instruction accounts: {} -> {}",
+                fixture.input.instruction_accounts.len(),
+                minimized.fixture.input.instruction_accounts.len(),
+            );
+            println!(
+                "Data bytes: {} -> {}",
+                data_len(&fixture),
+                data_len(&minimized.fixture),
+            );
+            println!("Runs: {}", minimized.runs);
+            println!("Minimized fixture written to {}", output);
+        }
+        FixtureCommand::Dedupe {
+            dir,
+            discriminator_len,
+            dry_run,
+        } => {
+            if !Path::new(&dir).is_dir() {
+                return Err(format!("Not a directory: {}", dir).into());
+            }
+            // Hold the index lock until the index is saved, so fixtures
+            // recorded meanwhile aren't dropped from it.
+            let _lock = FixtureIndex::lock(&dir);
+            let mut index = FixtureIndex::sync(&dir);
+            let total = index.fixtures.len();
+
+            let mut removed = Vec::new();
+            let clusters = index.clusters(discriminator_len);
+            for cluster in &clusters {
+                let file_size = |file: &str| {
+                    fs::metadata(Path::new(&dir).join(file))
+                        .map(|metadata| metadata.len())
+                        .unwrap_or(u64::MAX)
+                };
+                let representative = cluster
+                    .iter()
+                    .min_by_key(|entry| file_size(&entry.file))
+                    .unwrap();
+                let duplicates = cluster
+                    .iter()
+                    .filter(|entry| entry.file != representative.file)
+                    .map(|entry| entry.file.clone())
+                    .collect::<Vec<_>>();
+                if !duplicates.is_empty() {
+                    println!(
+                        "KEEP: {} ({} duplicates)",
+                        representative.file,
+                        duplicates.len()
+                    );
+                }
+                removed.extend(duplicates);
+            }
+            let clusters = clusters.len();
+
+            if !dry_run {
+                for file in &removed {
+                    let path = Path::new(&dir).join(file);
+                    fs::remove_file(&path)?;
+                    // Remove the JSON version of the fixture too, if it was
+                    // ejected to the same directory.
+                    let _ = fs::remove_file(path.with_extension("json"));
+                }
+                index
+                    .fixtures
+                    .retain(|entry| !removed.contains(&entry.file));
+                index.save(&dir);
+            }
+
+            println!();
+            println!(
+                "[DONE]: {} clusters, {} of {} fixtures {}",
+                clusters,
+                removed.len(),
+                total,
+                if dry_run { "duplicated" } else { "removed" },
+            );
+        }
+    }
+    Ok(())
+}
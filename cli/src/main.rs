@@ -1,13 +1,25 @@
 //! Mollusk CLI.
 
 mod config;
+mod diff;
+mod edit;
+mod fixture;
 mod runner;
+mod show;
 
 use {
-    crate::runner::{ProtoLayout, Runner},
+    crate::{
+        diff::DiffBackends,
+        fixture::FixtureCommand,
+        runner::{ProtoLayout, Runner},
+    },
     clap::{Parser, Subcommand},
     config::ConfigFile,
-    mollusk_svm::{result::Compare, Mollusk},
+    mollusk_svm::{
+        debugger::DebuggerConfig, fuzz::backend::ProcessBackend, result::Compare, Mollusk,
+    },
+    mollusk_svm_fuzz_fixture::proto::ChainFixture as ProtoChainFixture,
+    mollusk_svm_fuzz_fs::SerializableFixture,
     runner::CusReport,
     solana_pubkey::Pubkey,
     std::{fs, path::Path, str::FromStr},
@@ -39,6 +51,16 @@ enum SubCommand {
         /// Note this flag is ignored if `cus_report` is not set.
         #[arg(long)]
         cus_report_table_header: Option<String>,
+        /// Debug the program with GDB, listening for a client on this local
+        /// port. Each invocation of the program waits for a client to connect
+        /// before executing.
+        #[arg(long)]
+        debug_port: Option<u16>,
+        /// The program to debug, if not the fixture's program.
+        ///
+        /// Note this flag is ignored if `debug_port` is not set.
+        #[arg(long, value_parser = Pubkey::from_str)]
+        debug_program: Option<Pubkey>,
         /// Skip comparing compute unit consumption, but compare everything
         /// else.
         ///
@@ -107,6 +129,56 @@ enum SubCommand {
         #[arg(short, long)]
         verbose: bool,
     },
+    /// Execute fixtures with both Mollusk and another SVM backend, and report
+    /// where their results diverge.
+    ///
+    /// The backend is an executable which reads an encoded `InstrContext`
+    /// protobuf message from stdin, and writes the encoded `InstrEffects`
+    /// protobuf message to stdout. It's run once per fixture. Divergences are
+    /// reported with Mollusk's values as the expected ones.
+    DiffBackends {
+        /// Path to an instruction fixture (`.fix` file) or a directory
+        /// containing them.
+        #[arg(required = true)]
+        fixture: String,
+        /// The backend executable.
+        #[arg(required = true)]
+        backend: String,
+        /// An argument to pass to the backend executable. Can be repeated.
+        #[arg(long = "backend-arg", allow_hyphen_values = true)]
+        backend_args: Vec<String>,
+        /// The path to an ELF file to deploy at each fixture's program ID,
+        /// for fixtures which don't include their program.
+        #[arg(long)]
+        elf_path: Option<String>,
+
+        /// Path to the config file for validation checks.
+        #[arg(short, long)]
+        config: Option<String>,
+        /// Skip comparing compute unit consumption, but compare everything
+        /// else.
+        ///
+        /// Note this flag will override a `Compare::ComputeUnits` check in the
+        /// config file.
+        #[arg(long)]
+        ignore_compute_units: bool,
+        /// Enable emission of program logs to stdout. Disabled by default.
+        #[arg(long)]
+        program_logs: bool,
+        /// Protobuf layout of the fixtures. Mollusk fixtures are converted to
+        /// the Firedancer layout before execution.
+        #[arg(long, default_value = "firedancer")]
+        proto: ProtoLayout,
+        /// Enable verbose mode, printing both results of every fixture. Does
+        /// not enable program logs. Disabled by default.
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Inspect and maintain fixtures.
+    Fixture {
+        #[clap(subcommand)]
+        command: FixtureCommand,
+    },
 }
 
 #[derive(Parser)]
@@ -123,7 +195,16 @@ fn search_paths(path: &str, extension: &str) -> Result<Vec<String>, Box<dyn std:
     ) -> Result<(), Box<dyn std::error::Error>> {
         if path.is_dir() {
             for entry in fs::read_dir(path)? {
-                search_path_recursive(&entry?.path(), extension, result)?;
+                let path = entry?.path();
+                // Chain fixtures are ejected alongside instruction fixtures,
+                // but can't be processed as one.
+                if path
+                    .file_name()
+                    .is_some_and(|name| ProtoChainFixture::is_file_name(&name.to_string_lossy()))
+                {
+                    continue;
+                }
+                search_path_recursive(&path, extension, result)?;
             }
         } else if path.extension().is_some_and(|ext| ext == extension) {
             result.push(path.to_str().unwrap().to_string());
@@ -155,6 +236,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             config,
             cus_report,
             cus_report_table_header,
+            debug_port,
+            debug_program,
             ignore_compute_units,
             inputs_only,
             program_logs,
@@ -163,6 +246,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         } => {
             let mut mollusk = Mollusk::default();
             add_elf_to_mollusk(&mut mollusk, &elf_path, &program_id);
+            mollusk.debugger = debug_port.map(|port| {
+                DebuggerConfig::new(port).with_program_id(debug_program.unwrap_or(program_id))
+            });
 
             let checks = if let Some(config_path) = config {
                 ConfigFile::try_load(&config_path)?.checks
@@ -227,6 +313,43 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             )
             .run_all(Some(&mut mollusk_ground), &mut mollusk_test, &fixtures)?
         }
+        SubCommand::DiffBackends {
+            fixture,
+            backend,
+            backend_args,
+            elf_path,
+            config,
+            ignore_compute_units,
+            program_logs,
+            proto,
+            verbose,
+        } => {
+            let backend = backend_args
+                .into_iter()
+                .fold(ProcessBackend::new(backend), ProcessBackend::arg);
+
+            let checks = if let Some(config_path) = config {
+                ConfigFile::try_load(&config_path)?.checks
+            } else if ignore_compute_units {
+                Compare::everything_but_cus()
+            } else {
+                // Defaults to all checks.
+                Compare::everything()
+            };
+
+            let fixtures = search_paths(&fixture, "fix")?;
+
+            DiffBackends {
+                backend,
+                checks,
+                elf: elf_path.map(mollusk_svm::file::read_file),
+                program_logs,
+                proto,
+                verbose,
+            }
+            .run_all(&fixtures)?
+        }
+        SubCommand::Fixture { command } => fixture::run(command)?,
     }
     Ok(())
 }
@@ -0,0 +1,208 @@
+//! Pretty printing of fixtures, for `mollusk fixture show`.
+
+use {
+    mollusk_svm::{result::InstructionResult, sysvar::Sysvars},
+    solana_account::Account,
+    solana_instruction::Instruction,
+    solana_loader_v3_interface::state::UpgradeableLoaderState,
+    solana_pubkey::Pubkey,
+    solana_sdk_ids::{bpf_loader_upgradeable, sysvar},
+    spl_token::{
+        solana_program::program_pack::Pack,
+        state::{Account as TokenAccount, Mint},
+    },
+    std::fmt::Write,
+};
+
+const TOKEN_2022_PROGRAM_ID: Pubkey =
+    solana_pubkey::pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+// Token-2022 accounts with extensions are padded to the size of a token
+// account, followed by a byte for the account type.
+const TOKEN_2022_ACCOUNT_TYPE_MINT: u8 = 1;
+const TOKEN_2022_ACCOUNT_TYPE_ACCOUNT: u8 = 2;
+
+// The number of bytes of data printed, unless printing everything.
+const DATA_PREVIEW_LEN: usize = 64;
+
+/// The parts of a fixture's context common to both layouts.
+pub struct ContextView<'a> {
+    pub instruction: &'a Instruction,
+    pub accounts: &'a [(Pubkey, Account)],
+    pub compute_unit_limit: u64,
+    pub active_features: usize,
+    /// Mollusk fixtures carry every sysvar, Firedancer fixtures only the
+    /// slot.
+    pub sysvars: Result<&'a Sysvars, u64>,
+}
+
+pub fn hex(bytes: &[u8], full: bool) -> String {
+    let len = if full {
+        bytes.len()
+    } else {
+        bytes.len().min(DATA_PREVIEW_LEN)
+    };
+    let mut hex = bytes[..len].iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    });
+    if len < bytes.len() {
+        let _ = write!(hex, "... ({} more bytes)", bytes.len() - len);
+    }
+    hex
+}
+
+fn decode_sysvar(pubkey: &Pubkey, data: &[u8]) -> Option<String> {
+    if pubkey == &sysvar::clock::id() {
+        let clock: solana_clock::Clock = bincode::deserialize(data).ok()?;
+        Some(format!("{:?}", clock))
+    } else if pubkey == &sysvar::epoch_rewards::id() {
+        let epoch_rewards: solana_epoch_rewards::EpochRewards = bincode::deserialize(data).ok()?;
+        Some(format!("{:?}", epoch_rewards))
+    } else if pubkey == &sysvar::epoch_schedule::id() {
+        let epoch_schedule: solana_epoch_schedule::EpochSchedule =
+            bincode::deserialize(data).ok()?;
+        Some(format!("{:?}", epoch_schedule))
+    } else if pubkey == &sysvar::rent::id() {
+        let rent: solana_rent::Rent = bincode::deserialize(data).ok()?;
+        Some(format!("{:?}", rent))
+    } else if pubkey == &sysvar::slot_hashes::id() {
+        let slot_hashes: solana_slot_hashes::SlotHashes = bincode::deserialize(data).ok()?;
+        Some(format!("SlotHashes ({} entries)", slot_hashes.len()))
+    } else if pubkey == &sysvar::stake_history::id() {
+        let stake_history: solana_stake_interface::stake_history::StakeHistory =
+            bincode::deserialize(data).ok()?;
+        Some(format!("StakeHistory ({} entries)", stake_history.len()))
+    } else {
+        None
+    }
+}
+
+fn decode_token(data: &[u8]) -> Option<String> {
+    match data.len() {
+        Mint::LEN => Some(format!("{:?}", Mint::unpack_from_slice(data).ok()?)),
+        TokenAccount::LEN => Some(format!("{:?}", TokenAccount::unpack_from_slice(data).ok()?)),
+        _ => None,
+    }
+}
+
+fn decode_token_2022(data: &[u8]) -> Option<String> {
+    if data.len() <= TokenAccount::LEN {
+        return decode_token(data);
+    }
+    let decoded = match data[TokenAccount::LEN] {
+        TOKEN_2022_ACCOUNT_TYPE_MINT => decode_token(&data[..Mint::LEN])?,
+        TOKEN_2022_ACCOUNT_TYPE_ACCOUNT => decode_token(&data[..TokenAccount::LEN])?,
+        _ => return None,
+    };
+    let extensions = data.len() - TokenAccount::LEN - 1;
+    Some(format!("{} (extensions: {} bytes)", decoded, extensions))
+}
+
+/// Decode the data of an account of a known type: a sysvar, an upgradeable
+/// program, or an SPL Token or Token-2022 mint or token account.
+pub fn decode_account(pubkey: &Pubkey, account: &Account) -> Option<String> {
+    if account.owner == sysvar::id() {
+        decode_sysvar(pubkey, &account.data)
+    } else if account.owner == bpf_loader_upgradeable::id() {
+        let state: UpgradeableLoaderState = bincode::deserialize(&account.data).ok()?;
+        Some(format!("{:?}", state))
+    } else if account.owner == spl_token::id() {
+        decode_token(&account.data)
+    } else if account.owner == TOKEN_2022_PROGRAM_ID {
+        decode_token_2022(&account.data)
+    } else {
+        None
+    }
+}
+
+fn print_account(index: usize, pubkey: &Pubkey, account: &Account, full: bool) {
+    println!("    [{}] {}", index, pubkey);
+    println!("        Lamports: {}", account.lamports);
+    println!("        Owner: {}", account.owner);
+    println!("        Executable: {}", account.executable);
+    println!(
+        "        Data ({} bytes): {}",
+        account.data.len(),
+        hex(&account.data, full)
+    );
+    if let Some(decoded) = decode_account(pubkey, account) {
+        println!("        Decoded: {}", decoded);
+    }
+}
+
+fn print_context(context: &ContextView, full: bool) {
+    let instruction = context.instruction;
+    println!("Context:");
+    println!("  Program: {}", instruction.program_id);
+    println!(
+        "  Instruction data ({} bytes): {}",
+        instruction.data.len(),
+        hex(&instruction.data, full)
+    );
+    println!("  Instruction accounts:");
+    for (index, meta) in instruction.accounts.iter().enumerate() {
+        println!(
+            "    [{}] {}{}{}",
+            index,
+            meta.pubkey,
+            if meta.is_signer { " signer" } else { "" },
+            if meta.is_writable { " writable" } else { "" },
+        );
+    }
+    println!("  Compute unit limit: {}", context.compute_unit_limit);
+    println!("  Active features: {}", context.active_features);
+    match context.sysvars {
+        Ok(sysvars) => {
+            println!("  Sysvars:");
+            println!("    {:?}", sysvars.clock);
+            println!("    {:?}", sysvars.epoch_rewards);
+            println!("    {:?}", sysvars.epoch_schedule);
+            println!("    {:?}", sysvars.rent);
+            println!("    SlotHashes ({} entries)", sysvars.slot_hashes.len());
+            println!("    StakeHistory ({} entries)", sysvars.stake_history.len());
+        }
+        Err(slot) => println!("  Slot: {}", slot),
+    }
+    println!("  Accounts:");
+    for (index, (pubkey, account)) in context.accounts.iter().enumerate() {
+        print_account(index, pubkey, account, full);
+    }
+}
+
+/// Print the effects of a fixture, listing only the resulting accounts which
+/// differ from the context's.
+fn print_effects(context: &ContextView, effects: &InstructionResult, full: bool) {
+    println!("Effects:");
+    match &effects.raw_result {
+        Ok(()) => println!("  Program result: Success"),
+        Err(err) => println!("  Program result: {:?}", err),
+    }
+    println!(
+        "  Compute units consumed: {}",
+        effects.compute_units_consumed
+    );
+    println!("  Execution time: {}", effects.execution_time);
+    println!(
+        "  Return data ({} bytes): {}",
+        effects.return_data.len(),
+        hex(&effects.return_data, full)
+    );
+    println!("  Modified accounts:");
+    for (index, (pubkey, account)) in effects.resulting_accounts.iter().enumerate() {
+        let input = context
+            .accounts
+            .iter()
+            .find(|(key, _)| key == pubkey)
+            .map(|(_, account)| account);
+        if input != Some(account) {
+            print_account(index, pubkey, account, full);
+        }
+    }
+}
+
+pub fn print_fixture(context: &ContextView, effects: &InstructionResult, full: bool) {
+    print_context(context, full);
+    println!();
+    print_effects(context, effects, full);
+}
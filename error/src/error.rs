@@ -3,7 +3,7 @@
 
 use {
     solana_pubkey::Pubkey,
-    std::{fmt::Display, path::Path},
+    std::{fmt::Display, path::PathBuf},
     thiserror::Error,
 };
 
@@ -11,21 +11,45 @@ use {
 pub enum MolluskError<'a> {
     /// Failed to open file.
     #[error("    [MOLLUSK]: Failed to open file: {0}")]
-    FileOpenError(&'a Path),
+    FileOpenError(PathBuf),
     /// Failed to read file.
     #[error("    [MOLLUSK]: Failed to read file: {0}")]
-    FileReadError(&'a Path),
-    /// Program file not found.
-    #[error("    [MOLLUSK]: Program file not found: {0}")]
-    FileNotFound(&'a str),
+    FileReadError(PathBuf),
+    /// Program file not found in any of the search paths.
+    #[error(
+        "    [MOLLUSK]: Program file not found: {file_name}{}",
+        display_search(.searched, .nearby)
+    )]
+    FileNotFound {
+        /// The name of the file being searched for.
+        file_name: String,
+        /// Every path that was checked for the file.
+        searched: Vec<PathBuf>,
+        /// The names of any `.so` files found in the searched directories.
+        nearby: Vec<String>,
+    },
     /// An account required by the instruction was not provided.
     #[error("    [MOLLUSK]: An account required by the instruction was not provided: {0}")]
     AccountMissing(&'a Pubkey),
+    /// Account provided for a program does not contain a deployed program.
+    #[error("    [MOLLUSK]: Account does not contain a deployed program: {0}")]
+    InvalidProgramAccount(&'a Pubkey),
     /// Program targeted by the instruction is missing from the cache.
     #[error("    [MOLLUSK]: Program targeted by the instruction is missing from the cache: {0}")]
     ProgramNotCached(&'a Pubkey),
 }
 
+fn display_search(searched: &[PathBuf], nearby: &[String]) -> String {
+    let mut out = String::from("\n      Searched:");
+    for path in searched {
+        out.push_str(&format!("\n        - {}", path.display()));
+    }
+    if !nearby.is_empty() {
+        out.push_str(&format!("\n      Found instead: {}", nearby.join(", ")));
+    }
+    out
+}
+
 pub trait MolluskPanic<T> {
     fn or_panic_with(self, error: MolluskError) -> T;
 }
@@ -5,6 +5,7 @@ use {
     },
     crate::account::SeedAddress,
     agave_feature_set::FeatureSet,
+    prost::Message,
     solana_account::Account,
     solana_keccak_hasher::Hasher,
     solana_pubkey::Pubkey,
@@ -72,6 +73,18 @@ pub struct Context {
     pub epoch_context: EpochContext,
 }
 
+impl Context {
+    /// Decode a context from an encoded `InstrContext` protobuf message.
+    pub fn decode(blob: &[u8]) -> Result<Self, prost::DecodeError> {
+        ProtoContext::decode(blob).map(Into::into)
+    }
+
+    /// Encode the context as an `InstrContext` protobuf message.
+    pub fn encode(&self) -> Vec<u8> {
+        ProtoContext::from(self.clone()).encode_to_vec()
+    }
+}
+
 impl From<ProtoContext> for Context {
     fn from(value: ProtoContext) -> Self {
         let program_id_bytes: [u8; 32] = value
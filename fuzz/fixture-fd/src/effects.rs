@@ -1,7 +1,7 @@
 //! Post-invocation effects of an instruction.
 
 use {
-    super::proto::InstrEffects as ProtoEffects, crate::account::SeedAddress,
+    super::proto::InstrEffects as ProtoEffects, crate::account::SeedAddress, prost::Message,
     solana_account::Account, solana_keccak_hasher::Hasher, solana_pubkey::Pubkey,
 };
 
@@ -20,6 +20,18 @@ pub struct Effects {
     pub return_data: Vec<u8>,
 }
 
+impl Effects {
+    /// Decode effects from an encoded `InstrEffects` protobuf message.
+    pub fn decode(blob: &[u8]) -> Result<Self, prost::DecodeError> {
+        ProtoEffects::decode(blob).map(Into::into)
+    }
+
+    /// Encode the effects as an `InstrEffects` protobuf message.
+    pub fn encode(&self) -> Vec<u8> {
+        ProtoEffects::from(self.clone()).encode_to_vec()
+    }
+}
+
 impl From<ProtoEffects> for Effects {
     fn from(value: ProtoEffects) -> Self {
         let ProtoEffects {
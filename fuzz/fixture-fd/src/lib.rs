@@ -4,6 +4,10 @@
 //! Similar to the `mollusk-svm-fuzz-fixture` library, but built around
 //! Firedancer's protobuf layouts.
 //!
+//! The top-level `Fixture` is for Firedancer's instruction harness. Fixtures
+//! for its transaction and syscall harnesses are in the `txn` and `syscall`
+//! modules.
+//!
 //! Note: The fixtures defined in this library are compatible with Mollusk,
 //! which means developers can fuzz programs using the Mollusk harness.
 //! However, these fixtures (and this library) do not depend on the harness.
@@ -15,6 +19,8 @@ pub mod effects;
 pub mod feature_set;
 pub mod instr_account;
 pub mod metadata;
+pub mod syscall;
+pub mod txn;
 pub mod proto {
     include!(concat!(env!("OUT_DIR"), "/org.solana.sealevel.v1.rs"));
 }
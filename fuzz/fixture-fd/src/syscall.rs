@@ -0,0 +1,569 @@
+//! A fixture for invoking a syscall, for Firedancer's syscall harness.
+
+use {
+    super::proto::{
+        ErrKind as ProtoErrorKind, InputDataRegion as ProtoInputDataRegion,
+        SyscallContext as ProtoContext, SyscallEffects as ProtoEffects,
+        SyscallFixture as ProtoFixture, SyscallInvocation as ProtoInvocation,
+        VmContext as ProtoVmContext,
+    },
+    crate::{context::Context as InstructionContext, metadata::Metadata},
+    mollusk_svm_fuzz_fs::{FsHandler, IntoSerializableFixture, SerializableFixture},
+    solana_keccak_hasher::{Hash, Hasher},
+};
+
+/// A region of the VM's input data.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputDataRegion {
+    /// The offset of the region from the start of the input data.
+    pub offset: u64,
+    /// The contents of the region.
+    pub content: Vec<u8>,
+    /// Whether the region is writable.
+    pub is_writable: bool,
+}
+
+impl From<ProtoInputDataRegion> for InputDataRegion {
+    fn from(value: ProtoInputDataRegion) -> Self {
+        let ProtoInputDataRegion {
+            offset,
+            content,
+            is_writable,
+        } = value;
+        Self {
+            offset,
+            content,
+            is_writable,
+        }
+    }
+}
+
+impl From<InputDataRegion> for ProtoInputDataRegion {
+    fn from(value: InputDataRegion) -> Self {
+        let InputDataRegion {
+            offset,
+            content,
+            is_writable,
+        } = value;
+        Self {
+            offset,
+            content,
+            is_writable,
+        }
+    }
+}
+
+/// The state of the VM.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VmContext {
+    /// The size of the heap.
+    pub heap_max: u64,
+    /// The read-only data, mapped at the start of the program region.
+    pub rodata: Vec<u8>,
+    /// The offset of the text section in the read-only data.
+    pub rodata_text_section_offset: u64,
+    /// The length of the text section in the read-only data.
+    pub rodata_text_section_length: u64,
+    /// The input data regions.
+    pub input_data_regions: Vec<InputDataRegion>,
+    /// The registers, `r0` through `r10` followed by the program counter.
+    pub registers: [u64; 12],
+    /// The program counter of the entrypoint.
+    pub entry_pc: u64,
+    /// A bit set of the valid call destinations.
+    pub call_whitelist: Vec<u8>,
+    /// Whether memory accesses are checked for alignment.
+    pub check_align: bool,
+    /// Whether memory accesses are checked for size.
+    pub check_size: bool,
+    /// The SBPF version.
+    pub sbpf_version: u32,
+}
+
+impl From<ProtoVmContext> for VmContext {
+    fn from(value: ProtoVmContext) -> Self {
+        let ProtoVmContext {
+            heap_max,
+            rodata,
+            rodata_text_section_offset,
+            rodata_text_section_length,
+            input_data_regions,
+            r0,
+            r1,
+            r2,
+            r3,
+            r4,
+            r5,
+            r6,
+            r7,
+            r8,
+            r9,
+            r10,
+            r11,
+            entry_pc,
+            call_whitelist,
+            check_align,
+            check_size,
+            sbpf_version,
+        } = value;
+        Self {
+            heap_max,
+            rodata,
+            rodata_text_section_offset,
+            rodata_text_section_length,
+            input_data_regions: input_data_regions.into_iter().map(Into::into).collect(),
+            registers: [r0, r1, r2, r3, r4, r5, r6, r7, r8, r9, r10, r11],
+            entry_pc,
+            call_whitelist,
+            check_align,
+            check_size,
+            sbpf_version,
+        }
+    }
+}
+
+impl From<VmContext> for ProtoVmContext {
+    fn from(value: VmContext) -> Self {
+        let VmContext {
+            heap_max,
+            rodata,
+            rodata_text_section_offset,
+            rodata_text_section_length,
+            input_data_regions,
+            registers: [r0, r1, r2, r3, r4, r5, r6, r7, r8, r9, r10, r11],
+            entry_pc,
+            call_whitelist,
+            check_align,
+            check_size,
+            sbpf_version,
+        } = value;
+        Self {
+            heap_max,
+            rodata,
+            rodata_text_section_offset,
+            rodata_text_section_length,
+            input_data_regions: input_data_regions.into_iter().map(Into::into).collect(),
+            r0,
+            r1,
+            r2,
+            r3,
+            r4,
+            r5,
+            r6,
+            r7,
+            r8,
+            r9,
+            r10,
+            r11,
+            entry_pc,
+            call_whitelist,
+            check_align,
+            check_size,
+            sbpf_version,
+        }
+    }
+}
+
+/// The syscall to invoke.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyscallInvocation {
+    /// The name of the syscall, ie. `sol_sha256`.
+    pub function_name: Vec<u8>,
+    /// The start of the heap. The rest is zero-filled.
+    pub heap_prefix: Vec<u8>,
+    /// The start of the stack. The rest is zero-filled.
+    pub stack_prefix: Vec<u8>,
+}
+
+impl From<ProtoInvocation> for SyscallInvocation {
+    fn from(value: ProtoInvocation) -> Self {
+        let ProtoInvocation {
+            function_name,
+            heap_prefix,
+            stack_prefix,
+        } = value;
+        Self {
+            function_name,
+            heap_prefix,
+            stack_prefix,
+        }
+    }
+}
+
+impl From<SyscallInvocation> for ProtoInvocation {
+    fn from(value: SyscallInvocation) -> Self {
+        let SyscallInvocation {
+            function_name,
+            heap_prefix,
+            stack_prefix,
+        } = value;
+        Self {
+            function_name,
+            heap_prefix,
+            stack_prefix,
+        }
+    }
+}
+
+/// Syscall context fixture.
+///
+/// The syscall is invoked from within the instruction, with the arguments
+/// in registers `r1` through `r5`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Context {
+    /// The state of the VM.
+    pub vm_context: VmContext,
+    /// The instruction the syscall is invoked from.
+    pub instruction_context: InstructionContext,
+    /// The syscall to invoke.
+    pub invocation: SyscallInvocation,
+}
+
+impl From<ProtoContext> for Context {
+    fn from(value: ProtoContext) -> Self {
+        let ProtoContext {
+            vm_ctx,
+            instr_ctx,
+            syscall_invocation,
+        } = value;
+        Self {
+            vm_context: vm_ctx.map(Into::into).unwrap_or_default(),
+            // The instruction context requires a program ID.
+            instruction_context: instr_ctx.unwrap().into(),
+            invocation: syscall_invocation.map(Into::into).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<Context> for ProtoContext {
+    fn from(value: Context) -> Self {
+        let Context {
+            vm_context,
+            instruction_context,
+            invocation,
+        } = value;
+        Self {
+            vm_ctx: Some(vm_context.into()),
+            instr_ctx: Some(instruction_context.into()),
+            syscall_invocation: Some(invocation.into()),
+        }
+    }
+}
+
+/// The kind of error a syscall failed with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorKind {
+    #[default]
+    Unspecified,
+    /// An `EbpfError`, such as an access violation.
+    Ebpf,
+    /// A `SyscallError`.
+    Syscall,
+    /// An `InstructionError`.
+    Instruction,
+}
+
+impl From<ProtoErrorKind> for ErrorKind {
+    fn from(value: ProtoErrorKind) -> Self {
+        match value {
+            ProtoErrorKind::Unspecified => Self::Unspecified,
+            ProtoErrorKind::Ebpf => Self::Ebpf,
+            ProtoErrorKind::Syscall => Self::Syscall,
+            ProtoErrorKind::Instruction => Self::Instruction,
+        }
+    }
+}
+
+impl From<ErrorKind> for ProtoErrorKind {
+    fn from(value: ErrorKind) -> Self {
+        match value {
+            ErrorKind::Unspecified => Self::Unspecified,
+            ErrorKind::Ebpf => Self::Ebpf,
+            ErrorKind::Syscall => Self::Syscall,
+            ErrorKind::Instruction => Self::Instruction,
+        }
+    }
+}
+
+/// Represents the effects of a syscall invocation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Effects {
+    /// Error code. Zero is success, errors are non-zero.
+    pub error: i64,
+    /// The kind of error, if any.
+    pub error_kind: ErrorKind,
+    /// The return value.
+    pub r0: u64,
+    /// Compute units available after the invocation.
+    pub compute_units_available: u64,
+    /// The heap, after the invocation.
+    pub heap: Vec<u8>,
+    /// The stack, after the invocation.
+    pub stack: Vec<u8>,
+    /// The input data regions, after the invocation.
+    pub input_data_regions: Vec<InputDataRegion>,
+    /// The call frame count.
+    pub frame_count: u64,
+    /// The program log.
+    pub log: Vec<u8>,
+    /// The read-only data.
+    pub rodata: Vec<u8>,
+    /// The program counter.
+    pub pc: u64,
+}
+
+impl From<ProtoEffects> for Effects {
+    fn from(value: ProtoEffects) -> Self {
+        let error_kind = value.error_kind().into();
+        let ProtoEffects {
+            error,
+            r0,
+            cu_avail,
+            heap,
+            stack,
+            frame_count,
+            log,
+            rodata,
+            pc,
+            input_data_regions,
+            ..
+        } = value;
+        Self {
+            error,
+            error_kind,
+            r0,
+            compute_units_available: cu_avail,
+            heap,
+            stack,
+            input_data_regions: input_data_regions.into_iter().map(Into::into).collect(),
+            frame_count,
+            log,
+            rodata,
+            pc,
+        }
+    }
+}
+
+impl From<Effects> for ProtoEffects {
+    fn from(value: Effects) -> Self {
+        let Effects {
+            error,
+            error_kind,
+            r0,
+            compute_units_available,
+            heap,
+            stack,
+            input_data_regions,
+            frame_count,
+            log,
+            rodata,
+            pc,
+        } = value;
+        Self {
+            error,
+            error_kind: ProtoErrorKind::from(error_kind).into(),
+            r0,
+            cu_avail: compute_units_available,
+            heap,
+            stack,
+            input_data_regions: input_data_regions.into_iter().map(Into::into).collect(),
+            frame_count,
+            log,
+            rodata,
+            pc,
+        }
+    }
+}
+
+/// A fixture for invoking a single syscall against a simulated SVM program
+/// runtime environment.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fixture {
+    /// The fixture metadata.
+    pub metadata: Option<Metadata>,
+    /// The fixture inputs.
+    pub input: Context,
+    /// The fixture outputs.
+    pub output: Effects,
+}
+
+impl Fixture {
+    pub fn decode(blob: &[u8]) -> Self {
+        let proto_fixture = <ProtoFixture as SerializableFixture>::decode(blob);
+        proto_fixture.into()
+    }
+
+    pub fn load_from_blob_file(file_path: &str) -> Self {
+        let proto_fixture: ProtoFixture = FsHandler::load_from_blob_file(file_path);
+        proto_fixture.into()
+    }
+
+    pub fn load_from_json_file(file_path: &str) -> Self {
+        let proto_fixture: ProtoFixture = FsHandler::load_from_json_file(file_path);
+        proto_fixture.into()
+    }
+}
+
+impl From<ProtoFixture> for Fixture {
+    fn from(value: ProtoFixture) -> Self {
+        // All blobs should have an input and output.
+        Self {
+            metadata: value.metadata.map(Into::into),
+            input: value.input.unwrap().into(),
+            output: value.output.unwrap().into(),
+        }
+    }
+}
+
+impl From<Fixture> for ProtoFixture {
+    fn from(value: Fixture) -> Self {
+        Self {
+            metadata: value.metadata.map(Into::into),
+            input: Some(value.input.into()),
+            output: Some(value.output.into()),
+        }
+    }
+}
+
+fn hash_proto_input_data_regions(hasher: &mut Hasher, regions: &[ProtoInputDataRegion]) {
+    for region in regions {
+        hasher.hash(&region.offset.to_le_bytes());
+        hasher.hash(&region.content);
+        hasher.hash(&[region.is_writable as u8]);
+    }
+}
+
+fn hash_proto_context(hasher: &mut Hasher, context: &ProtoContext) {
+    if let Some(vm_context) = &context.vm_ctx {
+        hasher.hash(&vm_context.heap_max.to_le_bytes());
+        hasher.hash(&vm_context.rodata);
+        hasher.hash(&vm_context.rodata_text_section_offset.to_le_bytes());
+        hasher.hash(&vm_context.rodata_text_section_length.to_le_bytes());
+        hash_proto_input_data_regions(hasher, &vm_context.input_data_regions);
+        for register in [
+            vm_context.r0,
+            vm_context.r1,
+            vm_context.r2,
+            vm_context.r3,
+            vm_context.r4,
+            vm_context.r5,
+            vm_context.r6,
+            vm_context.r7,
+            vm_context.r8,
+            vm_context.r9,
+            vm_context.r10,
+            vm_context.r11,
+        ] {
+            hasher.hash(&register.to_le_bytes());
+        }
+        hasher.hash(&vm_context.entry_pc.to_le_bytes());
+        hasher.hash(&vm_context.call_whitelist);
+        hasher.hash(&[vm_context.check_align as u8, vm_context.check_size as u8]);
+        hasher.hash(&vm_context.sbpf_version.to_le_bytes());
+    }
+    if let Some(instr_context) = &context.instr_ctx {
+        crate::context::hash_proto_context(hasher, instr_context);
+    }
+    if let Some(invocation) = &context.syscall_invocation {
+        hasher.hash(&invocation.function_name);
+        hasher.hash(&invocation.heap_prefix);
+        hasher.hash(&invocation.stack_prefix);
+    }
+}
+
+fn hash_proto_effects(hasher: &mut Hasher, effects: &ProtoEffects) {
+    hasher.hash(&effects.error.to_le_bytes());
+    hasher.hash(&effects.error_kind.to_le_bytes());
+    hasher.hash(&effects.r0.to_le_bytes());
+    hasher.hash(&effects.cu_avail.to_le_bytes());
+    hasher.hash(&effects.heap);
+    hasher.hash(&effects.stack);
+    hash_proto_input_data_regions(hasher, &effects.input_data_regions);
+    hasher.hash(&effects.frame_count.to_le_bytes());
+    hasher.hash(&effects.log);
+    hasher.hash(&effects.rodata);
+    hasher.hash(&effects.pc.to_le_bytes());
+}
+
+impl SerializableFixture for ProtoFixture {
+    // Manually implemented for deterministic hashes.
+    fn hash(&self) -> Hash {
+        let mut hasher = Hasher::default();
+        if let Some(metadata) = &self.metadata {
+            crate::metadata::hash_proto_metadata(&mut hasher, metadata);
+        }
+        if let Some(input) = &self.input {
+            hash_proto_context(&mut hasher, input);
+        }
+        if let Some(output) = &self.output {
+            hash_proto_effects(&mut hasher, output);
+        }
+        hasher.result()
+    }
+}
+
+impl IntoSerializableFixture for Fixture {
+    type Fixture = ProtoFixture;
+
+    fn into(self) -> Self::Fixture {
+        Into::into(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Context, Effects, ErrorKind, Fixture, InputDataRegion, SyscallInvocation, VmContext,
+        },
+        crate::{
+            context::{Context as InstructionContext, EpochContext},
+            proto::FeatureSet,
+        },
+        mollusk_svm_fuzz_fs::IntoSerializableFixture,
+        prost::Message as _,
+        solana_pubkey::Pubkey,
+    };
+
+    #[test]
+    fn test_proto_round_trip() {
+        let region = InputDataRegion {
+            offset: 0,
+            content: vec![1; 16],
+            is_writable: true,
+        };
+        let fixture = Fixture {
+            metadata: None,
+            input: Context {
+                vm_context: VmContext {
+                    heap_max: 32 * 1024,
+                    input_data_regions: vec![region.clone()],
+                    registers: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+                    ..Default::default()
+                },
+                instruction_context: InstructionContext {
+                    program_id: Pubkey::new_unique(),
+                    // Decoded feature sets track every inactive feature.
+                    epoch_context: EpochContext {
+                        feature_set: FeatureSet::default().into(),
+                    },
+                    ..Default::default()
+                },
+                invocation: SyscallInvocation {
+                    function_name: b"sol_memset_".to_vec(),
+                    heap_prefix: vec![2; 8],
+                    stack_prefix: vec![3; 8],
+                },
+            },
+            output: Effects {
+                error: 1,
+                error_kind: ErrorKind::Syscall,
+                input_data_regions: vec![region],
+                ..Default::default()
+            },
+        };
+
+        let blob = IntoSerializableFixture::into(fixture.clone()).encode_to_vec();
+        assert_eq!(Fixture::decode(&blob), fixture);
+    }
+}
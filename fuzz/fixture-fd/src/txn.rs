@@ -0,0 +1,705 @@
+//! A fixture for executing a transaction, for Firedancer's transaction
+//! harness.
+
+use {
+    super::proto::{
+        CompiledInstruction as ProtoCompiledInstruction, FeeDetails as ProtoFeeDetails,
+        MessageAddressTableLookup as ProtoAddressTableLookup, MessageHeader as ProtoMessageHeader,
+        RentDebits as ProtoRentDebits, ResultingState as ProtoResultingState,
+        SanitizedTransaction as ProtoTransaction, TransactionMessage as ProtoMessage,
+        TxnContext as ProtoContext, TxnFixture as ProtoFixture, TxnResult as ProtoEffects,
+    },
+    crate::{
+        account::SeedAddress,
+        context::{EpochContext, SlotContext},
+        metadata::Metadata,
+    },
+    mollusk_svm_fuzz_fs::{FsHandler, IntoSerializableFixture, SerializableFixture},
+    solana_account::Account,
+    solana_keccak_hasher::{Hash, Hasher},
+    solana_pubkey::Pubkey,
+};
+
+fn pubkey_from_bytes(bytes: Vec<u8>, what: &str) -> Pubkey {
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .unwrap_or_else(|_| panic!("Invalid bytes for {}", what));
+    Pubkey::new_from_array(bytes)
+}
+
+/// The header of a transaction message.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MessageHeader {
+    /// The number of signatures required, from the first account keys.
+    pub num_required_signatures: u32,
+    /// The number of signed account keys which are read-only.
+    pub num_readonly_signed_accounts: u32,
+    /// The number of unsigned account keys which are read-only.
+    pub num_readonly_unsigned_accounts: u32,
+}
+
+impl From<ProtoMessageHeader> for MessageHeader {
+    fn from(value: ProtoMessageHeader) -> Self {
+        let ProtoMessageHeader {
+            num_required_signatures,
+            num_readonly_signed_accounts,
+            num_readonly_unsigned_accounts,
+        } = value;
+        Self {
+            num_required_signatures,
+            num_readonly_signed_accounts,
+            num_readonly_unsigned_accounts,
+        }
+    }
+}
+
+impl From<MessageHeader> for ProtoMessageHeader {
+    fn from(value: MessageHeader) -> Self {
+        let MessageHeader {
+            num_required_signatures,
+            num_readonly_signed_accounts,
+            num_readonly_unsigned_accounts,
+        } = value;
+        Self {
+            num_required_signatures,
+            num_readonly_signed_accounts,
+            num_readonly_unsigned_accounts,
+        }
+    }
+}
+
+/// An instruction, with its program and accounts referenced by their index
+/// in the message's account keys, followed by any loaded addresses.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompiledInstruction {
+    /// The index of the program ID.
+    pub program_id_index: u32,
+    /// The indices of the instruction's accounts.
+    pub accounts: Vec<u32>,
+    /// The instruction data.
+    pub data: Vec<u8>,
+}
+
+impl From<ProtoCompiledInstruction> for CompiledInstruction {
+    fn from(value: ProtoCompiledInstruction) -> Self {
+        let ProtoCompiledInstruction {
+            program_id_index,
+            accounts,
+            data,
+        } = value;
+        Self {
+            program_id_index,
+            accounts,
+            data,
+        }
+    }
+}
+
+impl From<CompiledInstruction> for ProtoCompiledInstruction {
+    fn from(value: CompiledInstruction) -> Self {
+        let CompiledInstruction {
+            program_id_index,
+            accounts,
+            data,
+        } = value;
+        Self {
+            program_id_index,
+            accounts,
+            data,
+        }
+    }
+}
+
+/// An address lookup table referenced by a v0 message.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AddressTableLookup {
+    /// The address of the lookup table.
+    pub account_key: Pubkey,
+    /// The indices of the addresses loaded as writable.
+    pub writable_indexes: Vec<u32>,
+    /// The indices of the addresses loaded as read-only.
+    pub readonly_indexes: Vec<u32>,
+}
+
+impl From<ProtoAddressTableLookup> for AddressTableLookup {
+    fn from(value: ProtoAddressTableLookup) -> Self {
+        let ProtoAddressTableLookup {
+            account_key,
+            writable_indexes,
+            readonly_indexes,
+        } = value;
+        Self {
+            account_key: pubkey_from_bytes(account_key, "lookup table"),
+            writable_indexes,
+            readonly_indexes,
+        }
+    }
+}
+
+impl From<AddressTableLookup> for ProtoAddressTableLookup {
+    fn from(value: AddressTableLookup) -> Self {
+        let AddressTableLookup {
+            account_key,
+            writable_indexes,
+            readonly_indexes,
+        } = value;
+        Self {
+            account_key: account_key.to_bytes().to_vec(),
+            writable_indexes,
+            readonly_indexes,
+        }
+    }
+}
+
+/// A legacy or v0 transaction message.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Message {
+    /// Whether this is a legacy message. Otherwise, a v0 message.
+    pub is_legacy: bool,
+    /// The message header.
+    pub header: MessageHeader,
+    /// The static account keys.
+    pub account_keys: Vec<Pubkey>,
+    /// The recent blockhash (32 bytes).
+    pub recent_blockhash: Vec<u8>,
+    /// The instructions.
+    pub instructions: Vec<CompiledInstruction>,
+    /// Address table lookups. Only for v0 messages.
+    pub address_table_lookups: Vec<AddressTableLookup>,
+}
+
+impl From<ProtoMessage> for Message {
+    fn from(value: ProtoMessage) -> Self {
+        let ProtoMessage {
+            is_legacy,
+            header,
+            account_keys,
+            recent_blockhash,
+            instructions,
+            address_table_lookups,
+        } = value;
+        Self {
+            is_legacy,
+            header: header.map(Into::into).unwrap_or_default(),
+            account_keys: account_keys
+                .into_iter()
+                .map(|key| pubkey_from_bytes(key, "account key"))
+                .collect(),
+            recent_blockhash,
+            instructions: instructions.into_iter().map(Into::into).collect(),
+            address_table_lookups: address_table_lookups.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<Message> for ProtoMessage {
+    fn from(value: Message) -> Self {
+        let Message {
+            is_legacy,
+            header,
+            account_keys,
+            recent_blockhash,
+            instructions,
+            address_table_lookups,
+        } = value;
+        Self {
+            is_legacy,
+            header: Some(header.into()),
+            account_keys: account_keys
+                .into_iter()
+                .map(|key| key.to_bytes().to_vec())
+                .collect(),
+            recent_blockhash,
+            instructions: instructions.into_iter().map(Into::into).collect(),
+            address_table_lookups: address_table_lookups.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// A sanitized transaction.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transaction {
+    /// The transaction message.
+    pub message: Message,
+    /// The hash of the message (32 bytes).
+    pub message_hash: Vec<u8>,
+    /// The signatures (64 bytes each).
+    pub signatures: Vec<Vec<u8>>,
+}
+
+impl From<ProtoTransaction> for Transaction {
+    fn from(value: ProtoTransaction) -> Self {
+        let ProtoTransaction {
+            message,
+            message_hash,
+            signatures,
+        } = value;
+        Self {
+            message: message.map(Into::into).unwrap_or_default(),
+            message_hash,
+            signatures,
+        }
+    }
+}
+
+impl From<Transaction> for ProtoTransaction {
+    fn from(value: Transaction) -> Self {
+        let Transaction {
+            message,
+            message_hash,
+            signatures,
+        } = value;
+        Self {
+            message: Some(message.into()),
+            message_hash,
+            signatures,
+        }
+    }
+}
+
+/// Transaction context fixture.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Context {
+    /// The transaction to execute.
+    pub transaction: Transaction,
+    /// Input accounts with state, including any address lookup tables.
+    pub accounts: Vec<(Pubkey, Account, Option<SeedAddress>)>,
+    /// Recent blockhashes, oldest first (32 bytes each).
+    pub blockhash_queue: Vec<Vec<u8>>,
+    /// Slot context.
+    pub slot_context: SlotContext,
+    /// Epoch context.
+    pub epoch_context: EpochContext,
+}
+
+impl From<ProtoContext> for Context {
+    fn from(value: ProtoContext) -> Self {
+        let ProtoContext {
+            tx,
+            account_shared_data,
+            blockhash_queue,
+            epoch_ctx,
+            slot_ctx,
+        } = value;
+        Self {
+            transaction: tx.map(Into::into).unwrap_or_default(),
+            accounts: account_shared_data.into_iter().map(Into::into).collect(),
+            blockhash_queue,
+            slot_context: slot_ctx.map(Into::into).unwrap_or_default(),
+            epoch_context: epoch_ctx.map(Into::into).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<Context> for ProtoContext {
+    fn from(value: Context) -> Self {
+        let Context {
+            transaction,
+            accounts,
+            blockhash_queue,
+            slot_context,
+            epoch_context,
+        } = value;
+        Self {
+            tx: Some(transaction.into()),
+            account_shared_data: accounts.into_iter().map(Into::into).collect(),
+            blockhash_queue,
+            epoch_ctx: Some(epoch_context.into()),
+            slot_ctx: Some(slot_context.into()),
+        }
+    }
+}
+
+/// The fees charged for a transaction.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeeDetails {
+    /// The signature fee.
+    pub transaction_fee: u64,
+    /// The prioritization fee.
+    pub prioritization_fee: u64,
+}
+
+impl From<ProtoFeeDetails> for FeeDetails {
+    fn from(value: ProtoFeeDetails) -> Self {
+        let ProtoFeeDetails {
+            transaction_fee,
+            prioritization_fee,
+        } = value;
+        Self {
+            transaction_fee,
+            prioritization_fee,
+        }
+    }
+}
+
+impl From<FeeDetails> for ProtoFeeDetails {
+    fn from(value: FeeDetails) -> Self {
+        let FeeDetails {
+            transaction_fee,
+            prioritization_fee,
+        } = value;
+        Self {
+            transaction_fee,
+            prioritization_fee,
+        }
+    }
+}
+
+/// Represents the effects of a transaction.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Effects {
+    /// Whether the transaction was executed. Transactions which fail
+    /// sanitization, or whose accounts or fees can't be loaded, aren't.
+    pub executed: bool,
+    /// Whether the transaction failed sanitization.
+    pub sanitization_error: bool,
+    /// Copies of the accounts loaded by the transaction, after execution.
+    pub resulting_accounts: Vec<(Pubkey, Account, Option<SeedAddress>)>,
+    /// Rent collected from each account.
+    pub rent_debits: Vec<(Pubkey, i64)>,
+    /// The rent collected by the transaction.
+    pub transaction_rent: u64,
+    /// The rent collected.
+    pub rent: u64,
+    /// Whether the transaction executed successfully.
+    pub is_ok: bool,
+    /// Transaction error code. Zero is success, errors are non-zero.
+    pub status: u32,
+    /// Instruction error code of the failed instruction, if any.
+    pub instruction_error: u32,
+    /// The index of the failed instruction, if any.
+    pub instruction_error_index: u32,
+    /// Custom error code of the failed instruction, if any.
+    pub custom_error: u32,
+    /// Transaction return data.
+    pub return_data: Vec<u8>,
+    /// Compute units consumed by the transaction.
+    pub executed_units: u64,
+    /// The fees charged.
+    pub fee_details: FeeDetails,
+    /// The size of the data of the accounts loaded.
+    pub loaded_accounts_data_size: u64,
+}
+
+impl From<ProtoEffects> for Effects {
+    fn from(value: ProtoEffects) -> Self {
+        let ProtoEffects {
+            executed,
+            sanitization_error,
+            resulting_state,
+            rent,
+            is_ok,
+            status,
+            instruction_error,
+            instruction_error_index,
+            custom_error,
+            return_data,
+            executed_units,
+            fee_details,
+            loaded_accounts_data_size,
+        } = value;
+
+        let ProtoResultingState {
+            acct_states,
+            rent_debits,
+            transaction_rent,
+        } = resulting_state.unwrap_or_default();
+
+        let rent_debits = rent_debits
+            .into_iter()
+            .map(|debit| {
+                (
+                    pubkey_from_bytes(debit.pubkey, "rent debit"),
+                    debit.rent_collected,
+                )
+            })
+            .collect();
+
+        Self {
+            executed,
+            sanitization_error,
+            resulting_accounts: acct_states.into_iter().map(Into::into).collect(),
+            rent_debits,
+            transaction_rent,
+            rent,
+            is_ok,
+            status,
+            instruction_error,
+            instruction_error_index,
+            custom_error,
+            return_data,
+            executed_units,
+            fee_details: fee_details.map(Into::into).unwrap_or_default(),
+            loaded_accounts_data_size,
+        }
+    }
+}
+
+impl From<Effects> for ProtoEffects {
+    fn from(value: Effects) -> Self {
+        let Effects {
+            executed,
+            sanitization_error,
+            resulting_accounts,
+            rent_debits,
+            transaction_rent,
+            rent,
+            is_ok,
+            status,
+            instruction_error,
+            instruction_error_index,
+            custom_error,
+            return_data,
+            executed_units,
+            fee_details,
+            loaded_accounts_data_size,
+        } = value;
+
+        let rent_debits = rent_debits
+            .into_iter()
+            .map(|(pubkey, rent_collected)| ProtoRentDebits {
+                pubkey: pubkey.to_bytes().to_vec(),
+                rent_collected,
+            })
+            .collect();
+
+        Self {
+            executed,
+            sanitization_error,
+            resulting_state: Some(ProtoResultingState {
+                acct_states: resulting_accounts.into_iter().map(Into::into).collect(),
+                rent_debits,
+                transaction_rent,
+            }),
+            rent,
+            is_ok,
+            status,
+            instruction_error,
+            instruction_error_index,
+            custom_error,
+            return_data,
+            executed_units,
+            fee_details: Some(fee_details.into()),
+            loaded_accounts_data_size,
+        }
+    }
+}
+
+/// A fixture for executing a transaction against a simulated SVM runtime
+/// environment.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fixture {
+    /// The fixture metadata.
+    pub metadata: Option<Metadata>,
+    /// The fixture inputs.
+    pub input: Context,
+    /// The fixture outputs.
+    pub output: Effects,
+}
+
+impl Fixture {
+    pub fn decode(blob: &[u8]) -> Self {
+        let proto_fixture = <ProtoFixture as SerializableFixture>::decode(blob);
+        proto_fixture.into()
+    }
+
+    pub fn load_from_blob_file(file_path: &str) -> Self {
+        let proto_fixture: ProtoFixture = FsHandler::load_from_blob_file(file_path);
+        proto_fixture.into()
+    }
+
+    pub fn load_from_json_file(file_path: &str) -> Self {
+        let proto_fixture: ProtoFixture = FsHandler::load_from_json_file(file_path);
+        proto_fixture.into()
+    }
+}
+
+impl From<ProtoFixture> for Fixture {
+    fn from(value: ProtoFixture) -> Self {
+        // All blobs should have an input and output.
+        Self {
+            metadata: value.metadata.map(Into::into),
+            input: value.input.unwrap().into(),
+            output: value.output.unwrap().into(),
+        }
+    }
+}
+
+impl From<Fixture> for ProtoFixture {
+    fn from(value: Fixture) -> Self {
+        Self {
+            metadata: value.metadata.map(Into::into),
+            input: Some(value.input.into()),
+            output: Some(value.output.into()),
+        }
+    }
+}
+
+fn hash_proto_message(hasher: &mut Hasher, message: &ProtoMessage) {
+    hasher.hash(&[message.is_legacy as u8]);
+    if let Some(header) = &message.header {
+        hasher.hash(&header.num_required_signatures.to_le_bytes());
+        hasher.hash(&header.num_readonly_signed_accounts.to_le_bytes());
+        hasher.hash(&header.num_readonly_unsigned_accounts.to_le_bytes());
+    }
+    for key in &message.account_keys {
+        hasher.hash(key);
+    }
+    hasher.hash(&message.recent_blockhash);
+    for instruction in &message.instructions {
+        hasher.hash(&instruction.program_id_index.to_le_bytes());
+        for index in &instruction.accounts {
+            hasher.hash(&index.to_le_bytes());
+        }
+        hasher.hash(&instruction.data);
+    }
+    for lookup in &message.address_table_lookups {
+        hasher.hash(&lookup.account_key);
+        for index in lookup
+            .writable_indexes
+            .iter()
+            .chain(&lookup.readonly_indexes)
+        {
+            hasher.hash(&index.to_le_bytes());
+        }
+    }
+}
+
+fn hash_proto_context(hasher: &mut Hasher, context: &ProtoContext) {
+    if let Some(tx) = &context.tx {
+        if let Some(message) = &tx.message {
+            hash_proto_message(hasher, message);
+        }
+        hasher.hash(&tx.message_hash);
+        for signature in &tx.signatures {
+            hasher.hash(signature);
+        }
+    }
+    crate::account::hash_proto_accounts(hasher, &context.account_shared_data);
+    for blockhash in &context.blockhash_queue {
+        hasher.hash(blockhash);
+    }
+    if let Some(slot_context) = &context.slot_ctx {
+        hasher.hash(&slot_context.slot.to_le_bytes());
+    }
+    if let Some(epoch_context) = &context.epoch_ctx {
+        if let Some(features) = &epoch_context.features {
+            crate::feature_set::hash_proto_feature_set(hasher, features);
+        }
+    }
+}
+
+fn hash_proto_effects(hasher: &mut Hasher, effects: &ProtoEffects) {
+    hasher.hash(&[effects.executed as u8, effects.sanitization_error as u8]);
+    if let Some(resulting_state) = &effects.resulting_state {
+        crate::account::hash_proto_accounts(hasher, &resulting_state.acct_states);
+        for debit in &resulting_state.rent_debits {
+            hasher.hash(&debit.pubkey);
+            hasher.hash(&debit.rent_collected.to_le_bytes());
+        }
+        hasher.hash(&resulting_state.transaction_rent.to_le_bytes());
+    }
+    hasher.hash(&effects.rent.to_le_bytes());
+    hasher.hash(&[effects.is_ok as u8]);
+    hasher.hash(&effects.status.to_le_bytes());
+    hasher.hash(&effects.instruction_error.to_le_bytes());
+    hasher.hash(&effects.instruction_error_index.to_le_bytes());
+    hasher.hash(&effects.custom_error.to_le_bytes());
+    hasher.hash(&effects.return_data);
+    hasher.hash(&effects.executed_units.to_le_bytes());
+    if let Some(fee_details) = &effects.fee_details {
+        hasher.hash(&fee_details.transaction_fee.to_le_bytes());
+        hasher.hash(&fee_details.prioritization_fee.to_le_bytes());
+    }
+    hasher.hash(&effects.loaded_accounts_data_size.to_le_bytes());
+}
+
+impl SerializableFixture for ProtoFixture {
+    // Manually implemented for deterministic hashes.
+    fn hash(&self) -> Hash {
+        let mut hasher = Hasher::default();
+        if let Some(metadata) = &self.metadata {
+            crate::metadata::hash_proto_metadata(&mut hasher, metadata);
+        }
+        if let Some(input) = &self.input {
+            hash_proto_context(&mut hasher, input);
+        }
+        if let Some(output) = &self.output {
+            hash_proto_effects(&mut hasher, output);
+        }
+        hasher.result()
+    }
+}
+
+impl IntoSerializableFixture for Fixture {
+    type Fixture = ProtoFixture;
+
+    fn into(self) -> Self::Fixture {
+        Into::into(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            CompiledInstruction, Context, Effects, Fixture, Message, MessageHeader, Transaction,
+        },
+        crate::{context::EpochContext, metadata::Metadata, proto::FeatureSet},
+        mollusk_svm_fuzz_fs::IntoSerializableFixture,
+        prost::Message as _,
+        solana_account::Account,
+        solana_pubkey::Pubkey,
+    };
+
+    #[test]
+    fn test_proto_round_trip() {
+        let payer = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let fixture = Fixture {
+            metadata: Some(Metadata {
+                entrypoint: String::from("sol_compat_txn_execute_v1"),
+            }),
+            input: Context {
+                transaction: Transaction {
+                    message: Message {
+                        is_legacy: true,
+                        header: MessageHeader {
+                            num_required_signatures: 1,
+                            num_readonly_signed_accounts: 0,
+                            num_readonly_unsigned_accounts: 1,
+                        },
+                        account_keys: vec![payer, program_id],
+                        recent_blockhash: vec![7; 32],
+                        instructions: vec![CompiledInstruction {
+                            program_id_index: 1,
+                            accounts: vec![0],
+                            data: vec![1, 2, 3],
+                        }],
+                        address_table_lookups: vec![],
+                    },
+                    message_hash: vec![8; 32],
+                    signatures: vec![vec![9; 64]],
+                },
+                accounts: vec![(payer, Account::new(42, 0, &Pubkey::default()), None)],
+                blockhash_queue: vec![vec![7; 32]],
+                // Decoded feature sets track every inactive feature.
+                epoch_context: EpochContext {
+                    feature_set: FeatureSet::default().into(),
+                },
+                ..Default::default()
+            },
+            output: Effects {
+                executed: true,
+                is_ok: true,
+                resulting_accounts: vec![(payer, Account::new(40, 0, &Pubkey::default()), None)],
+                rent_debits: vec![(payer, 0)],
+                executed_units: 150,
+                ..Default::default()
+            },
+        };
+
+        let blob = IntoSerializableFixture::into(fixture.clone()).encode_to_vec();
+        assert_eq!(Fixture::decode(&blob), fixture);
+    }
+}
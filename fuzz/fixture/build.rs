@@ -7,6 +7,7 @@ fn main() -> Result<()> {
         proto_base_path.join("compute_budget.proto"),
         proto_base_path.join("sysvars.proto"),
         proto_base_path.join("invoke.proto"),
+        proto_base_path.join("chain.proto"),
     ];
 
     protos
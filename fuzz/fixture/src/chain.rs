@@ -0,0 +1,253 @@
+//! Fixtures for a chain of instructions, each processed against the resulting
+//! accounts of the previous one.
+
+use {
+    crate::{
+        effects::Effects,
+        proto::{
+            ChainContext as ProtoChainContext, ChainEffects as ProtoChainEffects,
+            ChainFixture as ProtoChainFixture, ChainInstr as ProtoChainInstruction,
+            InstrAcct as ProtoInstructionAccount,
+        },
+        sysvars::Sysvars,
+    },
+    agave_feature_set::FeatureSet,
+    mollusk_svm_fuzz_fs::{FsHandler, IntoSerializableFixture, SerializableFixture},
+    solana_account::Account,
+    solana_compute_budget::compute_budget::ComputeBudget,
+    solana_instruction::{AccountMeta, Instruction},
+    solana_keccak_hasher::{Hash, Hasher},
+    solana_pubkey::Pubkey,
+};
+
+/// Instruction chain context fixture.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChainContext {
+    /// The compute budget to use for the simulation.
+    pub compute_budget: ComputeBudget,
+    /// The feature set to use for the simulation.
+    pub feature_set: FeatureSet,
+    /// The runtime sysvars to use for the simulation.
+    pub sysvars: Sysvars,
+    /// The instructions to process, in order.
+    pub instructions: Vec<Instruction>,
+    /// Input accounts with state, before the first instruction.
+    pub accounts: Vec<(Pubkey, Account)>,
+}
+
+/// The effects of each processed instruction in a chain.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChainEffects {
+    /// The effects of each processed instruction, in order. Processing stops
+    /// at the first failed instruction, so there may be fewer effects than
+    /// instructions.
+    pub instructions: Vec<Effects>,
+}
+
+/// A fixture for invoking a chain of instructions against a simulated SVM
+/// program runtime environment.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChainFixture {
+    /// The fixture inputs.
+    pub input: ChainContext,
+    /// The fixture outputs.
+    pub output: ChainEffects,
+}
+
+impl ChainFixture {
+    pub fn decode(blob: &[u8]) -> Self {
+        let proto_fixture = <ProtoChainFixture as SerializableFixture>::decode(blob);
+        proto_fixture.into()
+    }
+
+    pub fn load_from_blob_file(file_path: &str) -> Self {
+        let proto_fixture: ProtoChainFixture = FsHandler::load_from_blob_file(file_path);
+        proto_fixture.into()
+    }
+
+    pub fn load_from_json_file(file_path: &str) -> Self {
+        let proto_fixture: ProtoChainFixture = FsHandler::load_from_json_file(file_path);
+        proto_fixture.into()
+    }
+}
+
+impl From<ProtoChainContext> for ChainContext {
+    fn from(value: ProtoChainContext) -> Self {
+        let accounts: Vec<(Pubkey, Account)> = value.accounts.into_iter().map(Into::into).collect();
+
+        let instructions = value
+            .instructions
+            .into_iter()
+            .map(|instruction| {
+                let program_id_bytes: [u8; 32] = instruction
+                    .program_id
+                    .try_into()
+                    .expect("Invalid bytes for program ID");
+                let accounts = instruction
+                    .instr_accounts
+                    .into_iter()
+                    .map(
+                        |ProtoInstructionAccount {
+                             index,
+                             is_signer,
+                             is_writable,
+                         }| {
+                            let (pubkey, _) = accounts
+                                .get(index as usize)
+                                .expect("Invalid index for instruction account");
+                            AccountMeta {
+                                pubkey: *pubkey,
+                                is_signer,
+                                is_writable,
+                            }
+                        },
+                    )
+                    .collect();
+                Instruction {
+                    program_id: Pubkey::new_from_array(program_id_bytes),
+                    accounts,
+                    data: instruction.data,
+                }
+            })
+            .collect();
+
+        Self {
+            compute_budget: value.compute_budget.map(Into::into).unwrap_or_default(),
+            feature_set: value.feature_set.map(Into::into).unwrap_or_default(),
+            sysvars: value.sysvars.map(Into::into).unwrap_or_default(),
+            instructions,
+            accounts,
+        }
+    }
+}
+
+impl From<ChainContext> for ProtoChainContext {
+    fn from(value: ChainContext) -> Self {
+        let instructions = value
+            .instructions
+            .into_iter()
+            .map(|instruction| {
+                let instr_accounts = instruction
+                    .accounts
+                    .into_iter()
+                    .map(
+                        |AccountMeta {
+                             pubkey,
+                             is_signer,
+                             is_writable,
+                         }| {
+                            let index_of_account = value
+                                .accounts
+                                .iter()
+                                .position(|(key, _)| key == &pubkey)
+                                .unwrap();
+                            ProtoInstructionAccount {
+                                index: index_of_account as u32,
+                                is_signer,
+                                is_writable,
+                            }
+                        },
+                    )
+                    .collect();
+                ProtoChainInstruction {
+                    program_id: instruction.program_id.to_bytes().to_vec(),
+                    instr_accounts,
+                    data: instruction.data,
+                }
+            })
+            .collect();
+
+        let accounts = value.accounts.into_iter().map(Into::into).collect();
+
+        Self {
+            compute_budget: Some(value.compute_budget.into()),
+            feature_set: Some(value.feature_set.into()),
+            sysvars: Some(value.sysvars.into()),
+            instructions,
+            accounts,
+        }
+    }
+}
+
+impl From<ProtoChainEffects> for ChainEffects {
+    fn from(value: ProtoChainEffects) -> Self {
+        Self {
+            instructions: value.instructions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<ChainEffects> for ProtoChainEffects {
+    fn from(value: ChainEffects) -> Self {
+        Self {
+            instructions: value.instructions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<ProtoChainFixture> for ChainFixture {
+    fn from(value: ProtoChainFixture) -> Self {
+        // All blobs should have an input and output.
+        Self {
+            input: value.input.unwrap().into(),
+            output: value.output.unwrap().into(),
+        }
+    }
+}
+
+impl From<ChainFixture> for ProtoChainFixture {
+    fn from(value: ChainFixture) -> Self {
+        Self {
+            input: Some(value.input.into()),
+            output: Some(value.output.into()),
+        }
+    }
+}
+
+impl SerializableFixture for ProtoChainFixture {
+    const FILE_PREFIX: &'static str = "chain";
+
+    // Manually implemented for deterministic hashes.
+    fn hash(&self) -> Hash {
+        let mut hasher = Hasher::default();
+        if let Some(input) = &self.input {
+            hash_proto_chain_context(&mut hasher, input);
+        }
+        if let Some(output) = &self.output {
+            for effects in output.instructions.iter() {
+                crate::effects::hash_proto_effects(&mut hasher, effects);
+            }
+        }
+        hasher.result()
+    }
+}
+
+impl IntoSerializableFixture for ChainFixture {
+    type Fixture = ProtoChainFixture;
+
+    fn into(self) -> Self::Fixture {
+        Into::into(self)
+    }
+}
+
+fn hash_proto_chain_context(hasher: &mut Hasher, context: &ProtoChainContext) {
+    if let Some(compute_budget) = &context.compute_budget {
+        crate::compute_budget::hash_proto_compute_budget(hasher, compute_budget);
+    }
+    if let Some(feature_set) = &context.feature_set {
+        crate::feature_set::hash_proto_feature_set(hasher, feature_set);
+    }
+    if let Some(sysvars) = &context.sysvars {
+        crate::sysvars::hash_proto_sysvars(hasher, sysvars);
+    }
+    for instruction in context.instructions.iter() {
+        hasher.hash(&instruction.program_id);
+        for account in instruction.instr_accounts.iter() {
+            hasher.hash(&account.index.to_le_bytes());
+            hasher.hash(&[account.is_signer as u8]);
+            hasher.hash(&[account.is_writable as u8]);
+        }
+        hasher.hash(&instruction.data);
+    }
+    crate::account::hash_proto_accounts(hasher, &context.accounts);
+}
@@ -0,0 +1,377 @@
+//! A JSON index of the fixtures in a directory, recording what each one
+//! exercises, so near-identical fixtures can be found without decoding them.
+
+use {
+    crate::{proto::ChainFixture as ProtoChainFixture, Fixture},
+    fs2::FileExt,
+    mollusk_svm_fuzz_fs::SerializableFixture,
+    serde::{Deserialize, Serialize},
+    std::{
+        fmt::Write,
+        fs::{self, File},
+        path::Path,
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+};
+
+/// The name of the index file within a fixture directory.
+pub const INDEX_FILE_NAME: &str = "index.json";
+
+/// The number of leading instruction data bytes recorded as an instruction's
+/// discriminator. Clustering can use a shorter prefix.
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+/// The name of the file locked while updating the index, within a fixture
+/// directory.
+pub const INDEX_LOCK_FILE_NAME: &str = "index.json.lock";
+
+// Distinguishes the temporary files of concurrent saves within a process.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// The shape of an instruction account: its privileges and state, other than
+/// lamports and contents.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountShape {
+    pub is_signer: bool,
+    pub is_writable: bool,
+    pub owner: String,
+    pub data_len: usize,
+    /// Whether the instruction changed the account.
+    pub modified: bool,
+}
+
+/// An entry of the index, describing a single fixture.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexEntry {
+    /// The fixture's file name, within the directory.
+    pub file: String,
+    pub program_id: String,
+    /// The leading instruction data bytes, in hex.
+    pub discriminator: String,
+    /// The program result code. Zero is success, errors are non-zero.
+    pub program_result: u64,
+    pub compute_units_consumed: u64,
+    /// The name of the test the fixture was ejected from, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test: Option<String>,
+    /// The shape of each instruction account, in order.
+    pub accounts: Vec<AccountShape>,
+}
+
+impl IndexEntry {
+    pub fn new(file: &str, fixture: &Fixture, test: Option<&str>) -> Self {
+        let input = &fixture.input;
+        let discriminator = input.instruction_data.iter().take(DISCRIMINATOR_LEN).fold(
+            String::new(),
+            |mut hex, byte| {
+                let _ = write!(hex, "{:02x}", byte);
+                hex
+            },
+        );
+        let accounts = input
+            .instruction_accounts
+            .iter()
+            .map(|meta| {
+                let account = input
+                    .accounts
+                    .iter()
+                    .find(|(key, _)| key == &meta.pubkey)
+                    .map(|(_, account)| account);
+                let resulting_account = fixture
+                    .output
+                    .resulting_accounts
+                    .iter()
+                    .find(|(key, _)| key == &meta.pubkey)
+                    .map(|(_, account)| account);
+                AccountShape {
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                    owner: account
+                        .map(|account| account.owner.to_string())
+                        .unwrap_or_default(),
+                    data_len: account.map(|account| account.data.len()).unwrap_or(0),
+                    modified: resulting_account.is_some() && resulting_account != account,
+                }
+            })
+            .collect();
+        Self {
+            file: file.to_string(),
+            program_id: input.program_id.to_string(),
+            discriminator,
+            program_result: fixture.output.program_result,
+            compute_units_consumed: fixture.output.compute_units_consumed,
+            test: test.map(str::to_string),
+            accounts,
+        }
+    }
+}
+
+/// The index of a fixture directory.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixtureIndex {
+    pub fixtures: Vec<IndexEntry>,
+}
+
+impl FixtureIndex {
+    /// Load the index of a directory, or an empty index if it has none.
+    pub fn load(dir: &str) -> Self {
+        let path = Path::new(dir).join(INDEX_FILE_NAME);
+        match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .unwrap_or_else(|err| panic!("Failed to parse {}: {}", path.display(), err)),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Write the index to a directory. The index is written to a temporary
+    /// file first, then renamed, so readers never see a partial index.
+    pub fn save(&self, dir: &str) {
+        let json = serde_json::to_string_pretty(self).expect("Failed to serialize index");
+        fs::create_dir_all(dir).expect("Failed to create directory");
+        let temp_path = Path::new(dir).join(format!(
+            ".{}.{}.{}.tmp",
+            INDEX_FILE_NAME,
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed),
+        ));
+        fs::write(&temp_path, json).expect("Failed to write index");
+        fs::rename(&temp_path, Path::new(dir).join(INDEX_FILE_NAME))
+            .expect("Failed to write index");
+    }
+
+    /// Add an entry, replacing any existing entry for the same file.
+    pub fn insert(&mut self, entry: IndexEntry) {
+        match self
+            .fixtures
+            .iter_mut()
+            .find(|existing| existing.file == entry.file)
+        {
+            Some(existing) => *existing = entry,
+            None => self.fixtures.push(entry),
+        }
+    }
+
+    /// Lock the index of a directory, blocking until no other thread or
+    /// process holds the lock. Dropping the returned file releases it.
+    ///
+    /// Hold the lock from loading the index until saving it, so concurrent
+    /// updates don't lose each other's changes.
+    pub fn lock(dir: &str) -> File {
+        let lock_file = File::create(Path::new(dir).join(INDEX_LOCK_FILE_NAME))
+            .expect("Failed to create index lock file");
+        lock_file.lock_exclusive().expect("Failed to lock index");
+        lock_file
+    }
+
+    /// Add an entry to the index of a directory. The index is locked for the
+    /// update, so tests ejecting fixtures from parallel threads or processes
+    /// don't lose each other's entries.
+    pub fn record(dir: &str, entry: IndexEntry) {
+        fs::create_dir_all(dir).expect("Failed to create directory");
+        let _lock = Self::lock(dir);
+        let mut index = Self::load(dir);
+        index.insert(entry);
+        index.save(dir);
+    }
+
+    /// Load the index of a directory, bringing it up to date with the
+    /// fixture blobs in it: entries of removed files are dropped, and
+    /// fixtures without an entry are indexed, without a test name.
+    pub fn sync(dir: &str) -> Self {
+        let mut index = Self::load(dir);
+        index
+            .fixtures
+            .retain(|entry| Path::new(dir).join(&entry.file).is_file());
+
+        let mut files = fs::read_dir(dir)
+            .unwrap_or_else(|err| panic!("Failed to read directory {}: {}", dir, err))
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            // Chain fixtures aren't indexed.
+            .filter(|file| file.ends_with(".fix") && !ProtoChainFixture::is_file_name(file))
+            .filter(|file| !index.fixtures.iter().any(|entry| &entry.file == file))
+            .collect::<Vec<_>>();
+        files.sort();
+        for file in files {
+            let path = Path::new(dir).join(&file);
+            let fixture = Fixture::load_from_blob_file(path.to_str().unwrap());
+            index.insert(IndexEntry::new(&file, &fixture, None));
+        }
+        index
+    }
+
+    /// Group entries by program, discriminator, program result and the shape
+    /// of their instruction accounts, using the first `discriminator_len`
+    /// bytes of the recorded discriminator. Clusters are ordered by their
+    /// first entry.
+    pub fn clusters(&self, discriminator_len: usize) -> Vec<Vec<&IndexEntry>> {
+        let key = |entry: &IndexEntry| {
+            let discriminator = entry
+                .discriminator
+                .get(..discriminator_len * 2)
+                .unwrap_or(&entry.discriminator)
+                .to_string();
+            (
+                entry.program_id.clone(),
+                discriminator,
+                entry.program_result,
+                entry.accounts.clone(),
+            )
+        };
+        let mut clusters: Vec<(_, Vec<&IndexEntry>)> = Vec::new();
+        for entry in &self.fixtures {
+            let entry_key = key(entry);
+            match clusters.iter_mut().find(|(key, _)| key == &entry_key) {
+                Some((_, cluster)) => cluster.push(entry),
+                None => clusters.push((entry_key, vec![entry])),
+            }
+        }
+        clusters.into_iter().map(|(_, cluster)| cluster).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{context::Context, effects::Effects},
+        solana_account::Account,
+        solana_instruction::AccountMeta,
+        solana_pubkey::Pubkey,
+    };
+
+    fn fixture(program_id: Pubkey, data: &[u8], data_len: usize) -> Fixture {
+        let key = Pubkey::new_unique();
+        let account = Account::new(1_000, data_len, &program_id);
+        let mut resulting_account = account.clone();
+        resulting_account.lamports += 1;
+        Fixture {
+            input: Context {
+                program_id,
+                instruction_accounts: vec![AccountMeta::new(key, true)],
+                instruction_data: data.to_vec(),
+                accounts: vec![(key, account)],
+                ..Default::default()
+            },
+            output: Effects {
+                compute_units_consumed: data.len() as u64,
+                resulting_accounts: vec![(key, resulting_account)],
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_entry() {
+        let program_id = Pubkey::new_unique();
+        let entry = IndexEntry::new(
+            "instr-a.fix",
+            &fixture(program_id, &[1, 2, 3, 4, 5, 6, 7, 8, 9], 10),
+            Some("tests::test_a"),
+        );
+        assert_eq!(
+            entry,
+            IndexEntry {
+                file: "instr-a.fix".to_string(),
+                program_id: program_id.to_string(),
+                discriminator: "0102030405060708".to_string(),
+                program_result: 0,
+                compute_units_consumed: 9,
+                test: Some("tests::test_a".to_string()),
+                accounts: vec![AccountShape {
+                    is_signer: true,
+                    is_writable: true,
+                    owner: program_id.to_string(),
+                    data_len: 10,
+                    modified: true,
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn test_clusters() {
+        let program_id = Pubkey::new_unique();
+        let mut index = FixtureIndex::default();
+        for (file, data, data_len) in [
+            ("a", &[1, 0, 0][..], 10),
+            ("b", &[1, 5, 5], 10),
+            ("c", &[2, 0, 0], 10),
+            ("d", &[1, 0, 0], 20),
+        ] {
+            index.insert(IndexEntry::new(
+                file,
+                &fixture(program_id, data, data_len),
+                None,
+            ));
+        }
+        fn files(clusters: Vec<Vec<&IndexEntry>>) -> Vec<Vec<&str>> {
+            clusters
+                .iter()
+                .map(|cluster| cluster.iter().map(|entry| entry.file.as_str()).collect())
+                .collect()
+        }
+        assert_eq!(
+            files(index.clusters(1)),
+            vec![vec!["a", "b"], vec!["c"], vec!["d"]]
+        );
+        assert_eq!(
+            files(index.clusters(DISCRIMINATOR_LEN)),
+            vec![vec!["a"], vec!["b"], vec!["c"], vec!["d"]]
+        );
+
+        // Entries are replaced by file name.
+        index.insert(IndexEntry::new("a", &fixture(program_id, &[2], 10), None));
+        assert_eq!(index.fixtures.len(), 4);
+        assert_eq!(index.fixtures[0].discriminator, "02");
+    }
+
+    #[test]
+    fn test_record() {
+        let dir = std::env::temp_dir().join(format!("mollusk-index-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let program_id = Pubkey::new_unique();
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                scope.spawn(move || {
+                    let file = format!("instr-{}.fix", i);
+                    let entry = IndexEntry::new(&file, &fixture(program_id, &[i], 10), None);
+                    FixtureIndex::record(dir, entry);
+                });
+            }
+        });
+        let mut files = FixtureIndex::load(dir)
+            .fixtures
+            .into_iter()
+            .map(|entry| entry.file)
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(
+            files,
+            (0..8)
+                .map(|i| format!("instr-{}.fix", i))
+                .collect::<Vec<_>>()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_waits_for_lock() {
+        let dir = std::env::temp_dir().join(format!("mollusk-index-lock-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        fs::create_dir_all(dir).unwrap();
+        let entry = IndexEntry::new("instr.fix", &fixture(Pubkey::new_unique(), &[], 0), None);
+
+        let lock = FixtureIndex::lock(dir);
+        std::thread::scope(|scope| {
+            scope.spawn(|| FixtureIndex::record(dir, entry.clone()));
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            // The entry isn't recorded while the lock is held.
+            assert!(FixtureIndex::load(dir).fixtures.is_empty());
+            drop(lock);
+        });
+        assert_eq!(FixtureIndex::load(dir).fixtures, vec![entry]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
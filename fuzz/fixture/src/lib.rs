@@ -6,10 +6,12 @@
 //! They can be used to fuzz a custom entrypoint of the developer's choice.
 
 pub mod account;
+pub mod chain;
 pub mod compute_budget;
 pub mod context;
 pub mod effects;
 pub mod feature_set;
+pub mod index;
 pub mod proto {
     include!(concat!(env!("OUT_DIR"), "/org.mollusk.svm.rs"));
 }
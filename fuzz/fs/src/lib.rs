@@ -10,6 +10,16 @@ use {
 
 /// Represents a serializable fuzz fixture.
 pub trait SerializableFixture: Default + DeserializeOwned + Message + Serialize + Sized {
+    /// The prefix of the fixture's file names.
+    const FILE_PREFIX: &'static str = "instr";
+
+    /// Whether a file name is one this kind of fixture is dumped to.
+    fn is_file_name(file_name: &str) -> bool {
+        file_name
+            .strip_prefix(Self::FILE_PREFIX)
+            .is_some_and(|rest| rest.starts_with('-'))
+    }
+
     /// Decode a `Protobuf` blob into a fixture.
     fn decode(blob: &[u8]) -> Self {
         <Self as Message>::decode(blob)
@@ -52,27 +62,39 @@ where
         }
     }
 
-    /// Dumps the fixture to a protobuf binary blob file.
-    /// The file name is a hash of the fixture with the `.fix` extension.
-    pub fn dump_to_blob_file(&self, dir: &str) {
+    /// Dumps the fixture to a protobuf binary blob file, returning its name.
+    /// The file name is the fixture's prefix and hash, with the `.fix`
+    /// extension.
+    pub fn dump_to_blob_file(&self, dir: &str) -> String {
         let blob = SerializableFixture::encode(&self.serializable_fixture);
 
         let hash = self.serializable_fixture.hash();
-        let file_name = format!("instr-{}.fix", bs58::encode(hash).into_string());
+        let file_name = format!(
+            "{}-{}.fix",
+            SF::FILE_PREFIX,
+            bs58::encode(hash).into_string()
+        );
 
         write_file(Path::new(dir), &file_name, &blob);
+        file_name
     }
 
-    /// Dumps the fixture to a JSON file.
-    /// The file name is a hash of the fixture with the `.json` extension.
-    pub fn dump_to_json_file(self, dir_path: &str) {
+    /// Dumps the fixture to a JSON file, returning its name.
+    /// The file name is the fixture's prefix and hash, with the `.json`
+    /// extension.
+    pub fn dump_to_json_file(self, dir_path: &str) -> String {
         let json = serde_json::to_string_pretty(&self.serializable_fixture)
             .expect("Failed to serialize fixture to JSON");
 
         let hash = self.serializable_fixture.hash();
-        let file_name = format!("instr-{}.json", bs58::encode(hash).into_string());
+        let file_name = format!(
+            "{}-{}.json",
+            SF::FILE_PREFIX,
+            bs58::encode(hash).into_string()
+        );
 
         write_file(Path::new(dir_path), &file_name, json.as_bytes());
+        file_name
     }
 
     /// Loads a fixture from a protobuf binary blob file.
@@ -0,0 +1,353 @@
+//! Parsing of Anchor IDLs into [`ProgramSchema`]s.
+//!
+//! Both the current IDL format (Anchor 0.30 and later) and the legacy format
+//! are supported. Legacy IDLs don't list discriminators, so they're derived
+//! from instruction names, as Anchor does.
+
+use {
+    crate::schema::{AccountSchema, FieldType, InstructionSchema, ProgramSchema, SchemaError},
+    serde_json::Value as Json,
+    std::collections::HashMap,
+};
+
+fn invalid(message: impl Into<String>) -> SchemaError {
+    SchemaError::InvalidIdl(message.into())
+}
+
+fn array<'a>(json: &'a Json, key: &str) -> Result<&'a [Json], SchemaError> {
+    match json.get(key) {
+        Some(Json::Array(values)) => Ok(values),
+        None => Ok(&[]),
+        Some(_) => Err(invalid(format!("`{}` is not an array", key))),
+    }
+}
+
+fn name(json: &Json) -> Result<&str, SchemaError> {
+    json.get("name")
+        .and_then(Json::as_str)
+        .ok_or_else(|| invalid("Missing name"))
+}
+
+fn flag(json: &Json, keys: [&str; 2]) -> bool {
+    keys.iter()
+        .any(|key| json.get(*key).and_then(Json::as_bool).unwrap_or(false))
+}
+
+/// Parse an Anchor IDL, in JSON.
+pub fn parse_anchor_idl(idl: &str) -> Result<ProgramSchema, SchemaError> {
+    let idl: Json = serde_json::from_str(idl).map_err(|err| invalid(err.to_string()))?;
+    let types = array(&idl, "types")?
+        .iter()
+        .map(|ty| Ok((name(ty)?, ty)))
+        .collect::<Result<HashMap<_, _>, SchemaError>>()?;
+
+    let instructions = array(&idl, "instructions")?
+        .iter()
+        .map(|instruction| {
+            let name = name(instruction)?;
+            let discriminator = match instruction.get("discriminator") {
+                Some(discriminator) => serde_json::from_value(discriminator.clone())
+                    .map_err(|_| invalid(format!("Invalid discriminator for `{}`", name)))?,
+                None => legacy_discriminator(name),
+            };
+            let args = array(instruction, "args")?
+                .iter()
+                .map(|arg| {
+                    let ty = arg
+                        .get("type")
+                        .ok_or_else(|| invalid(format!("Missing type for `{}`", name)))?;
+                    parse_type(ty, &types, 0)
+                })
+                .collect::<Result<_, _>>()?;
+            let mut accounts = Vec::new();
+            parse_accounts(array(instruction, "accounts")?, &mut accounts)?;
+            Ok(InstructionSchema {
+                name: name.to_string(),
+                discriminator,
+                args,
+                accounts,
+            })
+        })
+        .collect::<Result<_, SchemaError>>()?;
+
+    Ok(ProgramSchema { instructions })
+}
+
+/// Flatten accounts, including nested account groups, in order.
+fn parse_accounts(accounts: &[Json], parsed: &mut Vec<AccountSchema>) -> Result<(), SchemaError> {
+    for account in accounts {
+        if account.get("accounts").is_some() {
+            parse_accounts(array(account, "accounts")?, parsed)?;
+            continue;
+        }
+        parsed.push(AccountSchema {
+            name: name(account)?.to_string(),
+            is_signer: flag(account, ["signer", "isSigner"]),
+            is_writable: flag(account, ["writable", "isMut"]),
+        });
+    }
+    Ok(())
+}
+
+// Bounds the expansion of recursive types, which Borsh can't encode anyway.
+const MAX_TYPE_DEPTH: usize = 32;
+
+fn parse_type(
+    ty: &Json,
+    types: &HashMap<&str, &Json>,
+    depth: usize,
+) -> Result<FieldType, SchemaError> {
+    if depth > MAX_TYPE_DEPTH {
+        return Err(invalid("Type nesting is too deep"));
+    }
+    let boxed = |ty: &Json| parse_type(ty, types, depth + 1).map(Box::new);
+
+    if let Some(primitive) = ty.as_str() {
+        return Ok(match primitive {
+            "bool" => FieldType::Bool,
+            "u8" => FieldType::U8,
+            "u16" => FieldType::U16,
+            "u32" => FieldType::U32,
+            "u64" => FieldType::U64,
+            "u128" => FieldType::U128,
+            "i8" => FieldType::I8,
+            "i16" => FieldType::I16,
+            "i32" => FieldType::I32,
+            "i64" => FieldType::I64,
+            "i128" => FieldType::I128,
+            // Floats are mutated as their raw bits.
+            "f32" => FieldType::U32,
+            "f64" => FieldType::U64,
+            "pubkey" | "publicKey" => FieldType::Pubkey,
+            "bytes" => FieldType::Bytes,
+            "string" => FieldType::String,
+            _ => return Err(invalid(format!("Unsupported type `{}`", primitive))),
+        });
+    }
+
+    let Some((kind, inner)) = ty.as_object().and_then(|ty| ty.iter().next()) else {
+        return Err(invalid(format!("Invalid type `{}`", ty)));
+    };
+    match kind.as_str() {
+        "option" => Ok(FieldType::Option(boxed(inner)?)),
+        "vec" => Ok(FieldType::Vec(boxed(inner)?)),
+        "array" => match inner.as_array().map(Vec::as_slice) {
+            Some([element, len]) => {
+                let len = len
+                    .as_u64()
+                    .ok_or_else(|| invalid("Array lengths must be literals"))?;
+                Ok(FieldType::Array(boxed(element)?, len as usize))
+            }
+            _ => Err(invalid(format!("Invalid array type `{}`", inner))),
+        },
+        "defined" => {
+            // `{"defined": {"name": "..."}}`, or `{"defined": "..."}` in
+            // legacy IDLs.
+            let defined = inner
+                .as_str()
+                .or_else(|| inner.get("name").and_then(Json::as_str))
+                .ok_or_else(|| invalid(format!("Invalid defined type `{}`", inner)))?;
+            if inner
+                .get("generics")
+                .is_some_and(|generics| generics != &Json::Array(vec![]))
+            {
+                return Err(invalid(format!("Unsupported generic type `{}`", defined)));
+            }
+            let definition = types
+                .get(defined)
+                .and_then(|definition| definition.get("type"))
+                .ok_or_else(|| invalid(format!("Unknown type `{}`", defined)))?;
+            parse_definition(definition, types, depth + 1)
+        }
+        _ => Err(invalid(format!("Unsupported type `{}`", kind))),
+    }
+}
+
+fn parse_definition(
+    definition: &Json,
+    types: &HashMap<&str, &Json>,
+    depth: usize,
+) -> Result<FieldType, SchemaError> {
+    // Fields are either named (`{"name": ..., "type": ...}`) or bare types,
+    // for tuple structs and variants.
+    let fields = |fields: &[Json]| {
+        fields
+            .iter()
+            .map(|field| parse_type(field.get("type").unwrap_or(field), types, depth))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    match definition.get("kind").and_then(Json::as_str) {
+        Some("struct") => Ok(FieldType::Struct(fields(array(definition, "fields")?)?)),
+        Some("enum") => Ok(FieldType::Enum(
+            array(definition, "variants")?
+                .iter()
+                .map(|variant| fields(array(variant, "fields")?))
+                .collect::<Result<_, _>>()?,
+        )),
+        Some("type") => definition
+            .get("alias")
+            .ok_or_else(|| invalid("Missing alias"))
+            .and_then(|alias| parse_type(alias, types, depth)),
+        kind => Err(invalid(format!("Unsupported type kind `{:?}`", kind))),
+    }
+}
+
+/// Anchor's discriminator for an instruction: the first eight bytes of the
+/// SHA-256 hash of `global:<snake_case_name>`.
+fn legacy_discriminator(name: &str) -> Vec<u8> {
+    let mut snake_case = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake_case.push('_');
+            }
+            snake_case.extend(c.to_lowercase());
+        } else {
+            snake_case.push(c);
+        }
+    }
+    solana_sha256_hasher::hash(format!("global:{}", snake_case).as_bytes()).to_bytes()[..8].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_anchor_idl() {
+        let idl = r#"{
+            "address": "11111111111111111111111111111111",
+            "instructions": [
+                {
+                    "name": "deposit",
+                    "discriminator": [242, 35, 198, 137, 82, 225, 242, 182],
+                    "accounts": [
+                        { "name": "owner", "writable": true, "signer": true },
+                        {
+                            "name": "vault",
+                            "accounts": [
+                                { "name": "state", "writable": true },
+                                { "name": "program" }
+                            ]
+                        }
+                    ],
+                    "args": [
+                        { "name": "amount", "type": "u64" },
+                        { "name": "params", "type": { "defined": { "name": "Params" } } }
+                    ]
+                }
+            ],
+            "types": [
+                {
+                    "name": "Params",
+                    "type": {
+                        "kind": "struct",
+                        "fields": [
+                            { "name": "memo", "type": { "option": "string" } },
+                            { "name": "side", "type": { "defined": { "name": "Side" } } }
+                        ]
+                    }
+                },
+                {
+                    "name": "Side",
+                    "type": {
+                        "kind": "enum",
+                        "variants": [{ "name": "Bid" }, { "name": "Ask", "fields": [{ "array": ["u8", 4] }] }]
+                    }
+                }
+            ]
+        }"#;
+
+        let schema = parse_anchor_idl(idl).unwrap();
+        assert_eq!(
+            schema.instructions,
+            vec![InstructionSchema {
+                name: "deposit".to_string(),
+                discriminator: vec![242, 35, 198, 137, 82, 225, 242, 182],
+                args: vec![
+                    FieldType::U64,
+                    FieldType::Struct(vec![
+                        FieldType::Option(Box::new(FieldType::String)),
+                        FieldType::Enum(vec![
+                            vec![],
+                            vec![FieldType::Array(Box::new(FieldType::U8), 4)],
+                        ]),
+                    ]),
+                ],
+                accounts: vec![
+                    AccountSchema {
+                        name: "owner".to_string(),
+                        is_signer: true,
+                        is_writable: true,
+                    },
+                    AccountSchema {
+                        name: "state".to_string(),
+                        is_signer: false,
+                        is_writable: true,
+                    },
+                    AccountSchema {
+                        name: "program".to_string(),
+                        is_signer: false,
+                        is_writable: false,
+                    },
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_legacy_anchor_idl() {
+        let idl = r#"{
+            "instructions": [
+                {
+                    "name": "deposit",
+                    "accounts": [{ "name": "owner", "isMut": true, "isSigner": true }],
+                    "args": [{ "name": "recipient", "type": "publicKey" }]
+                },
+                {
+                    "name": "initializeVault",
+                    "accounts": [],
+                    "args": [{ "name": "node", "type": { "defined": "Node" } }]
+                }
+            ],
+            "types": [
+                {
+                    "name": "Node",
+                    "type": { "kind": "struct", "fields": [{ "name": "next", "type": { "defined": "Node" } }] }
+                }
+            ]
+        }"#;
+
+        assert_eq!(
+            parse_anchor_idl(idl),
+            Err(SchemaError::InvalidIdl(
+                "Type nesting is too deep".to_string()
+            ))
+        );
+
+        let idl = idl.replace(r#"{ "defined": "Node" } }] }"#, r#""i32" }] }"#);
+        let schema = parse_anchor_idl(&idl).unwrap();
+        // `sha256("global:deposit")`, as listed in current IDLs.
+        assert_eq!(
+            schema.instructions[0].discriminator,
+            [242, 35, 198, 137, 82, 225, 242, 182]
+        );
+        assert_eq!(schema.instructions[0].args, [FieldType::Pubkey]);
+        assert_eq!(
+            schema.instructions[0].accounts,
+            [AccountSchema {
+                name: "owner".to_string(),
+                is_signer: true,
+                is_writable: true,
+            }]
+        );
+        assert_eq!(
+            schema.instructions[1].discriminator,
+            legacy_discriminator("initialize_vault")
+        );
+        assert_eq!(
+            schema.instructions[1].args,
+            [FieldType::Struct(vec![FieldType::I32])]
+        );
+    }
+}
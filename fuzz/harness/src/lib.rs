@@ -0,0 +1,489 @@
+//! Mollusk SVM Fuzz: coverage-guided fuzzing of SVM programs, built on
+//! Mollusk fixtures.
+//!
+//! A [`Fuzzer`] holds a corpus of seed fixtures, such as the ones ejected
+//! from tests with `EJECT_FUZZ_FIXTURES`. Each fuzz input picks a seed and
+//! structurally mutates its instruction data, instruction account privileges
+//! and account state (see [`mutate`]). The mutated fixture is processed with
+//! `Mollusk::process_fixture`, and the outcome is checked for:
+//!
+//! * Crashes: panics raised while processing the fixture.
+//! * VM panics: the program panicking, aborting, or faulting in the VM.
+//! * Lamport conservation: the total lamports of the accounts changing.
+//! * Invariants: checks provided with [`Fuzzer::add_invariant`].
+//!
+//! Given a [`schema::ProgramSchema`] for a program, such as one parsed from an
+//! Anchor IDL with [`idl::parse_anchor_idl`], its instructions are mutated
+//! field by field instead (see [`structured`]), so fuzz cases get past
+//! discriminator and deserialization checks.
+//!
+//! The fuzzer is driven by raw bytes, so it plugs straight into a
+//! `cargo-fuzz` (libFuzzer) target, which panics on any finding:
+//!
+//! ```rust,ignore
+//! #![no_main]
+//!
+//! use {
+//!     libfuzzer_sys::fuzz_target, mollusk_svm::Mollusk, mollusk_svm_fuzz::Fuzzer,
+//!     std::cell::RefCell,
+//! };
+//!
+//! thread_local! {
+//!     static FUZZER: RefCell<Fuzzer> = RefCell::new({
+//!         let mollusk = Mollusk::new(&program_id, "my_program");
+//!         let mut fuzzer = Fuzzer::new(mollusk);
+//!         fuzzer.load_corpus("./fuzz-fixtures");
+//!         fuzzer
+//!     });
+//! }
+//!
+//! fuzz_target!(|data: &[u8]| FUZZER.with(|fuzzer| fuzzer.borrow_mut().run(data)));
+//! ```
+//!
+//! Without libFuzzer, [`Fuzzer::run_iterations`] feeds the fuzzer
+//! pseudo-random inputs instead.
+
+pub mod idl;
+pub mod minimize;
+pub mod mutate;
+pub mod schema;
+pub mod structured;
+
+use {
+    crate::{mutate::Mutations, schema::ProgramSchema, structured::StructuredMutations},
+    arbitrary::{Arbitrary, Unstructured},
+    mollusk_svm::{result::InstructionResult, Mollusk},
+    mollusk_svm_fuzz_fixture::{
+        effects::Effects, proto::ChainFixture as ProtoChainFixture, Fixture,
+    },
+    mollusk_svm_fuzz_fs::{FsHandler, SerializableFixture},
+    solana_instruction::error::InstructionError,
+    solana_pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        fmt,
+        panic::{self, AssertUnwindSafe},
+    },
+};
+
+/// A check on the outcome of a fuzz case, returning a description of the
+/// violation if it fails.
+pub type Invariant = dyn Fn(&Fixture, &InstructionResult) -> Result<(), String>;
+
+/// What went wrong when processing a fuzz case.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FindingKind {
+    /// Processing the fixture panicked, with the provided message.
+    Crash(String),
+    /// The program panicked, aborted or faulted in the VM, which all surface
+    /// as `ProgramFailedToComplete`.
+    VmPanic,
+    /// The total lamports of the accounts changed.
+    LamportsNotConserved { before: u128, after: u128 },
+    /// An invariant failed.
+    Invariant { name: String, message: String },
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Crash(message) => write!(f, "Processing the fixture panicked: {}", message),
+            Self::VmPanic => write!(f, "The program panicked, aborted or faulted in the VM"),
+            Self::LamportsNotConserved { before, after } => write!(
+                f,
+                "Lamports were not conserved: {} before, {} after",
+                before, after
+            ),
+            Self::Invariant { name, message } => {
+                write!(f, "Invariant `{}` failed: {}", name, message)
+            }
+        }
+    }
+}
+
+/// A fuzz case which produced a finding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub kind: FindingKind,
+    /// The mutated fixture. Its effects are those observed when processing
+    /// it, unless processing crashed.
+    pub fixture: Fixture,
+}
+
+impl Finding {
+    /// Write the finding's fixture to a directory, as a protobuf blob.
+    pub fn save(&self, dir: &str) {
+        FsHandler::new(self.fixture.clone()).dump_to_blob_file(dir);
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (program {})",
+            self.kind, self.fixture.input.program_id
+        )
+    }
+}
+
+/// A fuzzer which mutates a corpus of Mollusk fixtures.
+pub struct Fuzzer {
+    /// The Mollusk instance fuzz cases are processed with. Its program cache
+    /// must contain every program invoked by the corpus.
+    pub mollusk: Mollusk,
+    /// A directory to save the fixture of every finding to, as `run` panics.
+    pub artifacts_dir: Option<String>,
+    corpus: Vec<Fixture>,
+    schemas: HashMap<Pubkey, ProgramSchema>,
+    invariants: Vec<(String, Box<Invariant>)>,
+}
+
+impl Fuzzer {
+    pub fn new(mollusk: Mollusk) -> Self {
+        Self {
+            mollusk,
+            artifacts_dir: None,
+            corpus: Vec::new(),
+            schemas: HashMap::new(),
+            invariants: Vec::new(),
+        }
+    }
+
+    /// The seed fixtures fuzz cases are derived from.
+    pub fn corpus(&self) -> &[Fixture] {
+        &self.corpus
+    }
+
+    /// Add a seed fixture to the corpus.
+    pub fn add_seed(&mut self, fixture: Fixture) {
+        self.corpus.push(fixture);
+    }
+
+    /// Add every fixture blob (`.fix` file) in a directory to the corpus.
+    ///
+    /// Chain fixtures are skipped, since they can't be processed as a single
+    /// instruction.
+    pub fn load_corpus(&mut self, dir: &str) {
+        let mut paths = std::fs::read_dir(dir)
+            .unwrap_or_else(|err| panic!("Failed to read corpus directory {}: {}", dir, err))
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.extension().is_some_and(|ext| ext == "fix")
+                    && !path.file_name().is_some_and(|name| {
+                        ProtoChainFixture::is_file_name(&name.to_string_lossy())
+                    })
+            })
+            .collect::<Vec<_>>();
+        // Keep fuzz inputs reproducible across runs.
+        paths.sort();
+        for path in paths {
+            self.add_seed(Fixture::load_from_blob_file(path.to_str().unwrap()));
+        }
+    }
+
+    /// Mutate the instructions of a program structurally, with a schema of
+    /// its instructions, rather than byte by byte.
+    pub fn add_schema(&mut self, program_id: &Pubkey, schema: ProgramSchema) {
+        self.schemas.insert(*program_id, schema);
+    }
+
+    /// Add an invariant, checked against the outcome of every fuzz case.
+    pub fn add_invariant<F>(&mut self, name: &str, invariant: F)
+    where
+        F: Fn(&Fixture, &InstructionResult) -> Result<(), String> + 'static,
+    {
+        self.invariants
+            .push((name.to_string(), Box::new(invariant)));
+    }
+
+    /// Derive a fuzz case from a fuzz input, or `None` if the corpus is
+    /// empty.
+    pub fn generate(&self, data: &[u8]) -> Option<Fixture> {
+        let mut unstructured = Unstructured::new(data);
+        let seed = unstructured.choose_index(self.corpus.len()).ok()?;
+        let mut fixture = self.corpus[seed].clone();
+        let mut mutations = Mutations::arbitrary(&mut unstructured).unwrap_or_default();
+
+        if let Some(schema) = self.schemas.get(&fixture.input.program_id) {
+            // The instruction is mutated structurally instead.
+            mutations.instruction_data.clear();
+            mutations.instruction_accounts.clear();
+            StructuredMutations::arbitrary(&mut unstructured)
+                .unwrap_or_default()
+                .apply(schema, &mut fixture.input);
+        }
+        mutations.apply(&mut fixture);
+        fixture.output = Effects::default();
+        Some(fixture)
+    }
+
+    /// Process the fuzz case derived from a fuzz input, returning the finding
+    /// it produced, if any.
+    pub fn fuzz_one(&mut self, data: &[u8]) -> Result<(), Box<Finding>> {
+        let Some(mut fixture) = self.generate(data) else {
+            return Ok(());
+        };
+
+        let outcome =
+            panic::catch_unwind(AssertUnwindSafe(|| self.mollusk.process_fixture(&fixture)));
+        let result = match outcome {
+            Ok(result) => result,
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "Unknown panic".to_string());
+                return Err(Box::new(Finding {
+                    kind: FindingKind::Crash(message),
+                    fixture,
+                }));
+            }
+        };
+        fixture.output = Effects::from(&result);
+
+        match self.check(&fixture, &result) {
+            Some(kind) => Err(Box::new(Finding { kind, fixture })),
+            None => Ok(()),
+        }
+    }
+
+    fn check(&self, fixture: &Fixture, result: &InstructionResult) -> Option<FindingKind> {
+        if result.raw_result == Err(InstructionError::ProgramFailedToComplete) {
+            return Some(FindingKind::VmPanic);
+        }
+        if let Some(kind) = check_lamports(fixture, result) {
+            return Some(kind);
+        }
+        self.invariants.iter().find_map(|(name, invariant)| {
+            invariant(fixture, result)
+                .err()
+                .map(|message| FindingKind::Invariant {
+                    name: name.clone(),
+                    message,
+                })
+        })
+    }
+
+    /// Process the fuzz case derived from a fuzz input, panicking on any
+    /// finding. This is the entrypoint for libFuzzer targets.
+    pub fn run(&mut self, data: &[u8]) {
+        if let Err(finding) = self.fuzz_one(data) {
+            if let Some(dir) = &self.artifacts_dir {
+                finding.save(dir);
+            }
+            panic!("{}", finding);
+        }
+    }
+
+    /// Process fuzz cases derived from pseudo-random inputs, returning every
+    /// finding. The same seed always produces the same inputs.
+    pub fn run_iterations(&mut self, iterations: usize, seed: u64) -> Vec<Finding> {
+        let mut rng = XorShift(seed.max(1));
+        let mut findings = Vec::new();
+        for _ in 0..iterations {
+            let len = (rng.next() % 512) as usize + 1;
+            let data = (0..len).map(|_| rng.next() as u8).collect::<Vec<_>>();
+            if let Err(finding) = self.fuzz_one(&data) {
+                findings.push(*finding);
+            }
+        }
+        findings
+    }
+}
+
+/// Check that the total lamports of the accounts are unchanged.
+pub fn check_lamports(fixture: &Fixture, result: &InstructionResult) -> Option<FindingKind> {
+    let total = |accounts: &[(Pubkey, solana_account::Account)]| {
+        accounts
+            .iter()
+            .map(|(_, account)| account.lamports as u128)
+            .sum::<u128>()
+    };
+    let before = total(&fixture.input.accounts);
+    let after = total(&result.resulting_accounts);
+    (before != after).then_some(FindingKind::LamportsNotConserved { before, after })
+}
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            mutate::{AccountMutation, BytesMutation, MetaMutation},
+            schema::{FieldType, InstructionSchema},
+        },
+        mollusk_svm_fuzz_fixture::context::Context,
+        solana_account::Account,
+        solana_instruction::AccountMeta,
+        solana_pubkey::Pubkey,
+    };
+
+    fn seed(program_id: Pubkey, accounts: Vec<(Pubkey, Account)>) -> Fixture {
+        let mollusk = Mollusk::default();
+        Fixture {
+            input: Context {
+                compute_budget: mollusk.compute_budget,
+                feature_set: mollusk.feature_set.clone(),
+                sysvars: (&mollusk.sysvars).into(),
+                program_id,
+                instruction_accounts: accounts
+                    .iter()
+                    .map(|(pubkey, _)| AccountMeta::new(*pubkey, false))
+                    .collect(),
+                instruction_data: vec![0; 4],
+                accounts,
+            },
+            output: Effects::default(),
+        }
+    }
+
+    #[test]
+    fn test_mutations() {
+        let mut bytes = vec![0; 4];
+        BytesMutation::FlipBit { index: 5, bit: 9 }.apply(&mut bytes);
+        assert_eq!(bytes, [0, 2, 0, 0]);
+        BytesMutation::SetU64 {
+            index: 2,
+            value: u64::MAX,
+        }
+        .apply(&mut bytes);
+        assert_eq!(bytes, [0, 2, 0xff, 0xff]);
+        BytesMutation::Truncate { len: 7 }.apply(&mut bytes);
+        assert_eq!(bytes, [0, 2, 0xff]);
+
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut fixture = seed(program_id, vec![(key, Account::default())]);
+        Mutations {
+            instruction_data: vec![BytesMutation::Replace(vec![7])],
+            instruction_accounts: vec![(3, MetaMutation::ToggleSigner)],
+            accounts: vec![
+                (1, AccountMutation::Lamports(42)),
+                (0, AccountMutation::Owner(0)),
+            ],
+        }
+        .apply(&mut fixture);
+        assert_eq!(fixture.input.instruction_data, [7]);
+        assert_eq!(
+            fixture.input.instruction_accounts,
+            [AccountMeta::new(key, true)]
+        );
+        assert_eq!(fixture.input.accounts[0].1.lamports, 42);
+        assert_eq!(fixture.input.accounts[0].1.owner, program_id);
+    }
+
+    #[test]
+    fn test_check_lamports() {
+        let key = Pubkey::new_unique();
+        let fixture = seed(Pubkey::new_unique(), vec![(key, Account::new(10, 0, &key))]);
+        let mut result = InstructionResult {
+            resulting_accounts: fixture.input.accounts.clone(),
+            ..Default::default()
+        };
+        assert_eq!(check_lamports(&fixture, &result), None);
+
+        result.resulting_accounts[0].1.lamports = 11;
+        assert_eq!(
+            check_lamports(&fixture, &result),
+            Some(FindingKind::LamportsNotConserved {
+                before: 10,
+                after: 11
+            })
+        );
+    }
+
+    #[test]
+    fn test_fuzzer() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+
+        let mut mollusk = Mollusk::default();
+        mollusk.add_mock_program(&program_id, |ctx| {
+            if ctx.instruction_data().len() > 8 {
+                panic!("Instruction too long");
+            }
+            Ok(())
+        });
+
+        let mut fuzzer = Fuzzer::new(mollusk);
+        assert_eq!(fuzzer.fuzz_one(&[1, 2, 3]), Ok(()));
+
+        fuzzer.add_seed(seed(
+            program_id,
+            vec![(key, Account::new(1_000, 0, &program_id))],
+        ));
+        assert_eq!(fuzzer.generate(&[4, 5, 6]), fuzzer.generate(&[4, 5, 6]));
+
+        let findings = fuzzer.run_iterations(500, 42);
+        assert!(!findings.is_empty());
+        assert!(findings.iter().all(|finding| {
+            finding.kind == FindingKind::Crash("Instruction too long".to_string())
+                && finding.fixture.input.instruction_data.len() > 8
+        }));
+        assert_eq!(findings, fuzzer.run_iterations(500, 42));
+
+        // Invariants are checked for every case which doesn't crash.
+        fuzzer.add_invariant("fails", |_, result| match result.raw_result {
+            Ok(()) => Err("Succeeded".to_string()),
+            Err(_) => Ok(()),
+        });
+        assert!(fuzzer.run_iterations(50, 7).iter().any(|finding| {
+            finding.kind
+                == FindingKind::Invariant {
+                    name: "fails".to_string(),
+                    message: "Succeeded".to_string(),
+                }
+        }));
+    }
+
+    #[test]
+    fn test_fuzzer_with_schema() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+
+        // Only a maximal amount gets past the discriminator check.
+        let mut mollusk = Mollusk::default();
+        mollusk.add_mock_program(&program_id, |ctx| {
+            if ctx.instruction_data().strip_prefix(&[7]) == Some(&u64::MAX.to_le_bytes()) {
+                panic!("Overflow");
+            }
+            Ok(())
+        });
+
+        let mut fuzzer = Fuzzer::new(mollusk);
+        fuzzer.add_seed(seed(
+            program_id,
+            vec![(key, Account::new(1_000, 0, &program_id))],
+        ));
+        fuzzer.add_schema(
+            &program_id,
+            ProgramSchema {
+                instructions: vec![InstructionSchema {
+                    name: "withdraw".to_string(),
+                    discriminator: vec![7],
+                    args: vec![FieldType::U64],
+                    accounts: vec![],
+                }],
+            },
+        );
+
+        let findings = fuzzer.run_iterations(500, 42);
+        assert!(!findings.is_empty());
+        assert!(findings
+            .iter()
+            .all(|finding| finding.kind == FindingKind::Crash("Overflow".to_string())));
+    }
+}
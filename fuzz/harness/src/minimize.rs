@@ -0,0 +1,287 @@
+//! Minimization of fixtures which reproduce a failure, by delta debugging.
+//!
+//! A fixture is shrunk by repeatedly dropping instruction accounts and
+//! accounts, removing instruction and account data, and zeroing what's left,
+//! keeping each reduction only if processing the fixture still fails with the
+//! same program result. Reductions are retried until none applies, so the
+//! resulting fixture can't be shrunk any further by any single one.
+
+use {
+    mollusk_svm::{result::ProgramResult, Mollusk},
+    mollusk_svm_fuzz_fixture::{effects::Effects, Fixture},
+    std::panic::{self, AssertUnwindSafe},
+};
+
+// Bounds the number of chunks a buffer is split into per pass, so large
+// buffers don't take a run per byte. Later passes work on the reduced buffer,
+// so buffers which shrink still get reduced byte by byte.
+const MAX_CHUNKS: usize = 256;
+
+/// The outcome of processing a fixture: its program result, or the message
+/// it panicked with.
+type Outcome = Result<ProgramResult, String>;
+
+/// A minimized fixture.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Minimized {
+    /// The smallest fixture found which reproduces the failure. Its effects
+    /// are those observed when processing it, unless processing panicked.
+    pub fixture: Fixture,
+    /// The program result reproduced, or `None` if processing the fixture
+    /// panicked.
+    pub program_result: Option<ProgramResult>,
+    /// The number of times a fixture was processed.
+    pub runs: usize,
+}
+
+struct Minimizer<'a> {
+    mollusk: &'a mut Mollusk,
+    expected: Outcome,
+    runs: usize,
+}
+
+impl Minimizer<'_> {
+    fn process(&mut self, fixture: &Fixture) -> (Outcome, Option<Effects>) {
+        self.runs += 1;
+        match panic::catch_unwind(AssertUnwindSafe(|| self.mollusk.process_fixture(fixture))) {
+            Ok(result) => (
+                Ok(result.program_result.clone()),
+                Some(Effects::from(&result)),
+            ),
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                (Err(message), None)
+            }
+        }
+    }
+
+    fn reproduces(&mut self, fixture: &Fixture) -> bool {
+        self.process(fixture).0 == self.expected
+    }
+
+    /// Remove chunks of a fixture's items, halving the chunk size each pass.
+    /// Returns whether any chunk was removed.
+    fn remove_chunks<T: Clone>(
+        &mut self,
+        fixture: &mut Fixture,
+        items: impl Fn(&mut Fixture) -> &mut Vec<T>,
+        remove: impl Fn(&mut Fixture, std::ops::Range<usize>),
+    ) -> bool {
+        let mut reduced = false;
+        let len = items(fixture).len();
+        let mut chunk = len.div_ceil(2);
+        let min_chunk = len.div_ceil(MAX_CHUNKS).max(1);
+        while chunk >= min_chunk {
+            let mut start = 0;
+            while start < items(fixture).len() {
+                let end = (start + chunk).min(items(fixture).len());
+                let mut candidate = fixture.clone();
+                remove(&mut candidate, start..end);
+                if self.reproduces(&candidate) {
+                    *fixture = candidate;
+                    reduced = true;
+                } else {
+                    start = end;
+                }
+            }
+            if chunk == 1 {
+                break;
+            }
+            chunk = chunk.div_ceil(2);
+        }
+        reduced
+    }
+
+    /// Remove, then zero, chunks of a byte buffer of a fixture. Returns
+    /// whether the buffer changed.
+    fn reduce_bytes(
+        &mut self,
+        fixture: &mut Fixture,
+        bytes: impl Fn(&mut Fixture) -> &mut Vec<u8> + Copy,
+    ) -> bool {
+        let mut reduced = self.remove_chunks(fixture, bytes, |fixture, range| {
+            bytes(fixture).drain(range);
+        });
+
+        let len = bytes(fixture).len();
+        let mut chunk = len.div_ceil(2);
+        let min_chunk = len.div_ceil(MAX_CHUNKS).max(1);
+        while chunk >= min_chunk {
+            for start in (0..len).step_by(chunk) {
+                let end = (start + chunk).min(len);
+                if bytes(fixture)[start..end].iter().all(|byte| *byte == 0) {
+                    continue;
+                }
+                let mut candidate = fixture.clone();
+                bytes(&mut candidate)[start..end].fill(0);
+                if self.reproduces(&candidate) {
+                    *fixture = candidate;
+                    reduced = true;
+                }
+            }
+            if chunk == 1 {
+                break;
+            }
+            chunk = chunk.div_ceil(2);
+        }
+        reduced
+    }
+}
+
+/// Shrink a fixture which fails, keeping the program result it fails with.
+///
+/// Failures include processing the fixture panicking, such as a mock program
+/// or Mollusk itself panicking, in which case the panic message must match
+/// instead. Returns `None` if the fixture succeeds.
+///
+/// The Mollusk instance's program cache must contain every program invoked
+/// by the fixture.
+pub fn minimize(mollusk: &mut Mollusk, fixture: &Fixture) -> Option<Minimized> {
+    let mut minimizer = Minimizer {
+        mollusk,
+        expected: Ok(ProgramResult::Success),
+        runs: 0,
+    };
+    let (expected, _) = minimizer.process(fixture);
+    if expected == Ok(ProgramResult::Success) {
+        return None;
+    }
+    minimizer.expected = expected;
+
+    let mut fixture = fixture.clone();
+    loop {
+        let mut reduced = false;
+
+        // Instruction accounts.
+        reduced |= minimizer.remove_chunks(
+            &mut fixture,
+            |fixture| &mut fixture.input.instruction_accounts,
+            |fixture, range| {
+                fixture.input.instruction_accounts.drain(range);
+            },
+        );
+
+        // Accounts, along with the instruction accounts referring to them.
+        reduced |= minimizer.remove_chunks(
+            &mut fixture,
+            |fixture| &mut fixture.input.accounts,
+            |fixture, range| {
+                let context = &mut fixture.input;
+                let removed = context
+                    .accounts
+                    .drain(range)
+                    .map(|(pubkey, _)| pubkey)
+                    .collect::<Vec<_>>();
+                context
+                    .instruction_accounts
+                    .retain(|meta| !removed.contains(&meta.pubkey));
+            },
+        );
+
+        reduced |=
+            minimizer.reduce_bytes(&mut fixture, |fixture| &mut fixture.input.instruction_data);
+
+        for index in 0..fixture.input.accounts.len() {
+            reduced |= minimizer.reduce_bytes(&mut fixture, move |fixture| {
+                &mut fixture.input.accounts[index].1.data
+            });
+        }
+
+        if !reduced {
+            break;
+        }
+    }
+
+    let (outcome, effects) = minimizer.process(&fixture);
+    fixture.output = effects.unwrap_or_default();
+    Some(Minimized {
+        fixture,
+        program_result: outcome.ok(),
+        runs: minimizer.runs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        mollusk_svm_fuzz_fixture::context::Context,
+        solana_account::Account,
+        solana_instruction::{error::InstructionError, AccountMeta},
+        solana_program_error::ProgramError,
+        solana_pubkey::Pubkey,
+    };
+
+    #[test]
+    fn test_minimize() {
+        let program_id = Pubkey::new_unique();
+        let target = Pubkey::new_unique();
+
+        // Fails if any instruction account's data starts with `0xff`, or if
+        // the instruction data has a non-zero byte after the first.
+        let mut mollusk = Mollusk::default();
+        mollusk.add_mock_program(&program_id, |ctx| {
+            if ctx
+                .accounts()?
+                .iter()
+                .any(|account| account.account.data.first() == Some(&0xff))
+            {
+                return Err(InstructionError::InvalidAccountData);
+            }
+            if ctx.instruction_data().iter().skip(1).any(|byte| *byte != 0) {
+                return Err(InstructionError::InvalidInstructionData);
+            }
+            Ok(())
+        });
+
+        let account = |data: Vec<u8>| Account {
+            lamports: 1_000,
+            data,
+            owner: program_id,
+            ..Default::default()
+        };
+        let mut accounts = (0..20)
+            .map(|i| (Pubkey::new_unique(), account(vec![i; 500])))
+            .collect::<Vec<_>>();
+        accounts.insert(13, (target, account(vec![0xff; 2_000])));
+        let fixture = Fixture {
+            input: Context {
+                program_id,
+                instruction_accounts: accounts
+                    .iter()
+                    .map(|(pubkey, _)| AccountMeta::new(*pubkey, false))
+                    .collect(),
+                instruction_data: vec![0; 64],
+                accounts,
+                ..Default::default()
+            },
+            output: Effects::default(),
+        };
+
+        let minimized = minimize(&mut mollusk, &fixture).unwrap();
+        assert_eq!(
+            minimized.program_result,
+            Some(ProgramResult::Failure(ProgramError::InvalidAccountData))
+        );
+        let input = &minimized.fixture.input;
+        assert_eq!(
+            input.instruction_accounts,
+            [AccountMeta::new(target, false)]
+        );
+        assert_eq!(input.accounts.len(), 1);
+        assert_eq!(input.accounts[0].0, target);
+        assert_eq!(input.accounts[0].1.data, [0xff]);
+        assert!(input.instruction_data.is_empty());
+        assert_ne!(minimized.fixture.output.program_result, 0);
+
+        // Nothing to minimize for a fixture which succeeds.
+        let mut succeeds = fixture.clone();
+        succeeds.input.accounts.remove(13);
+        succeeds.input.instruction_accounts.remove(13);
+        assert_eq!(minimize(&mut mollusk, &succeeds), None);
+    }
+}
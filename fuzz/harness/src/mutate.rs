@@ -0,0 +1,136 @@
+//! Structural mutations of a fixture's inputs.
+//!
+//! Mutations are generated from the fuzz input with [`arbitrary`], so the
+//! fuzzer's coverage feedback steers which parts of a fixture change, and
+//! how. Indices are taken modulo the length of whatever they index, so every
+//! generated mutation applies to any fixture.
+
+use {
+    arbitrary::Arbitrary,
+    mollusk_svm_fuzz_fixture::{context::Context, Fixture},
+    solana_pubkey::Pubkey,
+};
+
+/// A mutation of a byte buffer, such as instruction or account data.
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq)]
+pub enum BytesMutation {
+    /// Flip a single bit.
+    FlipBit { index: usize, bit: u8 },
+    /// Overwrite a single byte.
+    SetByte { index: usize, value: u8 },
+    /// Overwrite up to eight bytes with a little-endian integer, such as an
+    /// amount or a length.
+    SetU64 { index: usize, value: u64 },
+    /// Shorten the buffer.
+    Truncate { len: usize },
+    /// Extend the buffer.
+    Append(Vec<u8>),
+    /// Replace the whole buffer.
+    Replace(Vec<u8>),
+}
+
+impl BytesMutation {
+    pub fn apply(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Self::FlipBit { index, bit } => {
+                if let Some(byte) = index_into(bytes, *index) {
+                    *byte ^= 1 << (bit % 8);
+                }
+            }
+            Self::SetByte { index, value } => {
+                if let Some(byte) = index_into(bytes, *index) {
+                    *byte = *value;
+                }
+            }
+            Self::SetU64 { index, value } => {
+                if !bytes.is_empty() {
+                    let start = index % bytes.len();
+                    let end = (start + 8).min(bytes.len());
+                    bytes[start..end].copy_from_slice(&value.to_le_bytes()[..end - start]);
+                }
+            }
+            Self::Truncate { len } => {
+                if !bytes.is_empty() {
+                    bytes.truncate(len % bytes.len());
+                }
+            }
+            Self::Append(extra) => bytes.extend_from_slice(extra),
+            Self::Replace(replacement) => bytes.clone_from(replacement),
+        }
+    }
+}
+
+fn index_into(bytes: &mut [u8], index: usize) -> Option<&mut u8> {
+    let len = bytes.len();
+    (len > 0).then(|| &mut bytes[index % len])
+}
+
+/// A mutation of an instruction account's privileges.
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq)]
+pub enum MetaMutation {
+    ToggleSigner,
+    ToggleWritable,
+}
+
+/// A mutation of an input account's state.
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq)]
+pub enum AccountMutation {
+    /// Set the account's lamports.
+    Lamports(u64),
+    /// Mutate the account's data.
+    Data(BytesMutation),
+    /// Assign the account to the invoked program, or to the owner of one of
+    /// the fixture's accounts.
+    Owner(usize),
+}
+
+/// The mutations applied to a seed fixture to produce a fuzz case.
+#[derive(Arbitrary, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Mutations {
+    pub instruction_data: Vec<BytesMutation>,
+    pub instruction_accounts: Vec<(usize, MetaMutation)>,
+    pub accounts: Vec<(usize, AccountMutation)>,
+}
+
+impl Mutations {
+    /// Apply the mutations to a fixture's inputs.
+    pub fn apply(&self, fixture: &mut Fixture) {
+        let Context {
+            program_id,
+            instruction_accounts,
+            instruction_data,
+            accounts,
+            ..
+        } = &mut fixture.input;
+
+        for mutation in &self.instruction_data {
+            mutation.apply(instruction_data);
+        }
+
+        if !instruction_accounts.is_empty() {
+            for (index, mutation) in &self.instruction_accounts {
+                let len = instruction_accounts.len();
+                let meta = &mut instruction_accounts[index % len];
+                match mutation {
+                    MetaMutation::ToggleSigner => meta.is_signer = !meta.is_signer,
+                    MetaMutation::ToggleWritable => meta.is_writable = !meta.is_writable,
+                }
+            }
+        }
+
+        if !accounts.is_empty() {
+            let owners = std::iter::once(*program_id)
+                .chain(accounts.iter().map(|(_, account)| account.owner))
+                .collect::<Vec<Pubkey>>();
+            for (index, mutation) in &self.accounts {
+                let len = accounts.len();
+                let (_, account) = &mut accounts[index % len];
+                match mutation {
+                    AccountMutation::Lamports(lamports) => account.lamports = *lamports,
+                    AccountMutation::Data(mutation) => mutation.apply(&mut account.data),
+                    AccountMutation::Owner(owner) => account.owner = owners[owner % owners.len()],
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,403 @@
+//! Schemas describing the instructions of a program, used to mutate
+//! instruction data field by field rather than byte by byte.
+//!
+//! Instruction data is laid out as a discriminator followed by the
+//! Borsh-encoded arguments, as Anchor programs expect. A schema can be built
+//! by hand, or parsed from an Anchor IDL with [`crate::idl`].
+
+use {solana_pubkey::Pubkey, thiserror::Error};
+
+/// Errors building a schema or decoding instruction data with one.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SchemaError {
+    /// The instruction data doesn't start with the discriminator of any
+    /// instruction in the schema.
+    #[error("Unknown instruction discriminator")]
+    UnknownDiscriminator,
+    /// The instruction data ended before all arguments were decoded.
+    #[error("Unexpected end of instruction data")]
+    UnexpectedEnd,
+    /// The instruction data has bytes left over after all arguments were
+    /// decoded.
+    #[error("Trailing instruction data: {0} bytes")]
+    TrailingData(usize),
+    /// A value was out of range for its type, such as a boolean other than
+    /// `0` or `1`.
+    #[error("Invalid value for {0}")]
+    InvalidValue(&'static str),
+    /// The IDL couldn't be parsed.
+    #[error("Invalid IDL: {0}")]
+    InvalidIdl(String),
+}
+
+/// The type of an instruction argument, encoded with Borsh.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Pubkey,
+    /// Length-prefixed bytes.
+    Bytes,
+    /// Length-prefixed UTF-8 string.
+    String,
+    Option(Box<FieldType>),
+    /// Length-prefixed sequence.
+    Vec(Box<FieldType>),
+    /// Fixed-length sequence.
+    Array(Box<FieldType>, usize),
+    /// Fields encoded in order.
+    Struct(Vec<FieldType>),
+    /// A variant index, followed by the variant's fields.
+    Enum(Vec<Vec<FieldType>>),
+}
+
+/// An account expected by an instruction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountSchema {
+    pub name: String,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// The layout of an instruction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstructionSchema {
+    pub name: String,
+    /// The bytes identifying the instruction, before its arguments.
+    pub discriminator: Vec<u8>,
+    /// The instruction arguments, in order.
+    pub args: Vec<FieldType>,
+    /// The instruction accounts, in order.
+    pub accounts: Vec<AccountSchema>,
+}
+
+/// The instructions of a program.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProgramSchema {
+    pub instructions: Vec<InstructionSchema>,
+}
+
+impl ProgramSchema {
+    /// Decode instruction data, returning the index of the instruction and
+    /// its arguments.
+    ///
+    /// When discriminators overlap, the longest match wins.
+    pub fn decode(&self, data: &[u8]) -> Result<(usize, Vec<Value>), SchemaError> {
+        let (index, instruction) = self
+            .instructions
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| data.starts_with(&instruction.discriminator))
+            .max_by_key(|(_, instruction)| instruction.discriminator.len())
+            .ok_or(SchemaError::UnknownDiscriminator)?;
+
+        let mut input = &data[instruction.discriminator.len()..];
+        let args = instruction
+            .args
+            .iter()
+            .map(|ty| Value::decode(ty, &mut input))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !input.is_empty() {
+            return Err(SchemaError::TrailingData(input.len()));
+        }
+        Ok((index, args))
+    }
+
+    /// Encode instruction data for the instruction at `index`.
+    pub fn encode(&self, index: usize, args: &[Value]) -> Vec<u8> {
+        let mut data = self.instructions[index].discriminator.clone();
+        for arg in args {
+            arg.encode(&mut data);
+        }
+        data
+    }
+}
+
+/// A decoded instruction argument.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    /// An integer of `size` bytes, stored as its two's complement bits.
+    Int {
+        size: usize,
+        signed: bool,
+        bits: u128,
+    },
+    Pubkey(Pubkey),
+    Bytes(Vec<u8>),
+    String(String),
+    /// An optional value, along with the type of its contents.
+    Option(FieldType, Option<Box<Value>>),
+    /// A sequence, along with the type of its elements.
+    Vec(FieldType, Vec<Value>),
+    Array(Vec<Value>),
+    Struct(Vec<Value>),
+    /// An enum variant, along with the fields of every variant.
+    Enum {
+        variants: Vec<Vec<FieldType>>,
+        variant: usize,
+        fields: Vec<Value>,
+    },
+}
+
+fn int(size: usize, signed: bool) -> Value {
+    Value::Int {
+        size,
+        signed,
+        bits: 0,
+    }
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], SchemaError> {
+    if input.len() < len {
+        return Err(SchemaError::UnexpectedEnd);
+    }
+    let (taken, rest) = input.split_at(len);
+    *input = rest;
+    Ok(taken)
+}
+
+fn take_len(input: &mut &[u8]) -> Result<usize, SchemaError> {
+    let len = u32::from_le_bytes(take(input, 4)?.try_into().unwrap()) as usize;
+    // Every element takes at least a byte, except for zero-sized ones, which
+    // no schema has any use for.
+    if len > input.len() {
+        return Err(SchemaError::UnexpectedEnd);
+    }
+    Ok(len)
+}
+
+impl Value {
+    /// The zero value of a type: `false`, `0`, the default pubkey, empty
+    /// sequences, `None`, or the first enum variant.
+    pub fn default_for(ty: &FieldType) -> Self {
+        match ty {
+            FieldType::Bool => Self::Bool(false),
+            FieldType::U8 => int(1, false),
+            FieldType::U16 => int(2, false),
+            FieldType::U32 => int(4, false),
+            FieldType::U64 => int(8, false),
+            FieldType::U128 => int(16, false),
+            FieldType::I8 => int(1, true),
+            FieldType::I16 => int(2, true),
+            FieldType::I32 => int(4, true),
+            FieldType::I64 => int(8, true),
+            FieldType::I128 => int(16, true),
+            FieldType::Pubkey => Self::Pubkey(Pubkey::default()),
+            FieldType::Bytes => Self::Bytes(Vec::new()),
+            FieldType::String => Self::String(String::new()),
+            FieldType::Option(inner) => Self::Option(*inner.clone(), None),
+            FieldType::Vec(inner) => Self::Vec(*inner.clone(), Vec::new()),
+            FieldType::Array(inner, len) => Self::Array(vec![Self::default_for(inner); *len]),
+            FieldType::Struct(fields) => {
+                Self::Struct(fields.iter().map(Self::default_for).collect())
+            }
+            FieldType::Enum(variants) => Self::Enum {
+                variants: variants.clone(),
+                variant: 0,
+                fields: variants
+                    .first()
+                    .map(|fields| fields.iter().map(Self::default_for).collect())
+                    .unwrap_or_default(),
+            },
+        }
+    }
+
+    /// Decode a value of the provided type, advancing `input` past it.
+    pub fn decode(ty: &FieldType, input: &mut &[u8]) -> Result<Self, SchemaError> {
+        let value = match ty {
+            FieldType::Bool => match take(input, 1)?[0] {
+                0 => Self::Bool(false),
+                1 => Self::Bool(true),
+                _ => return Err(SchemaError::InvalidValue("bool")),
+            },
+            FieldType::Pubkey => {
+                Self::Pubkey(Pubkey::new_from_array(take(input, 32)?.try_into().unwrap()))
+            }
+            FieldType::Bytes => {
+                let len = take_len(input)?;
+                Self::Bytes(take(input, len)?.to_vec())
+            }
+            FieldType::String => {
+                let len = take_len(input)?;
+                let bytes = take(input, len)?.to_vec();
+                Self::String(
+                    String::from_utf8(bytes).map_err(|_| SchemaError::InvalidValue("string"))?,
+                )
+            }
+            FieldType::Option(inner) => match take(input, 1)?[0] {
+                0 => Self::Option(*inner.clone(), None),
+                1 => Self::Option(*inner.clone(), Some(Box::new(Self::decode(inner, input)?))),
+                _ => return Err(SchemaError::InvalidValue("option")),
+            },
+            FieldType::Vec(inner) => {
+                let len = take_len(input)?;
+                let elements = (0..len)
+                    .map(|_| Self::decode(inner, input))
+                    .collect::<Result<_, _>>()?;
+                Self::Vec(*inner.clone(), elements)
+            }
+            FieldType::Array(inner, len) => Self::Array(
+                (0..*len)
+                    .map(|_| Self::decode(inner, input))
+                    .collect::<Result<_, _>>()?,
+            ),
+            FieldType::Struct(fields) => Self::Struct(
+                fields
+                    .iter()
+                    .map(|field| Self::decode(field, input))
+                    .collect::<Result<_, _>>()?,
+            ),
+            FieldType::Enum(variants) => {
+                let variant = take(input, 1)?[0] as usize;
+                let fields = variants
+                    .get(variant)
+                    .ok_or(SchemaError::InvalidValue("enum"))?
+                    .iter()
+                    .map(|field| Self::decode(field, input))
+                    .collect::<Result<_, _>>()?;
+                Self::Enum {
+                    variants: variants.clone(),
+                    variant,
+                    fields,
+                }
+            }
+            // Integers.
+            _ => {
+                let Self::Int { size, signed, .. } = Self::default_for(ty) else {
+                    unreachable!()
+                };
+                let mut bits = [0; 16];
+                bits[..size].copy_from_slice(take(input, size)?);
+                let mut value = Self::Int {
+                    size,
+                    signed,
+                    bits: u128::from_le_bytes(bits),
+                };
+                value.normalize();
+                value
+            }
+        };
+        Ok(value)
+    }
+
+    /// Append the Borsh encoding of the value.
+    pub fn encode(&self, output: &mut Vec<u8>) {
+        match self {
+            Self::Bool(value) => output.push(*value as u8),
+            Self::Int { size, bits, .. } => output.extend_from_slice(&bits.to_le_bytes()[..*size]),
+            Self::Pubkey(pubkey) => output.extend_from_slice(pubkey.as_ref()),
+            Self::Bytes(bytes) => {
+                output.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                output.extend_from_slice(bytes);
+            }
+            Self::String(string) => {
+                output.extend_from_slice(&(string.len() as u32).to_le_bytes());
+                output.extend_from_slice(string.as_bytes());
+            }
+            Self::Option(_, value) => match value {
+                Some(value) => {
+                    output.push(1);
+                    value.encode(output);
+                }
+                None => output.push(0),
+            },
+            Self::Vec(_, elements) => {
+                output.extend_from_slice(&(elements.len() as u32).to_le_bytes());
+                elements.iter().for_each(|element| element.encode(output));
+            }
+            Self::Array(elements) | Self::Struct(elements) => {
+                elements.iter().for_each(|element| element.encode(output));
+            }
+            Self::Enum {
+                variant, fields, ..
+            } => {
+                output.push(*variant as u8);
+                fields.iter().for_each(|field| field.encode(output));
+            }
+        }
+    }
+
+    /// Sign-extend or truncate an integer's bits to its size, so equal
+    /// integers have equal bits.
+    pub(crate) fn normalize(&mut self) {
+        if let Self::Int { size, signed, bits } = self {
+            let shift = 128 - *size as u32 * 8;
+            *bits = if *signed {
+                (((*bits << shift) as i128) >> shift) as u128
+            } else {
+                (*bits << shift) >> shift
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let schema = ProgramSchema {
+            instructions: vec![
+                InstructionSchema {
+                    name: "a".to_string(),
+                    discriminator: vec![1],
+                    args: vec![FieldType::U64],
+                    accounts: vec![],
+                },
+                InstructionSchema {
+                    name: "b".to_string(),
+                    discriminator: vec![1, 2],
+                    args: vec![
+                        FieldType::I16,
+                        FieldType::Option(Box::new(FieldType::String)),
+                        FieldType::Vec(Box::new(FieldType::Bool)),
+                        FieldType::Enum(vec![vec![], vec![FieldType::Pubkey]]),
+                    ],
+                    accounts: vec![],
+                },
+            ],
+        };
+
+        let mut data = vec![
+            1, 2, 0xfe, 0xff, 1, 2, 0, 0, 0, b'h', b'i', 2, 0, 0, 0, 1, 0, 1,
+        ];
+        data.extend_from_slice(&[7; 32]);
+        let (index, args) = schema.decode(&data).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(
+            args[0],
+            Value::Int {
+                size: 2,
+                signed: true,
+                bits: -2i128 as u128,
+            }
+        );
+        assert_eq!(
+            args[3],
+            Value::Enum {
+                variants: vec![vec![], vec![FieldType::Pubkey]],
+                variant: 1,
+                fields: vec![Value::Pubkey(Pubkey::new_from_array([7; 32]))],
+            }
+        );
+        assert_eq!(schema.encode(index, &args), data);
+
+        assert_eq!(schema.decode(&[1, 0]), Err(SchemaError::UnexpectedEnd));
+        assert_eq!(schema.decode(&[0]), Err(SchemaError::UnknownDiscriminator));
+        assert_eq!(
+            schema.decode(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 9]),
+            Err(SchemaError::TrailingData(1))
+        );
+    }
+}
@@ -0,0 +1,396 @@
+//! Structure-aware mutations of a fixture's instruction, guided by a
+//! [`ProgramSchema`].
+//!
+//! Instruction data is decoded with the schema, its fields are mutated, and
+//! it's encoded again, so mutations get past discriminator and
+//! deserialization checks. Instruction accounts are mutated too, but only
+//! ever refer to accounts in the fixture, so the mutated context stays valid.
+
+use {
+    crate::schema::{ProgramSchema, Value},
+    arbitrary::Arbitrary,
+    mollusk_svm_fuzz_fixture::context::Context,
+    solana_instruction::AccountMeta,
+    solana_pubkey::Pubkey,
+};
+
+// Bounds the elements added to sequences, to keep instructions small.
+const MAX_SEQUENCE_LEN: usize = 64;
+
+/// A mutation of a decoded value. Mutations which don't apply to the value
+/// they're given, such as toggling an integer, leave it unchanged.
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq)]
+pub enum ValueMutation {
+    /// Set an integer to a boundary of its type: zero, one, the minimum, the
+    /// maximum, or either side of the sign bit.
+    Boundary(u8),
+    /// Set an integer, truncated to its size.
+    Set(u128),
+    /// Add to an integer, wrapping around.
+    Add(i64),
+    /// Set a pubkey to one of the fixture's accounts, or the program ID.
+    Key(usize),
+    /// Toggle a boolean, or an option between `None` and `Some` of the zero
+    /// value.
+    Toggle,
+    /// Resize a sequence, filling it with zero values.
+    Resize(u8),
+    /// Switch an enum to another variant, with zero values.
+    Variant(u8),
+}
+
+impl ValueMutation {
+    fn apply(&self, value: &mut Value, keys: &[Pubkey]) {
+        match (self, value) {
+            (Self::Boundary(boundary), value @ Value::Int { .. }) => {
+                let Value::Int { size, signed, bits } = value else {
+                    unreachable!()
+                };
+                let sign_bit = 1u128 << (*size as u32 * 8 - 1);
+                let boundaries = if *signed {
+                    [0, 1, u128::MAX, sign_bit, sign_bit - 1]
+                } else {
+                    [0, 1, u128::MAX, u128::MAX - 1, sign_bit]
+                };
+                *bits = boundaries[*boundary as usize % boundaries.len()];
+                value.normalize();
+            }
+            (Self::Set(new_bits), value @ Value::Int { .. }) => {
+                if let Value::Int { bits, .. } = value {
+                    *bits = *new_bits;
+                }
+                value.normalize();
+            }
+            (Self::Add(addend), value @ Value::Int { .. }) => {
+                if let Value::Int { bits, .. } = value {
+                    *bits = bits.wrapping_add(*addend as i128 as u128);
+                }
+                value.normalize();
+            }
+            (Self::Key(index), Value::Pubkey(pubkey)) => {
+                if !keys.is_empty() {
+                    *pubkey = keys[index % keys.len()];
+                }
+            }
+            (Self::Toggle, Value::Bool(value)) => *value = !*value,
+            (Self::Toggle, Value::Option(ty, value)) => {
+                *value = match value {
+                    Some(_) => None,
+                    None => Some(Box::new(Value::default_for(ty))),
+                }
+            }
+            (Self::Resize(len), value) => {
+                let len = *len as usize % (MAX_SEQUENCE_LEN + 1);
+                match value {
+                    Value::Bytes(bytes) => bytes.resize(len, 0),
+                    Value::String(string) => {
+                        // Stay on a character boundary, so the string stays
+                        // valid UTF-8.
+                        let mut end = len.min(string.len());
+                        while !string.is_char_boundary(end) {
+                            end -= 1;
+                        }
+                        string.truncate(end);
+                        let padding = len.saturating_sub(string.len());
+                        string.extend(std::iter::repeat_n('a', padding));
+                    }
+                    Value::Vec(ty, elements) => elements.resize(len, Value::default_for(ty)),
+                    _ => {}
+                }
+            }
+            (
+                Self::Variant(new_variant),
+                Value::Enum {
+                    variants,
+                    variant,
+                    fields,
+                },
+            ) => {
+                if !variants.is_empty() {
+                    *variant = *new_variant as usize % variants.len();
+                    *fields = variants[*variant].iter().map(Value::default_for).collect();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Visit every value in pre-order, until `visit` returns `true`.
+fn visit_values(values: &mut [Value], visit: &mut dyn FnMut(&mut Value) -> bool) -> bool {
+    values.iter_mut().any(|value| {
+        visit(value)
+            || match value {
+                Value::Option(_, Some(inner)) => visit_values(std::slice::from_mut(inner), visit),
+                Value::Vec(_, elements)
+                | Value::Array(elements)
+                | Value::Struct(elements)
+                | Value::Enum {
+                    fields: elements, ..
+                } => visit_values(elements, visit),
+                _ => false,
+            }
+    })
+}
+
+/// A mutation of an instruction's accounts.
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq)]
+pub enum AccountsMutation {
+    ToggleSigner(usize),
+    ToggleWritable(usize),
+    /// Swap the positions of two accounts.
+    Swap(usize, usize),
+    /// Replace an account with another of the fixture's accounts, keeping its
+    /// privileges.
+    Replace {
+        index: usize,
+        account: usize,
+    },
+}
+
+/// The structure-aware mutations applied to a seed fixture to produce a fuzz
+/// case.
+#[derive(Arbitrary, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StructuredMutations {
+    /// Switch to another instruction of the schema, with zero-valued
+    /// arguments and the accounts it expects.
+    pub instruction: Option<usize>,
+    /// Mutations of the instruction arguments, each applied to the value at
+    /// a pre-order index into the arguments.
+    pub args: Vec<(usize, ValueMutation)>,
+    pub accounts: Vec<AccountsMutation>,
+}
+
+impl StructuredMutations {
+    /// Apply the mutations to an instruction context.
+    ///
+    /// If the instruction data can't be decoded with the schema, the
+    /// instruction is switched to one of the schema's instead, as if
+    /// `instruction` were set.
+    pub fn apply(&self, schema: &ProgramSchema, context: &mut Context) {
+        if schema.instructions.is_empty() {
+            return;
+        }
+
+        let decoded = schema.decode(&context.instruction_data).ok();
+        let (index, mut args) = match (self.instruction, decoded) {
+            (None, Some(decoded)) => decoded,
+            (instruction, _) => {
+                let index = instruction.unwrap_or_default() % schema.instructions.len();
+                switch_instruction(schema, index, context);
+                let args = schema.instructions[index]
+                    .args
+                    .iter()
+                    .map(Value::default_for)
+                    .collect();
+                (index, args)
+            }
+        };
+
+        let keys = context
+            .accounts
+            .iter()
+            .map(|(pubkey, _)| *pubkey)
+            .chain(std::iter::once(context.program_id))
+            .collect::<Vec<_>>();
+        for (target, mutation) in &self.args {
+            let mut count = 0;
+            visit_values(&mut args, &mut |_| {
+                count += 1;
+                false
+            });
+            if count == 0 {
+                break;
+            }
+            let mut remaining = target % count;
+            visit_values(&mut args, &mut |value| {
+                if remaining == 0 {
+                    mutation.apply(value, &keys);
+                    return true;
+                }
+                remaining -= 1;
+                false
+            });
+        }
+        context.instruction_data = schema.encode(index, &args);
+
+        let metas = &mut context.instruction_accounts;
+        for mutation in &self.accounts {
+            if metas.is_empty() {
+                break;
+            }
+            let len = metas.len();
+            match mutation {
+                AccountsMutation::ToggleSigner(index) => {
+                    metas[index % len].is_signer ^= true;
+                }
+                AccountsMutation::ToggleWritable(index) => {
+                    metas[index % len].is_writable ^= true;
+                }
+                AccountsMutation::Swap(a, b) => metas.swap(a % len, b % len),
+                AccountsMutation::Replace { index, account } => {
+                    if !context.accounts.is_empty() {
+                        let (pubkey, _) = &context.accounts[account % context.accounts.len()];
+                        metas[index % len].pubkey = *pubkey;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Replace the instruction accounts with the ones the instruction at `index`
+/// expects, reusing the fixture's accounts in order.
+fn switch_instruction(schema: &ProgramSchema, index: usize, context: &mut Context) {
+    if context.accounts.is_empty() {
+        context.instruction_accounts.clear();
+        return;
+    }
+    context.instruction_accounts = schema.instructions[index]
+        .accounts
+        .iter()
+        .zip(context.accounts.iter().cycle())
+        .map(|(account, (pubkey, _))| AccountMeta {
+            pubkey: *pubkey,
+            is_signer: account.is_signer,
+            is_writable: account.is_writable,
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::schema::{AccountSchema, FieldType, InstructionSchema},
+        solana_account::Account,
+    };
+
+    fn schema() -> ProgramSchema {
+        ProgramSchema {
+            instructions: vec![
+                InstructionSchema {
+                    name: "transfer".to_string(),
+                    discriminator: vec![3],
+                    args: vec![FieldType::U64, FieldType::Pubkey],
+                    accounts: vec![],
+                },
+                InstructionSchema {
+                    name: "close".to_string(),
+                    discriminator: vec![9],
+                    args: vec![FieldType::Option(Box::new(FieldType::I8))],
+                    accounts: vec![
+                        AccountSchema {
+                            name: "account".to_string(),
+                            is_signer: false,
+                            is_writable: true,
+                        },
+                        AccountSchema {
+                            name: "authority".to_string(),
+                            is_signer: true,
+                            is_writable: false,
+                        },
+                    ],
+                },
+            ],
+        }
+    }
+
+    fn context() -> Context {
+        let accounts = vec![
+            (Pubkey::new_unique(), Account::default()),
+            (Pubkey::new_unique(), Account::default()),
+        ];
+        let mut instruction_data = vec![3];
+        instruction_data.extend_from_slice(&5u64.to_le_bytes());
+        instruction_data.extend_from_slice(accounts[0].0.as_ref());
+        Context {
+            program_id: Pubkey::new_unique(),
+            instruction_accounts: accounts
+                .iter()
+                .map(|(pubkey, _)| AccountMeta::new(*pubkey, false))
+                .collect(),
+            instruction_data,
+            accounts,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_mutate_args() {
+        let schema = schema();
+        let mut context = context();
+        StructuredMutations {
+            instruction: None,
+            args: vec![
+                (0, ValueMutation::Boundary(2)),
+                (3, ValueMutation::Key(2)),
+                // Doesn't apply to an integer.
+                (2, ValueMutation::Toggle),
+            ],
+            accounts: vec![],
+        }
+        .apply(&schema, &mut context);
+
+        let mut expected = vec![3];
+        expected.extend_from_slice(&u64::MAX.to_le_bytes());
+        expected.extend_from_slice(context.program_id.as_ref());
+        assert_eq!(context.instruction_data, expected);
+    }
+
+    #[test]
+    fn test_switch_instruction() {
+        let schema = schema();
+        let mut context = context();
+        StructuredMutations {
+            instruction: Some(3),
+            args: vec![(0, ValueMutation::Toggle), (1, ValueMutation::Boundary(3))],
+            accounts: vec![],
+        }
+        .apply(&schema, &mut context);
+
+        // `Some(i8::MIN)`.
+        assert_eq!(context.instruction_data, [9, 1, 0x80]);
+        assert_eq!(
+            context.instruction_accounts,
+            [
+                AccountMeta::new(context.accounts[0].0, false),
+                AccountMeta::new_readonly(context.accounts[1].0, true),
+            ]
+        );
+
+        // Undecodable data switches to an instruction of the schema.
+        context.instruction_data = vec![0xff];
+        StructuredMutations::default().apply(&schema, &mut context);
+        assert_eq!(schema.decode(&context.instruction_data).unwrap().0, 0);
+    }
+
+    #[test]
+    fn test_mutate_accounts() {
+        let schema = schema();
+        let mut context = context();
+        let a = context.accounts[0].0;
+        StructuredMutations {
+            instruction: None,
+            args: vec![],
+            accounts: vec![
+                AccountsMutation::ToggleSigner(2),
+                AccountsMutation::Swap(0, 1),
+                AccountsMutation::Replace {
+                    index: 0,
+                    account: 0,
+                },
+                AccountsMutation::ToggleWritable(1),
+            ],
+        }
+        .apply(&schema, &mut context);
+
+        assert_eq!(
+            context.instruction_accounts,
+            [
+                AccountMeta::new(a, false),
+                AccountMeta::new_readonly(a, true)
+            ]
+        );
+    }
+}
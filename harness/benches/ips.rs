@@ -1,7 +1,7 @@
 //! Benches Mollusk invocation (instructions per second)
 use {
-    criterion::{criterion_group, criterion_main, Criterion, Throughput},
-    mollusk_svm::{result::Check, Mollusk},
+    criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput},
+    mollusk_svm::{mt::MolluskMt, result::Check, Mollusk},
     solana_account::Account,
     solana_native_token::LAMPORTS_PER_SOL,
     solana_pubkey::Pubkey,
@@ -63,5 +63,62 @@ fn transfer_checked_unchecked(c: &mut Criterion) {
     g.finish();
 }
 
-criterion_group!(transfers, transfer_checked_unchecked);
+fn transfer_parallel(c: &mut Criterion) {
+    const BATCH_SIZE: usize = 1_024;
+
+    let base_lamports = 100 * LAMPORTS_PER_SOL;
+    let transfer_amount = 1;
+
+    // Every transfer uses its own accounts.
+    let batch = (0..BATCH_SIZE)
+        .map(|_| {
+            let sender = Pubkey::new_unique();
+            let recipient = Pubkey::new_unique();
+            let instruction = solana_system_interface::instruction::transfer(
+                &sender,
+                &recipient,
+                transfer_amount,
+            );
+            let accounts = vec![
+                (
+                    sender,
+                    Account::new(base_lamports, 0, &solana_sdk_ids::system_program::id()),
+                ),
+                (
+                    recipient,
+                    Account::new(base_lamports, 0, &solana_sdk_ids::system_program::id()),
+                ),
+            ];
+            (instruction, accounts)
+        })
+        .collect::<Vec<_>>();
+
+    // No logs for bench
+    let mollusk = MolluskMt::default();
+    solana_logger::setup_with("");
+
+    // Create parallel transfers group with elements/second, to show how
+    // throughput scales with the number of threads.
+    let mut g = c.benchmark_group("transfers_parallel");
+    g.throughput(Throughput::Elements(BATCH_SIZE as u64));
+
+    let max_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let thread_counts = std::iter::successors(Some(1), |n| Some(n * 2))
+        .take_while(|n| *n < max_threads)
+        .chain(std::iter::once(max_threads));
+
+    for threads in thread_counts {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        g.bench_with_input(BenchmarkId::new("threads", threads), &threads, |b, _| {
+            b.iter(|| pool.install(|| mollusk.process_instructions_parallel(&batch)));
+        });
+    }
+
+    g.finish();
+}
+
+criterion_group!(transfers, transfer_checked_unchecked, transfer_parallel);
 criterion_main!(transfers);
@@ -1,6 +1,16 @@
 //! A trait for implementing an account store, to be used with the
 /// `MolluskContext`.
-use {solana_account::Account, solana_pubkey::Pubkey, std::collections::HashMap};
+use {
+    solana_account::Account,
+    solana_pubkey::Pubkey,
+    std::{
+        cell::RefCell,
+        collections::HashMap,
+        ops::{Deref, DerefMut},
+        rc::Rc,
+        sync::{Arc, RwLock},
+    },
+};
 
 /// A trait for implementing an account store, to be used with the
 /// `MolluskContext`.
@@ -26,3 +36,47 @@ impl AccountStore for HashMap<Pubkey, Account> {
         self.insert(pubkey, account);
     }
 }
+
+/// An account store shared between a `MolluskContext` and its users.
+///
+/// `MolluskContext` holds its store in an `Rc<RefCell>`, and
+/// `MolluskContextMt` in an `Arc<RwLock>`, so it can be shared across
+/// threads.
+pub trait SharedAccountStore<AS: AccountStore> {
+    /// Share an account store.
+    fn new(account_store: AS) -> Self;
+
+    /// Borrow the account store for reading.
+    fn borrow_store(&self) -> impl Deref<Target = AS> + '_;
+
+    /// Borrow the account store for writing.
+    fn borrow_store_mut(&self) -> impl DerefMut<Target = AS> + '_;
+}
+
+impl<AS: AccountStore> SharedAccountStore<AS> for Rc<RefCell<AS>> {
+    fn new(account_store: AS) -> Self {
+        Rc::new(RefCell::new(account_store))
+    }
+
+    fn borrow_store(&self) -> impl Deref<Target = AS> + '_ {
+        self.borrow()
+    }
+
+    fn borrow_store_mut(&self) -> impl DerefMut<Target = AS> + '_ {
+        self.borrow_mut()
+    }
+}
+
+impl<AS: AccountStore> SharedAccountStore<AS> for Arc<RwLock<AS>> {
+    fn new(account_store: AS) -> Self {
+        Arc::new(RwLock::new(account_store))
+    }
+
+    fn borrow_store(&self) -> impl Deref<Target = AS> + '_ {
+        self.read().unwrap()
+    }
+
+    fn borrow_store_mut(&self) -> impl DerefMut<Target = AS> + '_ {
+        self.write().unwrap()
+    }
+}
@@ -19,6 +19,9 @@ pub struct CompiledAccounts {
     pub program_id_index: u16,
     pub instruction_accounts: Vec<InstructionAccount>,
     pub transaction_accounts: Vec<TransactionAccount>,
+    /// The instruction's accounts which aren't signers, in order of first
+    /// appearance, used to explain a `MissingRequiredSignature` failure.
+    pub non_signer_accounts: Vec<Pubkey>,
 }
 
 pub fn compile_accounts(
@@ -43,9 +46,17 @@ pub fn compile_accounts(
         Some(Box::new(stub_out_program_account)),
     );
 
+    let mut non_signer_accounts = Vec::new();
+    for meta in &instruction.accounts {
+        if !key_map.is_signer(&meta.pubkey) && !non_signer_accounts.contains(&meta.pubkey) {
+            non_signer_accounts.push(meta.pubkey);
+        }
+    }
+
     CompiledAccounts {
         program_id_index: compiled_instruction.program_id_index as u16,
         instruction_accounts,
         transaction_accounts,
+        non_signer_accounts,
     }
 }
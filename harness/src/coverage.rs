@@ -0,0 +1,352 @@
+//! Source line coverage of SBF programs.
+//!
+//! A [`CoverageCollector`] attached to a Mollusk instance with
+//! [`MolluskCore::set_coverage_collector`](crate::MolluskCore::set_coverage_collector)
+//! records the program counters executed by every SBF program each processed
+//! instruction invokes, including CPIs. Collectors are cheap to clone, and
+//! clones share their recorded coverage, so a single collector can be
+//! attached to every Mollusk instance in a test binary.
+//!
+//! When the last clone of a collector is dropped, the executed program
+//! counters are resolved to source lines using the DWARF line tables of each
+//! program's ELF, and written to an lcov file. The first write to a file
+//! replaces whatever earlier runs left in it, while later writes from the
+//! same process merge with it, so collectors from several tests in a test
+//! binary can share one file. Give each test binary its own file, since they
+//! run as separate processes. Programs without DWARF debug info can't be
+//! mapped to source lines, and are left out.
+//!
+//! ```rust,ignore
+//! let coverage = CoverageCollector::new("target/coverage/lcov.info");
+//!
+//! let mut mollusk = Mollusk::new(&program_id, "my_program");
+//! mollusk.set_coverage_collector(coverage.clone());
+//!
+//! mollusk.process_instruction(&instruction, &accounts);
+//! ```
+
+use {
+    crate::{debug_info::DebugInfo, program::ProgramCacheProvider},
+    mollusk_svm_result::ProgramTrace,
+    solana_hash::Hash,
+    solana_pubkey::Pubkey,
+    std::{
+        collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
+        fmt::Write,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    },
+};
+
+/// The lcov files written by this process. Also serializes writes, so
+/// collectors dropped by concurrent tests don't lose each other's coverage.
+static WRITTEN_PATHS: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Collects the source lines of SBF programs executed by Mollusk, and writes
+/// them to an lcov file when dropped.
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Clone)]
+pub struct CoverageCollector {
+    coverage: Arc<Mutex<Coverage>>,
+}
+
+impl CoverageCollector {
+    /// Create a collector writing to the lcov file at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            coverage: Arc::new(Mutex::new(Coverage {
+                path: path.into(),
+                programs: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Merge the coverage recorded so far into the lcov file, clearing it
+    /// from the collector. The first write of this process replaces the
+    /// file's content instead.
+    ///
+    /// This happens automatically when the last clone of the collector is
+    /// dropped.
+    pub fn write(&self) -> std::io::Result<()> {
+        self.coverage.lock().unwrap().write()
+    }
+
+    /// Record the program counters executed by SBF programs.
+    pub(crate) fn record<PC: ProgramCacheProvider>(
+        &self,
+        program_cache: &PC,
+        execution_trace: &[ProgramTrace],
+    ) {
+        let mut coverage = self.coverage.lock().unwrap();
+        for trace in execution_trace {
+            let Some(elf_hash) = program_cache.program_elf_hash(&trace.program_id) else {
+                continue;
+            };
+            let Some(program) = coverage.program_mut(trace.program_id, elf_hash, || {
+                program_cache.program_elf(&trace.program_id)
+            }) else {
+                continue;
+            };
+            for entry in &trace.entries {
+                *program.hits.entry(entry.pc).or_default() += 1;
+            }
+        }
+    }
+}
+
+struct Coverage {
+    path: PathBuf,
+    /// Coverage by program ID and ELF hash.
+    programs: HashMap<(Pubkey, Hash), ProgramCoverage>,
+}
+
+impl Coverage {
+    /// The coverage of a program loaded from the ELF with the given hash,
+    /// which is only fetched when the ELF wasn't covered yet.
+    fn program_mut(
+        &mut self,
+        program_id: Pubkey,
+        elf_hash: Hash,
+        elf: impl FnOnce() -> Option<Arc<[u8]>>,
+    ) -> Option<&mut ProgramCoverage> {
+        match self.programs.entry((program_id, elf_hash)) {
+            Entry::Occupied(entry) => Some(entry.into_mut()),
+            Entry::Vacant(entry) => Some(entry.insert(ProgramCoverage {
+                elf: elf()?,
+                hits: HashMap::new(),
+            })),
+        }
+    }
+
+    fn write(&mut self) -> std::io::Result<()> {
+        let mut written_paths = WRITTEN_PATHS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        // Coverage left by earlier runs is stale, so it's only merged with
+        // once this process has written the file.
+        let mut report = if written_paths.contains(&self.path) {
+            match std::fs::read_to_string(&self.path) {
+                Ok(content) => LcovReport::parse(&content),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => LcovReport::default(),
+                Err(err) => return Err(err),
+            }
+        } else {
+            LcovReport::default()
+        };
+        for (_, program) in self.programs.drain() {
+            program.add_to(&mut report);
+        }
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, report.render())?;
+        written_paths.insert(self.path.clone());
+        Ok(())
+    }
+}
+
+impl Drop for Coverage {
+    fn drop(&mut self) {
+        if let Err(err) = self.write() {
+            eprintln!(
+                "Failed to write coverage to {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+/// The program counters executed by a program, loaded from a particular ELF.
+struct ProgramCoverage {
+    elf: Arc<[u8]>,
+    /// Execution counts by program counter.
+    hits: HashMap<u64, u64>,
+}
+
+impl ProgramCoverage {
+    /// Resolve the executed program counters to source lines, and add them
+    /// to a report.
+    fn add_to(&self, report: &mut LcovReport) {
+        let Some(debug_info) = DebugInfo::load(&self.elf) else {
+            return;
+        };
+        let (text_start, text_end) = debug_info.text_range();
+        let Ok(rows) = debug_info.context.find_location_range(text_start, text_end) else {
+            return;
+        };
+
+        // Every line with instructions is instrumented, and counts as
+        // executed as many times as its most executed instruction.
+        let mut lines: BTreeMap<(String, u32), u64> = BTreeMap::new();
+        for (address, size, location) in rows {
+            let (Some(file), Some(line)) = (location.file, location.line) else {
+                continue;
+            };
+            let first_pc = debug_info.pc(address);
+            let last_pc = debug_info.pc(address + size.max(1) - 1);
+            let hits = (first_pc..=last_pc)
+                .filter_map(|pc| self.hits.get(&pc))
+                .copied()
+                .max()
+                .unwrap_or_default();
+            let count = lines.entry((file.to_string(), line)).or_default();
+            *count = (*count).max(hits);
+        }
+
+        for ((file, line), hits) in lines {
+            report.add(&file, line, hits);
+        }
+    }
+}
+
+/// Line execution counts by source file, as recorded in an lcov file.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct LcovReport {
+    files: BTreeMap<String, BTreeMap<u32, u64>>,
+}
+
+impl LcovReport {
+    /// Parse the line records of an lcov file. Any other records are
+    /// ignored.
+    fn parse(content: &str) -> Self {
+        let mut report = Self::default();
+        let mut file = None;
+        for record in content.lines() {
+            if let Some(path) = record.strip_prefix("SF:") {
+                file = Some(path.to_string());
+            } else if record == "end_of_record" {
+                file = None;
+            } else if let (Some(file), Some(line)) = (&file, record.strip_prefix("DA:")) {
+                let mut fields = line.split(',');
+                let line = fields.next().and_then(|line| line.parse().ok());
+                let hits = fields.next().and_then(|hits| hits.parse().ok());
+                if let (Some(line), Some(hits)) = (line, hits) {
+                    report.add(file, line, hits);
+                }
+            }
+        }
+        report
+    }
+
+    fn add(&mut self, file: &str, line: u32, hits: u64) {
+        *self
+            .files
+            .entry(file.to_string())
+            .or_default()
+            .entry(line)
+            .or_default() += hits;
+    }
+
+    fn render(&self) -> String {
+        let mut content = String::new();
+        for (file, lines) in &self.files {
+            writeln!(content, "TN:\nSF:{}", file).unwrap();
+            for (line, hits) in lines {
+                writeln!(content, "DA:{},{}", line, hits).unwrap();
+            }
+            let hit = lines.values().filter(|hits| **hits > 0).count();
+            writeln!(content, "LF:{}\nLH:{}\nend_of_record", lines.len(), hit).unwrap();
+        }
+        content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lcov_report() {
+        let mut report = LcovReport::default();
+        report.add("src/lib.rs", 3, 2);
+        report.add("src/lib.rs", 1, 0);
+        report.add("src/entrypoint.rs", 7, 1);
+
+        let rendered = report.render();
+        assert_eq!(
+            rendered,
+            "TN:\nSF:src/entrypoint.rs\nDA:7,1\nLF:1\nLH:1\nend_of_record\nTN:\nSF:src/lib.rs\nDA:\
+             1,0\nDA:3,2\nLF:2\nLH:1\nend_of_record\n",
+        );
+        assert_eq!(LcovReport::parse(&rendered), report);
+
+        // Parsing merges counts with those already in the report, and ignores
+        // records other than lines.
+        let mut merged = LcovReport::parse(&format!(
+            "{}TN:\nSF:src/lib.rs\nFN:1,entrypoint\nDA:1,4,checksum\nend_of_record\n",
+            rendered,
+        ));
+        merged.add("src/lib.rs", 3, 1);
+        assert_eq!(merged.files["src/lib.rs"], BTreeMap::from([(1, 4), (3, 3)]));
+        assert_eq!(merged.files["src/entrypoint.rs"], BTreeMap::from([(7, 1)]));
+    }
+
+    #[test]
+    fn test_program_coverage_lines() {
+        // An object with one instruction on each of lines 1 to 4. See
+        // `lines.ll` for its source.
+        let program = ProgramCoverage {
+            elf: Arc::from(&include_bytes!("../tests/elf/lines.o")[..]),
+            hits: HashMap::from([(0, 2), (1, 2), (3, 1)]),
+        };
+
+        let mut report = LcovReport::default();
+        program.add_to(&mut report);
+        assert_eq!(
+            report.render(),
+            "TN:\nSF:/program/src/lib.c\nDA:1,2\nDA:2,2\nDA:3,0\nDA:4,1\nLF:4\nLH:3\n\
+             end_of_record\n",
+        );
+
+        // Programs without debug info are left out.
+        let program = ProgramCoverage {
+            elf: Arc::from(&include_bytes!("../../programs/memo/src/elf/memo.so")[..]),
+            hits: HashMap::from([(0, 1)]),
+        };
+        let mut report = LcovReport::default();
+        program.add_to(&mut report);
+        assert_eq!(report, LcovReport::default());
+    }
+
+    #[test]
+    fn test_write_replaces_earlier_runs() {
+        let dir = std::env::temp_dir().join(format!("mollusk-coverage-{}", Pubkey::new_unique()));
+        let path = dir.join("lcov.info");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            &path,
+            "TN:\nSF:src/stale.rs\nDA:1,5\nLF:1\nLH:1\nend_of_record\n",
+        )
+        .unwrap();
+
+        let write = || {
+            let mut coverage = Coverage {
+                path: path.clone(),
+                programs: HashMap::from([(
+                    (Pubkey::new_unique(), Hash::default()),
+                    ProgramCoverage {
+                        elf: Arc::from(&include_bytes!("../tests/elf/lines.o")[..]),
+                        hits: HashMap::from([(0, 1)]),
+                    },
+                )]),
+            };
+            coverage.write().unwrap();
+            LcovReport::parse(&std::fs::read_to_string(&path).unwrap())
+        };
+        let lines = |hits| BTreeMap::from([(1, hits), (2, 0), (3, 0), (4, 0)]);
+
+        // The first write replaces the coverage of an earlier run, and later
+        // ones merge with it.
+        let report = write();
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files["/program/src/lib.c"], lines(1));
+        let report = write();
+        assert_eq!(report.files["/program/src/lib.c"], lines(2));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+//! Cross-program invocation syscall wrappers, and translation of their
+//! arguments from program memory.
+//!
+//! CPIs made by SBF programs go through the `sol_invoke_signed_c` and
+//! `sol_invoke_signed_rust` syscalls. With the `invocation-inspect-callback`
+//! feature enabled, the program runtime environment used by Mollusk replaces
+//! both with the wrappers in `crate::invocation_inspect`. When privilege
+//! diagnostics are enabled with
+//! [`MolluskCore::set_privilege_diagnostics`](crate::MolluskCore::set_privilege_diagnostics),
+//! they're replaced with the wrappers in [`crate::privilege`] instead, which
+//! explain privilege failures before delegating to the former.
+
+#[cfg(feature = "invocation-inspect-callback")]
+pub(crate) use crate::invocation_inspect::{
+    SyscallInvokeSignedCInspected as InvokeSignedC,
+    SyscallInvokeSignedRustInspected as InvokeSignedRust,
+};
+#[cfg(not(feature = "invocation-inspect-callback"))]
+pub(crate) use solana_bpf_loader_program::syscalls::{
+    SyscallInvokeSignedC as InvokeSignedC, SyscallInvokeSignedRust as InvokeSignedRust,
+};
+use {
+    crate::program::copy_program_runtime_environment,
+    solana_instruction::{AccountMeta, Instruction},
+    solana_program_runtime::{
+        invoke_context::{BuiltinFunctionWithContext, InvokeContext},
+        solana_sbpf::{
+            memory_region::{AccessType, MemoryMapping},
+            program::BuiltinProgram,
+        },
+    },
+    solana_pubkey::Pubkey,
+    std::error::Error,
+};
+
+/// Copy a program runtime environment, with its CPI syscalls replaced by
+/// Mollusk's wrappers. Privilege failures are only explained if
+/// `privilege_diagnostics` is set.
+pub(crate) fn hook_cpi_syscalls(
+    environment: &BuiltinProgram<InvokeContext<'static>>,
+    privilege_diagnostics: bool,
+) -> BuiltinProgram<InvokeContext<'static>> {
+    let (invoke_signed_c, invoke_signed_rust): (
+        BuiltinFunctionWithContext,
+        BuiltinFunctionWithContext,
+    ) = if privilege_diagnostics {
+        (
+            crate::privilege::SyscallInvokeSignedCDiagnosed::vm,
+            crate::privilege::SyscallInvokeSignedRustDiagnosed::vm,
+        )
+    } else {
+        (InvokeSignedC::vm, InvokeSignedRust::vm)
+    };
+    copy_program_runtime_environment(environment, environment.get_config().clone(), |name| {
+        match name {
+            "sol_invoke_signed_c" => Some(invoke_signed_c),
+            "sol_invoke_signed_rust" => Some(invoke_signed_rust),
+            _ => None,
+        }
+    })
+}
+
+pub(crate) type InvokeSigned = fn(
+    &mut InvokeContext,
+    u64,
+    u64,
+    u64,
+    u64,
+    u64,
+    &mut MemoryMapping,
+) -> Result<u64, Box<dyn Error>>;
+
+pub(crate) type TranslateInstruction = fn(&MemoryMapping, u64) -> Option<Instruction>;
+
+fn read_bytes(memory_mapping: &MemoryMapping, addr: u64, len: u64) -> Option<Vec<u8>> {
+    if len == 0 {
+        return Some(Vec::new());
+    }
+    let host_addr = Result::from(memory_mapping.map(AccessType::Load, addr, len)).ok()?;
+    // SAFETY: The memory mapping has validated that `len` bytes are readable
+    // from the host address.
+    let bytes = unsafe { std::slice::from_raw_parts(host_addr as *const u8, len as usize) };
+    Some(bytes.to_vec())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_pubkey(bytes: &[u8], offset: usize) -> Pubkey {
+    Pubkey::new_from_array(bytes[offset..offset + 32].try_into().unwrap())
+}
+
+/// Translate a `StableInstruction`, laid out as:
+///
+/// * `accounts`: address, capacity and length of `AccountMeta`s (34 bytes each:
+///   pubkey, is_signer, is_writable).
+/// * `data`: address, capacity and length of bytes.
+/// * `program_id`: pubkey.
+pub(crate) fn translate_instruction_rust(
+    memory_mapping: &MemoryMapping,
+    addr: u64,
+) -> Option<Instruction> {
+    const ACCOUNT_META_SIZE: u64 = 34;
+
+    let instruction = read_bytes(memory_mapping, addr, 80)?;
+    let accounts_len = read_u64(&instruction, 16);
+    let accounts = read_bytes(
+        memory_mapping,
+        read_u64(&instruction, 0),
+        accounts_len.checked_mul(ACCOUNT_META_SIZE)?,
+    )?
+    .chunks_exact(ACCOUNT_META_SIZE as usize)
+    .map(|meta| AccountMeta {
+        pubkey: read_pubkey(meta, 0),
+        is_signer: meta[32] != 0,
+        is_writable: meta[33] != 0,
+    })
+    .collect();
+    let data = read_bytes(
+        memory_mapping,
+        read_u64(&instruction, 24),
+        read_u64(&instruction, 40),
+    )?;
+
+    Some(Instruction {
+        program_id: read_pubkey(&instruction, 48),
+        accounts,
+        data,
+    })
+}
+
+/// Translate a `SolInstruction`, laid out as the addresses of the program ID,
+/// `SolAccountMeta`s (16 bytes each: pubkey address, is_writable, is_signer)
+/// and data, along with the number of account metas and data bytes.
+pub(crate) fn translate_instruction_c(
+    memory_mapping: &MemoryMapping,
+    addr: u64,
+) -> Option<Instruction> {
+    const ACCOUNT_META_SIZE: u64 = 16;
+
+    let instruction = read_bytes(memory_mapping, addr, 40)?;
+    let program_id = read_bytes(memory_mapping, read_u64(&instruction, 0), 32)?;
+    let accounts_len = read_u64(&instruction, 16);
+    let accounts = read_bytes(
+        memory_mapping,
+        read_u64(&instruction, 8),
+        accounts_len.checked_mul(ACCOUNT_META_SIZE)?,
+    )?
+    .chunks_exact(ACCOUNT_META_SIZE as usize)
+    .map(|meta| {
+        let pubkey = read_bytes(memory_mapping, read_u64(meta, 0), 32)?;
+        Some(AccountMeta {
+            pubkey: read_pubkey(&pubkey, 0),
+            is_writable: meta[8] != 0,
+            is_signer: meta[9] != 0,
+        })
+    })
+    .collect::<Option<_>>()?;
+    let data = read_bytes(
+        memory_mapping,
+        read_u64(&instruction, 24),
+        read_u64(&instruction, 32),
+    )?;
+
+    Some(Instruction {
+        program_id: read_pubkey(&program_id, 0),
+        accounts,
+        data,
+    })
+}
+
+/// Translate the signer seeds passed to a CPI into the program addresses they
+/// sign for. Both the Rust and C syscalls take a slice of signers, each a
+/// slice of seeds, with every slice laid out as an address and a length.
+pub(crate) fn translate_signers(
+    memory_mapping: &MemoryMapping,
+    addr: u64,
+    len: u64,
+    program_id: &Pubkey,
+) -> Option<Vec<Pubkey>> {
+    const SLICE_SIZE: u64 = 16;
+
+    let translate_slices = |addr: u64, len: u64| {
+        read_bytes(memory_mapping, addr, len.checked_mul(SLICE_SIZE)?).map(|bytes| {
+            bytes
+                .chunks_exact(SLICE_SIZE as usize)
+                .map(|slice| (read_u64(slice, 0), read_u64(slice, 8)))
+                .collect::<Vec<_>>()
+        })
+    };
+
+    translate_slices(addr, len)?
+        .into_iter()
+        .map(|(addr, len)| {
+            let seeds = translate_slices(addr, len)?
+                .into_iter()
+                .map(|(addr, len)| read_bytes(memory_mapping, addr, len))
+                .collect::<Option<Vec<_>>>()?;
+            let seeds = seeds.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            Pubkey::create_program_address(&seeds, program_id).ok()
+        })
+        .collect()
+}
@@ -0,0 +1,60 @@
+//! DWARF debug info of program ELFs, used to resolve program counters to
+//! functions and source lines.
+
+use {
+    gimli::{EndianRcSlice, RunTimeEndian},
+    object::{Object, ObjectSection},
+    solana_program_runtime::solana_sbpf::ebpf,
+    std::rc::Rc,
+};
+
+/// The DWARF debug info of a program ELF.
+pub(crate) struct DebugInfo {
+    pub(crate) context: addr2line::Context<EndianRcSlice<RunTimeEndian>>,
+    text_address: u64,
+    text_size: u64,
+}
+
+impl DebugInfo {
+    /// Load the DWARF debug info of an ELF, if it has any.
+    pub(crate) fn load(elf: &[u8]) -> Option<Self> {
+        let file = object::File::parse(elf).ok()?;
+        file.section_by_name(".debug_info")?;
+        let text = file.section_by_name(".text")?;
+
+        let endian = if file.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+        let dwarf = gimli::Dwarf::load(|id| -> Result<_, gimli::Error> {
+            let data = file
+                .section_by_name(id.name())
+                .and_then(|section| section.uncompressed_data().ok())
+                .unwrap_or_default();
+            Ok(EndianRcSlice::new(Rc::from(&*data), endian))
+        })
+        .ok()?;
+
+        Some(Self {
+            context: addr2line::Context::from_dwarf(dwarf).ok()?,
+            text_address: text.address(),
+            text_size: text.size(),
+        })
+    }
+
+    /// The address in the ELF of the instruction at a program counter.
+    pub(crate) fn address(&self, pc: u64) -> u64 {
+        self.text_address + pc * ebpf::INSN_SIZE as u64
+    }
+
+    /// The program counter of the instruction at an address in the ELF.
+    pub(crate) fn pc(&self, address: u64) -> u64 {
+        (address - self.text_address) / ebpf::INSN_SIZE as u64
+    }
+
+    /// The address range of the text section.
+    pub(crate) fn text_range(&self) -> (u64, u64) {
+        (self.text_address, self.text_address + self.text_size)
+    }
+}
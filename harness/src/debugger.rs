@@ -0,0 +1,347 @@
+//! Remote debugging of SBF programs with GDB.
+//!
+//! Requires the `sbpf-debugger` feature, which enables the debugger of
+//! `solana-sbpf`. When a [`DebuggerConfig`] is set on a Mollusk instance, the
+//! SBF program invocations it selects are run in the interpreter, paused
+//! before their first instruction, while a GDB remote protocol server waits
+//! for a client on the configured local port. Once connected, the client can
+//! set breakpoints, step and continue, and inspect registers and VM memory.
+//!
+//! ```text
+//! $ gdb
+//! (gdb) target remote 127.0.0.1:1212
+//! (gdb) break *0x1f00
+//! (gdb) continue
+//! ```
+//!
+//! The client should continue the program until it exits: disconnecting
+//! earlier stops the program where it is. The server speaks GDB's BPF
+//! register layout, so the client needs to support the BPF architecture, like
+//! the one shipped with the Solana platform tools.
+//!
+//! The BPF loader of Agave 2.3 doesn't hand a debug port to the VMs it
+//! creates, so with this feature Mollusk wraps the loaders' entrypoint. The
+//! wrapper looks up the debugger configuration of the instance processing the
+//! instruction on the current thread, and creates the VM of the selected
+//! invocations itself, with the loader's `create_vm!`. Every other invocation
+//! is delegated to the BPF loader as usual.
+
+use {
+    solana_bpf_loader_program::{create_vm, MEMORY_POOL},
+    solana_instruction::error::InstructionError,
+    solana_log_collector::ic_logger_msg,
+    solana_program_entrypoint::{MAX_PERMITTED_DATA_INCREASE, SUCCESS},
+    solana_program_runtime::{
+        invoke_context::InvokeContext,
+        loaded_programs::ProgramCacheEntryType,
+        serialization,
+        solana_sbpf::{
+            declare_builtin_function,
+            elf::Executable,
+            error::{EbpfError, ProgramResult},
+            memory_region::{AccessType, MemoryMapping},
+            vm::ContextObject,
+        },
+        stable_log,
+    },
+    solana_pubkey::Pubkey,
+    solana_sdk_ids::{bpf_loader_deprecated, native_loader},
+    solana_transaction_context::IndexOfAccount,
+    std::{cell::Cell, net::TcpListener},
+};
+
+/// Which SBF program invocations to debug, and where to serve the debugger.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DebuggerConfig {
+    /// The local port to listen for a GDB client on.
+    pub port: u16,
+    /// The program to debug. Every invocation of the program, including
+    /// CPIs, waits for its own client to connect.
+    ///
+    /// If `None`, only the first SBF program invoked by each instruction is
+    /// debugged.
+    pub program_id: Option<Pubkey>,
+}
+
+impl DebuggerConfig {
+    /// Debug the first SBF program invoked by each instruction.
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            program_id: None,
+        }
+    }
+
+    /// Debug every invocation of a particular program.
+    pub fn with_program_id(mut self, program_id: Pubkey) -> Self {
+        self.program_id = Some(program_id);
+        self
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ActiveDebugger {
+    config: DebuggerConfig,
+    /// Whether a program invoked by the current instruction was debugged.
+    attached: bool,
+}
+
+thread_local! {
+    static ACTIVE_DEBUGGER: Cell<Option<ActiveDebugger>> = const { Cell::new(None) };
+}
+
+/// Makes a debugger configuration visible to the loader entrypoint until
+/// dropped.
+pub(crate) struct ActiveDebuggerGuard {
+    previous: Option<ActiveDebugger>,
+}
+
+impl ActiveDebuggerGuard {
+    pub(crate) fn new(config: DebuggerConfig) -> Self {
+        let previous = ACTIVE_DEBUGGER.with(|active| {
+            active.replace(Some(ActiveDebugger {
+                config,
+                attached: false,
+            }))
+        });
+        Self { previous }
+    }
+}
+
+impl Drop for ActiveDebuggerGuard {
+    fn drop(&mut self) {
+        ACTIVE_DEBUGGER.with(|active| active.set(self.previous));
+    }
+}
+
+/// Get the port to debug an invocation of `program_id` on, if it's selected
+/// by the active configuration.
+fn debug_port(program_id: &Pubkey) -> Option<u16> {
+    ACTIVE_DEBUGGER.with(|active| {
+        let mut debugger = active.get()?;
+        let selected = match debugger.config.program_id {
+            Some(debugged_program_id) => debugged_program_id == *program_id,
+            None => !debugger.attached,
+        };
+        if !selected {
+            return None;
+        }
+        debugger.attached = true;
+        active.set(Some(debugger));
+        Some(debugger.config.port)
+    })
+}
+
+declare_builtin_function!(
+    /// The BPF loader's entrypoint, which runs the invocations selected by
+    /// the active debugger configuration under the debugger.
+    Entrypoint,
+    fn rust(
+        invoke_context: &mut InvokeContext,
+        arg0: u64,
+        arg1: u64,
+        arg2: u64,
+        arg3: u64,
+        arg4: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let transaction_context = &invoke_context.transaction_context;
+        let instruction_context = transaction_context.get_current_instruction_context()?;
+        let program_account =
+            instruction_context.try_borrow_last_program_account(transaction_context)?;
+
+        // Loader management instructions, and programs the loader would
+        // refuse to run, are left to the loader.
+        #[allow(deprecated)]
+        let runnable = !native_loader::check_id(program_account.get_owner())
+            && (program_account.is_executable()
+                || invoke_context
+                    .get_feature_set()
+                    .remove_accounts_executable_flag_checks);
+        let executor = if !runnable {
+            None
+        } else {
+            debug_port(program_account.get_key()).and_then(|port| {
+                let executor = invoke_context
+                    .program_cache_for_tx_batch
+                    .find(program_account.get_key())?;
+                matches!(executor.program, ProgramCacheEntryType::Loaded(_))
+                    .then_some((port, executor))
+            })
+        };
+        drop(program_account);
+
+        match executor {
+            Some((port, executor)) => {
+                let ProgramCacheEntryType::Loaded(executable) = &executor.program else {
+                    unreachable!();
+                };
+                execute(executable, invoke_context, port).map(|_| 0)
+            }
+            None => solana_bpf_loader_program::Entrypoint::rust(
+                invoke_context,
+                arg0,
+                arg1,
+                arg2,
+                arg3,
+                arg4,
+                memory_mapping,
+            ),
+        }
+    }
+);
+
+/// Execute a program in the interpreter, handing it over to the debugger of
+/// `solana-sbpf`. Otherwise mirrors how the BPF loader executes programs.
+fn execute<'a, 'b: 'a>(
+    executable: &'a Executable<InvokeContext<'static>>,
+    invoke_context: &'a mut InvokeContext<'b>,
+    port: u16,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // SAFETY: Program cache entries erase the lifetime of the invoke context
+    // their executables are bound to. This restores it, as the BPF loader
+    // does.
+    let executable = unsafe {
+        std::mem::transmute::<
+            &'a Executable<InvokeContext<'static>>,
+            &'a Executable<InvokeContext<'b>>,
+        >(executable)
+    };
+    let log_collector = invoke_context.get_log_collector();
+    let transaction_context = &invoke_context.transaction_context;
+    let instruction_context = transaction_context.get_current_instruction_context()?;
+    let (program_id, is_loader_deprecated) = {
+        let program_account =
+            instruction_context.try_borrow_last_program_account(transaction_context)?;
+        (
+            *program_account.get_key(),
+            *program_account.get_owner() == bpf_loader_deprecated::id(),
+        )
+    };
+
+    // The debugger panics if it can't listen on the port, so fail the
+    // instruction up front instead.
+    if let Err(err) = TcpListener::bind(("127.0.0.1", port)) {
+        ic_logger_msg!(
+            log_collector,
+            "Failed to listen for a GDB client on port {}: {}",
+            port,
+            err
+        );
+        return Err(Box::new(InstructionError::ProgramEnvironmentSetupFailure));
+    }
+
+    let direct_mapping = invoke_context
+        .get_feature_set()
+        .bpf_account_data_direct_mapping;
+    let (parameter_bytes, regions, accounts_metadata) = serialization::serialize_parameters(
+        invoke_context.transaction_context,
+        instruction_context,
+        !direct_mapping,
+        invoke_context
+            .get_feature_set()
+            .mask_out_rent_epoch_in_vm_serialization,
+    )?;
+    let account_region_addrs = accounts_metadata
+        .iter()
+        .map(|metadata| {
+            let max_data_increase = if is_loader_deprecated {
+                0
+            } else {
+                MAX_PERMITTED_DATA_INCREASE as u64
+            };
+            let vm_end = metadata
+                .vm_data_addr
+                .saturating_add(metadata.original_data_len as u64)
+                .saturating_add(max_data_increase);
+            metadata.vm_data_addr..vm_end
+        })
+        .collect::<Vec<_>>();
+
+    let compute_meter_prev = invoke_context.get_remaining();
+    let (compute_units_consumed, result) = {
+        create_vm!(vm, executable, regions, accounts_metadata, invoke_context);
+        let (mut vm, stack, heap) = match vm {
+            Ok(info) => info,
+            Err(err) => {
+                ic_logger_msg!(log_collector, "Failed to create SBF VM: {}", err);
+                return Err(Box::new(InstructionError::ProgramEnvironmentSetupFailure));
+            }
+        };
+        vm.debug_port = Some(port);
+        let result = vm.execute_program(executable, /* interpreted */ true);
+        MEMORY_POOL.with_borrow_mut(|memory_pool| {
+            memory_pool.put_stack(stack);
+            memory_pool.put_heap(heap);
+        });
+        result
+    };
+
+    ic_logger_msg!(
+        log_collector,
+        "Program {} consumed {} of {} compute units",
+        &program_id,
+        compute_units_consumed,
+        compute_meter_prev
+    );
+    let (_, return_data) = invoke_context.transaction_context.get_return_data();
+    if !return_data.is_empty() {
+        stable_log::program_return(&log_collector, &program_id, return_data);
+    }
+
+    match result {
+        ProgramResult::Ok(status) if status != SUCCESS => {
+            return Err(Box::new(InstructionError::from(status)));
+        }
+        ProgramResult::Err(mut error) => {
+            if !matches!(error, EbpfError::SyscallError(_)) {
+                invoke_context.consume(invoke_context.get_remaining());
+            }
+            // Writes to read-only account data are access violations under
+            // direct mapping, which the loader reports more specifically.
+            if let EbpfError::AccessViolation(AccessType::Store, address, _, _) = error {
+                let index = account_region_addrs
+                    .iter()
+                    .position(|region| region.contains(&address));
+                if let (true, Some(index)) = (direct_mapping, index) {
+                    let transaction_context = &invoke_context.transaction_context;
+                    let account = transaction_context
+                        .get_current_instruction_context()?
+                        .try_borrow_instruction_account(
+                            transaction_context,
+                            index as IndexOfAccount,
+                        )?;
+                    #[allow(deprecated)]
+                    let err = if !invoke_context
+                        .get_feature_set()
+                        .remove_accounts_executable_flag_checks
+                        && account.is_executable()
+                    {
+                        InstructionError::ExecutableDataModified
+                    } else if account.is_writable() {
+                        InstructionError::ExternalAccountDataModified
+                    } else {
+                        InstructionError::ReadonlyDataModified
+                    };
+                    error = EbpfError::SyscallError(Box::new(err));
+                }
+            }
+            return Err(match error {
+                EbpfError::SyscallError(err) => err,
+                error => error.into(),
+            });
+        }
+        ProgramResult::Ok(_) => {}
+    }
+
+    serialization::deserialize_parameters(
+        invoke_context.transaction_context,
+        invoke_context
+            .transaction_context
+            .get_current_instruction_context()?,
+        !direct_mapping,
+        parameter_bytes.as_slice(),
+        &invoke_context.get_syscall_context()?.accounts_metadata,
+    )?;
+    Ok(())
+}
@@ -12,8 +12,15 @@
 //! * `tests/fixtures`
 //! * `BPF_OUT_DIR`
 //! * `SBF_OUT_DIR`
+//! * `target/deploy` under the workspace root (or `CARGO_TARGET_DIR`)
 //! * The current working directory
 //!
+//! Additional search paths can be provided with
+//! [`try_load_program_elf_from`], with
+//! [`Mollusk::new_with_search_paths`](crate::Mollusk::new_with_search_paths),
+//! or with
+//! [`Mollusk::with_program_search_path`](crate::Mollusk::with_program_search_path).
+//!
 //! Since these functions are intended for the local filesystem and for testing
 //! purposes, most of them will panic if the file is not found or if there is an
 //! error reading the file.
@@ -27,7 +34,9 @@ use {
     },
 };
 
-fn default_shared_object_dirs() -> Vec<PathBuf> {
+/// The default search paths for program ELF files, in the order they're
+/// checked.
+pub fn default_shared_object_dirs() -> Vec<PathBuf> {
     let mut search_path = vec![PathBuf::from("tests/fixtures")];
 
     if let Ok(bpf_out_dir) = std::env::var("BPF_OUT_DIR") {
@@ -38,6 +47,10 @@ fn default_shared_object_dirs() -> Vec<PathBuf> {
         search_path.push(PathBuf::from(bpf_out_dir));
     }
 
+    if let Some(deploy_dir) = workspace_deploy_dir() {
+        search_path.push(deploy_dir);
+    }
+
     if let Ok(dir) = std::env::current_dir() {
         search_path.push(dir);
     }
@@ -45,27 +58,91 @@ fn default_shared_object_dirs() -> Vec<PathBuf> {
     search_path
 }
 
-fn find_file(filename: &str) -> Option<PathBuf> {
-    for dir in default_shared_object_dirs() {
-        let candidate = dir.join(filename);
-        if candidate.exists() {
-            return Some(candidate);
+/// Locate the `deploy` directory written to by `cargo build-sbf` for the
+/// current workspace.
+///
+/// Honors `CARGO_TARGET_DIR`. Otherwise, walks up from the current package
+/// (`CARGO_MANIFEST_DIR`, or the current working directory) looking for the
+/// workspace root manifest.
+fn workspace_deploy_dir() -> Option<PathBuf> {
+    if let Ok(target_dir) = std::env::var("CARGO_TARGET_DIR") {
+        return Some(PathBuf::from(target_dir).join("deploy"));
+    }
+
+    let start = std::env::var("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .or_else(|_| std::env::current_dir())
+        .ok()?;
+
+    let mut package_root = None;
+    for dir in start.ancestors() {
+        let manifest = dir.join("Cargo.toml");
+        if !manifest.is_file() {
+            continue;
+        }
+        let is_workspace_root = std::fs::read_to_string(&manifest)
+            .map(|contents| contents.lines().any(|line| line.trim() == "[workspace]"))
+            .unwrap_or(false);
+        if is_workspace_root {
+            return Some(dir.join("target/deploy"));
         }
+        package_root.get_or_insert(dir);
     }
-    None
+
+    // Not part of a workspace, so the package has its own target directory.
+    package_root.map(|dir| dir.join("target/deploy"))
+}
+
+/// List the names of any `.so` files in the provided directories, to help
+/// diagnose a misnamed or misplaced program ELF.
+fn nearby_shared_objects(dirs: &[PathBuf]) -> Vec<String> {
+    let mut names = dirs
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.flatten())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "so"))
+        .filter_map(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn find_file(filename: &str, search_paths: &[PathBuf]) -> Result<PathBuf, MolluskError<'static>> {
+    search_paths
+        .iter()
+        .map(|dir| dir.join(filename))
+        .find(|candidate| candidate.exists())
+        .ok_or_else(|| MolluskError::FileNotFound {
+            file_name: filename.to_string(),
+            searched: search_paths.to_vec(),
+            nearby: nearby_shared_objects(search_paths),
+        })
 }
 
 /// Read the contents of a file into a `Vec<u8>`.
 pub fn read_file<P: AsRef<Path>>(path: P) -> Vec<u8> {
     let path = path.as_ref();
-    let mut file = File::open(path).or_panic_with(MolluskError::FileOpenError(path));
+    let mut file = File::open(path).or_panic_with(MolluskError::FileOpenError(path.into()));
 
     let mut file_data = Vec::new();
     file.read_to_end(&mut file_data)
-        .or_panic_with(MolluskError::FileReadError(path));
+        .or_panic_with(MolluskError::FileReadError(path.into()));
     file_data
 }
 
+/// Read the contents of a file into a `Vec<u8>`, returning an error if it
+/// can't be opened or read.
+fn try_read_file(path: &Path) -> Result<Vec<u8>, MolluskError<'static>> {
+    let mut file = File::open(path).map_err(|_| MolluskError::FileOpenError(path.into()))?;
+
+    let mut file_data = Vec::new();
+    file.read_to_end(&mut file_data)
+        .map_err(|_| MolluskError::FileReadError(path.into()))?;
+    Ok(file_data)
+}
+
 /// Load a program ELF file from the local filesystem by program name.
 ///
 /// The program ELF file is expected to be located in one of the default search
@@ -74,11 +151,32 @@ pub fn read_file<P: AsRef<Path>>(path: P) -> Vec<u8> {
 /// * `tests/fixtures`
 /// * `BPF_OUT_DIR`
 /// * `SBF_OUT_DIR`
+/// * `target/deploy` under the workspace root (or `CARGO_TARGET_DIR`)
 /// * The current working directory
 ///
 /// The name of the program ELF file is expected to be `{program_name}.so`.
 pub fn load_program_elf(program_name: &str) -> Vec<u8> {
+    try_load_program_elf(program_name).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Load a program ELF file from the local filesystem by program name,
+/// returning an error if it can't be found in any of the default search
+/// paths.
+///
+/// The error lists every path that was checked, as well as any `.so` files
+/// found there instead.
+pub fn try_load_program_elf(program_name: &str) -> Result<Vec<u8>, MolluskError<'static>> {
+    try_load_program_elf_from(program_name, &default_shared_object_dirs())
+}
+
+/// Load a program ELF file from the local filesystem by program name,
+/// searching only the provided directories, in order.
+///
+/// Returns an error if the file can't be found, or can't be opened or read.
+pub fn try_load_program_elf_from(
+    program_name: &str,
+    search_paths: &[PathBuf],
+) -> Result<Vec<u8>, MolluskError<'static>> {
     let file_name = format!("{program_name}.so");
-    let program_file = find_file(&file_name).or_panic_with(MolluskError::FileNotFound(&file_name));
-    read_file(program_file)
+    try_read_file(&find_file(&file_name, search_paths)?)
 }
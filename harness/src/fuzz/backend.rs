@@ -0,0 +1,176 @@
+//! Execution backends, for differential execution of instructions between
+//! Mollusk and other SVM implementations.
+//!
+//! An `ExecutionBackend` executes the instruction described by a Firedancer
+//! instruction context, which is the layout shared by solfuzz's harnesses.
+//! Mollusk is a backend itself, and `ProcessBackend` runs any executable
+//! which speaks the protobuf layout over stdin and stdout, such as a shim
+//! around solfuzz-agave or Firedancer's shared objects.
+//!
+//! Only available when the `fuzz-fd` feature is enabled.
+
+use {
+    super::firedancer::parse_fixture_effects,
+    crate::{
+        logger::Logger,
+        program::{add_program_from_account, ProgramCacheProvider},
+        MolluskCore,
+    },
+    mollusk_svm_fuzz_fixture_firedancer::{
+        context::Context as FuzzContext, effects::Effects as FuzzEffects, Fixture as FuzzFixture,
+    },
+    mollusk_svm_result::InstructionResult,
+    solana_pubkey::Pubkey,
+    std::{
+        fmt,
+        io::Write,
+        path::PathBuf,
+        process::{Command, ExitStatus, Stdio},
+    },
+};
+
+/// An error executing an instruction with a backend.
+#[derive(Debug)]
+pub enum BackendError {
+    /// The instruction's program couldn't be loaded.
+    ProgramNotCached(Pubkey),
+    /// The backend process couldn't be run.
+    Io(std::io::Error),
+    /// The backend process exited unsuccessfully.
+    Failed { status: ExitStatus, stderr: String },
+    /// The backend process wrote effects which couldn't be decoded.
+    InvalidEffects(String),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ProgramNotCached(program_id) => write!(f, "Program not cached: {}", program_id),
+            Self::Io(err) => write!(f, "Failed to run backend: {}", err),
+            Self::Failed { status, stderr } => {
+                write!(f, "Backend exited with {}", status)?;
+                if !stderr.is_empty() {
+                    write!(f, ": {}", stderr.trim_end())?;
+                }
+                Ok(())
+            }
+            Self::InvalidEffects(err) => write!(f, "Invalid effects from backend: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+impl From<std::io::Error> for BackendError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// An SVM implementation which can execute instructions.
+pub trait ExecutionBackend {
+    /// Execute the instruction described by a Firedancer instruction context.
+    fn execute(&mut self, context: &FuzzContext) -> Result<InstructionResult, BackendError>;
+}
+
+impl<PC: ProgramCacheProvider, L: Logger> ExecutionBackend for MolluskCore<PC, L> {
+    /// Execute the instruction with Mollusk, as a Firedancer fixture.
+    ///
+    /// If the program isn't in the program cache, but is deployed in the
+    /// context's accounts with BPF Loader v3 or BPF Loader 4, it's added to
+    /// the cache first.
+    fn execute(&mut self, context: &FuzzContext) -> Result<InstructionResult, BackendError> {
+        let program_id = &context.program_id;
+        if !crate::program::precompile_keys::is_precompile(program_id)
+            && self.program_cache.load_program(program_id).is_none()
+        {
+            let find_account = |key: &Pubkey| {
+                context
+                    .accounts
+                    .iter()
+                    .find(|(k, _, _)| k == key)
+                    .map(|(_, account, _)| account.clone())
+            };
+            let loaded = find_account(program_id).is_some_and(|account| {
+                add_program_from_account(&self.program_cache, program_id, &account, find_account)
+            });
+            if !loaded {
+                return Err(BackendError::ProgramNotCached(*program_id));
+            }
+        }
+
+        let fixture = FuzzFixture {
+            metadata: None,
+            input: context.clone(),
+            output: FuzzEffects::default(),
+        };
+        Ok(self.process_firedancer_fixture(&fixture))
+    }
+}
+
+/// A backend which executes instructions in a separate process.
+///
+/// For each instruction, the process is run with the encoded `InstrContext`
+/// protobuf message on stdin, and must write the encoded `InstrEffects`
+/// protobuf message to stdout, then exit successfully.
+pub struct ProcessBackend {
+    program: PathBuf,
+    args: Vec<String>,
+}
+
+impl ProcessBackend {
+    /// Create a backend running the provided executable.
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Add an argument to pass to the executable.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+}
+
+impl ExecutionBackend for ProcessBackend {
+    fn execute(&mut self, context: &FuzzContext) -> Result<InstructionResult, BackendError> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Write the context from another thread, so a process writing effects
+        // before it has read all of its input can't deadlock.
+        let mut stdin = child.stdin.take().unwrap();
+        let input = context.encode();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+        let output = child.wait_with_output()?;
+        // A process which exits without reading its input breaks the pipe,
+        // which is reported through its exit status instead.
+        let _ = writer.join().unwrap();
+        if !output.status.success() {
+            return Err(BackendError::Failed {
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+
+        let effects = FuzzEffects::decode(&output.stdout)
+            .map_err(|err| BackendError::InvalidEffects(err.to_string()))?;
+        let accounts = context
+            .accounts
+            .iter()
+            .map(|(key, account, _)| (*key, account.clone()))
+            .collect::<Vec<_>>();
+        Ok(parse_fixture_effects(
+            &accounts,
+            context.compute_units_available,
+            &effects,
+        ))
+    }
+}
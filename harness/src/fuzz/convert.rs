@@ -0,0 +1,386 @@
+//! Module for converting fixtures directly between the Mollusk and Firedancer
+//! layouts, without processing them.
+//!
+//! The layouts don't carry the same information. Firedancer fixtures carry
+//! only the slot rather than every sysvar, only the compute unit limit of the
+//! compute budget, and no execution time, while Mollusk fixtures carry no
+//! seed addresses or entrypoint, and can only represent program results which
+//! are program errors. Conversions keep everything the target layout can
+//! represent, and report every field which it can't.
+//!
+//! Sysvars of Firedancer fixtures are converted to the defaults, warped to
+//! the fixture's slot, so a Mollusk fixture survives a round trip through the
+//! Firedancer layout unchanged if nothing is reported, apart from the program
+//! account the Firedancer layout requires.
+//!
+//! Only available when both the `fuzz` and `fuzz-fd` features are enabled.
+
+use {
+    super::firedancer::{
+        instr_err_to_num, instruction_metadata, num_to_instr_err, BUILTIN_PROGRAM_IDS,
+    },
+    crate::{sysvar::Sysvars, DEFAULT_LOADER_KEY},
+    mollusk_svm_fuzz_fixture::{
+        context::Context as MolluskContext, effects::Effects as MolluskEffects,
+        sysvars::Sysvars as MolluskSysvars, Fixture as MolluskFixture,
+    },
+    mollusk_svm_fuzz_fixture_firedancer::{
+        context::{Context as FdContext, EpochContext, SlotContext},
+        effects::Effects as FdEffects,
+        Fixture as FdFixture,
+    },
+    solana_account::Account,
+    solana_compute_budget::compute_budget::ComputeBudget,
+    solana_instruction::{error::InstructionError, AccountMeta},
+    solana_program_error::ProgramError,
+    solana_pubkey::Pubkey,
+    solana_transaction_context::InstructionAccount,
+    std::fmt,
+};
+
+/// A field of a fixture which the layout it was converted to can't
+/// represent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Unrepresentable {
+    /// A sysvar which differs from the default for the fixture's slot.
+    /// Firedancer fixtures carry only the slot.
+    Sysvar(&'static str),
+    /// Compute budget parameters other than the compute unit limit.
+    ComputeBudget,
+    /// Compute units consumed beyond the compute unit limit. Firedancer
+    /// fixtures carry the compute units left instead.
+    ComputeUnitsConsumed(u64),
+    /// A non-zero execution time.
+    ExecutionTime(u64),
+    /// A program result with no counterpart in the target layout.
+    ProgramResult(String),
+    /// The seed address of an account. Mollusk fixtures carry none.
+    SeedAddress(Pubkey),
+    /// An entrypoint other than instruction execution.
+    Entrypoint(String),
+    /// An instruction account missing from the fixture's accounts. It's left
+    /// out of the instruction.
+    MissingInstructionAccount(Pubkey),
+    /// An instruction account whose transaction index is out of the range of
+    /// the fixture's accounts. It's left out of the instruction.
+    InvalidInstructionAccountIndex(u16),
+}
+
+impl fmt::Display for Unrepresentable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sysvar(name) => write!(f, "sysvar {} differs from the default", name),
+            Self::ComputeBudget => write!(f, "compute budget other than the compute unit limit"),
+            Self::ComputeUnitsConsumed(consumed) => write!(
+                f,
+                "{} compute units consumed, beyond the compute unit limit",
+                consumed
+            ),
+            Self::ExecutionTime(time) => write!(f, "execution time {}", time),
+            Self::ProgramResult(result) => write!(f, "program result {}", result),
+            Self::SeedAddress(pubkey) => write!(f, "seed address of account {}", pubkey),
+            Self::Entrypoint(entrypoint) => write!(f, "entrypoint {}", entrypoint),
+            Self::MissingInstructionAccount(pubkey) => {
+                write!(f, "instruction account {} missing from accounts", pubkey)
+            }
+            Self::InvalidInstructionAccountIndex(index) => {
+                write!(f, "instruction account index {} out of range", index)
+            }
+        }
+    }
+}
+
+/// A converted fixture, with the fields which couldn't be represented.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Converted<T> {
+    pub fixture: T,
+    pub unrepresentable: Vec<Unrepresentable>,
+}
+
+/// The sysvars a Firedancer fixture converts to: the defaults, warped to its
+/// slot.
+fn default_sysvars(slot: u64) -> MolluskSysvars {
+    let mut sysvars = Sysvars::default();
+    // Warping also updates the clock's leader schedule epoch, which the
+    // defaults leave at zero.
+    if slot != 0 {
+        sysvars.warp_to_slot(slot);
+    }
+    (&sysvars).into()
+}
+
+fn compute_budget(compute_unit_limit: u64) -> ComputeBudget {
+    ComputeBudget {
+        compute_unit_limit,
+        ..Default::default()
+    }
+}
+
+/// Convert a Mollusk fixture to the Firedancer layout.
+///
+/// The program account is stubbed out if the fixture doesn't include it, as
+/// Firedancer requires, and only the resulting accounts which differ from the
+/// input accounts are kept, as modified accounts.
+pub fn mollusk_to_firedancer(fixture: &MolluskFixture) -> Converted<FdFixture> {
+    let MolluskFixture { input, output } = fixture;
+    let mut unrepresentable = Vec::new();
+
+    let slot = input.sysvars.clock.slot;
+    let defaults = default_sysvars(slot);
+    let sysvars = &input.sysvars;
+    for (name, differs) in [
+        ("clock", sysvars.clock != defaults.clock),
+        (
+            "epoch_rewards",
+            sysvars.epoch_rewards != defaults.epoch_rewards,
+        ),
+        (
+            "epoch_schedule",
+            sysvars.epoch_schedule != defaults.epoch_schedule,
+        ),
+        ("rent", sysvars.rent != defaults.rent),
+        ("slot_hashes", sysvars.slot_hashes != defaults.slot_hashes),
+        (
+            "stake_history",
+            sysvars.stake_history != defaults.stake_history,
+        ),
+    ] {
+        if differs {
+            unrepresentable.push(Unrepresentable::Sysvar(name));
+        }
+    }
+
+    let compute_unit_limit = input.compute_budget.compute_unit_limit;
+    if input.compute_budget != compute_budget(compute_unit_limit) {
+        unrepresentable.push(Unrepresentable::ComputeBudget);
+    }
+
+    let mut accounts = input
+        .accounts
+        .iter()
+        .map(|(pubkey, account)| (*pubkey, account.clone(), None))
+        .collect::<Vec<_>>();
+    if !accounts
+        .iter()
+        .any(|(pubkey, _, _)| pubkey == &input.program_id)
+    {
+        let loader_key = if BUILTIN_PROGRAM_IDS.contains(&input.program_id) {
+            solana_sdk_ids::native_loader::id()
+        } else {
+            DEFAULT_LOADER_KEY
+        };
+        let program_account = Account {
+            owner: loader_key,
+            executable: true,
+            ..Default::default()
+        };
+        accounts.push((input.program_id, program_account, None));
+    }
+
+    let instruction_accounts = input
+        .instruction_accounts
+        .iter()
+        .filter_map(|meta| {
+            let Some(index) = accounts
+                .iter()
+                .position(|(pubkey, _, _)| pubkey == &meta.pubkey)
+            else {
+                let report = Unrepresentable::MissingInstructionAccount(meta.pubkey);
+                if !unrepresentable.contains(&report) {
+                    unrepresentable.push(report);
+                }
+                return None;
+            };
+            Some((meta, index as u16))
+        })
+        .collect::<Vec<_>>();
+    let instruction_accounts = instruction_accounts
+        .iter()
+        .map(|&(meta, index)| {
+            let index_in_callee = instruction_accounts
+                .iter()
+                .position(|(other, _)| other.pubkey == meta.pubkey)
+                .unwrap() as u16;
+            InstructionAccount {
+                index_in_transaction: index,
+                index_in_caller: index,
+                index_in_callee,
+                is_signer: meta.is_signer,
+                is_writable: meta.is_writable,
+            }
+        })
+        .collect();
+
+    let (program_result, program_custom_code) = match output.program_result {
+        0 => (0, 0),
+        code => {
+            let error = InstructionError::from(code);
+            if error == InstructionError::InvalidError {
+                unrepresentable.push(Unrepresentable::ProgramResult(code.to_string()));
+            }
+            let custom_code = match error {
+                InstructionError::Custom(custom_code) => custom_code,
+                _ => 0,
+            };
+            (instr_err_to_num(&error), custom_code)
+        }
+    };
+
+    if output.compute_units_consumed > compute_unit_limit {
+        unrepresentable.push(Unrepresentable::ComputeUnitsConsumed(
+            output.compute_units_consumed,
+        ));
+    }
+    if output.execution_time != 0 {
+        unrepresentable.push(Unrepresentable::ExecutionTime(output.execution_time));
+    }
+
+    let modified_accounts = output
+        .resulting_accounts
+        .iter()
+        .filter(|(pubkey, account)| {
+            !accounts
+                .iter()
+                .any(|(key, input_account, _)| key == pubkey && input_account == account)
+        })
+        .map(|(pubkey, account)| (*pubkey, account.clone(), None))
+        .collect();
+
+    let fixture = FdFixture {
+        metadata: Some(instruction_metadata()),
+        input: FdContext {
+            program_id: input.program_id,
+            accounts,
+            instruction_accounts,
+            instruction_data: input.instruction_data.clone(),
+            compute_units_available: compute_unit_limit,
+            slot_context: SlotContext { slot },
+            epoch_context: EpochContext {
+                feature_set: input.feature_set.clone(),
+            },
+        },
+        output: FdEffects {
+            program_result,
+            program_custom_code,
+            modified_accounts,
+            compute_units_available: compute_unit_limit
+                .saturating_sub(output.compute_units_consumed),
+            return_data: output.return_data.clone(),
+        },
+    };
+    Converted {
+        fixture,
+        unrepresentable,
+    }
+}
+
+/// Convert a Firedancer fixture to the Mollusk layout.
+///
+/// The resulting accounts are the input accounts, with the modified
+/// accounts' changes applied, followed by any modified account which isn't
+/// an input account.
+pub fn firedancer_to_mollusk(fixture: &FdFixture) -> Converted<MolluskFixture> {
+    let FdFixture {
+        metadata,
+        input,
+        output,
+    } = fixture;
+    let mut unrepresentable = Vec::new();
+
+    let entrypoint = instruction_metadata().entrypoint;
+    if let Some(metadata) = metadata {
+        if metadata.entrypoint != entrypoint {
+            unrepresentable.push(Unrepresentable::Entrypoint(metadata.entrypoint.clone()));
+        }
+    }
+
+    for (pubkey, _, seed_address) in input.accounts.iter().chain(&output.modified_accounts) {
+        let report = Unrepresentable::SeedAddress(*pubkey);
+        if seed_address.is_some() && !unrepresentable.contains(&report) {
+            unrepresentable.push(report);
+        }
+    }
+
+    let accounts = input
+        .accounts
+        .iter()
+        .map(|(pubkey, account, _)| (*pubkey, account.clone()))
+        .collect::<Vec<_>>();
+
+    let instruction_accounts = input
+        .instruction_accounts
+        .iter()
+        .filter_map(|instruction_account| {
+            let index = instruction_account.index_in_transaction;
+            let Some((pubkey, _)) = accounts.get(index as usize) else {
+                let report = Unrepresentable::InvalidInstructionAccountIndex(index);
+                if !unrepresentable.contains(&report) {
+                    unrepresentable.push(report);
+                }
+                return None;
+            };
+            Some(AccountMeta {
+                pubkey: *pubkey,
+                is_signer: instruction_account.is_signer,
+                is_writable: instruction_account.is_writable,
+            })
+        })
+        .collect();
+
+    let program_result = match output.program_result {
+        0 => 0,
+        num => {
+            let error = num_to_instr_err(num, output.program_custom_code);
+            match ProgramError::try_from(error) {
+                Ok(error) => u64::from(error),
+                Err(error) => {
+                    unrepresentable.push(Unrepresentable::ProgramResult(format!("{:?}", error)));
+                    // Mollusk's code for errors which aren't program errors.
+                    u64::MAX
+                }
+            }
+        }
+    };
+
+    let mut resulting_accounts = accounts
+        .iter()
+        .map(|(pubkey, account)| {
+            let account = output
+                .modified_accounts
+                .iter()
+                .find(|(key, _, _)| key == pubkey)
+                .map_or(account, |(_, modified_account, _)| modified_account);
+            (*pubkey, account.clone())
+        })
+        .collect::<Vec<_>>();
+    for (pubkey, account, _) in &output.modified_accounts {
+        if !accounts.iter().any(|(key, _)| key == pubkey) {
+            resulting_accounts.push((*pubkey, account.clone()));
+        }
+    }
+
+    let fixture = MolluskFixture {
+        input: MolluskContext {
+            compute_budget: compute_budget(input.compute_units_available),
+            feature_set: input.epoch_context.feature_set.clone(),
+            sysvars: default_sysvars(input.slot_context.slot),
+            program_id: input.program_id,
+            instruction_accounts,
+            instruction_data: input.instruction_data.clone(),
+            accounts,
+        },
+        output: MolluskEffects {
+            compute_units_consumed: input
+                .compute_units_available
+                .saturating_sub(output.compute_units_available),
+            execution_time: 0,
+            program_result,
+            return_data: output.return_data.clone(),
+            resulting_accounts,
+        },
+    };
+    Converted {
+        fixture,
+        unrepresentable,
+    }
+}
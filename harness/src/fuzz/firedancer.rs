@@ -7,7 +7,8 @@
 use {
     crate::{
         compile_accounts::{compile_accounts, CompiledAccounts},
-        Mollusk, DEFAULT_LOADER_KEY,
+        program::ProgramCacheProvider,
+        MolluskCore, DEFAULT_LOADER_KEY,
     },
     agave_feature_set::FeatureSet,
     mollusk_svm_fuzz_fixture_firedancer::{
@@ -19,14 +20,15 @@ use {
         metadata::Metadata as FuzzMetadata,
         Fixture as FuzzFixture,
     },
-    mollusk_svm_result::InstructionResult,
+    mollusk_svm_result::{Config, InstructionResult},
     solana_account::Account,
     solana_compute_budget::compute_budget::ComputeBudget,
     solana_instruction::{error::InstructionError, AccountMeta, Instruction},
     solana_pubkey::Pubkey,
+    std::fmt::Debug,
 };
 
-static BUILTIN_PROGRAM_IDS: &[Pubkey] = &[
+pub(crate) static BUILTIN_PROGRAM_IDS: &[Pubkey] = &[
     solana_sdk_ids::system_program::id(),
     solana_sdk_ids::vote::id(),
     solana_sdk_ids::stake::id(),
@@ -41,12 +43,12 @@ static BUILTIN_PROGRAM_IDS: &[Pubkey] = &[
     solana_sdk_ids::zk_elgamal_proof_program::id(),
 ];
 
-fn instr_err_to_num(error: &InstructionError) -> i32 {
+pub(crate) fn instr_err_to_num(error: &InstructionError) -> i32 {
     let serialized_err = bincode::serialize(error).unwrap();
     i32::from_le_bytes((&serialized_err[0..4]).try_into().unwrap()) + 1
 }
 
-fn num_to_instr_err(num: i32, custom_code: u32) -> InstructionError {
+pub(crate) fn num_to_instr_err(num: i32, custom_code: u32) -> InstructionError {
     let val = (num - 1) as u64;
     let le = val.to_le_bytes();
     let mut deser = bincode::deserialize(&le).unwrap();
@@ -225,18 +227,44 @@ pub(crate) fn parse_fixture_effects(
         compute_units_consumed: compute_unit_limit.saturating_sub(effects.compute_units_available),
         return_data,
         resulting_accounts,
+        execution_trace: vec![],
+        privilege_diagnostic: None,
     }
 }
 
-fn instruction_metadata() -> FuzzMetadata {
+/// Compare a field of two results, reporting a mismatch the way comparisons
+/// of instruction results do.
+pub(crate) fn compare_field<T: PartialEq + Debug>(
+    config: &Config,
+    check: &str,
+    left: T,
+    right: T,
+) -> bool {
+    if left == right {
+        return true;
+    }
+    let msg = format!(
+        "CHECK FAILED: {}\n  Expected: `{:?}`,\n Got: `{:?}`",
+        check, left, right
+    );
+    if config.panic {
+        panic!("{}", msg);
+    }
+    if config.verbose {
+        println!("{}", msg);
+    }
+    false
+}
+
+pub(crate) fn instruction_metadata() -> FuzzMetadata {
     FuzzMetadata {
         // Mollusk is always an instruction harness.
         entrypoint: String::from("sol_compat_instr_execute_v1"),
     }
 }
 
-pub fn build_fixture_from_mollusk_test(
-    mollusk: &Mollusk,
+pub fn build_fixture_from_mollusk_test<PC: ProgramCacheProvider, L>(
+    mollusk: &MolluskCore<PC, L>,
     instruction: &Instruction,
     accounts: &[(Pubkey, Account)],
     result: &InstructionResult,
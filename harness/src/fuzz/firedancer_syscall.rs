@@ -0,0 +1,474 @@
+//! Module for processing Firedancer syscall fixtures, from solfuzz's syscall
+//! harness, with Mollusk.
+//!
+//! A syscall fixture invokes a single syscall, by name, on a VM whose memory
+//! regions and registers are provided by the fixture, in the context of an
+//! instruction. Mollusk builds the same VM around the program runtime
+//! environment of its program cache, so any syscalls registered there,
+//! including custom ones, can be invoked.
+//!
+//! The VM context's `check_align` and `check_size` flags are ignored. As in
+//! the runtime, alignment checks follow the loader owning the instruction's
+//! program account. The syscall's return value is reported as zero if it
+//! fails, and the fixture's frame count, log, rodata and program counter
+//! aren't compared.
+//!
+//! Fixtures naming a syscall which isn't registered, or with a heap larger
+//! than the runtime's maximum heap frame, aren't invoked. They're reported as
+//! an unspecified error, with a code of -1.
+//!
+//! Only available when the `fuzz-fd` feature is enabled.
+
+use {
+    super::firedancer::{compare_field, instr_err_to_num, parse_fixture_context},
+    crate::{
+        compile_accounts::{compile_accounts, CompiledAccounts},
+        logger::Logger,
+        program::{clone_program_runtime_environment, ProgramCacheProvider},
+        MolluskCore, MolluskInvokeContextCallback, DEFAULT_LOADER_KEY,
+    },
+    mollusk_svm_fuzz_fixture_firedancer::syscall::{
+        Context as FuzzContext, Effects as FuzzEffects, ErrorKind, InputDataRegion, VmContext,
+    },
+    mollusk_svm_result::Config,
+    solana_bpf_loader_program::syscalls::SyscallError,
+    solana_hash::Hash,
+    solana_instruction::error::InstructionError,
+    solana_program_runtime::{
+        execution_budget::MAX_HEAP_FRAME_BYTES,
+        invoke_context::{BpfAllocator, EnvironmentConfig, InvokeContext, SyscallContext},
+        solana_sbpf::{
+            aligned_memory::AlignedMemory,
+            ebpf::{self, HOST_ALIGN},
+            error::{EbpfError, ProgramResult},
+            memory_region::{MemoryMapping, MemoryRegion},
+            program::{BuiltinProgram, SBPFVersion},
+            vm::{ContextObject, EbpfVm},
+        },
+    },
+    solana_transaction_context::TransactionContext,
+    std::sync::Arc,
+};
+
+/// The result of processing a syscall fixture.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyscallResult {
+    /// The error code, or zero if the syscall succeeded.
+    pub error: i64,
+    /// The kind of error, if any.
+    pub error_kind: ErrorKind,
+    /// The syscall's return value.
+    pub r0: u64,
+    pub compute_units_consumed: u64,
+    /// The heap, after the invocation.
+    pub heap: Vec<u8>,
+    /// The stack, after the invocation.
+    pub stack: Vec<u8>,
+    /// The input data regions, after the invocation.
+    pub input_data_regions: Vec<InputDataRegion>,
+}
+
+/// Checks to run when comparing syscall results.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyscallCompare {
+    /// Validate compute units consumed.
+    ComputeUnits,
+    /// Validate the error, its kind, and the return value.
+    Result,
+    /// Validate the heap.
+    Heap,
+    /// Validate the stack.
+    Stack,
+    /// Validate the input data regions.
+    InputData,
+}
+
+impl SyscallCompare {
+    /// Validate all possible checks.
+    pub fn everything() -> Vec<Self> {
+        vec![
+            Self::ComputeUnits,
+            Self::Result,
+            Self::Heap,
+            Self::Stack,
+            Self::InputData,
+        ]
+    }
+}
+
+impl SyscallResult {
+    /// Compare this result with another, using the provided checks.
+    pub fn compare_with_config(
+        &self,
+        b: &Self,
+        checks: &[SyscallCompare],
+        config: &Config,
+    ) -> bool {
+        let mut pass = true;
+        for check in checks {
+            pass &= match check {
+                SyscallCompare::ComputeUnits => compare_field(
+                    config,
+                    "compute_units_consumed",
+                    self.compute_units_consumed,
+                    b.compute_units_consumed,
+                ),
+                SyscallCompare::Result => {
+                    compare_field(config, "error", self.error, b.error)
+                        & compare_field(config, "error_kind", self.error_kind, b.error_kind)
+                        & compare_field(config, "r0", self.r0, b.r0)
+                }
+                SyscallCompare::Heap => compare_field(config, "heap", &self.heap, &b.heap),
+                SyscallCompare::Stack => compare_field(config, "stack", &self.stack, &b.stack),
+                SyscallCompare::InputData => compare_field(
+                    config,
+                    "input_data_regions",
+                    &self.input_data_regions,
+                    &b.input_data_regions,
+                ),
+            };
+        }
+        pass
+    }
+}
+
+fn sbpf_version_from_num(num: u32) -> SBPFVersion {
+    match num {
+        0 => SBPFVersion::V0,
+        1 => SBPFVersion::V1,
+        2 => SBPFVersion::V2,
+        3 => SBPFVersion::V3,
+        _ => SBPFVersion::Reserved,
+    }
+}
+
+fn syscall_err_to_num(error: &SyscallError) -> i64 {
+    let index = match error {
+        SyscallError::InvalidString(..) => 0,
+        SyscallError::Abort => 1,
+        SyscallError::Panic(..) => 2,
+        SyscallError::InvokeContextBorrowFailed => 3,
+        SyscallError::MalformedSignerSeed(..) => 4,
+        SyscallError::BadSeeds(..) => 5,
+        SyscallError::ProgramNotSupported(..) => 6,
+        SyscallError::UnalignedPointer => 7,
+        SyscallError::TooManySigners => 8,
+        SyscallError::InstructionTooLarge(..) => 9,
+        SyscallError::TooManyAccounts => 10,
+        SyscallError::CopyOverlapping => 11,
+        SyscallError::ReturnDataTooLarge(..) => 12,
+        SyscallError::TooManySlices => 13,
+        SyscallError::InvalidLength => 14,
+        SyscallError::MaxInstructionDataLenExceeded { .. } => 15,
+        SyscallError::MaxInstructionAccountsExceeded { .. } => 16,
+        SyscallError::MaxInstructionAccountInfosExceeded { .. } => 17,
+        SyscallError::InvalidAttribute => 18,
+        SyscallError::InvalidPointer => 19,
+        SyscallError::ArithmeticOverflow => 20,
+    };
+    index + 1
+}
+
+fn ebpf_err_to_num(error: &EbpfError) -> i64 {
+    let index = match error {
+        EbpfError::ElfError(..) => 0,
+        EbpfError::FunctionAlreadyRegistered(..) => 1,
+        EbpfError::CallDepthExceeded => 2,
+        EbpfError::ExitRootCallFrame => 3,
+        EbpfError::DivideByZero => 4,
+        EbpfError::DivideOverflow => 5,
+        EbpfError::ExecutionOverrun => 6,
+        EbpfError::CallOutsideTextSegment => 7,
+        EbpfError::ExceededMaxInstructions => 8,
+        EbpfError::JitNotCompiled => 9,
+        EbpfError::InvalidMemoryRegion(..) => 10,
+        EbpfError::AccessViolation(..) => 11,
+        EbpfError::StackAccessViolation(..) => 12,
+        EbpfError::InvalidInstruction => 13,
+        EbpfError::UnsupportedInstruction => 14,
+        EbpfError::ExhaustedTextSegment(..) => 15,
+        EbpfError::LibcInvocationFailed(..) => 16,
+        EbpfError::VerifierError(..) => 17,
+        EbpfError::SyscallError(..) => 18,
+    };
+    index + 1
+}
+
+/// Map the error a syscall failed with to its Firedancer error code and kind.
+fn error_to_num(error: &EbpfError) -> (i64, ErrorKind) {
+    let EbpfError::SyscallError(error) = error else {
+        return (ebpf_err_to_num(error), ErrorKind::Ebpf);
+    };
+    if let Some(error) = error.downcast_ref::<InstructionError>() {
+        (instr_err_to_num(error) as i64, ErrorKind::Instruction)
+    } else if let Some(error) = error.downcast_ref::<SyscallError>() {
+        (syscall_err_to_num(error), ErrorKind::Syscall)
+    } else if let Some(error) = error.downcast_ref::<EbpfError>() {
+        (ebpf_err_to_num(error), ErrorKind::Ebpf)
+    } else {
+        (-1, ErrorKind::Unspecified)
+    }
+}
+
+/// The memory regions of a syscall fixture's VM.
+struct VmMemory {
+    rodata: AlignedMemory<HOST_ALIGN>,
+    stack: AlignedMemory<HOST_ALIGN>,
+    heap: AlignedMemory<HOST_ALIGN>,
+    input_data_regions: Vec<(u64, AlignedMemory<HOST_ALIGN>, bool)>,
+}
+
+impl VmMemory {
+    fn new(vm_context: &VmContext, prefix: (&[u8], &[u8]), stack_size: usize) -> Self {
+        let (heap_prefix, stack_prefix) = prefix;
+        let filled = |size: usize, prefix: &[u8]| {
+            let mut memory = AlignedMemory::<HOST_ALIGN>::zero_filled(size.max(prefix.len()));
+            memory.as_slice_mut()[..prefix.len()].copy_from_slice(prefix);
+            memory
+        };
+        Self {
+            rodata: AlignedMemory::from_slice(&vm_context.rodata),
+            stack: filled(stack_size, stack_prefix),
+            heap: filled(vm_context.heap_max as usize, heap_prefix),
+            input_data_regions: vm_context
+                .input_data_regions
+                .iter()
+                .map(|region| {
+                    (
+                        region.offset,
+                        AlignedMemory::from_slice(&region.content),
+                        region.is_writable,
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Invoke a syscall by name on a VM built from the fixture's VM context,
+/// returning `None` if the syscall isn't registered.
+fn invoke_syscall<'b>(
+    loader: &Arc<BuiltinProgram<InvokeContext<'static>>>,
+    invoke_context: &mut InvokeContext<'b>,
+    vm_context: &VmContext,
+    function_name: &[u8],
+    memory: &mut VmMemory,
+) -> Option<ProgramResult> {
+    // SAFETY: Program runtime environments erase the lifetime of the invoke
+    // context their syscalls are bound to. This restores it, as the BPF
+    // loader does.
+    let loader = unsafe {
+        std::mem::transmute::<
+            Arc<BuiltinProgram<InvokeContext<'static>>>,
+            Arc<BuiltinProgram<InvokeContext<'b>>>,
+        >(loader.clone())
+    };
+    let (_, function) = loader
+        .get_function_registry()
+        .lookup_by_name(function_name)?;
+
+    let config = loader.get_config();
+    let sbpf_version = sbpf_version_from_num(vm_context.sbpf_version);
+    let stack_len = memory.stack.len();
+    let stack_gap = if !sbpf_version.dynamic_stack_frames() && config.enable_stack_frame_gaps {
+        config.stack_frame_size as u64
+    } else {
+        0
+    };
+    let regions = [
+        MemoryRegion::new_readonly(memory.rodata.as_slice(), ebpf::MM_RODATA_START),
+        MemoryRegion::new_writable_gapped(
+            memory.stack.as_slice_mut(),
+            ebpf::MM_STACK_START,
+            stack_gap,
+        ),
+        MemoryRegion::new_writable(memory.heap.as_slice_mut(), ebpf::MM_HEAP_START),
+    ]
+    .into_iter()
+    .chain(
+        memory
+            .input_data_regions
+            .iter_mut()
+            .map(|(offset, content, is_writable)| {
+                let vm_addr = ebpf::MM_INPUT_START.saturating_add(*offset);
+                if *is_writable {
+                    MemoryRegion::new_writable(content.as_slice_mut(), vm_addr)
+                } else {
+                    MemoryRegion::new_readonly(content.as_slice(), vm_addr)
+                }
+            }),
+    )
+    .collect();
+    let memory_mapping = match MemoryMapping::new(regions, config, sbpf_version) {
+        Ok(memory_mapping) => memory_mapping,
+        Err(err) => return Some(ProgramResult::Err(err)),
+    };
+
+    let mut vm = EbpfVm::new(
+        loader.clone(),
+        sbpf_version,
+        invoke_context,
+        memory_mapping,
+        stack_len,
+    );
+    vm.registers = vm_context.registers;
+    vm.invoke_function(function);
+    Some(std::mem::replace(
+        &mut vm.program_result,
+        ProgramResult::Ok(0),
+    ))
+}
+
+pub(crate) fn process_syscall<PC: ProgramCacheProvider, L: Logger>(
+    mollusk: &MolluskCore<PC, L>,
+    context: &FuzzContext,
+) -> SyscallResult {
+    let FuzzContext {
+        vm_context,
+        instruction_context,
+        invocation,
+    } = context;
+    let unspecified = SyscallResult {
+        error: -1,
+        error_kind: ErrorKind::Unspecified,
+        ..Default::default()
+    };
+    if vm_context.heap_max > MAX_HEAP_FRAME_BYTES as u64 {
+        return unspecified;
+    }
+
+    let parsed = parse_fixture_context(instruction_context);
+    let instruction = &parsed.instruction;
+    let accounts = &parsed.accounts;
+
+    let loader_key = accounts
+        .iter()
+        .find(|(key, _)| *key == instruction.program_id)
+        .map(|(_, account)| account.owner)
+        .unwrap_or(DEFAULT_LOADER_KEY);
+    let CompiledAccounts {
+        program_id_index,
+        instruction_accounts,
+        transaction_accounts,
+        ..
+    } = compile_accounts(instruction, accounts, loader_key);
+
+    let mut transaction_context = TransactionContext::new(
+        transaction_accounts,
+        mollusk.sysvars.rent.clone(),
+        mollusk.compute_budget.max_instruction_stack_depth,
+        mollusk.compute_budget.max_instruction_trace_length,
+    );
+
+    let loader =
+        clone_program_runtime_environment(mollusk.program_cache.program_runtime_environment());
+    let mut memory = VmMemory::new(
+        vm_context,
+        (&invocation.heap_prefix, &invocation.stack_prefix),
+        loader.get_config().stack_size(),
+    );
+
+    let (program_result, compute_units_consumed) = {
+        let mut program_cache = mollusk.program_cache.cache();
+        let callback = MolluskInvokeContextCallback {
+            epoch_stake: &mollusk.epoch_stake,
+            feature_set: &mollusk.feature_set,
+        };
+        let runtime_features = mollusk.feature_set.runtime_features();
+        let sysvar_cache = mollusk.sysvars.setup_sysvar_cache(accounts);
+        let mut invoke_context = InvokeContext::new(
+            &mut transaction_context,
+            &mut program_cache,
+            EnvironmentConfig::new(
+                Hash::default(),
+                /* blockhash_lamports_per_signature */ 5000, // The default value
+                &callback,
+                &runtime_features,
+                &sysvar_cache,
+            ),
+            None,
+            mollusk.compute_budget.to_budget(),
+            mollusk.compute_budget.to_cost(),
+        );
+
+        let setup = invoke_context
+            .transaction_context
+            .get_next_instruction_context()
+            .map(|instruction_context| {
+                instruction_context.configure(
+                    &[program_id_index],
+                    &instruction_accounts,
+                    &instruction.data,
+                )
+            })
+            .and_then(|_| invoke_context.push())
+            .and_then(|_| {
+                invoke_context.set_syscall_context(SyscallContext {
+                    allocator: BpfAllocator::new(vm_context.heap_max),
+                    accounts_metadata: Vec::new(),
+                    trace_log: Vec::new(),
+                })
+            });
+
+        let program_result = match setup {
+            Ok(()) => invoke_syscall(
+                &loader,
+                &mut invoke_context,
+                vm_context,
+                &invocation.function_name,
+                &mut memory,
+            ),
+            Err(err) => Some(ProgramResult::Err(EbpfError::SyscallError(Box::new(err)))),
+        };
+        let compute_units_consumed = mollusk
+            .compute_budget
+            .compute_unit_limit
+            .saturating_sub(invoke_context.get_remaining());
+        (program_result, compute_units_consumed)
+    };
+
+    let Some(program_result) = program_result else {
+        return unspecified;
+    };
+    let (error, error_kind, r0) = match program_result {
+        ProgramResult::Ok(r0) => (0, ErrorKind::Unspecified, r0),
+        ProgramResult::Err(err) => {
+            let (error, error_kind) = error_to_num(&err);
+            (error, error_kind, 0)
+        }
+    };
+
+    SyscallResult {
+        error,
+        error_kind,
+        r0,
+        compute_units_consumed,
+        heap: memory.heap.as_slice().to_vec(),
+        stack: memory.stack.as_slice().to_vec(),
+        input_data_regions: vm_context
+            .input_data_regions
+            .iter()
+            .zip(&memory.input_data_regions)
+            .map(|(region, (_, content, _))| InputDataRegion {
+                offset: region.offset,
+                content: content.as_slice().to_vec(),
+                is_writable: region.is_writable,
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn parse_syscall_effects(
+    compute_unit_limit: u64,
+    effects: &FuzzEffects,
+) -> SyscallResult {
+    SyscallResult {
+        error: effects.error,
+        error_kind: effects.error_kind,
+        r0: effects.r0,
+        compute_units_consumed: compute_unit_limit.saturating_sub(effects.compute_units_available),
+        heap: effects.heap.clone(),
+        stack: effects.stack.clone(),
+        input_data_regions: effects.input_data_regions.clone(),
+    }
+}
@@ -0,0 +1,465 @@
+//! Module for processing Firedancer transaction fixtures, from solfuzz's
+//! transaction harness, with Mollusk.
+//!
+//! Mollusk has no runtime bank, so transactions are modelled by processing
+//! their instructions in order against a shared compute meter, after
+//! deducting the transaction's fee from the fee payer. Compute budget
+//! instructions are parsed rather than executed, and each is charged the
+//! compute budget program's default of 150 compute units.
+//!
+//! The following aren't modelled, so fixtures exercising them won't match:
+//!
+//! * Sanitization, signature verification and blockhash checks. Only account
+//!   indexes out of the range of the transaction's account keys are reported
+//!   as sanitization errors; other sanitization failures are expected to be
+//!   filtered out before processing.
+//! * Rent collection and the loaded accounts data size limit.
+//! * Validation of the fee payer beyond its lamports balance.
+//!
+//! Only available when the `fuzz-fd` feature is enabled.
+
+use {
+    super::firedancer::{compare_field, num_to_instr_err, BUILTIN_PROGRAM_IDS},
+    crate::{
+        logger::Logger,
+        program::{add_program_from_account, ProgramCacheProvider},
+        MolluskCore,
+    },
+    agave_feature_set::FeatureSet,
+    mollusk_svm_fuzz_fixture_firedancer::txn::{
+        Context as FuzzContext, Effects as FuzzEffects, FeeDetails,
+    },
+    mollusk_svm_result::{Compare, Config, InstructionResult},
+    solana_account::Account,
+    solana_instruction::{error::InstructionError, AccountMeta, Instruction},
+    solana_pubkey::Pubkey,
+};
+
+/// The compute units charged for each compute budget instruction.
+const COMPUTE_BUDGET_INSTRUCTION_UNITS: u64 = 150;
+/// The default compute unit limit of each instruction which isn't a compute
+/// budget instruction.
+const DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT: u32 = 200_000;
+/// The maximum compute unit limit of a transaction.
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+/// The fee charged for each signature, including precompile signatures.
+const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+const MICRO_LAMPORTS_PER_LAMPORT: u128 = 1_000_000;
+
+/// The size of the metadata preceding the addresses in an address lookup
+/// table account.
+const LOOKUP_TABLE_META_SIZE: usize = 56;
+
+/// The result of processing a transaction fixture.
+#[derive(Debug, Default)]
+pub struct TransactionResult {
+    /// Whether the transaction's instructions were executed. Transactions
+    /// whose fee payer can't pay the fee, or whose accounts can't be loaded,
+    /// are not.
+    pub executed: bool,
+    /// Whether the transaction failed sanitization, and so wasn't executed.
+    pub sanitization_error: bool,
+    /// The index of the instruction which failed, if any.
+    pub instruction_error_index: Option<u32>,
+    /// The fee charged to the fee payer.
+    pub fee_details: FeeDetails,
+    /// The result of the transaction's instructions, over all of the
+    /// transaction's accounts.
+    pub result: InstructionResult,
+}
+
+impl TransactionResult {
+    /// Compare this result with another, using the provided checks.
+    ///
+    /// Whether the transaction was executed or failed sanitization, and the
+    /// index of the failed instruction, are compared alongside
+    /// `Compare::ProgramResult`. Fees
+    /// aren't compared separately, since they're reflected in the fee
+    /// payer's lamports.
+    pub fn compare_with_config(&self, b: &Self, checks: &[Compare], config: &Config) -> bool {
+        let mut pass = true;
+        if checks
+            .iter()
+            .any(|check| matches!(check, Compare::ProgramResult))
+        {
+            pass &= compare_field(config, "executed", self.executed, b.executed);
+            pass &= compare_field(
+                config,
+                "sanitization_error",
+                self.sanitization_error,
+                b.sanitization_error,
+            );
+            pass &= compare_field(
+                config,
+                "instruction_error_index",
+                self.instruction_error_index,
+                b.instruction_error_index,
+            );
+        }
+        pass &= self.result.compare_with_config(&b.result, checks, config);
+        pass
+    }
+}
+
+/// A transaction fixture's context, resolved into Mollusk types.
+pub struct ParsedTransactionContext {
+    /// Every account of the transaction, in the order of its account keys,
+    /// followed by the addresses loaded from lookup tables.
+    pub accounts: Vec<(Pubkey, Account)>,
+    /// The transaction's instructions, with account privileges resolved.
+    /// `None` if an address lookup couldn't be resolved, or the transaction
+    /// failed sanitization.
+    pub instructions: Option<Vec<Instruction>>,
+    /// Whether an instruction references an account index out of the range
+    /// of the transaction's account keys.
+    pub sanitization_error: bool,
+    /// The number of signatures the fee is charged for.
+    pub num_signatures: u64,
+    pub feature_set: FeatureSet,
+    pub slot: u64,
+}
+
+pub(crate) fn parse_transaction_context(context: &FuzzContext) -> ParsedTransactionContext {
+    let message = &context.transaction.message;
+    let header = &message.header;
+
+    let find_account = |key: &Pubkey| {
+        context
+            .accounts
+            .iter()
+            .find(|(k, _, _)| k == key)
+            .map(|(_, account, _)| account.clone())
+    };
+
+    // Resolve the addresses loaded from lookup tables, writable ones first.
+    let mut loaded_writable = Vec::new();
+    let mut loaded_readonly = Vec::new();
+    let mut lookups_resolved = true;
+    for lookup in &message.address_table_lookups {
+        let Some(table) = find_account(&lookup.account_key) else {
+            lookups_resolved = false;
+            continue;
+        };
+        let address = |index: &u32| {
+            let start = LOOKUP_TABLE_META_SIZE + *index as usize * 32;
+            table
+                .data
+                .get(start..start + 32)
+                .map(|bytes| Pubkey::try_from(bytes).unwrap())
+        };
+        for (indexes, loaded) in [
+            (&lookup.writable_indexes, &mut loaded_writable),
+            (&lookup.readonly_indexes, &mut loaded_readonly),
+        ] {
+            for index in indexes {
+                match address(index) {
+                    Some(key) => loaded.push(key),
+                    None => lookups_resolved = false,
+                }
+            }
+        }
+    }
+
+    let num_static = message.account_keys.len();
+    let num_signed = header.num_required_signatures as usize;
+    let num_loaded_writable = loaded_writable.len();
+    let keys = message
+        .account_keys
+        .iter()
+        .chain(&loaded_writable)
+        .chain(&loaded_readonly)
+        .copied()
+        .collect::<Vec<_>>();
+
+    let accounts = keys
+        .iter()
+        .map(|key| (*key, find_account(key).unwrap_or_default()))
+        .collect::<Vec<_>>();
+
+    let program_ids = message
+        .instructions
+        .iter()
+        .filter_map(|ix| keys.get(ix.program_id_index as usize))
+        .collect::<Vec<_>>();
+    let upgradeable_loader_present = keys.contains(&solana_sdk_ids::bpf_loader_upgradeable::id());
+
+    let sanitization_error = message.instructions.iter().any(|ix| {
+        std::iter::once(&ix.program_id_index)
+            .chain(&ix.accounts)
+            .any(|index| *index as usize >= keys.len())
+    });
+
+    let is_writable = |index: usize| {
+        let writable = if index < num_signed {
+            index < num_signed.saturating_sub(header.num_readonly_signed_accounts as usize)
+        } else if index < num_static {
+            index < num_static.saturating_sub(header.num_readonly_unsigned_accounts as usize)
+        } else {
+            index < num_static + num_loaded_writable
+        };
+        // Invoked programs, builtins and sysvars are demoted to read-only, as
+        // the runtime does.
+        let (key, account) = &accounts[index];
+        let demoted = (program_ids.contains(&key) && !upgradeable_loader_present)
+            || BUILTIN_PROGRAM_IDS.contains(key)
+            || account.owner == solana_sdk_ids::sysvar::id();
+        writable && !demoted
+    };
+
+    let instructions = (lookups_resolved && !sanitization_error).then(|| {
+        message
+            .instructions
+            .iter()
+            .map(|ix| {
+                let metas = ix
+                    .accounts
+                    .iter()
+                    .map(|index| {
+                        let index = *index as usize;
+                        AccountMeta {
+                            pubkey: keys[index],
+                            is_signer: index < num_signed,
+                            is_writable: is_writable(index),
+                        }
+                    })
+                    .collect();
+                Instruction::new_with_bytes(keys[ix.program_id_index as usize], &ix.data, metas)
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let precompile_signatures = instructions
+        .iter()
+        .flatten()
+        .filter(|ix| crate::program::precompile_keys::is_precompile(&ix.program_id))
+        .map(|ix| ix.data.first().copied().unwrap_or_default() as u64)
+        .sum::<u64>();
+
+    ParsedTransactionContext {
+        accounts,
+        instructions,
+        sanitization_error,
+        num_signatures: num_signed as u64 + precompile_signatures,
+        feature_set: context.epoch_context.feature_set.clone(),
+        slot: context.slot_context.slot,
+    }
+}
+
+/// The parameters set by a transaction's compute budget instructions.
+struct ComputeBudgetLimits {
+    compute_unit_limit: u32,
+    compute_unit_price: u64,
+    heap_size: Option<u32>,
+}
+
+/// Parse a transaction's compute budget instructions, returning `None` if
+/// any is invalid.
+fn parse_compute_budget_limits(instructions: &[Instruction]) -> Option<ComputeBudgetLimits> {
+    let mut compute_unit_limit = None;
+    let mut compute_unit_price = 0;
+    let mut heap_size = None;
+    let mut num_other_instructions = 0u32;
+    for ix in instructions {
+        if ix.program_id != solana_sdk_ids::compute_budget::id() {
+            num_other_instructions += 1;
+            continue;
+        }
+        match ix.data.split_first()? {
+            (1, value) => heap_size = Some(u32::from_le_bytes(value.try_into().ok()?)),
+            (2, value) => compute_unit_limit = Some(u32::from_le_bytes(value.try_into().ok()?)),
+            (3, value) => compute_unit_price = u64::from_le_bytes(value.try_into().ok()?),
+            // The loaded accounts data size limit isn't modelled.
+            (4, value) if value.len() == 4 => {}
+            _ => return None,
+        }
+    }
+    let compute_unit_limit = compute_unit_limit
+        .unwrap_or(num_other_instructions.saturating_mul(DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT))
+        .min(MAX_COMPUTE_UNIT_LIMIT);
+    Some(ComputeBudgetLimits {
+        compute_unit_limit,
+        compute_unit_price,
+        heap_size,
+    })
+}
+
+pub(crate) fn process_transaction<PC: ProgramCacheProvider, L: Logger>(
+    mollusk: &mut MolluskCore<PC, L>,
+    parsed: &ParsedTransactionContext,
+) -> TransactionResult {
+    let mut accounts = parsed.accounts.clone();
+    let not_executed =
+        |accounts: Vec<(Pubkey, Account)>, fee_details: FeeDetails| TransactionResult {
+            sanitization_error: parsed.sanitization_error,
+            fee_details,
+            result: InstructionResult {
+                resulting_accounts: accounts,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+    let Some(instructions) = &parsed.instructions else {
+        return not_executed(accounts, FeeDetails::default());
+    };
+    let Some(limits) = parse_compute_budget_limits(instructions) else {
+        return not_executed(accounts, FeeDetails::default());
+    };
+
+    // Charge the fee to the fee payer, the first account.
+    let prioritization_fee = (limits.compute_unit_price as u128 * limits.compute_unit_limit as u128)
+        .div_ceil(MICRO_LAMPORTS_PER_LAMPORT) as u64;
+    let fee_details = FeeDetails {
+        transaction_fee: parsed.num_signatures * LAMPORTS_PER_SIGNATURE,
+        prioritization_fee,
+    };
+    let fee = fee_details
+        .transaction_fee
+        .saturating_add(fee_details.prioritization_fee);
+    match accounts.first_mut() {
+        Some((_, fee_payer)) if fee_payer.lamports >= fee => fee_payer.lamports -= fee,
+        _ => return not_executed(accounts, FeeDetails::default()),
+    }
+
+    // Load any deployed programs the cache doesn't have yet. Transactions
+    // invoking programs which can't be loaded are charged, but not executed.
+    for ix in instructions {
+        let program_id = &ix.program_id;
+        if *program_id == solana_sdk_ids::compute_budget::id()
+            || crate::program::precompile_keys::is_precompile(program_id)
+            || mollusk.program_cache.load_program(program_id).is_some()
+        {
+            continue;
+        }
+        let loaded = accounts
+            .iter()
+            .find(|(key, _)| key == program_id)
+            .is_some_and(|(_, account)| {
+                add_program_from_account(&mollusk.program_cache, program_id, account, |key| {
+                    accounts
+                        .iter()
+                        .find(|(k, _)| k == key)
+                        .map(|(_, account)| account.clone())
+                })
+            });
+        if !loaded {
+            return not_executed(accounts, fee_details);
+        }
+    }
+
+    if let Some(heap_size) = limits.heap_size {
+        mollusk.compute_budget.heap_size = heap_size;
+    }
+
+    let mut result = InstructionResult {
+        resulting_accounts: accounts.clone(),
+        ..Default::default()
+    };
+    let mut instruction_error_index = None;
+    let mut remaining = limits.compute_unit_limit as u64;
+    for (index, ix) in instructions.iter().enumerate() {
+        let mut ix_result = if ix.program_id == solana_sdk_ids::compute_budget::id() {
+            let consumed = COMPUTE_BUDGET_INSTRUCTION_UNITS.min(remaining);
+            let raw_result = if consumed < COMPUTE_BUDGET_INSTRUCTION_UNITS {
+                Err(InstructionError::ComputationalBudgetExceeded)
+            } else {
+                Ok(())
+            };
+            InstructionResult {
+                compute_units_consumed: consumed,
+                program_result: raw_result.clone().into(),
+                raw_result,
+                resulting_accounts: result.resulting_accounts.clone(),
+                ..Default::default()
+            }
+        } else {
+            mollusk.compute_budget.compute_unit_limit = remaining;
+            mollusk.process_instruction(ix, &result.resulting_accounts)
+        };
+        remaining = remaining.saturating_sub(ix_result.compute_units_consumed);
+
+        // Only accounts the instruction could write to can change. Program
+        // accounts, in particular, come back from Mollusk stubbed out.
+        for ((key, account), (_, previous)) in ix_result
+            .resulting_accounts
+            .iter_mut()
+            .zip(&result.resulting_accounts)
+        {
+            if !ix
+                .accounts
+                .iter()
+                .any(|meta| meta.pubkey == *key && meta.is_writable)
+            {
+                *account = previous.clone();
+            }
+        }
+
+        // Transaction return data is whatever was last set.
+        let return_data = if ix_result.return_data.is_empty() {
+            std::mem::take(&mut result.return_data)
+        } else {
+            ix_result.return_data.clone()
+        };
+        result.absorb(ix_result);
+        result.return_data = return_data;
+
+        if result.raw_result.is_err() {
+            instruction_error_index = Some(index as u32);
+            result.resulting_accounts = accounts;
+            break;
+        }
+    }
+
+    TransactionResult {
+        executed: true,
+        sanitization_error: false,
+        instruction_error_index,
+        fee_details,
+        result,
+    }
+}
+
+pub(crate) fn parse_transaction_effects(
+    accounts: &[(Pubkey, Account)],
+    effects: &FuzzEffects,
+) -> TransactionResult {
+    let (raw_result, instruction_error_index) = if effects.instruction_error == 0 {
+        (Ok(()), None)
+    } else {
+        (
+            Err(num_to_instr_err(
+                effects.instruction_error as i32,
+                effects.custom_error,
+            )),
+            Some(effects.instruction_error_index),
+        )
+    };
+
+    let resulting_accounts = accounts
+        .iter()
+        .map(|(key, account)| {
+            let resulting_account = effects
+                .resulting_accounts
+                .iter()
+                .find(|(k, _, _)| k == key)
+                .map(|(_, account, _)| account.clone())
+                .unwrap_or_else(|| account.clone());
+            (*key, resulting_account)
+        })
+        .collect();
+
+    TransactionResult {
+        executed: effects.executed,
+        sanitization_error: effects.sanitization_error,
+        instruction_error_index,
+        fee_details: effects.fee_details.clone(),
+        result: InstructionResult {
+            program_result: raw_result.clone().into(),
+            raw_result,
+            compute_units_consumed: effects.executed_units,
+            return_data: effects.return_data.clone(),
+            resulting_accounts,
+            ..Default::default()
+        },
+    }
+}
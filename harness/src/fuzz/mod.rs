@@ -1,51 +1,159 @@
 #[cfg(feature = "fuzz-fd")]
+pub mod backend;
+#[cfg(all(feature = "fuzz", feature = "fuzz-fd"))]
+pub mod convert;
+#[cfg(feature = "fuzz-fd")]
 pub mod firedancer;
+#[cfg(feature = "fuzz-fd")]
+pub mod firedancer_syscall;
+#[cfg(feature = "fuzz-fd")]
+pub mod firedancer_txn;
 #[cfg(feature = "fuzz")]
 pub mod mollusk;
 
+#[cfg(feature = "fuzz")]
+use mollusk_svm_fuzz_fixture::index::{FixtureIndex, IndexEntry};
 use {
-    crate::Mollusk, mollusk_svm_fuzz_fs::FsHandler, mollusk_svm_result::InstructionResult,
-    solana_account::Account, solana_instruction::Instruction, solana_pubkey::Pubkey,
+    crate::{program::ProgramCacheProvider, MolluskCore},
+    mollusk_svm_fuzz_fs::FsHandler,
+    mollusk_svm_result::InstructionResult,
+    solana_account::Account,
+    solana_instruction::Instruction,
+    solana_pubkey::Pubkey,
 };
 
-pub fn generate_fixtures_from_mollusk_test(
-    mollusk: &Mollusk,
+/// Where the `process_and_validate_*` methods eject fixtures to.
+///
+/// Each directory is an alternative to its environment variable, and takes
+/// precedence over it when set, so fixtures can be ejected without touching
+/// the environment of the whole test binary. See
+/// [`MolluskCore::process_and_validate_instruction`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FixtureEjection {
+    /// Directory for Mollusk fixtures, as protobuf blobs. Overrides
+    /// `EJECT_FUZZ_FIXTURES`.
+    #[cfg(feature = "fuzz")]
+    pub blob_dir: Option<String>,
+    /// Directory for Mollusk fixtures, as JSON. Overrides
+    /// `EJECT_FUZZ_FIXTURES_JSON`.
+    #[cfg(feature = "fuzz")]
+    pub json_dir: Option<String>,
+    /// Directory for Firedancer fixtures, as protobuf blobs. Overrides
+    /// `EJECT_FUZZ_FIXTURES_FD`.
+    #[cfg(feature = "fuzz-fd")]
+    pub fd_blob_dir: Option<String>,
+    /// Directory for Firedancer fixtures, as JSON. Overrides
+    /// `EJECT_FUZZ_FIXTURES_JSON_FD`.
+    #[cfg(feature = "fuzz-fd")]
+    pub fd_json_dir: Option<String>,
+}
+
+impl FixtureEjection {
+    /// Eject every enabled fixture layout, as both protobuf blobs and JSON,
+    /// to the provided directory.
+    pub fn all(dir: impl Into<String>) -> Self {
+        let dir = dir.into();
+        Self {
+            #[cfg(feature = "fuzz")]
+            blob_dir: Some(dir.clone()),
+            #[cfg(feature = "fuzz")]
+            json_dir: Some(dir.clone()),
+            #[cfg(feature = "fuzz-fd")]
+            fd_blob_dir: Some(dir.clone()),
+            #[cfg(feature = "fuzz-fd")]
+            fd_json_dir: Some(dir),
+        }
+    }
+}
+
+fn resolve_dir(dir: &Option<String>, var: &str) -> Option<String> {
+    dir.clone().or_else(|| std::env::var(var).ok())
+}
+
+/// Eject fixtures from a processed instruction, to the directories set in
+/// the instance's [`FixtureEjection`] or the `EJECT_FUZZ_FIXTURES*`
+/// environment variables.
+pub fn generate_fixtures_from_mollusk_test<PC: ProgramCacheProvider, L>(
+    mollusk: &MolluskCore<PC, L>,
     instruction: &Instruction,
     accounts: &[(Pubkey, Account)],
     result: &InstructionResult,
 ) {
     #[cfg(feature = "fuzz")]
     {
-        if std::env::var("EJECT_FUZZ_FIXTURES").is_ok()
-            || std::env::var("EJECT_FUZZ_FIXTURES_JSON").is_ok()
-        {
+        let ejection = &mollusk.fixture_ejection;
+        let blob_dir = resolve_dir(&ejection.blob_dir, "EJECT_FUZZ_FIXTURES");
+        let json_dir = resolve_dir(&ejection.json_dir, "EJECT_FUZZ_FIXTURES_JSON");
+        if blob_dir.is_some() || json_dir.is_some() {
             let fixture =
                 mollusk::build_fixture_from_mollusk_test(mollusk, instruction, accounts, result);
-            let handler = FsHandler::new(fixture);
-            if let Ok(blob_dir) = std::env::var("EJECT_FUZZ_FIXTURES") {
-                handler.dump_to_blob_file(&blob_dir);
+            let handler = FsHandler::new(fixture.clone());
+            if let Some(blob_dir) = blob_dir {
+                let file = handler.dump_to_blob_file(&blob_dir);
+                // The test harness names each test's thread after the test,
+                // unless it runs tests on the main thread, such as with
+                // `--test-threads=1`.
+                let test = std::thread::current()
+                    .name()
+                    .filter(|name| *name != "main")
+                    .map(str::to_string);
+                let entry = IndexEntry::new(&file, &fixture, test.as_deref());
+                FixtureIndex::record(&blob_dir, entry);
             }
 
-            if let Ok(json_dir) = std::env::var("EJECT_FUZZ_FIXTURES_JSON") {
+            if let Some(json_dir) = json_dir {
                 handler.dump_to_json_file(&json_dir);
             }
         }
     }
     #[cfg(feature = "fuzz-fd")]
     {
-        if std::env::var("EJECT_FUZZ_FIXTURES_FD").is_ok()
-            || std::env::var("EJECT_FUZZ_FIXTURES_JSON_FD").is_ok()
-        {
+        let ejection = &mollusk.fixture_ejection;
+        let blob_dir = resolve_dir(&ejection.fd_blob_dir, "EJECT_FUZZ_FIXTURES_FD");
+        let json_dir = resolve_dir(&ejection.fd_json_dir, "EJECT_FUZZ_FIXTURES_JSON_FD");
+        if blob_dir.is_some() || json_dir.is_some() {
             let fixture =
                 firedancer::build_fixture_from_mollusk_test(mollusk, instruction, accounts, result);
             let handler = FsHandler::new(fixture);
-            if let Ok(blob_dir) = std::env::var("EJECT_FUZZ_FIXTURES_FD") {
+            if let Some(blob_dir) = blob_dir {
                 handler.dump_to_blob_file(&blob_dir);
             }
 
-            if let Ok(json_dir) = std::env::var("EJECT_FUZZ_FIXTURES_JSON_FD") {
+            if let Some(json_dir) = json_dir {
                 handler.dump_to_json_file(&json_dir);
             }
         }
     }
 }
+
+/// Eject a chain fixture from a processed instruction chain, to the
+/// directories set in the instance's [`FixtureEjection`] or the
+/// `EJECT_FUZZ_FIXTURES` and `EJECT_FUZZ_FIXTURES_JSON` environment
+/// variables.
+#[cfg(feature = "fuzz")]
+pub fn generate_chain_fixture_from_mollusk_test<PC: ProgramCacheProvider, L>(
+    mollusk: &MolluskCore<PC, L>,
+    instructions: &[&Instruction],
+    accounts: &[(Pubkey, Account)],
+    results: &[InstructionResult],
+) {
+    let ejection = &mollusk.fixture_ejection;
+    let blob_dir = resolve_dir(&ejection.blob_dir, "EJECT_FUZZ_FIXTURES");
+    let json_dir = resolve_dir(&ejection.json_dir, "EJECT_FUZZ_FIXTURES_JSON");
+    if blob_dir.is_some() || json_dir.is_some() {
+        let fixture = mollusk::build_chain_fixture_from_mollusk_test(
+            mollusk,
+            instructions,
+            accounts,
+            results,
+        );
+        let handler = FsHandler::new(fixture);
+        if let Some(blob_dir) = blob_dir {
+            handler.dump_to_blob_file(&blob_dir);
+        }
+
+        if let Some(json_dir) = json_dir {
+            handler.dump_to_json_file(&json_dir);
+        }
+    }
+}
@@ -5,11 +5,17 @@
 //! Only available when the `fuzz` feature is enabled.
 
 use {
-    crate::{sysvar::Sysvars, Mollusk},
+    crate::{program::ProgramCacheProvider, sysvar::Sysvars, MolluskCore},
     agave_feature_set::FeatureSet,
     mollusk_svm_fuzz_fixture::{
-        context::Context as FuzzContext, effects::Effects as FuzzEffects,
-        sysvars::Sysvars as FuzzSysvars, Fixture as FuzzFixture,
+        chain::{
+            ChainContext as FuzzChainContext, ChainEffects as FuzzChainEffects,
+            ChainFixture as FuzzChainFixture,
+        },
+        context::Context as FuzzContext,
+        effects::Effects as FuzzEffects,
+        sysvars::Sysvars as FuzzSysvars,
+        Fixture as FuzzFixture,
     },
     mollusk_svm_result::InstructionResult,
     solana_account::Account,
@@ -102,8 +108,8 @@ pub(crate) fn parse_fixture_context(context: &FuzzContext) -> ParsedFixtureConte
     }
 }
 
-pub fn build_fixture_from_mollusk_test(
-    mollusk: &Mollusk,
+pub fn build_fixture_from_mollusk_test<PC: ProgramCacheProvider, L>(
+    mollusk: &MolluskCore<PC, L>,
     instruction: &Instruction,
     accounts: &[(Pubkey, Account)],
     result: &InstructionResult,
@@ -129,3 +135,67 @@ pub fn load_fixture(
         InstructionResult::from(&fixture.output),
     )
 }
+
+pub struct ParsedChainFixtureContext {
+    pub accounts: Vec<(Pubkey, Account)>,
+    pub compute_budget: ComputeBudget,
+    pub feature_set: FeatureSet,
+    pub instructions: Vec<Instruction>,
+    pub sysvars: Sysvars,
+}
+
+pub(crate) fn parse_chain_fixture_context(context: &FuzzChainContext) -> ParsedChainFixtureContext {
+    let FuzzChainContext {
+        compute_budget,
+        feature_set,
+        sysvars,
+        instructions,
+        accounts,
+    } = context;
+
+    ParsedChainFixtureContext {
+        accounts: accounts.clone(),
+        compute_budget: *compute_budget,
+        feature_set: feature_set.clone(),
+        instructions: instructions.clone(),
+        sysvars: sysvars.into(),
+    }
+}
+
+/// Build a chain fixture from the instructions of a chain, and the results
+/// of each one processed.
+pub fn build_chain_fixture_from_mollusk_test<PC: ProgramCacheProvider, L>(
+    mollusk: &MolluskCore<PC, L>,
+    instructions: &[&Instruction],
+    accounts: &[(Pubkey, Account)],
+    results: &[InstructionResult],
+) -> FuzzChainFixture {
+    let input = FuzzChainContext {
+        compute_budget: mollusk.compute_budget,
+        feature_set: mollusk.feature_set.clone(),
+        sysvars: (&mollusk.sysvars).into(),
+        instructions: instructions
+            .iter()
+            .map(|instruction| (*instruction).clone())
+            .collect(),
+        accounts: accounts.to_vec(),
+    };
+    let output = FuzzChainEffects {
+        instructions: results.iter().map(FuzzEffects::from).collect(),
+    };
+    FuzzChainFixture { input, output }
+}
+
+pub fn load_chain_fixture(
+    fixture: &FuzzChainFixture,
+) -> (ParsedChainFixtureContext, Vec<InstructionResult>) {
+    (
+        parse_chain_fixture_context(&fixture.input),
+        fixture
+            .output
+            .instructions
+            .iter()
+            .map(InstructionResult::from)
+            .collect(),
+    )
+}
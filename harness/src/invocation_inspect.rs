@@ -0,0 +1,158 @@
+//! Support for the hooks of an
+//! [`InvocationInspectCallback`](crate::InvocationInspectCallback) which run
+//! around each cross-program invocation.
+//!
+//! CPIs made by SBF programs go through the `sol_invoke_signed_c` and
+//! `sol_invoke_signed_rust` syscalls, which Mollusk replaces with wrappers
+//! (see [`crate::cpi`]). When the `invocation-inspect-callback` feature is
+//! enabled, those wrappers delegate to the ones in this module, which
+//! translate the instruction being invoked and pass it to the callback before
+//! and after delegating to the original syscall.
+//!
+//! Since syscalls are plain function pointers, the callback of the `Mollusk`
+//! or `MolluskMt` instance processing an instruction is made visible to the
+//! wrappers on the current thread for the duration of the instruction.
+
+use {
+    crate::{
+        cpi::{
+            translate_instruction_c, translate_instruction_rust, InvokeSigned, TranslateInstruction,
+        },
+        InvocationInspectCallback,
+    },
+    solana_bpf_loader_program::syscalls::{SyscallInvokeSignedC, SyscallInvokeSignedRust},
+    solana_instruction::error::InstructionError,
+    solana_program_runtime::{
+        invoke_context::InvokeContext,
+        solana_sbpf::{declare_builtin_function, memory_region::MemoryMapping},
+    },
+    std::{cell::Cell, error::Error},
+};
+
+thread_local! {
+    static ACTIVE_CALLBACK: Cell<Option<*const dyn InvocationInspectCallback>> =
+        const { Cell::new(None) };
+}
+
+/// Makes an invocation inspect callback available to the CPI syscall
+/// wrappers until dropped.
+pub(crate) struct ActiveCallbackGuard<'a> {
+    previous: Option<*const dyn InvocationInspectCallback>,
+    _callback: std::marker::PhantomData<&'a dyn InvocationInspectCallback>,
+}
+
+impl<'a> ActiveCallbackGuard<'a> {
+    pub(crate) fn new(callback: &'a (dyn InvocationInspectCallback + 'static)) -> Self {
+        let pointer = callback as *const dyn InvocationInspectCallback;
+        let previous = ACTIVE_CALLBACK.with(|active| active.replace(Some(pointer)));
+        Self {
+            previous,
+            _callback: std::marker::PhantomData,
+        }
+    }
+}
+
+impl Drop for ActiveCallbackGuard<'_> {
+    fn drop(&mut self) {
+        ACTIVE_CALLBACK.with(|active| active.set(self.previous));
+    }
+}
+
+fn active_callback() -> Option<&'static dyn InvocationInspectCallback> {
+    ACTIVE_CALLBACK
+        .with(|active| active.get())
+        // SAFETY: The pointer is only set while an instruction is being
+        // processed on this thread, during which the callback outlives it.
+        .map(|pointer| unsafe { &*pointer })
+}
+
+declare_builtin_function!(
+    /// Cross-program invocation called from Rust, with inspection hooks.
+    SyscallInvokeSignedRustInspected,
+    fn rust(
+        invoke_context: &mut InvokeContext,
+        instruction_addr: u64,
+        account_infos_addr: u64,
+        account_infos_len: u64,
+        signers_seeds_addr: u64,
+        signers_seeds_len: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn Error>> {
+        invoke_signed_inspected(
+            SyscallInvokeSignedRust::rust,
+            translate_instruction_rust,
+            invoke_context,
+            [
+                instruction_addr,
+                account_infos_addr,
+                account_infos_len,
+                signers_seeds_addr,
+                signers_seeds_len,
+            ],
+            memory_mapping,
+        )
+    }
+);
+
+declare_builtin_function!(
+    /// Cross-program invocation called from C, with inspection hooks.
+    SyscallInvokeSignedCInspected,
+    fn rust(
+        invoke_context: &mut InvokeContext,
+        instruction_addr: u64,
+        account_infos_addr: u64,
+        account_infos_len: u64,
+        signers_seeds_addr: u64,
+        signers_seeds_len: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn Error>> {
+        invoke_signed_inspected(
+            SyscallInvokeSignedC::rust,
+            translate_instruction_c,
+            invoke_context,
+            [
+                instruction_addr,
+                account_infos_addr,
+                account_infos_len,
+                signers_seeds_addr,
+                signers_seeds_len,
+            ],
+            memory_mapping,
+        )
+    }
+);
+
+fn invoke_signed_inspected(
+    invoke_signed: InvokeSigned,
+    translate_instruction: TranslateInstruction,
+    invoke_context: &mut InvokeContext,
+    [a, b, c, d, e]: [u64; 5],
+    memory_mapping: &mut MemoryMapping,
+) -> Result<u64, Box<dyn Error>> {
+    // If the instruction can't be translated, the syscall itself will fail
+    // with the appropriate error, so there's nothing to inspect.
+    let inspected = active_callback()
+        .and_then(|callback| Some((callback, translate_instruction(memory_mapping, a)?)));
+
+    let Some((callback, instruction)) = inspected else {
+        return invoke_signed(invoke_context, a, b, c, d, e, memory_mapping);
+    };
+
+    callback.before_cpi(&instruction, invoke_context)?;
+
+    let result = invoke_signed(invoke_context, a, b, c, d, e, memory_mapping);
+
+    let instruction_result = match &result {
+        Ok(_) => Ok(()),
+        Err(error) => Err(error
+            .downcast_ref::<InstructionError>()
+            .cloned()
+            .unwrap_or(InstructionError::ProgramFailedToComplete)),
+    };
+    let hook_result = callback.after_cpi(&instruction, invoke_context, instruction_result);
+
+    // A failed invocation keeps its original error.
+    let value = result?;
+    hook_result?;
+    Ok(value)
+}
@@ -372,6 +372,16 @@
 //! EJECT_FUZZ_FIXTURES="./fuzz-fixtures" cargo test-sbf ...
 //! ```
 //!
+//! Every `process_and_validate_*` method ejects fixtures, on both `Mollusk`
+//! and `MolluskMt` and their contexts, which eject the accounts loaded from
+//! their account store. The output directories can also be set on an
+//! instance with `fuzz::FixtureEjection`, taking precedence over the
+//! environment variables.
+//!
+//! ```rust,ignore
+//! mollusk.fixture_ejection = FixtureEjection::all("./fuzz-fixtures");
+//! ```
+//!
 //! Note that Mollusk currently supports two types of fixtures: Mollusk's own
 //! fixture layout and the fixture layout used by the Firedancer team. Both of
 //! these layouts stem from Protobuf definitions.
@@ -438,19 +448,41 @@
 //!
 //! Fixtures can be loaded from files or decoded from raw blobs. These
 //! capabilities are provided by the respective fixture crates.
+//!
+//! With the `fuzz-fd` feature, fixtures of Firedancer's transaction and
+//! syscall harnesses can also be replayed, with
+//! `process_firedancer_transaction_fixture` and
+//! `process_firedancer_syscall_fixture` and their validating variants. See the
+//! `fuzz::firedancer_txn` and `fuzz::firedancer_syscall` modules for what they
+//! model.
 
 pub mod account_store;
 mod compile_accounts;
+pub mod coverage;
+mod cpi;
+mod debug_info;
+#[cfg(feature = "sbpf-debugger")]
+pub mod debugger;
 pub mod epoch_stake;
 pub mod file;
 #[cfg(any(feature = "fuzz", feature = "fuzz-fd"))]
 pub mod fuzz;
+#[cfg(feature = "invocation-inspect-callback")]
+mod invocation_inspect;
+pub mod logger;
+pub mod mock_program;
 pub mod mt;
+pub mod native_program;
+mod privilege;
+pub mod profile;
 pub mod program;
 pub mod program_mt;
 pub mod sysvar;
+mod trace;
 
 // Re-export result module from mollusk-svm-result crate
+#[cfg(feature = "sbpf-debugger")]
+use crate::debugger::DebuggerConfig;
 pub use mollusk_svm_result as result;
 #[cfg(any(feature = "fuzz", feature = "fuzz-fd"))]
 use mollusk_svm_result::Compare;
@@ -458,8 +490,14 @@ use mollusk_svm_result::Compare;
 use solana_transaction_context::InstructionAccount;
 use {
     crate::{
-        account_store::AccountStore, compile_accounts::CompiledAccounts, epoch_stake::EpochStake,
-        program::ProgramCache, sysvar::Sysvars,
+        account_store::{AccountStore, SharedAccountStore},
+        compile_accounts::CompiledAccounts,
+        coverage::CoverageCollector,
+        epoch_stake::EpochStake,
+        logger::Logger,
+        mock_program::MockContext,
+        program::{loader_keys, ProgramCache, ProgramCacheProvider},
+        sysvar::Sysvars,
     },
     agave_feature_set::FeatureSet,
     mollusk_svm_error::error::{MolluskError, MolluskPanic},
@@ -467,37 +505,67 @@ use {
     solana_account::Account,
     solana_compute_budget::compute_budget::ComputeBudget,
     solana_hash::Hash,
-    solana_instruction::{AccountMeta, Instruction},
+    solana_instruction::{error::InstructionError, AccountMeta, Instruction},
+    solana_loader_v3_interface::state::UpgradeableLoaderState,
     solana_log_collector::LogCollector,
     solana_precompile_error::PrecompileError,
+    solana_program_entrypoint::ProcessInstruction,
     solana_program_runtime::invoke_context::{EnvironmentConfig, InvokeContext},
     solana_pubkey::Pubkey,
+    solana_rent::Rent,
     solana_svm_callback::InvokeContextCallback,
+    solana_sysvar::Sysvar,
+    solana_sysvar_id::SysvarId,
     solana_timings::ExecuteTimings,
     solana_transaction_context::TransactionContext,
-    std::{cell::RefCell, collections::HashSet, iter::once, rc::Rc},
+    std::{cell::RefCell, collections::HashSet, iter::once, path::PathBuf, rc::Rc},
 };
 
 pub(crate) const DEFAULT_LOADER_KEY: Pubkey = solana_sdk_ids::bpf_loader_upgradeable::id();
 
 /// The Mollusk API, providing a simple interface for testing Solana programs.
 ///
+/// See [`MolluskCore`] for the available methods, and [`mt::MolluskMt`] for a
+/// variant which can be shared across threads.
+pub type Mollusk = MolluskCore<ProgramCache, Option<Rc<RefCell<LogCollector>>>>;
+
+/// The execution core behind both [`Mollusk`] and [`mt::MolluskMt`], generic
+/// over the program cache and the logger.
+///
 /// All fields can be manipulated through a handful of helper methods, but
 /// users can also directly access and modify them if they desire more control.
-pub struct Mollusk {
+pub struct MolluskCore<PC: ProgramCacheProvider, L> {
     pub config: Config,
     pub compute_budget: ComputeBudget,
     pub epoch_stake: EpochStake,
     pub feature_set: FeatureSet,
-    pub logger: Option<Rc<RefCell<LogCollector>>>,
-    pub program_cache: ProgramCache,
+    pub logger: L,
+    pub program_cache: PC,
     pub sysvars: Sysvars,
 
+    /// Additional directories to search for program ELF files, checked in
+    /// order before the default search paths.
+    pub program_search_paths: Vec<PathBuf>,
+
+    /// Serves the selected SBF program invocations to a GDB client, if set.
+    /// See the [`debugger`] module.
+    #[cfg(feature = "sbpf-debugger")]
+    pub debugger: Option<DebuggerConfig>,
+
+    /// Where `process_and_validate_*` methods eject fixtures to, alongside
+    /// the `EJECT_FUZZ_FIXTURES*` environment variables. See
+    /// [`fuzz::FixtureEjection`].
+    #[cfg(any(feature = "fuzz", feature = "fuzz-fd"))]
+    pub fixture_ejection: fuzz::FixtureEjection,
+
     /// The callback which can be used to inspect invoke_context
     /// and extract low-level information such as bpf traces, transaction
-    /// context, detailed timings, etc.
+    /// context, detailed timings, etc., as well as to rewrite instructions and
+    /// inject failures. See [`InvocationInspectCallback`].
+    ///
+    /// `MolluskMt` requires the callback to be `Send + Sync`.
     #[cfg(feature = "invocation-inspect-callback")]
-    pub invocation_inspect_callback: Box<dyn InvocationInspectCallback>,
+    pub invocation_inspect_callback: Box<PC::InvocationInspectCallback>,
 
     /// This field stores the slot only to be able to convert to and from FD
     /// fixtures and a Mollusk instance, since FD fixtures have a
@@ -508,31 +576,95 @@ pub struct Mollusk {
     pub slot: u64,
 }
 
+/// Inspects, and optionally influences, the processing of each instruction.
+///
+/// Every hook has a default implementation, so implementors only need to
+/// provide the ones they're interested in:
+///
+/// * `before_instruction` can rewrite the instruction and its accounts before
+///   they're processed, or abort processing with an error.
+/// * `before_invocation` and `after_invocation` can inspect the invoke context
+///   the instruction is processed in.
+/// * `after_instruction` can inspect the result, including the resulting
+///   accounts, and inject a failure.
+/// * `before_cpi` and `after_cpi` run around each cross-program invocation made
+///   by an SBF program, and can abort the invocation or inject a failure.
+///
+/// A callback set on `MolluskMt` must also be `Send + Sync`, so it can be
+/// invoked from multiple threads.
 #[cfg(feature = "invocation-inspect-callback")]
 pub trait InvocationInspectCallback {
+    /// Called before an instruction's accounts are compiled. The instruction
+    /// and accounts may be modified, and are processed as modified.
+    ///
+    /// Returning an error aborts processing, and the instruction fails with
+    /// it, consuming no compute units and leaving the accounts unchanged.
+    fn before_instruction(
+        &self,
+        _instruction: &mut Instruction,
+        _accounts: &mut Vec<(Pubkey, Account)>,
+    ) -> Result<(), InstructionError> {
+        Ok(())
+    }
+
     fn before_invocation(
         &self,
-        program_id: &Pubkey,
-        instruction_data: &[u8],
-        instruction_accounts: &[InstructionAccount],
-        invoke_context: &InvokeContext,
-    );
+        _program_id: &Pubkey,
+        _instruction_data: &[u8],
+        _instruction_accounts: &[InstructionAccount],
+        _invoke_context: &InvokeContext,
+    ) {
+    }
+
+    fn after_invocation(&self, _invoke_context: &InvokeContext) {}
 
-    fn after_invocation(&self, invoke_context: &InvokeContext);
+    /// Called once an instruction has been processed successfully.
+    ///
+    /// Returning an error turns the result into a failure with it, discarding
+    /// any changes made to the accounts.
+    fn after_instruction(
+        &self,
+        _instruction: &Instruction,
+        _result: &InstructionResult,
+    ) -> Result<(), InstructionError> {
+        Ok(())
+    }
+
+    /// Called before an SBF program invokes the provided instruction via CPI,
+    /// with the invoking program's context.
+    ///
+    /// Returning an error aborts the invocation, which fails the invoking
+    /// program with it.
+    fn before_cpi(
+        &self,
+        _instruction: &Instruction,
+        _invoke_context: &InvokeContext,
+    ) -> Result<(), InstructionError> {
+        Ok(())
+    }
+
+    /// Called once a CPI made by an SBF program completes, with the invoking
+    /// program's context and the result of the invocation.
+    ///
+    /// Returning an error fails the invoking program with it, even if the
+    /// invocation succeeded. A failed invocation can't be made to succeed.
+    fn after_cpi(
+        &self,
+        _instruction: &Instruction,
+        _invoke_context: &InvokeContext,
+        result: Result<(), InstructionError>,
+    ) -> Result<(), InstructionError> {
+        result
+    }
 }
 
 #[cfg(feature = "invocation-inspect-callback")]
 pub struct EmptyInvocationInspectCallback;
 
 #[cfg(feature = "invocation-inspect-callback")]
-impl InvocationInspectCallback for EmptyInvocationInspectCallback {
-    fn before_invocation(&self, _: &Pubkey, _: &[u8], _: &[InstructionAccount], _: &InvokeContext) {
-    }
+impl InvocationInspectCallback for EmptyInvocationInspectCallback {}
 
-    fn after_invocation(&self, _: &InvokeContext) {}
-}
-
-impl Default for Mollusk {
+impl<PC: ProgramCacheProvider, L: Logger + Default> Default for MolluskCore<PC, L> {
     fn default() -> Self {
         #[rustfmt::skip]
         solana_logger::setup_with_default(
@@ -553,18 +685,25 @@ impl Default for Mollusk {
         };
         #[cfg(not(feature = "fuzz"))]
         let feature_set = FeatureSet::all_enabled();
-        let program_cache = ProgramCache::new(&feature_set, &compute_budget);
+        let program_cache = PC::new(&feature_set, &compute_budget);
         Self {
             config: Config::default(),
             compute_budget,
             epoch_stake: EpochStake::default(),
             feature_set,
-            logger: None,
+            logger: L::default(),
             program_cache,
             sysvars: Sysvars::default(),
+            program_search_paths: Vec::new(),
+
+            #[cfg(feature = "sbpf-debugger")]
+            debugger: None,
+
+            #[cfg(any(feature = "fuzz", feature = "fuzz-fd"))]
+            fixture_ejection: fuzz::FixtureEjection::default(),
 
             #[cfg(feature = "invocation-inspect-callback")]
-            invocation_inspect_callback: Box::new(EmptyInvocationInspectCallback {}),
+            invocation_inspect_callback: PC::empty_invocation_inspect_callback(),
 
             #[cfg(feature = "fuzz-fd")]
             slot: 0,
@@ -572,7 +711,7 @@ impl Default for Mollusk {
     }
 }
 
-impl CheckContext for Mollusk {
+impl<PC: ProgramCacheProvider, L> CheckContext for MolluskCore<PC, L> {
     fn is_rent_exempt(&self, lamports: u64, space: usize, owner: Pubkey) -> bool {
         owner.eq(&Pubkey::default()) && lamports == 0
             || self.sysvars.rent.is_exempt(lamports, space)
@@ -615,7 +754,7 @@ impl InvokeContextCallback for MolluskInvokeContextCallback<'_> {
     }
 }
 
-impl Mollusk {
+impl<PC: ProgramCacheProvider, L: Logger + Default> MolluskCore<PC, L> {
     /// Create a new Mollusk instance containing the provided program.
     ///
     /// Attempts to load the program's ELF file from the default search paths.
@@ -629,18 +768,60 @@ impl Mollusk {
     /// - `tests/fixtures`
     /// - The directory specified by the `BPF_OUT_DIR` environment variable
     /// - The directory specified by the `SBF_OUT_DIR` environment variable
+    /// - `target/deploy` under the workspace root (or `CARGO_TARGET_DIR`)
     /// - The current working directory
     pub fn new(program_id: &Pubkey, program_name: &str) -> Self {
-        let mut mollusk = Self::default();
+        Self::new_with_search_paths(program_id, program_name, Vec::<PathBuf>::new())
+    }
+
+    /// Create a new Mollusk instance containing the provided program, loading
+    /// its ELF file from the provided directories before the default search
+    /// paths.
+    ///
+    /// The directories are kept in `program_search_paths`, so they also apply
+    /// to programs added later.
+    pub fn new_with_search_paths<P: Into<PathBuf>>(
+        program_id: &Pubkey,
+        program_name: &str,
+        search_paths: impl IntoIterator<Item = P>,
+    ) -> Self {
+        let mut mollusk = Self {
+            program_search_paths: search_paths.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        };
         mollusk.add_program(program_id, program_name, &DEFAULT_LOADER_KEY);
         mollusk
     }
+}
+
+impl<PC: ProgramCacheProvider, L: Logger> MolluskCore<PC, L> {
+    /// Add a directory to search for program ELF files, before the default
+    /// search paths.
+    ///
+    /// Only applies to programs added after the search path, ie. with
+    /// [`add_program`](Self::add_program). To load the program passed to
+    /// `new` from a custom directory, use
+    /// [`new_with_search_paths`](Self::new_with_search_paths).
+    pub fn with_program_search_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.program_search_paths.push(path.into());
+        self
+    }
 
     /// Add a program to the test environment.
     ///
+    /// The program's ELF is loaded from `program_search_paths`, followed by the
+    /// default search paths.
+    ///
     /// If you intend to CPI to a program, this is likely what you want to use.
     pub fn add_program(&mut self, program_id: &Pubkey, program_name: &str, loader_key: &Pubkey) {
-        let elf = file::load_program_elf(program_name);
+        let search_paths = self
+            .program_search_paths
+            .iter()
+            .cloned()
+            .chain(file::default_shared_object_dirs())
+            .collect::<Vec<_>>();
+        let elf = file::try_load_program_elf_from(program_name, &search_paths)
+            .unwrap_or_else(|err| panic!("{err}"));
         self.add_program_with_elf_and_loader(program_id, &elf, loader_key);
     }
 
@@ -657,11 +838,160 @@ impl Mollusk {
         self.program_cache.add_program(program_id, loader_key, elf);
     }
 
+    /// Add a program to the test environment from a BPF Loader v3
+    /// (Upgradeable) program data account, such as one dumped from a live
+    /// cluster.
+    ///
+    /// The loader's metadata is stripped from the account data, and the
+    /// remaining ELF is added under BPF Loader v3.
+    pub fn add_program_from_programdata_account(
+        &mut self,
+        program_id: &Pubkey,
+        programdata_account: &Account,
+    ) {
+        let elf = program::elf_from_program_data_account_loader_v3(programdata_account)
+            .or_panic_with(MolluskError::InvalidProgramAccount(program_id));
+        self.add_program_with_elf_and_loader(program_id, elf, &loader_keys::LOADER_V3);
+    }
+
+    /// Add a program to the test environment from a BPF Loader 4 program
+    /// account, such as one dumped from a live cluster.
+    ///
+    /// The loader's metadata is stripped from the account data, and the
+    /// remaining ELF is added under BPF Loader 4.
+    pub fn add_program_from_loader_v4_account(
+        &mut self,
+        program_id: &Pubkey,
+        program_account: &Account,
+    ) {
+        let elf = program::elf_from_program_account_loader_v4(program_account)
+            .or_panic_with(MolluskError::InvalidProgramAccount(program_id));
+        self.add_program_with_elf_and_loader(program_id, elf, &loader_keys::LOADER_V4);
+    }
+
+    /// Set the upgrade authority of a program in the test environment.
+    ///
+    /// The authority is reflected in the program's accounts when they're
+    /// loaded into a context's account store: the program data account for
+    /// BPF Loader v3 (Upgradeable) programs, or the program account for BPF
+    /// Loader 4 programs. Programs default to having no authority.
+    pub fn set_program_upgrade_authority(
+        &mut self,
+        program_id: &Pubkey,
+        authority: Option<Pubkey>,
+    ) {
+        self.program_cache
+            .set_upgrade_authority(program_id, authority);
+    }
+
+    /// Add a native program to the test environment, executed on the host by
+    /// calling the provided entrypoint, rather than as SBF.
+    ///
+    /// The program's accounts are serialized and deserialized exactly as the
+    /// BPF loader would, so the same tests can be run against a native build
+    /// of the program, ie. to use a debugger or coverage tools.
+    ///
+    /// See the [`native_program`] module for more details.
+    pub fn add_native_program(&mut self, program_id: &Pubkey, entrypoint: ProcessInstruction) {
+        self.program_cache
+            .add_native_program(program_id, entrypoint);
+    }
+
     /// Warp the test environment to a slot by updating sysvars.
     pub fn warp_to_slot(&mut self, slot: u64) {
         self.sysvars.warp_to_slot(slot)
     }
 
+    /// Get a sysvar from the test environment.
+    pub fn get_sysvar<T: Sysvar + SysvarId>(&self) -> T {
+        self.sysvars.get()
+    }
+
+    /// Set a sysvar in the test environment.
+    pub fn set_sysvar<T: Sysvar + SysvarId>(&mut self, sysvar: &T) {
+        self.sysvars.set(sysvar)
+    }
+
+    /// Expire the current blockhash by advancing the clock to the next slot,
+    /// and recording a new, unique blockhash for it in `SlotHashes`.
+    pub fn expire_blockhash(&mut self) {
+        let current_slot = self.sysvars.clock.slot;
+        let current_time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let mut hash_data = [0u8; 32];
+        hash_data[0..8].copy_from_slice(&current_slot.to_le_bytes());
+        hash_data[8..16].copy_from_slice(&current_time.to_le_bytes());
+        hash_data[16] = 0xFF;
+
+        let next_slot = current_slot + 1;
+        self.sysvars
+            .slot_hashes
+            .add(next_slot, Hash::new_from_array(hash_data));
+        self.sysvars.clock.slot = next_slot;
+    }
+
+    /// Get the minimum balance required to make an account with the provided
+    /// data length rent exempt.
+    pub fn minimum_balance_for_rent_exemption(&self, data_len: usize) -> u64 {
+        1.max(self.sysvars.rent.minimum_balance(data_len))
+    }
+
+    /// Enable or disable SBF instruction tracing.
+    ///
+    /// When enabled, the VM's debugging features are turned on, and the
+    /// result of each instruction contains the execution trace of every SBF
+    /// program it invoked: the program counter, registers and opcode of each
+    /// executed instruction, along with a disassembly-annotated text dump.
+    /// See [`InstructionResult::execution_trace`] and
+    /// [`InstructionResult::trace_dump`].
+    ///
+    /// Every program with an ELF is reloaded, so this can be toggled at any
+    /// time. Tracing slows execution down considerably. It can't be disabled
+    /// while a coverage collector is attached, since coverage is collected
+    /// from the traces.
+    pub fn set_tracing(&mut self, enabled: bool) {
+        let enabled = enabled || self.program_cache.coverage_collector().is_some();
+        let config = crate::program::create_program_runtime_environment(
+            &self.feature_set,
+            &self.compute_budget,
+            /* debugging_features */ enabled,
+        )
+        .get_config()
+        .clone();
+        let program_runtime_environment = crate::program::copy_program_runtime_environment(
+            self.program_cache.program_runtime_environment(),
+            config,
+            |_| None,
+        );
+        self.program_cache
+            .set_program_runtime_environment(program_runtime_environment);
+    }
+
+    /// Enable or disable privilege diagnostics for CPIs.
+    ///
+    /// A top-level instruction failing for a missing signature is explained
+    /// in [`InstructionResult::privilege_diagnostic`], provided it made no
+    /// CPIs, since the signature may have been missing from any of them. When
+    /// enabled, the CPI syscalls are replaced with wrappers that also explain
+    /// privilege failures of CPIs made by SBF programs, such as a signer
+    /// escalation or a mismatched PDA signer seed, and instructions which made
+    /// CPIs are explained at the top level if no CPI failure was recorded.
+    ///
+    /// Every program with an ELF is reloaded, so this can be toggled at any
+    /// time.
+    pub fn set_privilege_diagnostics(&mut self, enabled: bool) {
+        let program_runtime_environment = crate::cpi::hook_cpi_syscalls(
+            self.program_cache.program_runtime_environment(),
+            enabled,
+        );
+        self.program_cache
+            .set_program_runtime_environment(program_runtime_environment);
+        self.program_cache.set_privilege_diagnostics(enabled);
+    }
+
     /// Process an instruction using the minified Solana Virtual Machine (SVM)
     /// environment. Simply returns the result.
     pub fn process_instruction(
@@ -669,6 +999,134 @@ impl Mollusk {
         instruction: &Instruction,
         accounts: &[(Pubkey, Account)],
     ) -> InstructionResult {
+        let log_collector = self.logger.log_collector();
+        let (result, _) =
+            self.process_instruction_log(instruction, accounts, log_collector.clone());
+        self.logger.collect(log_collector);
+        result
+    }
+
+    /// Attach a coverage collector, recording the source lines executed by
+    /// the SBF programs invoked by every instruction processed from now on.
+    ///
+    /// Coverage is collected from execution traces, so this enables tracing,
+    /// which then stays enabled. See the [`coverage`] module for more
+    /// details.
+    pub fn set_coverage_collector(&mut self, coverage_collector: CoverageCollector) {
+        self.program_cache
+            .set_coverage_collector(coverage_collector);
+        self.set_tracing(true);
+    }
+
+    /// The attached coverage collector, if any. See
+    /// [`MolluskCore::set_coverage_collector`].
+    pub fn coverage_collector(&self) -> Option<&CoverageCollector> {
+        self.program_cache.coverage_collector()
+    }
+
+    /// Process an instruction, profiling the compute units spent in each
+    /// function of the SBF programs it invokes.
+    ///
+    /// The instruction is processed with tracing enabled, which is restored
+    /// to its previous state afterwards. See the [`profile`] module for how
+    /// compute units are attributed.
+    pub fn profile_instruction(
+        &mut self,
+        instruction: &Instruction,
+        accounts: &[(Pubkey, Account)],
+    ) -> (InstructionResult, profile::Profile) {
+        let tracing = self
+            .program_cache
+            .program_runtime_environment()
+            .get_config()
+            .enable_instruction_tracing;
+        if !tracing {
+            self.set_tracing(true);
+        }
+
+        let log_collector = self.logger.log_collector();
+        let (result, transaction_context) =
+            self.process_instruction_log(instruction, accounts, log_collector.clone());
+        self.logger.collect(log_collector);
+        let profile = profile::build_profile(
+            &self.program_cache,
+            &transaction_context,
+            &result.execution_trace,
+            result.compute_units_consumed,
+        );
+
+        if !tracing {
+            self.set_tracing(false);
+        }
+        (result, profile)
+    }
+
+    /// Process an instruction using the minified Solana Virtual Machine (SVM)
+    /// environment, recording program logs into the provided log collector
+    /// instead of the instance's `logger`.
+    ///
+    /// The transaction context the instruction was processed in is returned
+    /// alongside the result, ie. to inspect the instruction trace.
+    pub fn process_instruction_log(
+        &self,
+        instruction: &Instruction,
+        accounts: &[(Pubkey, Account)],
+        log: Option<Rc<RefCell<LogCollector>>>,
+    ) -> (InstructionResult, TransactionContext) {
+        #[cfg(feature = "invocation-inspect-callback")]
+        let result = self.process_instruction_inspected(instruction, accounts, log);
+        #[cfg(not(feature = "invocation-inspect-callback"))]
+        let result = self.execute_instruction(instruction, accounts, log);
+        result
+    }
+
+    /// Process an instruction, running the hooks of the
+    /// `invocation_inspect_callback` around it.
+    #[cfg(feature = "invocation-inspect-callback")]
+    fn process_instruction_inspected(
+        &self,
+        instruction: &Instruction,
+        accounts: &[(Pubkey, Account)],
+        log: Option<Rc<RefCell<LogCollector>>>,
+    ) -> (InstructionResult, TransactionContext) {
+        let callback = PC::dyn_invocation_inspect_callback(&self.invocation_inspect_callback);
+
+        let mut instruction = instruction.clone();
+        let mut inspected_accounts = accounts.to_vec();
+        if let Err(err) = callback.before_instruction(&mut instruction, &mut inspected_accounts) {
+            return (
+                InstructionResult {
+                    program_result: Err(err.clone()).into(),
+                    raw_result: Err(err),
+                    resulting_accounts: accounts.to_vec(),
+                    ..Default::default()
+                },
+                TransactionContext::new(vec![], self.sysvars.rent.clone(), 0, 0),
+            );
+        }
+
+        let (mut result, transaction_context) = {
+            let _callback = invocation_inspect::ActiveCallbackGuard::new(callback);
+            self.execute_instruction(&instruction, &inspected_accounts, log)
+        };
+
+        if result.raw_result.is_ok() {
+            if let Err(err) = callback.after_instruction(&instruction, &result) {
+                result.program_result = Err(err.clone()).into();
+                result.raw_result = Err(err);
+                result.resulting_accounts = accounts.to_vec();
+            }
+        }
+
+        (result, transaction_context)
+    }
+
+    fn execute_instruction(
+        &self,
+        instruction: &Instruction,
+        accounts: &[(Pubkey, Account)],
+        log: Option<Rc<RefCell<LogCollector>>>,
+    ) -> (InstructionResult, TransactionContext) {
         let mut compute_units_consumed = 0;
         let mut timings = ExecuteTimings::default();
 
@@ -686,6 +1144,7 @@ impl Mollusk {
             program_id_index,
             instruction_accounts,
             transaction_accounts,
+            non_signer_accounts,
         } = crate::compile_accounts::compile_accounts(instruction, accounts, loader_key);
 
         let mut transaction_context = TransactionContext::new(
@@ -695,7 +1154,14 @@ impl Mollusk {
             self.compute_budget.max_instruction_trace_length,
         );
 
-        let invoke_result = {
+        let (invoke_result, trace_logs, privilege_diagnostic) = {
+            let _mock_programs = self.program_cache.activate_mock_programs();
+            let privilege_recorder = self
+                .program_cache
+                .privilege_diagnostics()
+                .then(privilege::CpiDiagnosticRecorder::new);
+            #[cfg(feature = "sbpf-debugger")]
+            let _debugger = self.debugger.map(debugger::ActiveDebuggerGuard::new);
             let mut program_cache = self.program_cache.cache();
             let callback = MolluskInvokeContextCallback {
                 epoch_stake: &self.epoch_stake,
@@ -713,7 +1179,7 @@ impl Mollusk {
                     &runtime_features,
                     &sysvar_cache,
                 ),
-                self.logger.clone(),
+                log,
                 self.compute_budget.to_budget(),
                 self.compute_budget.to_cost(),
             );
@@ -748,7 +1214,27 @@ impl Mollusk {
             self.invocation_inspect_callback
                 .after_invocation(&invoke_context);
 
-            result
+            let privilege_diagnostic = match (&privilege_recorder, &result) {
+                (Some(recorder), _) => {
+                    recorder.diagnose(&result, instruction, &non_signer_accounts)
+                }
+                // Without CPI diagnostics, a failure can only be attributed to
+                // the instruction itself if it made no CPIs.
+                (None, Err(error))
+                    if invoke_context
+                        .transaction_context
+                        .get_instruction_trace_length()
+                        == 1 =>
+                {
+                    privilege::diagnose_instruction(error, instruction, &non_signer_accounts)
+                }
+                (None, _) => None,
+            };
+            (
+                result,
+                invoke_context.get_traces().clone(),
+                privilege_diagnostic,
+            )
         };
 
         let return_data = transaction_context.get_return_data().1.to_vec();
@@ -775,14 +1261,25 @@ impl Mollusk {
             accounts.to_vec()
         };
 
-        InstructionResult {
-            compute_units_consumed,
-            execution_time: timings.details.execute_us.0,
-            program_result: invoke_result.clone().into(),
-            raw_result: invoke_result,
-            return_data,
-            resulting_accounts,
+        let execution_trace =
+            trace::collect_traces(&self.program_cache, &transaction_context, &trace_logs);
+        if let Some(coverage_collector) = self.program_cache.coverage_collector() {
+            coverage_collector.record(&self.program_cache, &execution_trace);
         }
+
+        (
+            InstructionResult {
+                compute_units_consumed,
+                execution_time: timings.details.execute_us.0,
+                program_result: invoke_result.clone().into(),
+                raw_result: invoke_result,
+                return_data,
+                resulting_accounts,
+                execution_trace,
+                privilege_diagnostic,
+            },
+            transaction_context,
+        )
     }
 
     /// Process a chain of instructions using the minified Solana Virtual
@@ -818,6 +1315,41 @@ impl Mollusk {
         result
     }
 
+    /// Process a chain of instructions using the minified Solana Virtual
+    /// Machine (SVM) environment, recording program logs into the provided
+    /// log collector instead of the instance's `logger`.
+    ///
+    /// The transaction context the _last_ instruction was processed in is
+    /// returned alongside the result. See
+    /// [`process_instruction_chain`](Self::process_instruction_chain) for
+    /// details on the result.
+    pub fn process_instruction_chain_log(
+        &self,
+        instructions: &[Instruction],
+        accounts: &[(Pubkey, Account)],
+        log: Option<Rc<RefCell<LogCollector>>>,
+    ) -> (InstructionResult, TransactionContext) {
+        let mut result = InstructionResult {
+            resulting_accounts: accounts.to_vec(),
+            ..Default::default()
+        };
+        let mut transaction_context = TransactionContext::new(vec![], Rent::default(), 0, 0);
+
+        for instruction in instructions {
+            let (this_result, this_transaction_context) =
+                self.process_instruction_log(instruction, &result.resulting_accounts, log.clone());
+
+            result.absorb(this_result);
+            transaction_context = this_transaction_context;
+
+            if result.program_result.is_err() {
+                break;
+            }
+        }
+
+        (result, transaction_context)
+    }
+
     /// Process an instruction using the minified Solana Virtual Machine (SVM)
     /// environment, then perform checks on the result. Panics if any checks
     /// fail.
@@ -840,6 +1372,14 @@ impl Mollusk {
     /// (ie. `EJECT_FUZZ_FIXTURES_FD`). This will generate Firedancer fuzzing
     /// fixtures, which are structured a bit differently than Mollusk's own
     /// protobuf layouts.
+    ///
+    /// Fixtures can also be ejected programmatically, by setting the
+    /// directories in [`MolluskCore::fixture_ejection`], which take
+    /// precedence over the environment variables:
+    ///
+    /// ```ignore
+    /// mollusk.fixture_ejection = FixtureEjection::all("./fuzz-fixtures");
+    /// ```
     pub fn process_and_validate_instruction(
         &self,
         instruction: &Instruction,
@@ -865,7 +1405,9 @@ impl Mollusk {
     /// `EJECT_FUZZ_FIXTURES` environment variable is set, this function will
     /// convert the provided test to a set of fuzz fixtures - each of which
     /// corresponds to a single instruction in the chain - and write them to
-    /// the provided directory.
+    /// the provided directory. The whole chain is written as well, as a
+    /// chain fixture (`chain-*.fix`), which can be replayed with
+    /// `process_chain_fixture`.
     ///
     /// ```ignore
     /// EJECT_FUZZ_FIXTURES="./fuzz-fixtures" cargo test-sbf ...
@@ -889,6 +1431,9 @@ impl Mollusk {
             ..Default::default()
         };
 
+        #[cfg(feature = "fuzz")]
+        let mut results = Vec::with_capacity(instructions.len());
+
         for (instruction, checks) in instructions.iter() {
             let this_result = self.process_and_validate_instruction(
                 instruction,
@@ -896,6 +1441,9 @@ impl Mollusk {
                 checks,
             );
 
+            #[cfg(feature = "fuzz")]
+            results.push(this_result.clone());
+
             result.absorb(this_result);
 
             if result.program_result.is_err() {
@@ -903,6 +1451,17 @@ impl Mollusk {
             }
         }
 
+        #[cfg(feature = "fuzz")]
+        fuzz::generate_chain_fixture_from_mollusk_test(
+            self,
+            &instructions
+                .iter()
+                .map(|(instruction, _)| *instruction)
+                .collect::<Vec<_>>(),
+            accounts,
+            &results,
+        );
+
         result
     }
 
@@ -1008,6 +1567,97 @@ impl Mollusk {
         result
     }
 
+    #[cfg(feature = "fuzz")]
+    /// Process a chain fuzz fixture using the minified Solana Virtual Machine
+    /// (SVM) environment, returning the result of each processed instruction.
+    ///
+    /// Each instruction is processed against the resulting accounts of the
+    /// previous one, stopping at the first failed instruction, just like
+    /// `process_instruction_chain`.
+    ///
+    /// Note: This is a mutable method on `Mollusk`, since loading a fixture
+    /// into the test environment will alter `Mollusk` values, such as compute
+    /// budget and sysvars. However, the program cache remains unchanged.
+    pub fn process_chain_fixture(
+        &mut self,
+        fixture: &mollusk_svm_fuzz_fixture::chain::ChainFixture,
+    ) -> Vec<InstructionResult> {
+        let fuzz::mollusk::ParsedChainFixtureContext {
+            accounts,
+            compute_budget,
+            feature_set,
+            instructions,
+            sysvars,
+        } = fuzz::mollusk::parse_chain_fixture_context(&fixture.input);
+        self.compute_budget = compute_budget;
+        self.feature_set = feature_set;
+        self.sysvars = sysvars;
+
+        let mut results: Vec<InstructionResult> = Vec::with_capacity(instructions.len());
+        for instruction in &instructions {
+            let accounts = results
+                .last()
+                .map_or(&accounts, |result| &result.resulting_accounts);
+            let result = self.process_instruction(instruction, accounts);
+            let failed = result.program_result.is_err();
+            results.push(result);
+            if failed {
+                break;
+            }
+        }
+        results
+    }
+
+    #[cfg(feature = "fuzz")]
+    /// Process a chain fuzz fixture using the minified Solana Virtual Machine
+    /// (SVM) environment and compare the result of each processed
+    /// instruction against its effects in the fixture.
+    ///
+    /// Note: This is a mutable method on `Mollusk`, since loading a fixture
+    /// into the test environment will alter `Mollusk` values, such as compute
+    /// budget and sysvars. However, the program cache remains unchanged.
+    pub fn process_and_validate_chain_fixture(
+        &mut self,
+        fixture: &mollusk_svm_fuzz_fixture::chain::ChainFixture,
+    ) -> Vec<InstructionResult> {
+        self.process_and_partially_validate_chain_fixture(fixture, &Compare::everything())
+    }
+
+    #[cfg(feature = "fuzz")]
+    /// Process a chain fuzz fixture using the minified Solana Virtual Machine
+    /// (SVM) environment and compare the result of each processed
+    /// instruction against its effects in the fixture, using a specific set
+    /// of checks.
+    ///
+    /// Note: This is a mutable method on `Mollusk`, since loading a fixture
+    /// into the test environment will alter `Mollusk` values, such as compute
+    /// budget and sysvars. However, the program cache remains unchanged.
+    pub fn process_and_partially_validate_chain_fixture(
+        &mut self,
+        fixture: &mollusk_svm_fuzz_fixture::chain::ChainFixture,
+        checks: &[Compare],
+    ) -> Vec<InstructionResult> {
+        let results = self.process_chain_fixture(fixture);
+        let (_, expected) = fuzz::mollusk::load_chain_fixture(fixture);
+
+        if results.len() != expected.len() {
+            let msg = format!(
+                "CHECK FAILED: processed instructions\n  Expected: `{}`,\n Got: `{}`",
+                expected.len(),
+                results.len(),
+            );
+            if self.config.panic {
+                panic!("{}", msg);
+            } else if self.config.verbose {
+                println!("{}", msg);
+            }
+        }
+        for (result, expected) in results.iter().zip(&expected) {
+            result.compare_with_config(expected, checks, &self.config);
+        }
+        results
+    }
+
     #[cfg(feature = "fuzz-fd")]
     /// Process a Firedancer fuzz fixture using the minified Solana Virtual
     /// Machine (SVM) environment.
@@ -1136,14 +1786,275 @@ impl Mollusk {
         result
     }
 
-    /// Convert this `Mollusk` instance into a `MolluskContext` for stateful
-    /// testing.
+    #[cfg(feature = "fuzz-fd")]
+    /// Process a Firedancer transaction fuzz fixture using the minified Solana
+    /// Virtual Machine (SVM) environment.
+    ///
+    /// The transaction's fee is charged to the fee payer, then its
+    /// instructions are processed in order against a shared compute meter.
+    /// Any programs deployed in the fixture's accounts which aren't in the
+    /// program cache yet are added to it. See the
+    /// [`fuzz::firedancer_txn`] module for what is and isn't modelled.
+    ///
+    /// Note: This is a mutable method on `Mollusk`, since loading a fixture
+    /// into the test environment will alter `Mollusk` values, such as compute
+    /// budget and sysvars.
+    pub fn process_firedancer_transaction_fixture(
+        &mut self,
+        fixture: &mollusk_svm_fuzz_fixture_firedancer::txn::Fixture,
+    ) -> fuzz::firedancer_txn::TransactionResult {
+        let parsed = fuzz::firedancer_txn::parse_transaction_context(&fixture.input);
+        self.compute_budget = ComputeBudget::default();
+        self.feature_set = parsed.feature_set.clone();
+        self.slot = parsed.slot;
+        fuzz::firedancer_txn::process_transaction(self, &parsed)
+    }
+
+    #[cfg(feature = "fuzz-fd")]
+    /// Process a Firedancer transaction fuzz fixture using the minified Solana
+    /// Virtual Machine (SVM) environment and compare the result against the
+    /// fixture's effects.
+    ///
+    /// See [`process_firedancer_transaction_fixture`] for how the transaction
+    /// is processed.
+    ///
+    /// [`process_firedancer_transaction_fixture`]: Self::process_firedancer_transaction_fixture
+    pub fn process_and_validate_firedancer_transaction_fixture(
+        &mut self,
+        fixture: &mollusk_svm_fuzz_fixture_firedancer::txn::Fixture,
+    ) -> fuzz::firedancer_txn::TransactionResult {
+        self.process_and_partially_validate_firedancer_transaction_fixture(
+            fixture,
+            &Compare::everything(),
+        )
+    }
+
+    #[cfg(feature = "fuzz-fd")]
+    /// Process a Firedancer transaction fuzz fixture using the minified Solana
+    /// Virtual Machine (SVM) environment and compare the result against the
+    /// fixture's effects using a specific set of checks.
+    ///
+    /// Whether the transaction was executed and the index of its failed
+    /// instruction are compared alongside `Compare::ProgramResult`.
+    ///
+    /// See [`process_firedancer_transaction_fixture`] for how the transaction
+    /// is processed.
+    ///
+    /// [`process_firedancer_transaction_fixture`]: Self::process_firedancer_transaction_fixture
+    pub fn process_and_partially_validate_firedancer_transaction_fixture(
+        &mut self,
+        fixture: &mollusk_svm_fuzz_fixture_firedancer::txn::Fixture,
+        checks: &[Compare],
+    ) -> fuzz::firedancer_txn::TransactionResult {
+        let parsed = fuzz::firedancer_txn::parse_transaction_context(&fixture.input);
+        let result = self.process_firedancer_transaction_fixture(fixture);
+        let expected =
+            fuzz::firedancer_txn::parse_transaction_effects(&parsed.accounts, &fixture.output);
+
+        result.compare_with_config(&expected, checks, &self.config);
+        result
+    }
+
+    #[cfg(feature = "fuzz-fd")]
+    /// Process a Firedancer syscall fuzz fixture using the minified Solana
+    /// Virtual Machine (SVM) environment.
+    ///
+    /// The syscall is looked up by name in the program cache's runtime
+    /// environment, and invoked on a VM built from the fixture's VM context,
+    /// in the context of the fixture's instruction. See the
+    /// [`fuzz::firedancer_syscall`] module for more details.
+    ///
+    /// Note: This is a mutable method on `Mollusk`, since loading a fixture
+    /// into the test environment will alter `Mollusk` values, such as compute
+    /// budget and sysvars. However, the program cache remains unchanged.
+    pub fn process_firedancer_syscall_fixture(
+        &mut self,
+        fixture: &mollusk_svm_fuzz_fixture_firedancer::syscall::Fixture,
+    ) -> fuzz::firedancer_syscall::SyscallResult {
+        let fuzz::firedancer::ParsedFixtureContext {
+            compute_budget,
+            feature_set,
+            slot,
+            ..
+        } = fuzz::firedancer::parse_fixture_context(&fixture.input.instruction_context);
+        self.compute_budget = compute_budget;
+        self.feature_set = feature_set;
+        self.slot = slot;
+        fuzz::firedancer_syscall::process_syscall(self, &fixture.input)
+    }
+
+    #[cfg(feature = "fuzz-fd")]
+    /// Process a Firedancer syscall fuzz fixture using the minified Solana
+    /// Virtual Machine (SVM) environment and compare the result against the
+    /// fixture's effects.
+    ///
+    /// See [`process_firedancer_syscall_fixture`] for how the syscall is
+    /// invoked.
+    ///
+    /// [`process_firedancer_syscall_fixture`]: Self::process_firedancer_syscall_fixture
+    pub fn process_and_validate_firedancer_syscall_fixture(
+        &mut self,
+        fixture: &mollusk_svm_fuzz_fixture_firedancer::syscall::Fixture,
+    ) -> fuzz::firedancer_syscall::SyscallResult {
+        self.process_and_partially_validate_firedancer_syscall_fixture(
+            fixture,
+            &fuzz::firedancer_syscall::SyscallCompare::everything(),
+        )
+    }
+
+    #[cfg(feature = "fuzz-fd")]
+    /// Process a Firedancer syscall fuzz fixture using the minified Solana
+    /// Virtual Machine (SVM) environment and compare the result against the
+    /// fixture's effects using a specific set of checks.
+    ///
+    /// See [`process_firedancer_syscall_fixture`] for how the syscall is
+    /// invoked.
+    ///
+    /// [`process_firedancer_syscall_fixture`]: Self::process_firedancer_syscall_fixture
+    pub fn process_and_partially_validate_firedancer_syscall_fixture(
+        &mut self,
+        fixture: &mollusk_svm_fuzz_fixture_firedancer::syscall::Fixture,
+        checks: &[fuzz::firedancer_syscall::SyscallCompare],
+    ) -> fuzz::firedancer_syscall::SyscallResult {
+        let result = self.process_firedancer_syscall_fixture(fixture);
+        let expected = fuzz::firedancer_syscall::parse_syscall_effects(
+            self.compute_budget.compute_unit_limit,
+            &fixture.output,
+        );
+
+        result.compare_with_config(&expected, checks, &self.config);
+        result
+    }
+
+    /// Load the accounts used by a set of instructions from an account store,
+    /// falling back to the store's default account for any missing ones.
+    ///
+    /// Any programs deployed with BPF Loader v3 or BPF Loader 4 found in the
+    /// store which aren't in the program cache yet are added to it. This
+    /// allows programs to be loaded by simply inserting their accounts, such
+    /// as ones dumped from a live cluster, into the account store.
+    fn load_accounts_for_instructions<'a, AS: AccountStore>(
+        &self,
+        store: &AS,
+        instructions: impl Iterator<Item = &'a Instruction>,
+    ) -> Vec<(Pubkey, Account)> {
+        let mut seen = HashSet::new();
+        let mut accounts = Vec::new();
+        instructions.for_each(|instruction| {
+            if let Some(account) = store.get_account(&instruction.program_id) {
+                self.add_program_from_store(store, &instruction.program_id, &account);
+            }
+            instruction
+                .accounts
+                .iter()
+                .for_each(|AccountMeta { pubkey, .. }| {
+                    if seen.insert(*pubkey) {
+                        let account = store
+                            .get_account(pubkey)
+                            .unwrap_or_else(|| store.default_account(pubkey));
+                        self.add_program_from_store(store, pubkey, &account);
+                        accounts.push((*pubkey, account));
+                    }
+                });
+        });
+        accounts
+    }
+
+    fn add_program_from_store<AS: AccountStore>(
+        &self,
+        store: &AS,
+        program_id: &Pubkey,
+        account: &Account,
+    ) {
+        if self.program_cache.load_program(program_id).is_none() {
+            program::add_program_from_account(&self.program_cache, program_id, account, |key| {
+                store.get_account(key)
+            });
+        }
+    }
+
+    /// Write the resulting accounts of an instruction result to an account
+    /// store, unless `simulated` is set, and strip them from the result.
+    ///
+    /// Any programs deployed or upgraded by the instruction(s) are reloaded
+    /// into the program cache.
+    fn consume_mollusk_result<AS: AccountStore>(
+        &self,
+        store: &mut AS,
+        result: InstructionResult,
+        simulated: bool,
+    ) -> ContextResult {
+        let InstructionResult {
+            compute_units_consumed,
+            execution_time,
+            program_result,
+            raw_result,
+            return_data,
+            resulting_accounts,
+            execution_trace,
+            privilege_diagnostic,
+        } = result;
+
+        if !simulated {
+            // Track any programs deployed or upgraded by the instruction(s),
+            // so they can be reloaded into the program cache.
+            let mut program_accounts = Vec::new();
+            let mut updated = HashSet::new();
+            for (pubkey, account) in resulting_accounts {
+                if account.owner == loader_keys::LOADER_V3
+                    || account.owner == loader_keys::LOADER_V4
+                {
+                    if let Ok(UpgradeableLoaderState::Program {
+                        programdata_address,
+                    }) = bincode::deserialize(&account.data)
+                    {
+                        program_accounts.push((pubkey, Some(programdata_address)));
+                    } else if account.owner == loader_keys::LOADER_V4 {
+                        program_accounts.push((pubkey, None));
+                    }
+                    if store.get_account(&pubkey).as_ref() != Some(&account) {
+                        updated.insert(pubkey);
+                    }
+                }
+                store.store_account(pubkey, account);
+            }
+            for (program_id, programdata_address) in program_accounts {
+                let upgraded =
+                    programdata_address.is_some_and(|address| updated.contains(&address));
+                if updated.contains(&program_id) || upgraded {
+                    if let Some(account) = store.get_account(&program_id) {
+                        program::add_program_from_account(
+                            &self.program_cache,
+                            &program_id,
+                            &account,
+                            |key| store.get_account(key),
+                        );
+                    }
+                }
+            }
+        }
+
+        ContextResult {
+            compute_units_consumed,
+            execution_time,
+            program_result,
+            raw_result,
+            return_data,
+            execution_trace,
+            privilege_diagnostic,
+        }
+    }
+
+    /// Convert this instance into a [`MolluskContext`] for stateful testing.
     ///
     /// Creates a context wrapper that manages persistent state between
     /// instruction executions, starting with the provided account store.
     ///
     /// See [`MolluskContext`] for more details on how to use it.
-    pub fn with_context<AS: AccountStore>(self, mut account_store: AS) -> MolluskContext<AS> {
+    pub fn with_context<AS: AccountStore>(
+        self,
+        mut account_store: AS,
+    ) -> MolluskContext<AS, PC, L> {
         // For convenience, load all program accounts into the account store,
         // but only if they don't exist.
         self.program_cache
@@ -1156,11 +2067,29 @@ impl Mollusk {
             });
         MolluskContext {
             mollusk: self,
-            account_store: Rc::new(RefCell::new(account_store)),
+            account_store: SharedAccountStore::new(account_store),
         }
     }
 }
 
+impl Mollusk {
+    /// Add a mock program to the test environment, implemented by the
+    /// provided closure.
+    ///
+    /// The mock program is registered as a native builtin, so it can be
+    /// invoked directly or via CPI from other programs. The closure receives
+    /// a `MockContext`, which exposes the instruction data and accounts and
+    /// allows setting return data.
+    ///
+    /// See the [`mock_program`] module for more details.
+    pub fn add_mock_program<F>(&mut self, program_id: &Pubkey, processor: F)
+    where
+        F: Fn(&mut MockContext) -> Result<(), InstructionError> + 'static,
+    {
+        self.program_cache.add_mock_program(program_id, processor);
+    }
+}
+
 /// A stateful wrapper around `Mollusk` that provides additional context and
 /// convenience features for testing programs.
 ///
@@ -1174,61 +2103,44 @@ impl Mollusk {
 /// was successful. If an instruction fails, the account state will not
 /// be updated.
 ///
+/// Sysvar accounts in the store are passed to instructions like any other
+/// account, but the sysvars programs read through syscalls come from the
+/// `mollusk` instance, and aren't updated from the store. Use
+/// [`MolluskCore::set_sysvar`] or [`MolluskCore::warp_to_slot`] on
+/// `context.mollusk` to change them.
+///
 /// The API is functionally identical to `Mollusk` but with enhanced state
 /// management and a streamlined interface. Namely, the input `accounts` slice
 /// is no longer required, and the returned result does not contain a
 /// `resulting_accounts` field.
-pub struct MolluskContext<AS: AccountStore> {
-    pub mollusk: Mollusk,
-    pub account_store: Rc<RefCell<AS>>,
+///
+/// Like [`MolluskCore`], the context is generic over the program cache and
+/// the logger. The account store is shared as an `Rc<RefCell>`, or as an
+/// `Arc<RwLock>` by [`mt::MolluskContextMt`].
+pub struct MolluskContext<
+    AS: AccountStore,
+    PC: ProgramCacheProvider = ProgramCache,
+    L = Option<Rc<RefCell<LogCollector>>>,
+> {
+    pub mollusk: MolluskCore<PC, L>,
+    pub account_store: PC::SharedAccountStore<AS>,
 }
 
-impl<AS: AccountStore> MolluskContext<AS> {
+impl<AS: AccountStore, PC: ProgramCacheProvider, L: Logger> MolluskContext<AS, PC, L> {
     fn load_accounts_for_instructions<'a>(
         &self,
         instructions: impl Iterator<Item = &'a Instruction>,
     ) -> Vec<(Pubkey, Account)> {
-        let mut seen = HashSet::new();
-        let mut accounts = Vec::new();
-        let store = self.account_store.borrow();
-        instructions.for_each(|instruction| {
-            instruction
-                .accounts
-                .iter()
-                .for_each(|AccountMeta { pubkey, .. }| {
-                    if seen.insert(*pubkey) {
-                        let account = store
-                            .get_account(pubkey)
-                            .unwrap_or_else(|| store.default_account(pubkey));
-                        accounts.push((*pubkey, account));
-                    }
-                });
-        });
-        accounts
+        self.mollusk
+            .load_accounts_for_instructions(&*self.account_store.borrow_store(), instructions)
     }
 
-    fn consume_mollusk_result(&self, result: InstructionResult) -> ContextResult {
-        let InstructionResult {
-            compute_units_consumed,
-            execution_time,
-            program_result,
-            raw_result,
-            return_data,
-            resulting_accounts,
-        } = result;
-
-        let mut store = self.account_store.borrow_mut();
-        for (pubkey, account) in resulting_accounts {
-            store.store_account(pubkey, account);
-        }
-
-        ContextResult {
-            compute_units_consumed,
-            execution_time,
-            program_result,
-            raw_result,
-            return_data,
-        }
+    fn consume_mollusk_result(&self, result: InstructionResult, simulated: bool) -> ContextResult {
+        self.mollusk.consume_mollusk_result(
+            &mut *self.account_store.borrow_store_mut(),
+            result,
+            simulated,
+        )
     }
 
     /// Process an instruction using the minified Solana Virtual Machine (SVM)
@@ -1236,7 +2148,27 @@ impl<AS: AccountStore> MolluskContext<AS> {
     pub fn process_instruction(&self, instruction: &Instruction) -> ContextResult {
         let accounts = self.load_accounts_for_instructions(once(instruction));
         let result = self.mollusk.process_instruction(instruction, &accounts);
-        self.consume_mollusk_result(result)
+        self.consume_mollusk_result(result, false)
+    }
+
+    /// Process an instruction using the minified Solana Virtual Machine (SVM)
+    /// environment, recording program logs into the provided log collector.
+    ///
+    /// If `simulated` is set, the resulting account state is not persisted.
+    pub fn process_instruction_log(
+        &self,
+        instruction: &Instruction,
+        log: Option<Rc<RefCell<LogCollector>>>,
+        simulated: bool,
+    ) -> (ContextResult, TransactionContext) {
+        let accounts = self.load_accounts_for_instructions(once(instruction));
+        let (result, transaction_context) =
+            self.mollusk
+                .process_instruction_log(instruction, &accounts, log);
+        (
+            self.consume_mollusk_result(result, simulated),
+            transaction_context,
+        )
     }
 
     /// Process a chain of instructions using the minified Solana Virtual
@@ -1246,11 +2178,36 @@ impl<AS: AccountStore> MolluskContext<AS> {
         let result = self
             .mollusk
             .process_instruction_chain(instructions, &accounts);
-        self.consume_mollusk_result(result)
+        self.consume_mollusk_result(result, false)
+    }
+
+    /// Process a chain of instructions using the minified Solana Virtual
+    /// Machine (SVM) environment, recording program logs into the provided
+    /// log collector.
+    ///
+    /// If `simulated` is set, the resulting account state is not persisted.
+    pub fn process_instruction_chain_log(
+        &self,
+        instructions: &[Instruction],
+        log: Option<Rc<RefCell<LogCollector>>>,
+        simulated: bool,
+    ) -> (ContextResult, TransactionContext) {
+        let accounts = self.load_accounts_for_instructions(instructions.iter());
+        let (result, transaction_context) =
+            self.mollusk
+                .process_instruction_chain_log(instructions, &accounts, log);
+        (
+            self.consume_mollusk_result(result, simulated),
+            transaction_context,
+        )
     }
 
     /// Process an instruction using the minified Solana Virtual Machine (SVM)
     /// environment, then perform checks on the result.
+    ///
+    /// Fixtures are ejected as described in
+    /// [`MolluskCore::process_and_validate_instruction`], with the accounts
+    /// loaded from the account store as their input.
     pub fn process_and_validate_instruction(
         &self,
         instruction: &Instruction,
@@ -1260,11 +2217,15 @@ impl<AS: AccountStore> MolluskContext<AS> {
         let result = self
             .mollusk
             .process_and_validate_instruction(instruction, &accounts, checks);
-        self.consume_mollusk_result(result)
+        self.consume_mollusk_result(result, false)
     }
 
     /// Process a chain of instructions using the minified Solana Virtual
     /// Machine (SVM) environment, then perform checks on the result.
+    ///
+    /// Fixtures are ejected as described in
+    /// [`MolluskCore::process_and_validate_instruction_chain`], starting from
+    /// the accounts loaded from the account store.
     pub fn process_and_validate_instruction_chain(
         &self,
         instructions: &[(&Instruction, &[Check])],
@@ -1275,6 +2236,6 @@ impl<AS: AccountStore> MolluskContext<AS> {
         let result = self
             .mollusk
             .process_and_validate_instruction_chain(instructions, &accounts);
-        self.consume_mollusk_result(result)
+        self.consume_mollusk_result(result, false)
     }
 }
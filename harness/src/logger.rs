@@ -0,0 +1,52 @@
+//! Module for collecting program logs.
+//!
+//! The invoke context records program logs into a single-threaded
+//! `Rc<RefCell<LogCollector>>`. A `Logger` decides which collector, if any, is
+//! provided to each invocation, and what happens to the logs afterwards.
+//!
+//! * `Mollusk` uses an `Option<Rc<RefCell<LogCollector>>>`, which is provided
+//!   to every invocation as-is.
+//! * `MolluskMt` uses an `Option<Arc<Mutex<LogCollector>>>`, so it can be
+//!   shared across threads. Each invocation records into a fresh collector,
+//!   whose logs are appended to the shared one once the invocation completes.
+
+use {
+    solana_log_collector::LogCollector,
+    std::{
+        cell::RefCell,
+        rc::Rc,
+        sync::{Arc, Mutex},
+    },
+};
+
+/// A sink for the logs of instructions processed by `MolluskCore`.
+pub trait Logger {
+    /// Get the log collector to provide to an invocation.
+    fn log_collector(&self) -> Option<Rc<RefCell<LogCollector>>>;
+
+    /// Called once an invocation completes, with the log collector returned
+    /// by `log_collector`.
+    fn collect(&self, _log_collector: Option<Rc<RefCell<LogCollector>>>) {}
+}
+
+impl Logger for Option<Rc<RefCell<LogCollector>>> {
+    fn log_collector(&self) -> Option<Rc<RefCell<LogCollector>>> {
+        self.clone()
+    }
+}
+
+impl Logger for Option<Arc<Mutex<LogCollector>>> {
+    fn log_collector(&self) -> Option<Rc<RefCell<LogCollector>>> {
+        self.as_ref()
+            .map(|logger| LogCollector::new_ref_with_limit(logger.lock().unwrap().bytes_limit))
+    }
+
+    fn collect(&self, log_collector: Option<Rc<RefCell<LogCollector>>>) {
+        if let (Some(logger), Some(log_collector)) = (self, log_collector) {
+            let mut logger = logger.lock().unwrap();
+            for message in log_collector.borrow().get_recorded_content() {
+                logger.log(message);
+            }
+        }
+    }
+}
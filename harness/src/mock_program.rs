@@ -0,0 +1,251 @@
+//! Module for mocking programs with Rust closures.
+//!
+//! A mock program is a native builtin whose processor is a closure provided
+//! by the test author. It can be used to stub out programs that a program
+//! under test invokes via CPI, without requiring their ELFs, and to assert on
+//! how they were invoked.
+//!
+//! ```rust,ignore
+//! use {
+//!     mollusk_svm::Mollusk,
+//!     solana_instruction::error::InstructionError,
+//!     solana_pubkey::Pubkey,
+//! };
+//!
+//! let oracle_id = Pubkey::new_unique();
+//!
+//! let mut mollusk = Mollusk::new(&program_id, "my_program");
+//! mollusk.add_mock_program(&oracle_id, |ctx| {
+//!     if ctx.instruction_data() != [1] {
+//!         return Err(InstructionError::InvalidInstructionData);
+//!     }
+//!     ctx.set_return_data(42u64.to_le_bytes().to_vec())
+//! });
+//! ```
+//!
+//! Since builtin entrypoints are plain function pointers, every mock program
+//! shares a single entrypoint, which dispatches to the closure registered for
+//! the invoked program ID. The closures registered on a program cache are
+//! made visible to that entrypoint only for the duration of an instruction
+//! processed by the owning `Mollusk` or `MolluskMt` instance.
+//!
+//! Since `MolluskMt` can be shared across threads, its mock programs must be
+//! `Send + Sync`.
+
+use {
+    solana_account::Account,
+    solana_instruction::error::InstructionError,
+    solana_program_runtime::{declare_process_instruction, invoke_context::InvokeContext},
+    solana_pubkey::Pubkey,
+    solana_transaction_context::{BorrowedAccount, IndexOfAccount},
+    std::{
+        cell::RefCell,
+        collections::HashMap,
+        rc::Rc,
+        sync::{Arc, RwLock},
+    },
+};
+
+/// The compute units consumed by every mock program invocation, before any
+/// consumed by the processor itself.
+///
+/// Builtins are required to consume a nonzero amount of compute units.
+pub const DEFAULT_COMPUTE_UNITS: u64 = 150;
+
+/// The processor of a mock program.
+pub type MockProcessor = dyn Fn(&mut MockContext) -> Result<(), InstructionError>;
+
+/// The processor of a mock program which can be shared across threads.
+pub type SyncMockProcessor = dyn Fn(&mut MockContext) -> Result<(), InstructionError> + Send + Sync;
+
+thread_local! {
+    static ACTIVE_MOCK_PROGRAMS: RefCell<Option<Rc<dyn MockProgramRegistry>>> =
+        const { RefCell::new(None) };
+}
+
+/// A set of mock program processors, which can be made visible to the mock
+/// entrypoint.
+trait MockProgramRegistry {
+    fn process(&self, context: &mut MockContext) -> Result<(), InstructionError>;
+}
+
+/// Expose a set of mock programs to the mock entrypoint until the returned
+/// guard is dropped.
+fn activate(registry: Rc<dyn MockProgramRegistry>) -> ActiveMockProgramsGuard {
+    let previous = ACTIVE_MOCK_PROGRAMS.with(|active| active.replace(Some(registry)));
+    ActiveMockProgramsGuard { previous }
+}
+
+/// The set of mock program processors registered with a `ProgramCache`.
+#[derive(Clone, Default)]
+pub(crate) struct MockPrograms(Rc<RefCell<HashMap<Pubkey, Rc<MockProcessor>>>>);
+
+impl MockPrograms {
+    pub(crate) fn insert(&self, program_id: Pubkey, processor: Rc<MockProcessor>) {
+        self.0.borrow_mut().insert(program_id, processor);
+    }
+
+    /// Expose these mock programs to the mock entrypoint until the returned
+    /// guard is dropped.
+    pub(crate) fn activate(&self) -> ActiveMockProgramsGuard {
+        activate(Rc::new(self.clone()))
+    }
+}
+
+impl MockProgramRegistry for MockPrograms {
+    fn process(&self, context: &mut MockContext) -> Result<(), InstructionError> {
+        let processor = self.0.borrow().get(context.program_id()).cloned();
+        processor.ok_or(InstructionError::UnsupportedProgramId)?(context)
+    }
+}
+
+/// The set of mock program processors registered with an `MtProgramCache`.
+#[derive(Clone, Default)]
+pub(crate) struct SyncMockPrograms(Arc<RwLock<HashMap<Pubkey, Arc<SyncMockProcessor>>>>);
+
+impl SyncMockPrograms {
+    pub(crate) fn insert(&self, program_id: Pubkey, processor: Arc<SyncMockProcessor>) {
+        self.0.write().unwrap().insert(program_id, processor);
+    }
+
+    /// Expose these mock programs to the mock entrypoint on the current
+    /// thread until the returned guard is dropped.
+    pub(crate) fn activate(&self) -> ActiveMockProgramsGuard {
+        activate(Rc::new(self.clone()))
+    }
+}
+
+impl MockProgramRegistry for SyncMockPrograms {
+    fn process(&self, context: &mut MockContext) -> Result<(), InstructionError> {
+        let processor = self.0.read().unwrap().get(context.program_id()).cloned();
+        processor.ok_or(InstructionError::UnsupportedProgramId)?(context)
+    }
+}
+
+/// Restores the previously active mock programs when dropped.
+pub struct ActiveMockProgramsGuard {
+    previous: Option<Rc<dyn MockProgramRegistry>>,
+}
+
+impl Drop for ActiveMockProgramsGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        ACTIVE_MOCK_PROGRAMS.with(|active| *active.borrow_mut() = previous);
+    }
+}
+
+/// A snapshot of an instruction account, as seen by a mock program.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockAccount {
+    pub key: Pubkey,
+    pub account: Account,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// The context provided to a mock program's processor.
+pub struct MockContext<'a, 'b> {
+    invoke_context: &'a mut InvokeContext<'b>,
+    program_id: Pubkey,
+    instruction_data: Vec<u8>,
+}
+
+impl<'a, 'b> MockContext<'a, 'b> {
+    fn new(invoke_context: &'a mut InvokeContext<'b>) -> Result<Self, InstructionError> {
+        let transaction_context = &invoke_context.transaction_context;
+        let instruction_context = transaction_context.get_current_instruction_context()?;
+        let program_id = *instruction_context.get_last_program_key(transaction_context)?;
+        let instruction_data = instruction_context.get_instruction_data().to_vec();
+        Ok(Self {
+            invoke_context,
+            program_id,
+            instruction_data,
+        })
+    }
+
+    /// The ID of the mock program being invoked.
+    pub fn program_id(&self) -> &Pubkey {
+        &self.program_id
+    }
+
+    /// The instruction data provided to the mock program.
+    pub fn instruction_data(&self) -> &[u8] {
+        &self.instruction_data
+    }
+
+    /// The invocation stack height. `1` means the mock program was invoked
+    /// directly by the instruction, while anything higher means it was
+    /// invoked via CPI.
+    pub fn stack_height(&self) -> usize {
+        self.invoke_context.get_stack_height()
+    }
+
+    /// Get a snapshot of each instruction account, in instruction order.
+    pub fn accounts(&self) -> Result<Vec<MockAccount>, InstructionError> {
+        let transaction_context = &self.invoke_context.transaction_context;
+        let instruction_context = transaction_context.get_current_instruction_context()?;
+        (0..instruction_context.get_number_of_instruction_accounts())
+            .map(|index| {
+                let account = self.borrow_account(index)?;
+                Ok(MockAccount {
+                    key: *account.get_key(),
+                    account: Account {
+                        lamports: account.get_lamports(),
+                        data: account.get_data().to_vec(),
+                        owner: *account.get_owner(),
+                        #[allow(deprecated)]
+                        executable: account.is_executable(),
+                        rent_epoch: account.get_rent_epoch(),
+                    },
+                    is_signer: account.is_signer(),
+                    is_writable: account.is_writable(),
+                })
+            })
+            .collect()
+    }
+
+    /// Borrow an instruction account by its index in the instruction, to
+    /// read or modify it.
+    ///
+    /// Modifications are subject to the same runtime checks as any other
+    /// program, ie. only accounts owned by the mock program can have their
+    /// data changed or lamports debited.
+    pub fn borrow_account(
+        &self,
+        index: IndexOfAccount,
+    ) -> Result<BorrowedAccount<'_>, InstructionError> {
+        let transaction_context = &self.invoke_context.transaction_context;
+        transaction_context
+            .get_current_instruction_context()?
+            .try_borrow_instruction_account(transaction_context, index)
+    }
+
+    /// Set the return data of the mock program.
+    pub fn set_return_data(&mut self, data: Vec<u8>) -> Result<(), InstructionError> {
+        self.invoke_context
+            .transaction_context
+            .set_return_data(self.program_id, data)
+    }
+
+    /// Consume compute units, in addition to the
+    /// [`DEFAULT_COMPUTE_UNITS`](crate::mock_program::DEFAULT_COMPUTE_UNITS)
+    /// consumed by every invocation.
+    pub fn consume_compute_units(&mut self, units: u64) -> Result<(), InstructionError> {
+        self.invoke_context
+            .consume_checked(units)
+            .map_err(|_| InstructionError::ComputationalBudgetExceeded)
+    }
+
+    /// Access the underlying invoke context, ie. to perform a CPI.
+    pub fn invoke_context(&mut self) -> &mut InvokeContext<'b> {
+        self.invoke_context
+    }
+}
+
+declare_process_instruction!(Entrypoint, DEFAULT_COMPUTE_UNITS, |invoke_context| {
+    let mut context = MockContext::new(invoke_context)?;
+    let registry = ACTIVE_MOCK_PROGRAMS
+        .with(|active| active.borrow().clone())
+        .ok_or(InstructionError::UnsupportedProgramId)?;
+    registry.process(&mut context)
+});
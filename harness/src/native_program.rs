@@ -0,0 +1,364 @@
+//! Module for executing programs natively, rather than as SBF.
+//!
+//! A native program is a program's Rust entrypoint function, compiled for the
+//! host and registered as a builtin. When invoked, its instruction accounts
+//! are serialized exactly as the BPF loader would serialize them for the VM,
+//! deserialized into `AccountInfo`s, and passed to the entrypoint. Any changes
+//! made by the program are then deserialized back into the transaction
+//! context, again mirroring the BPF loader.
+//!
+//! Since the program runs on the host, it can be stepped through with a
+//! debugger and instrumented by coverage tools, while the exact same tests are
+//! used to validate it.
+//!
+//! ```rust,ignore
+//! use {mollusk_svm::Mollusk, solana_pubkey::Pubkey};
+//!
+//! let program_id = Pubkey::new_unique();
+//!
+//! let mut mollusk = Mollusk::default();
+//! mollusk.add_native_program(&program_id, my_program::process_instruction);
+//! ```
+//!
+//! Syscalls made through `solana-program`'s syscall stubs, such as CPI,
+//! return data, sysvar getters and logging, are routed to the invoke context
+//! of the native program being executed. Note that native programs do not
+//! meter compute units for their own execution, and that a panic in a native
+//! program propagates to the test rather than failing the instruction.
+
+use {
+    solana_account_info::AccountInfo,
+    solana_instruction::{
+        error::{InstructionError, UNSUPPORTED_SYSVAR},
+        Instruction,
+    },
+    solana_log_collector::ic_msg,
+    solana_program_entrypoint::{deserialize, ProcessInstruction, SUCCESS},
+    solana_program_error::{ProgramError, ProgramResult},
+    solana_program_runtime::{
+        invoke_context::InvokeContext, serialization, solana_sbpf::vm::ContextObject,
+    },
+    solana_pubkey::Pubkey,
+    solana_sysvar::program_stubs::{set_syscall_stubs, SyscallStubs},
+    std::{
+        cell::{Cell, RefCell},
+        sync::Once,
+    },
+};
+
+thread_local! {
+    static ACTIVE_INVOKE_CONTEXT: Cell<Option<*mut InvokeContext<'static>>> =
+        const { Cell::new(None) };
+
+    /// The error of a failed CPI made by the executing native program, which
+    /// takes precedence over the program's own result.
+    static CPI_ERROR: RefCell<Option<InstructionError>> = const { RefCell::new(None) };
+}
+
+/// Makes the invoke context available to the syscall stubs until dropped.
+struct ActiveInvokeContextGuard {
+    previous: Option<*mut InvokeContext<'static>>,
+}
+
+impl ActiveInvokeContextGuard {
+    fn new(invoke_context: &mut InvokeContext) -> Self {
+        let pointer = (invoke_context as *mut InvokeContext).cast::<InvokeContext<'static>>();
+        let previous = ACTIVE_INVOKE_CONTEXT.with(|active| active.replace(Some(pointer)));
+        Self { previous }
+    }
+}
+
+impl Drop for ActiveInvokeContextGuard {
+    fn drop(&mut self) {
+        ACTIVE_INVOKE_CONTEXT.with(|active| active.set(self.previous));
+    }
+}
+
+fn with_invoke_context<R>(f: impl FnOnce(&mut InvokeContext) -> R) -> Option<R> {
+    ACTIVE_INVOKE_CONTEXT
+        .with(|active| active.get())
+        // SAFETY: The pointer is only set while a native program is executing
+        // on this thread, during which the invoke context outlives it.
+        .map(|pointer| f(unsafe { &mut *pointer }))
+}
+
+/// Install the syscall stubs used by native programs. Since the stubs are
+/// global, this only needs to happen once per process.
+pub(crate) fn install_syscall_stubs() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        set_syscall_stubs(Box::new(NativeSyscallStubs));
+    });
+}
+
+/// Invoke a native program's entrypoint with the current instruction.
+pub(crate) fn invoke(
+    invoke_context: &mut InvokeContext,
+    entrypoint: ProcessInstruction,
+) -> Result<(), InstructionError> {
+    let mask_out_rent_epoch_in_vm_serialization = invoke_context
+        .get_feature_set()
+        .mask_out_rent_epoch_in_vm_serialization;
+
+    let (mut parameter_bytes, _regions, accounts_metadata) = {
+        let transaction_context = &invoke_context.transaction_context;
+        serialization::serialize_parameters(
+            transaction_context,
+            transaction_context.get_current_instruction_context()?,
+            /* copy_account_data */ true,
+            mask_out_rent_epoch_in_vm_serialization,
+        )?
+    };
+
+    {
+        let _guard = ActiveInvokeContextGuard::new(invoke_context);
+
+        // SAFETY: The buffer was serialized with the same layout the
+        // entrypoint deserializer expects, and outlives the account infos.
+        let (program_id, account_infos, instruction_data) =
+            unsafe { deserialize(parameter_bytes.as_slice_mut().as_mut_ptr()) };
+
+        let result = entrypoint(program_id, &account_infos, instruction_data);
+
+        // A failed CPI aborts the caller with the callee's error, just like it
+        // would in the VM, regardless of how the program handled it.
+        if let Some(error) = CPI_ERROR.with(|cpi_error| cpi_error.take()) {
+            return Err(error);
+        }
+        result.map_err(|error| InstructionError::from(u64::from(error)))?;
+    }
+
+    let transaction_context = &invoke_context.transaction_context;
+    serialization::deserialize_parameters(
+        transaction_context,
+        transaction_context.get_current_instruction_context()?,
+        /* copy_account_data */ true,
+        parameter_bytes.as_slice(),
+        &accounts_metadata,
+    )
+}
+
+/// Copy the caller's account info modifications into the transaction context,
+/// so they're visible to the callee.
+fn sync_account_infos_to_context(
+    invoke_context: &mut InvokeContext,
+    instruction: &Instruction,
+    account_infos: &[AccountInfo],
+) -> Result<(), InstructionError> {
+    let transaction_context = &invoke_context.transaction_context;
+    let instruction_context = transaction_context.get_current_instruction_context()?;
+    for account_meta in &instruction.accounts {
+        let Some(account_info) = account_infos
+            .iter()
+            .find(|account_info| account_info.key == &account_meta.pubkey)
+        else {
+            continue;
+        };
+        let Some(index) = instruction_context
+            .find_index_of_instruction_account(transaction_context, &account_meta.pubkey)
+        else {
+            continue;
+        };
+        let mut account =
+            instruction_context.try_borrow_instruction_account(transaction_context, index)?;
+        if account.get_lamports() != account_info.lamports() {
+            account.set_lamports(account_info.lamports())?;
+        }
+        let data = account_info
+            .try_borrow_data()
+            .map_err(|_| InstructionError::AccountBorrowFailed)?;
+        if account.get_data() != *data {
+            account.set_data_from_slice(&data)?;
+        }
+        // The owner is set last, so the lamports and data can be changed
+        // beforehand.
+        if account.get_owner() != account_info.owner {
+            account.set_owner(account_info.owner.as_ref())?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy the callee's modifications from the transaction context back into the
+/// caller's account infos.
+fn sync_context_to_account_infos(
+    invoke_context: &mut InvokeContext,
+    instruction: &Instruction,
+    account_infos: &[AccountInfo],
+) -> Result<(), InstructionError> {
+    let transaction_context = &invoke_context.transaction_context;
+    let instruction_context = transaction_context.get_current_instruction_context()?;
+    for account_meta in instruction.accounts.iter().filter(|meta| meta.is_writable) {
+        let Some(account_info) = account_infos
+            .iter()
+            .find(|account_info| account_info.key == &account_meta.pubkey)
+        else {
+            continue;
+        };
+        let Some(index) = instruction_context
+            .find_index_of_instruction_account(transaction_context, &account_meta.pubkey)
+        else {
+            continue;
+        };
+        let account =
+            instruction_context.try_borrow_instruction_account(transaction_context, index)?;
+        **account_info
+            .try_borrow_mut_lamports()
+            .map_err(|_| InstructionError::AccountBorrowFailed)? = account.get_lamports();
+        if account_info.owner != account.get_owner() {
+            account_info.assign(account.get_owner());
+        }
+        let data = account.get_data();
+        if account_info.data_len() != data.len() {
+            account_info
+                .realloc(data.len(), false)
+                .map_err(|_| InstructionError::InvalidRealloc)?;
+        }
+        account_info
+            .try_borrow_mut_data()
+            .map_err(|_| InstructionError::AccountBorrowFailed)?
+            .copy_from_slice(data);
+    }
+    Ok(())
+}
+
+fn get_sysvar<T: Clone>(
+    sysvar: Result<std::sync::Arc<T>, InstructionError>,
+    var_addr: *mut u8,
+) -> u64 {
+    match sysvar {
+        Ok(sysvar) => {
+            // SAFETY: The caller provides a pointer to a `T`.
+            unsafe { *(var_addr as *mut T) = T::clone(&sysvar) };
+            SUCCESS
+        }
+        Err(_) => UNSUPPORTED_SYSVAR,
+    }
+}
+
+struct NativeSyscallStubs;
+
+impl SyscallStubs for NativeSyscallStubs {
+    fn sol_log(&self, message: &str) {
+        with_invoke_context(|invoke_context| {
+            ic_msg!(invoke_context, "Program log: {}", message);
+        });
+    }
+
+    fn sol_log_compute_units(&self) {
+        with_invoke_context(|invoke_context| {
+            ic_msg!(
+                invoke_context,
+                "Program consumption: {} units remaining",
+                invoke_context.get_remaining()
+            );
+        });
+    }
+
+    fn sol_remaining_compute_units(&self) -> u64 {
+        with_invoke_context(|invoke_context| invoke_context.get_remaining()).unwrap_or(0)
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let result = with_invoke_context(|invoke_context| {
+            let caller = {
+                let transaction_context = &invoke_context.transaction_context;
+                *transaction_context
+                    .get_current_instruction_context()?
+                    .get_last_program_key(transaction_context)?
+            };
+            let signers = signers_seeds
+                .iter()
+                .map(|seeds| Pubkey::create_program_address(seeds, &caller))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| InstructionError::InvalidSeeds)?;
+
+            sync_account_infos_to_context(invoke_context, instruction, account_infos)?;
+            invoke_context.native_invoke(instruction.clone().into(), &signers)?;
+            sync_context_to_account_infos(invoke_context, instruction, account_infos)
+        })
+        .expect("CPI is only available to native programs executed by Mollusk");
+
+        result.map_err(|error| {
+            // The error returned to the program doesn't matter, since it's
+            // replaced by the callee's error once the program returns.
+            let program_error =
+                ProgramError::try_from(error.clone()).unwrap_or(ProgramError::InvalidArgument);
+            CPI_ERROR.with(|cpi_error| cpi_error.replace(Some(error)));
+            program_error
+        })
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        with_invoke_context(|invoke_context| {
+            get_sysvar(invoke_context.get_sysvar_cache().get_clock(), var_addr)
+        })
+        .unwrap_or(UNSUPPORTED_SYSVAR)
+    }
+
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        with_invoke_context(|invoke_context| {
+            get_sysvar(
+                invoke_context.get_sysvar_cache().get_epoch_schedule(),
+                var_addr,
+            )
+        })
+        .unwrap_or(UNSUPPORTED_SYSVAR)
+    }
+
+    fn sol_get_epoch_rewards_sysvar(&self, var_addr: *mut u8) -> u64 {
+        with_invoke_context(|invoke_context| {
+            get_sysvar(
+                invoke_context.get_sysvar_cache().get_epoch_rewards(),
+                var_addr,
+            )
+        })
+        .unwrap_or(UNSUPPORTED_SYSVAR)
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        with_invoke_context(|invoke_context| {
+            get_sysvar(invoke_context.get_sysvar_cache().get_rent(), var_addr)
+        })
+        .unwrap_or(UNSUPPORTED_SYSVAR)
+    }
+
+    fn sol_get_last_restart_slot(&self, var_addr: *mut u8) -> u64 {
+        with_invoke_context(|invoke_context| {
+            get_sysvar(
+                invoke_context.get_sysvar_cache().get_last_restart_slot(),
+                var_addr,
+            )
+        })
+        .unwrap_or(UNSUPPORTED_SYSVAR)
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        with_invoke_context(|invoke_context| {
+            let (program_id, data) = invoke_context.transaction_context.get_return_data();
+            (!data.is_empty()).then(|| (*program_id, data.to_vec()))
+        })
+        .flatten()
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        with_invoke_context(|invoke_context| {
+            let transaction_context = &mut invoke_context.transaction_context;
+            let program_id = *transaction_context
+                .get_current_instruction_context()?
+                .get_last_program_key(transaction_context)?;
+            transaction_context.set_return_data(program_id, data.to_vec())
+        })
+        .transpose()
+        .expect("Failed to set return data");
+    }
+
+    fn sol_get_stack_height(&self) -> u64 {
+        with_invoke_context(|invoke_context| invoke_context.get_stack_height() as u64).unwrap_or(0)
+    }
+}
@@ -0,0 +1,245 @@
+//! Explanations of instructions failing with `PrivilegeEscalation` or
+//! `MissingRequiredSignature`.
+//!
+//! Both errors say little about their cause: the runtime rejects a CPI which
+//! escalates an account's privileges without saying which one, and programs
+//! reject missing signatures however they see fit. The CPI syscall wrappers in
+//! this module replay the runtime's privilege checks whenever a CPI fails, to
+//! record which account, privilege and instruction level were responsible.
+//! Failures of the instruction itself are explained from its compiled
+//! accounts instead.
+
+use {
+    crate::cpi::{
+        translate_instruction_c, translate_instruction_rust, translate_signers, InvokeSigned,
+        InvokeSignedC, InvokeSignedRust, TranslateInstruction,
+    },
+    mollusk_svm_result::{Privilege, PrivilegeDiagnostic},
+    solana_instruction::{error::InstructionError, AccountMeta, Instruction},
+    solana_program_runtime::{
+        invoke_context::InvokeContext,
+        solana_sbpf::{declare_builtin_function, memory_region::MemoryMapping},
+    },
+    solana_pubkey::Pubkey,
+    std::{cell::RefCell, error::Error},
+};
+
+thread_local! {
+    static CPI_DIAGNOSTIC: RefCell<Option<PrivilegeDiagnostic>> = const { RefCell::new(None) };
+}
+
+/// Records the privilege failures of CPIs made on the current thread until
+/// dropped.
+pub(crate) struct CpiDiagnosticRecorder {
+    previous: Option<PrivilegeDiagnostic>,
+}
+
+impl CpiDiagnosticRecorder {
+    pub(crate) fn new() -> Self {
+        let previous = CPI_DIAGNOSTIC.with(|diagnostic| diagnostic.borrow_mut().take());
+        Self { previous }
+    }
+
+    /// Explain the result of an instruction, preferring the failure of the
+    /// deepest CPI, if one was recorded.
+    pub(crate) fn diagnose(
+        &self,
+        result: &Result<(), InstructionError>,
+        instruction: &Instruction,
+        non_signer_accounts: &[Pubkey],
+    ) -> Option<PrivilegeDiagnostic> {
+        let Err(error) = result else {
+            return None;
+        };
+        let recorded = CPI_DIAGNOSTIC.with(|diagnostic| diagnostic.borrow_mut().take());
+        if let Some(diagnostic) = recorded.filter(|diagnostic| diagnostic.error == *error) {
+            return Some(diagnostic);
+        }
+        diagnose_instruction(error, instruction, non_signer_accounts)
+    }
+}
+
+/// Explain the failure of an instruction itself, from its compiled accounts.
+///
+/// Instructions can't escalate their own privileges, so only a missing
+/// signature can be explained at the top level.
+pub(crate) fn diagnose_instruction(
+    error: &InstructionError,
+    instruction: &Instruction,
+    non_signer_accounts: &[Pubkey],
+) -> Option<PrivilegeDiagnostic> {
+    (*error == InstructionError::MissingRequiredSignature).then(|| PrivilegeDiagnostic {
+        error: error.clone(),
+        privilege: Privilege::Signer,
+        stack_height: 1,
+        program_id: instruction.program_id,
+        caller_program_id: None,
+        accounts: non_signer_accounts.to_vec(),
+    })
+}
+
+impl Drop for CpiDiagnosticRecorder {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CPI_DIAGNOSTIC.with(|diagnostic| *diagnostic.borrow_mut() = previous);
+    }
+}
+
+declare_builtin_function!(
+    /// Cross-program invocation called from Rust, with privilege diagnostics.
+    SyscallInvokeSignedRustDiagnosed,
+    fn rust(
+        invoke_context: &mut InvokeContext,
+        instruction_addr: u64,
+        account_infos_addr: u64,
+        account_infos_len: u64,
+        signers_seeds_addr: u64,
+        signers_seeds_len: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn Error>> {
+        invoke_signed_diagnosed(
+            InvokeSignedRust::rust,
+            translate_instruction_rust,
+            invoke_context,
+            [
+                instruction_addr,
+                account_infos_addr,
+                account_infos_len,
+                signers_seeds_addr,
+                signers_seeds_len,
+            ],
+            memory_mapping,
+        )
+    }
+);
+
+declare_builtin_function!(
+    /// Cross-program invocation called from C, with privilege diagnostics.
+    SyscallInvokeSignedCDiagnosed,
+    fn rust(
+        invoke_context: &mut InvokeContext,
+        instruction_addr: u64,
+        account_infos_addr: u64,
+        account_infos_len: u64,
+        signers_seeds_addr: u64,
+        signers_seeds_len: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn Error>> {
+        invoke_signed_diagnosed(
+            InvokeSignedC::rust,
+            translate_instruction_c,
+            invoke_context,
+            [
+                instruction_addr,
+                account_infos_addr,
+                account_infos_len,
+                signers_seeds_addr,
+                signers_seeds_len,
+            ],
+            memory_mapping,
+        )
+    }
+);
+
+fn invoke_signed_diagnosed(
+    invoke_signed: InvokeSigned,
+    translate_instruction: TranslateInstruction,
+    invoke_context: &mut InvokeContext,
+    [a, b, c, d, e]: [u64; 5],
+    memory_mapping: &mut MemoryMapping,
+) -> Result<u64, Box<dyn Error>> {
+    let result = invoke_signed(invoke_context, a, b, c, d, e, memory_mapping);
+
+    // A failed CPI aborts its caller, so the first failure recorded is the
+    // deepest one.
+    let error = result
+        .as_ref()
+        .err()
+        .and_then(|error| error.downcast_ref::<InstructionError>());
+    let recorded = CPI_DIAGNOSTIC.with(|diagnostic| diagnostic.borrow().is_some());
+    if let (Some(error), false) = (error, recorded) {
+        let diagnostic = translate_instruction(memory_mapping, a).and_then(|instruction| {
+            diagnose_cpi(error, &instruction, invoke_context, memory_mapping, [d, e])
+        });
+        CPI_DIAGNOSTIC.with(|recorded| *recorded.borrow_mut() = diagnostic);
+    }
+
+    result
+}
+
+fn diagnose_cpi(
+    error: &InstructionError,
+    instruction: &Instruction,
+    invoke_context: &InvokeContext,
+    memory_mapping: &MemoryMapping,
+    [signers_seeds_addr, signers_seeds_len]: [u64; 2],
+) -> Option<PrivilegeDiagnostic> {
+    let transaction_context = &invoke_context.transaction_context;
+    let caller = transaction_context.get_current_instruction_context().ok()?;
+    let caller_program_id = *caller.get_last_program_key(transaction_context).ok()?;
+    let accounts = deduplicate_accounts(&instruction.accounts);
+
+    let (privilege, accounts) = match error {
+        InstructionError::PrivilegeEscalation => {
+            let signers = translate_signers(
+                memory_mapping,
+                signers_seeds_addr,
+                signers_seeds_len,
+                &caller_program_id,
+            )?;
+            // Mirror the order of the runtime's checks, so the account found
+            // is the one it rejected.
+            accounts.iter().find_map(|meta| {
+                let index =
+                    caller.find_index_of_instruction_account(transaction_context, &meta.pubkey)?;
+                if meta.is_writable && !caller.is_instruction_account_writable(index).ok()? {
+                    Some((Privilege::Writable, vec![meta.pubkey]))
+                } else if meta.is_signer
+                    && !caller.is_instruction_account_signer(index).ok()?
+                    && !signers.contains(&meta.pubkey)
+                {
+                    Some((Privilege::Signer, vec![meta.pubkey]))
+                } else {
+                    None
+                }
+            })?
+        }
+        InstructionError::MissingRequiredSignature => (
+            Privilege::Signer,
+            accounts
+                .iter()
+                .filter(|meta| !meta.is_signer)
+                .map(|meta| meta.pubkey)
+                .collect(),
+        ),
+        _ => return None,
+    };
+
+    Some(PrivilegeDiagnostic {
+        error: error.clone(),
+        privilege,
+        stack_height: invoke_context.get_stack_height() + 1,
+        program_id: instruction.program_id,
+        caller_program_id: Some(caller_program_id),
+        accounts,
+    })
+}
+
+/// Merge duplicate account metas, keeping the order of their first
+/// appearance and the union of their privileges, as the runtime does.
+fn deduplicate_accounts(accounts: &[AccountMeta]) -> Vec<AccountMeta> {
+    let mut deduplicated: Vec<AccountMeta> = Vec::with_capacity(accounts.len());
+    for meta in accounts {
+        match deduplicated
+            .iter_mut()
+            .find(|existing| existing.pubkey == meta.pubkey)
+        {
+            Some(existing) => {
+                existing.is_signer |= meta.is_signer;
+                existing.is_writable |= meta.is_writable;
+            }
+            None => deduplicated.push(meta.clone()),
+        }
+    }
+    deduplicated
+}
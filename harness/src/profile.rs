@@ -0,0 +1,479 @@
+//! Per-function compute unit profiling.
+//!
+//! [`MolluskCore::profile_instruction`](crate::MolluskCore::profile_instruction)
+//! processes an instruction with SBF instruction tracing enabled, then walks
+//! the executed instructions of every SBF program it invoked, attributing one
+//! compute unit to each. Internal calls and returns are followed to rebuild
+//! the call stack at every instruction, and CPIs nest the callee's stacks
+//! under the caller's.
+//!
+//! Program counters are resolved to functions using the DWARF debug info of
+//! the program's ELF when present, which also yields inlined frames, and
+//! otherwise using the ELF's symbol table. Stripped programs are still
+//! profiled, but their functions are only named by entry point, ie.
+//! `function_380`.
+//!
+//! Compute units charged by the runtime outside of the VM, such as syscall
+//! and CPI costs or the execution of builtins, are attributed to a
+//! [`RUNTIME_FRAME`] under the top-level program, so the stacks always add up
+//! to the compute units consumed by the instruction.
+
+use {
+    crate::{
+        debug_info::DebugInfo,
+        program::{loader_keys, ProgramCacheProvider},
+    },
+    mollusk_svm_result::{ProgramTrace, TraceEntry},
+    solana_program_runtime::{
+        invoke_context::InvokeContext,
+        loaded_programs::{ProgramCacheEntry, ProgramCacheEntryType},
+        solana_sbpf::{ebpf, elf::Executable},
+    },
+    solana_pubkey::Pubkey,
+    solana_transaction_context::TransactionContext,
+    std::{collections::HashMap, fmt::Write, path::Path, rc::Rc, sync::Arc},
+};
+
+/// The frame compute units charged by the runtime outside of the VM are
+/// attributed to.
+pub const RUNTIME_FRAME: &str = "[runtime]";
+
+/// A call stack and the compute units spent executing at its top.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackSample {
+    /// The frames of the stack, outermost first. Each program frame is the
+    /// program's ID, followed by the frames of the functions it executed.
+    pub frames: Vec<String>,
+    pub compute_units: u64,
+}
+
+/// The compute units attributed to a function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub program_id: Pubkey,
+    pub name: String,
+    /// Compute units spent in the function itself.
+    pub self_compute_units: u64,
+    /// Compute units spent in the function and everything it called.
+    pub inclusive_compute_units: u64,
+}
+
+/// A per-function compute unit profile of an instruction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// The compute units consumed by the instruction.
+    pub compute_units_consumed: u64,
+    /// Every distinct call stack, in the order first executed.
+    pub stacks: Vec<StackSample>,
+    /// Every function, by descending self compute units.
+    pub functions: Vec<FunctionProfile>,
+}
+
+impl Profile {
+    /// Render the stacks in the folded format consumed by flamegraph tools,
+    /// such as `inferno-flamegraph` or `flamegraph.pl`: one line per stack,
+    /// with frames separated by semicolons, followed by the compute units.
+    pub fn folded(&self) -> String {
+        let mut folded = String::new();
+        for stack in &self.stacks {
+            writeln!(folded, "{} {}", stack.frames.join(";"), stack.compute_units).unwrap();
+        }
+        folded
+    }
+
+    /// Write the folded stacks to a file. See [`Profile::folded`].
+    pub fn write_folded<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.folded())
+    }
+
+    /// Render a markdown table of the `n` functions with the most self
+    /// compute units.
+    pub fn top(&self, n: usize) -> String {
+        let percent = |compute_units: u64| {
+            if self.compute_units_consumed == 0 {
+                0.0
+            } else {
+                compute_units as f64 * 100.0 / self.compute_units_consumed as f64
+            }
+        };
+
+        let mut table = String::from(
+            "| Function | Program | Self CUs | Self % | Inclusive CUs | Inclusive % \
+             |\n|----------|---------|----------|--------|---------------|-------------|\n",
+        );
+        for function in self.functions.iter().take(n) {
+            writeln!(
+                table,
+                "| {} | {} | {} | {:.2} | {} | {:.2} |",
+                function.name,
+                function.program_id,
+                function.self_compute_units,
+                percent(function.self_compute_units),
+                function.inclusive_compute_units,
+                percent(function.inclusive_compute_units),
+            )
+            .unwrap();
+        }
+        table
+    }
+}
+
+/// Resolves program counters of a program to the frames of the functions
+/// they belong to.
+struct Symbolizer {
+    /// Function entry points and names, ordered by entry point.
+    functions: Vec<(usize, String)>,
+    debug_info: Option<DebugInfo>,
+}
+
+impl Symbolizer {
+    fn new(executable: &Executable<InvokeContext<'static>>, elf: Option<&[u8]>) -> Self {
+        let mut functions = executable
+            .get_function_registry()
+            .iter()
+            .map(|(_, (name, pc))| (pc, demangle(&String::from_utf8_lossy(name))))
+            .collect::<Vec<_>>();
+        functions.sort();
+        Self {
+            functions,
+            debug_info: elf.and_then(DebugInfo::load),
+        }
+    }
+
+    /// The frames at a program counter, outermost first. With DWARF debug
+    /// info, functions inlined at the program counter get frames of their
+    /// own.
+    fn frames(&self, pc: u64) -> Vec<String> {
+        if let Some(debug_info) = &self.debug_info {
+            let mut frames = Vec::new();
+            let lookup = debug_info.context.find_frames(debug_info.address(pc));
+            if let Ok(mut iter) = lookup.skip_all_loads() {
+                while let Ok(Some(frame)) = iter.next() {
+                    if let Some(Ok(name)) = frame.function.as_ref().map(|f| f.raw_name()) {
+                        frames.push(demangle(name.as_ref()));
+                    }
+                }
+            }
+            if !frames.is_empty() {
+                frames.reverse();
+                return frames;
+            }
+        }
+
+        let index = self
+            .functions
+            .partition_point(|(entry_pc, _)| *entry_pc as u64 <= pc);
+        match index.checked_sub(1).map(|index| &self.functions[index]) {
+            Some((_, name)) => vec![name.clone()],
+            None => vec![format!("{:#x}", pc)],
+        }
+    }
+}
+
+fn demangle(name: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(name))
+}
+
+/// A frame of a call stack: either a program, or a function executed by a
+/// program.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum StackFrame {
+    Program(Pubkey),
+    Function(Pubkey, String),
+}
+
+impl StackFrame {
+    fn function(program_id: Pubkey, name: impl Into<String>) -> Self {
+        Self::Function(program_id, name.into())
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Self::Program(program_id) => program_id.to_string(),
+            Self::Function(_, name) => name.clone(),
+        }
+    }
+}
+
+/// A frame of the instruction trace, with the execution trace of the program
+/// if it ran in the VM.
+struct InstructionFrame<'a> {
+    program_id: Pubkey,
+    stack_height: usize,
+    trace: Option<&'a ProgramTrace>,
+}
+
+/// A loaded program, along with the symbolizer for its program counters.
+type LoadedProgram = Rc<(Arc<ProgramCacheEntry>, Symbolizer)>;
+
+struct Profiler<'a, PC> {
+    program_cache: &'a PC,
+    frames: Vec<InstructionFrame<'a>>,
+    programs: HashMap<Pubkey, Option<LoadedProgram>>,
+    samples: HashMap<Vec<StackFrame>, u64>,
+    /// The stacks sampled, in the order they were first sampled.
+    stacks: Vec<Vec<StackFrame>>,
+}
+
+impl<PC: ProgramCacheProvider> Profiler<'_, PC> {
+    fn sample(&mut self, stack: Vec<StackFrame>, compute_units: u64) {
+        if compute_units == 0 {
+            return;
+        }
+        let total = self.samples.entry(stack).or_insert_with_key(|stack| {
+            self.stacks.push(stack.clone());
+            0
+        });
+        *total += compute_units;
+    }
+
+    fn load_program(&mut self, program_id: &Pubkey) -> Option<LoadedProgram> {
+        let program_cache = self.program_cache;
+        self.programs
+            .entry(*program_id)
+            .or_insert_with(|| {
+                let entry = program_cache.load_program(program_id)?;
+                let ProgramCacheEntryType::Loaded(executable) = &entry.program else {
+                    return None;
+                };
+                let elf = program_cache.program_elf(program_id);
+                let symbolizer = Symbolizer::new(executable, elf.as_deref());
+                Some(Rc::new((entry, symbolizer)))
+            })
+            .clone()
+    }
+
+    /// The CPIs made by the instruction frame at `index`, in the order they
+    /// were made.
+    fn cpis(&self, index: usize) -> std::vec::IntoIter<usize> {
+        let stack_height = self.frames[index].stack_height;
+        self.frames[index + 1..]
+            .iter()
+            .take_while(|frame| frame.stack_height > stack_height)
+            .enumerate()
+            .filter(|(_, frame)| frame.stack_height == stack_height + 1)
+            .map(|(offset, _)| index + 1 + offset)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Attribute the executed instructions of the instruction frame at
+    /// `index` and its CPIs, returning the number of compute units
+    /// attributed.
+    ///
+    /// CPIs that can't be matched to the instruction that made them, such as
+    /// those made by builtins, are nested directly under the program's frame.
+    fn profile_frame(&mut self, index: usize, mut stack: Vec<StackFrame>) -> u64 {
+        let InstructionFrame {
+            program_id, trace, ..
+        } = self.frames[index];
+        stack.push(StackFrame::Program(program_id));
+        let mut cpis = self.cpis(index);
+
+        let mut attributed = match trace {
+            Some(trace) => self.profile_trace(program_id, trace, &stack, &mut cpis),
+            None => 0,
+        };
+        for cpi in cpis {
+            attributed += self.profile_frame(cpi, stack.clone());
+        }
+        attributed
+    }
+
+    /// Attribute the executed instructions of a program's execution trace,
+    /// along with the CPIs it made at the call sites they were made from.
+    fn profile_trace(
+        &mut self,
+        program_id: Pubkey,
+        trace: &ProgramTrace,
+        stack: &[StackFrame],
+        cpis: &mut std::vec::IntoIter<usize>,
+    ) -> u64 {
+        let Some(program) = self.load_program(&program_id) else {
+            self.sample(stack.to_vec(), trace.entries.len() as u64);
+            return trace.entries.len() as u64;
+        };
+        let (entry, symbolizer) = &*program;
+        let ProgramCacheEntryType::Loaded(executable) = &entry.program else {
+            unreachable!()
+        };
+        let (_, text) = executable.get_text_bytes();
+        let return_opcode = match executable.get_sbpf_version().static_syscalls() {
+            true => ebpf::RETURN,
+            false => ebpf::EXIT,
+        };
+
+        let mut attributed = 0;
+        // The frames of each call site on the path to the current function.
+        let mut call_sites: Vec<Vec<StackFrame>> = Vec::new();
+        for (position, &TraceEntry { pc, opcode, .. }) in trace.entries.iter().enumerate() {
+            let frames = symbolizer
+                .frames(pc)
+                .into_iter()
+                .map(|name| StackFrame::function(program_id, name))
+                .collect::<Vec<_>>();
+            let current = stack
+                .iter()
+                .chain(call_sites.iter().flatten())
+                .chain(&frames)
+                .cloned()
+                .collect::<Vec<_>>();
+            self.sample(current.clone(), 1);
+            attributed += 1;
+
+            if opcode == return_opcode {
+                call_sites.pop();
+            } else if matches!(opcode, ebpf::CALL_IMM | ebpf::CALL_REG | ebpf::SYSCALL) {
+                // Internal calls jump, while syscalls fall through.
+                let next_pc = trace.entries.get(position + 1).map(|entry| entry.pc);
+                if next_pc.is_some_and(|next_pc| next_pc != pc + 1) {
+                    call_sites.push(frames);
+                } else if is_cpi(executable, text, pc) {
+                    if let Some(cpi) = cpis.next() {
+                        attributed += self.profile_frame(cpi, current);
+                    }
+                }
+            }
+        }
+        attributed
+    }
+}
+
+/// Whether the instruction at `pc` calls one of the CPI syscalls.
+fn is_cpi(executable: &Executable<InvokeContext<'static>>, text: &[u8], pc: u64) -> bool {
+    if (pc as usize + 1) * ebpf::INSN_SIZE > text.len() {
+        return false;
+    }
+    let key = ebpf::get_insn_unchecked(text, pc as usize).imm as u32;
+    matches!(
+        executable
+            .get_loader()
+            .get_function_registry()
+            .lookup_by_key(key),
+        Some((b"sol_invoke_signed_c" | b"sol_invoke_signed_rust", _))
+    )
+}
+
+/// Build the profile of an instruction from the execution traces in its
+/// result and the instruction trace of the transaction context it was
+/// processed in.
+pub(crate) fn build_profile<PC: ProgramCacheProvider>(
+    program_cache: &PC,
+    transaction_context: &TransactionContext,
+    execution_trace: &[ProgramTrace],
+    compute_units_consumed: u64,
+) -> Profile {
+    // Match the execution traces, which are in invocation order, to the
+    // frames of the SBF programs in the instruction trace.
+    let mut traces = execution_trace.iter().peekable();
+    let frames = (0..transaction_context.get_instruction_trace_length())
+        .filter_map(|index_in_trace| {
+            let instruction_context = transaction_context
+                .get_instruction_context_at_index_in_trace(index_in_trace)
+                .ok()?;
+            let program_account = instruction_context
+                .try_borrow_last_program_account(transaction_context)
+                .ok()?;
+            let program_id = *program_account.get_key();
+            let stack_height = instruction_context.get_stack_height();
+            // Builtins are owned by the native loader and don't run in the VM.
+            let trace = (*program_account.get_owner() != loader_keys::NATIVE_LOADER)
+                .then(|| {
+                    traces.next_if(|trace| {
+                        trace.program_id == program_id && trace.stack_height == stack_height
+                    })
+                })
+                .flatten();
+            Some(InstructionFrame {
+                program_id,
+                stack_height,
+                trace,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut profiler = Profiler {
+        program_cache,
+        frames,
+        programs: HashMap::new(),
+        samples: HashMap::new(),
+        stacks: Vec::new(),
+    };
+    if let Some(root) = profiler.frames.first().map(|frame| frame.program_id) {
+        let attributed = profiler.profile_frame(0, Vec::new());
+        profiler.sample(
+            vec![
+                StackFrame::Program(root),
+                StackFrame::function(root, RUNTIME_FRAME),
+            ],
+            compute_units_consumed.saturating_sub(attributed),
+        );
+    }
+
+    let Profiler {
+        mut samples,
+        stacks,
+        ..
+    } = profiler;
+    let stacks = stacks
+        .into_iter()
+        .map(|stack| {
+            let compute_units = samples.remove(&stack).unwrap();
+            (stack, compute_units)
+        })
+        .collect::<Vec<_>>();
+
+    Profile {
+        compute_units_consumed,
+        functions: function_profiles(&stacks),
+        stacks: stacks
+            .into_iter()
+            .map(|(stack, compute_units)| StackSample {
+                frames: stack.iter().map(StackFrame::name).collect(),
+                compute_units,
+            })
+            .collect(),
+    }
+}
+
+/// Aggregate the compute units of each stack by function.
+fn function_profiles(stacks: &[(Vec<StackFrame>, u64)]) -> Vec<FunctionProfile> {
+    let mut functions: HashMap<&StackFrame, (u64, u64)> = HashMap::new();
+    for (stack, compute_units) in stacks {
+        let mut stack = stack
+            .iter()
+            .filter(|frame| matches!(frame, StackFrame::Function(..)))
+            .collect::<Vec<_>>();
+        if let Some(leaf) = stack.last() {
+            functions.entry(leaf).or_default().0 += compute_units;
+        }
+        // Recursive functions only count once towards their inclusive total.
+        stack.sort();
+        stack.dedup();
+        for function in stack {
+            functions.entry(function).or_default().1 += compute_units;
+        }
+    }
+
+    let mut functions = functions
+        .into_iter()
+        .filter_map(|(frame, (self_compute_units, inclusive_compute_units))| {
+            let StackFrame::Function(program_id, name) = frame else {
+                return None;
+            };
+            Some(FunctionProfile {
+                program_id: *program_id,
+                name: name.clone(),
+                self_compute_units,
+                inclusive_compute_units,
+            })
+        })
+        .collect::<Vec<_>>();
+    functions.sort_by(|a, b| {
+        b.self_compute_units
+            .cmp(&a.self_compute_units)
+            .then(b.inclusive_compute_units.cmp(&a.inclusive_compute_units))
+            .then(a.name.cmp(&b.name))
+    });
+    functions
+}
@@ -1,22 +1,36 @@
 //! Module for working with Solana programs.
 
+#[cfg(feature = "invocation-inspect-callback")]
+use crate::{EmptyInvocationInspectCallback, InvocationInspectCallback};
 use {
+    crate::{
+        account_store::{AccountStore, SharedAccountStore},
+        coverage::CoverageCollector,
+        mock_program::{self, ActiveMockProgramsGuard, MockContext, MockPrograms},
+        native_program,
+    },
     agave_feature_set::FeatureSet,
+    mollusk_svm_error::error::{MolluskError, MolluskPanic},
     solana_account::Account,
     solana_bpf_loader_program::syscalls::create_program_runtime_environment_v1,
     solana_compute_budget::compute_budget::ComputeBudget,
+    solana_hash::Hash,
+    solana_instruction::error::InstructionError,
     solana_loader_v3_interface::state::UpgradeableLoaderState,
     solana_loader_v4_interface::state::{LoaderV4State, LoaderV4Status},
+    solana_program_entrypoint::ProcessInstruction,
     solana_program_runtime::{
         invoke_context::{BuiltinFunctionWithContext, InvokeContext},
         loaded_programs::{LoadProgramMetrics, ProgramCacheEntry, ProgramCacheForTxBatch},
-        solana_sbpf::program::BuiltinProgram,
+        solana_sbpf::{program::BuiltinProgram, vm::Config},
     },
     solana_pubkey::Pubkey,
     solana_rent::Rent,
+    solana_sha256_hasher::hash,
     std::{
         cell::{RefCell, RefMut},
         collections::HashMap,
+        ops::DerefMut,
         rc::Rc,
         sync::Arc,
     },
@@ -56,116 +70,523 @@ pub struct ProgramCache {
     // all program accounts they may use, when `Mollusk` has that information
     // already.
     //
-    // K: program ID, V: loader key
-    entries_cache: Rc<RefCell<HashMap<Pubkey, Pubkey>>>,
+    // K: program ID, V: loader key, ELF and upgrade authority
+    entries_cache: Rc<RefCell<HashMap<Pubkey, CachedProgram>>>,
     // The function registry (syscalls) to use for verifying and loading
     // program ELFs.
     pub program_runtime_environment: BuiltinProgram<InvokeContext<'static>>,
+    // Processors for any mock programs added to the cache.
+    mock_programs: MockPrograms,
+    // Whether the program runtime environment's CPI syscalls explain
+    // privilege failures.
+    privilege_diagnostics: bool,
+    // Records the source lines executed by SBF programs, if set.
+    coverage_collector: Option<CoverageCollector>,
+}
+
+/// A program cache which instructions can be executed against, allowing
+/// `MolluskCore` to be shared between the single-threaded `Mollusk` and the
+/// thread-safe `MolluskMt`.
+pub trait ProgramCacheProvider {
+    /// A guard providing exclusive access to the underlying cache for the
+    /// duration of an invocation.
+    type Cache<'a>: DerefMut<Target = ProgramCacheForTxBatch>
+    where
+        Self: 'a;
+
+    /// The invocation inspect callback held by a `MolluskCore` built on this
+    /// cache. The thread-safe cache requires it to be `Send + Sync`.
+    #[cfg(feature = "invocation-inspect-callback")]
+    type InvocationInspectCallback: ?Sized + InvocationInspectCallback;
+
+    /// How a `MolluskContext` built on this cache shares its account store.
+    /// The thread-safe cache shares it across threads.
+    type SharedAccountStore<AS: AccountStore>: SharedAccountStore<AS>;
+
+    /// Create a new program cache, containing the default builtins.
+    fn new(feature_set: &FeatureSet, compute_budget: &ComputeBudget) -> Self;
+
+    /// The callback a `MolluskCore` built on this cache starts with, which
+    /// doesn't inspect anything.
+    #[cfg(feature = "invocation-inspect-callback")]
+    fn empty_invocation_inspect_callback() -> Box<Self::InvocationInspectCallback>;
+
+    /// View a callback as a plain trait object, ie. to expose it to the CPI
+    /// syscall wrappers.
+    #[cfg(feature = "invocation-inspect-callback")]
+    fn dyn_invocation_inspect_callback(
+        callback: &Self::InvocationInspectCallback,
+    ) -> &(dyn InvocationInspectCallback + 'static);
+
+    /// Access the underlying cache, ie. to create an invoke context.
+    fn cache(&self) -> Self::Cache<'_>;
+
+    /// The function registry (syscalls) used to verify and load program ELFs.
+    fn program_runtime_environment(&self) -> &BuiltinProgram<InvokeContext<'static>>;
+
+    /// Insert an entry into the cache, replacing any existing entry for the
+    /// program, and track the program's ELF so its accounts can be created.
+    fn replenish(&self, program_id: Pubkey, entry: Arc<ProgramCacheEntry>, elf: &[u8]);
+
+    /// Replace the function registry (syscalls) used to verify and load
+    /// program ELFs, reloading every program with an ELF against it.
+    fn set_program_runtime_environment(
+        &mut self,
+        program_runtime_environment: BuiltinProgram<InvokeContext<'static>>,
+    );
+
+    /// Whether the CPI syscalls of the program runtime environment explain
+    /// privilege failures.
+    fn privilege_diagnostics(&self) -> bool;
+
+    /// Record whether the CPI syscalls of the program runtime environment
+    /// explain privilege failures.
+    fn set_privilege_diagnostics(&mut self, enabled: bool);
+
+    /// The collector recording the source lines executed by SBF programs, if
+    /// one is attached.
+    fn coverage_collector(&self) -> Option<&CoverageCollector>;
+
+    /// Attach a collector recording the source lines executed by SBF
+    /// programs.
+    fn set_coverage_collector(&mut self, coverage_collector: CoverageCollector);
+
+    /// Expose the cache's mock programs to the mock entrypoint on the current
+    /// thread until the returned guard is dropped.
+    fn activate_mock_programs(&self) -> ActiveMockProgramsGuard;
+
+    /// Add a native program to the cache, executed on the host by calling the
+    /// provided entrypoint rather than loading an ELF.
+    ///
+    /// See the `native_program` module for more details.
+    fn add_native_program(&mut self, program_id: &Pubkey, entrypoint: ProcessInstruction);
+
+    /// Set the upgrade authority of a program in the cache, which is written
+    /// to the program's accounts when they're created for a context.
+    ///
+    /// For BPF Loader v3 (Upgradeable) programs, this is the program data
+    /// account's upgrade authority. For BPF Loader 4 programs, this is the
+    /// program account's authority, where `None` marks the program as
+    /// finalized. Programs default to having no authority.
+    fn set_upgrade_authority(&mut self, program_id: &Pubkey, authority: Option<Pubkey>);
+
+    /// Load a program from the cache.
+    fn load_program(&self, program_id: &Pubkey) -> Option<Arc<ProgramCacheEntry>>;
+
+    /// Get the ELF a program in the cache was loaded from, if any.
+    fn program_elf(&self, program_id: &Pubkey) -> Option<Arc<[u8]>>;
+
+    /// Get the SHA-256 hash of the ELF a program in the cache was loaded
+    /// from, if any. The hash is computed once, when the program is loaded.
+    fn program_elf_hash(&self, program_id: &Pubkey) -> Option<Hash>;
+
+    /// Get the keys and accounts of every program in the cache.
+    ///
+    /// NOTE: Builtin program accounts are only stubs. This will "just work",
+    /// since Agave's SVM stubs out program accounts in transaction execution
+    /// already. Accounts for programs with ELFs mirror what would be on chain,
+    /// so programs that inspect other programs' accounts see real data.
+    fn get_all_keyed_program_accounts(&self) -> Vec<(Pubkey, Account)>;
+
+    /// Add a builtin program to the cache.
+    fn add_builtin(&mut self, builtin: Builtin) {
+        let program_id = builtin.program_id;
+        let entry = builtin.program_cache_entry();
+        self.replenish(program_id, entry, &[]);
+    }
+
+    /// Add a program to the cache.
+    fn add_program(&mut self, program_id: &Pubkey, loader_key: &Pubkey, elf: &[u8]) {
+        let entry = new_program_entry(self.program_runtime_environment(), loader_key, elf).unwrap();
+        self.replenish(*program_id, entry, elf);
+    }
+}
+
+/// Add the program deployed in a BPF Loader v3 (Upgradeable) or BPF
+/// Loader 4 program account to a program cache. For v3 programs, the program
+/// data account is retrieved with `get_account`.
+///
+/// Returns `false`, leaving the cache untouched, if the account doesn't
+/// contain a valid deployed program.
+pub(crate) fn add_program_from_account<PC: ProgramCacheProvider>(
+    program_cache: &PC,
+    program_id: &Pubkey,
+    account: &Account,
+    get_account: impl FnOnce(&Pubkey) -> Option<Account>,
+) -> bool {
+    let elf = match account.owner {
+        loader_keys::LOADER_V3 => {
+            let Ok(UpgradeableLoaderState::Program {
+                programdata_address,
+            }) = bincode::deserialize(&account.data)
+            else {
+                return false;
+            };
+            get_account(&programdata_address).and_then(|programdata_account| {
+                elf_from_program_data_account_loader_v3(&programdata_account).map(<[u8]>::to_vec)
+            })
+        }
+        loader_keys::LOADER_V4 => elf_from_program_account_loader_v4(account).map(<[u8]>::to_vec),
+        _ => None,
+    };
+    let Some(elf) = elf else {
+        return false;
+    };
+    let Ok(entry) = new_program_entry(
+        program_cache.program_runtime_environment(),
+        &account.owner,
+        &elf,
+    ) else {
+        return false;
+    };
+    program_cache.replenish(*program_id, entry, &elf);
+    true
+}
+
+fn new_program_entry(
+    program_runtime_environment: &BuiltinProgram<InvokeContext<'static>>,
+    loader_key: &Pubkey,
+    elf: &[u8],
+) -> Result<Arc<ProgramCacheEntry>, Box<dyn std::error::Error>> {
+    ProgramCacheEntry::new(
+        loader_key,
+        clone_program_runtime_environment(program_runtime_environment),
+        0,
+        0,
+        elf,
+        elf.len(),
+        &mut LoadProgramMetrics::default(),
+    )
+    .map(Arc::new)
+}
+
+pub(crate) fn clone_program_runtime_environment(
+    program_runtime_environment: &BuiltinProgram<InvokeContext<'static>>,
+) -> Arc<BuiltinProgram<InvokeContext<'static>>> {
+    // This might look rough, but it's actually functionally the same as
+    // calling `create_program_runtime_environment_v1` on every addition.
+    Arc::new(copy_program_runtime_environment(
+        program_runtime_environment,
+        program_runtime_environment.get_config().clone(),
+        |_| None,
+    ))
+}
+
+/// Copy a program runtime environment with a different VM configuration,
+/// keeping its syscalls, except those `replace` returns a function for.
+pub(crate) fn copy_program_runtime_environment(
+    program_runtime_environment: &BuiltinProgram<InvokeContext<'static>>,
+    config: Config,
+    replace: impl Fn(&str) -> Option<BuiltinFunctionWithContext>,
+) -> BuiltinProgram<InvokeContext<'static>> {
+    let mut loader = BuiltinProgram::new_loader(config);
+
+    for (_key, (name, value)) in program_runtime_environment.get_function_registry().iter() {
+        let name = std::str::from_utf8(name).unwrap();
+        let value = replace(name).unwrap_or(value);
+        loader.register_function(name, value).unwrap();
+    }
+
+    loader
+}
+
+/// Create the function registry (syscalls) used to verify and load program
+/// ELFs.
+///
+/// Enabling `debugging_features` makes the VM record a trace of every
+/// instruction it executes, and keeps symbol and section labels around for
+/// disassembly.
+pub(crate) fn create_program_runtime_environment(
+    feature_set: &FeatureSet,
+    compute_budget: &ComputeBudget,
+    debugging_features: bool,
+) -> BuiltinProgram<InvokeContext<'static>> {
+    let environment = create_program_runtime_environment_v1(
+        &feature_set.runtime_features(),
+        &compute_budget.to_budget(),
+        /* reject_deployment_of_broken_elfs */ false,
+        debugging_features,
+    )
+    .unwrap();
+    #[cfg(feature = "invocation-inspect-callback")]
+    let environment = crate::cpi::hook_cpi_syscalls(&environment, false);
+    environment
+}
+
+/// Create new cache entries for every tracked program with an ELF, loaded
+/// against the provided function registry.
+pub(crate) fn reload_programs(
+    programs: &HashMap<Pubkey, CachedProgram>,
+    program_runtime_environment: &BuiltinProgram<InvokeContext<'static>>,
+) -> Vec<(Pubkey, Arc<ProgramCacheEntry>)> {
+    programs
+        .iter()
+        .filter(|(_, program)| !program.elf.is_empty())
+        .map(|(program_id, program)| {
+            let entry = new_program_entry(
+                program_runtime_environment,
+                &program.loader_key,
+                &program.elf,
+            )
+            .unwrap();
+            (*program_id, entry)
+        })
+        .collect()
 }
 
 impl ProgramCache {
     pub fn new(feature_set: &FeatureSet, compute_budget: &ComputeBudget) -> Self {
+        let program_runtime_environment = create_program_runtime_environment(
+            feature_set,
+            compute_budget,
+            /* debugging_features */ false,
+        );
+        let mut cache = ProgramCacheForTxBatch::default();
+        cache.environments.program_runtime_v1 =
+            clone_program_runtime_environment(&program_runtime_environment);
         let me = Self {
-            cache: Rc::new(RefCell::new(ProgramCacheForTxBatch::default())),
+            cache: Rc::new(RefCell::new(cache)),
             entries_cache: Rc::new(RefCell::new(HashMap::new())),
-            program_runtime_environment: create_program_runtime_environment_v1(
-                &feature_set.runtime_features(),
-                &compute_budget.to_budget(),
-                /* reject_deployment_of_broken_elfs */ false,
-                /* debugging_features */ false,
-            )
-            .unwrap(),
+            program_runtime_environment,
+            mock_programs: MockPrograms::default(),
+            privilege_diagnostics: false,
+            coverage_collector: None,
         };
         BUILTINS.iter().for_each(|builtin| {
             let program_id = builtin.program_id;
             let entry = builtin.program_cache_entry();
-            me.replenish(program_id, entry);
+            me.replenish(program_id, entry, &[]);
         });
         me
     }
 
-    pub(crate) fn cache(&self) -> RefMut<ProgramCacheForTxBatch> {
+    /// Add a builtin program to the cache.
+    pub fn add_builtin(&mut self, builtin: Builtin) {
+        ProgramCacheProvider::add_builtin(self, builtin)
+    }
+
+    /// Add a program to the cache.
+    pub fn add_program(&mut self, program_id: &Pubkey, loader_key: &Pubkey, elf: &[u8]) {
+        ProgramCacheProvider::add_program(self, program_id, loader_key, elf)
+    }
+
+    /// Load a program from the cache.
+    pub fn load_program(&self, program_id: &Pubkey) -> Option<Arc<ProgramCacheEntry>> {
+        ProgramCacheProvider::load_program(self, program_id)
+    }
+
+    /// Add a mock program to the cache, backed by the provided processor.
+    ///
+    /// See the `mock_program` module for more details.
+    pub fn add_mock_program<F>(&mut self, program_id: &Pubkey, processor: F)
+    where
+        F: Fn(&mut MockContext) -> Result<(), InstructionError> + 'static,
+    {
+        self.mock_programs.insert(*program_id, Rc::new(processor));
+        self.add_builtin(Builtin::new(
+            *program_id,
+            "mock_program",
+            mock_program::Entrypoint::vm,
+        ));
+    }
+}
+
+impl ProgramCacheProvider for ProgramCache {
+    type Cache<'a> = RefMut<'a, ProgramCacheForTxBatch>;
+    #[cfg(feature = "invocation-inspect-callback")]
+    type InvocationInspectCallback = dyn InvocationInspectCallback;
+    type SharedAccountStore<AS: AccountStore> = Rc<RefCell<AS>>;
+
+    fn new(feature_set: &FeatureSet, compute_budget: &ComputeBudget) -> Self {
+        Self::new(feature_set, compute_budget)
+    }
+
+    #[cfg(feature = "invocation-inspect-callback")]
+    fn empty_invocation_inspect_callback() -> Box<Self::InvocationInspectCallback> {
+        Box::new(EmptyInvocationInspectCallback)
+    }
+
+    #[cfg(feature = "invocation-inspect-callback")]
+    fn dyn_invocation_inspect_callback(
+        callback: &Self::InvocationInspectCallback,
+    ) -> &(dyn InvocationInspectCallback + 'static) {
+        callback
+    }
+
+    fn cache(&self) -> Self::Cache<'_> {
         self.cache.borrow_mut()
     }
 
-    fn replenish(&self, program_id: Pubkey, entry: Arc<ProgramCacheEntry>) {
+    fn program_runtime_environment(&self) -> &BuiltinProgram<InvokeContext<'static>> {
+        &self.program_runtime_environment
+    }
+
+    fn replenish(&self, program_id: Pubkey, entry: Arc<ProgramCacheEntry>, elf: &[u8]) {
         self.entries_cache
             .borrow_mut()
-            .insert(program_id, entry.account_owner());
+            .entry(program_id)
+            .and_modify(|program| program.update(entry.account_owner(), elf))
+            .or_insert_with(|| CachedProgram::new(entry.account_owner(), elf));
         self.cache.borrow_mut().replenish(program_id, entry);
     }
 
-    /// Add a builtin program to the cache.
-    pub fn add_builtin(&mut self, builtin: Builtin) {
-        let program_id = builtin.program_id;
-        let entry = builtin.program_cache_entry();
-        self.replenish(program_id, entry);
+    fn set_program_runtime_environment(
+        &mut self,
+        program_runtime_environment: BuiltinProgram<InvokeContext<'static>>,
+    ) {
+        let entries = reload_programs(&self.entries_cache.borrow(), &program_runtime_environment);
+        let mut cache = self.cache.borrow_mut();
+        cache.environments.program_runtime_v1 =
+            clone_program_runtime_environment(&program_runtime_environment);
+        for (program_id, entry) in entries {
+            cache.replenish(program_id, entry);
+        }
+        self.program_runtime_environment = program_runtime_environment;
     }
 
-    /// Add a program to the cache.
-    pub fn add_program(&mut self, program_id: &Pubkey, loader_key: &Pubkey, elf: &[u8]) {
-        // This might look rough, but it's actually functionally the same as
-        // calling `create_program_runtime_environment_v1` on every addition.
-        let environment = {
-            let config = self.program_runtime_environment.get_config().clone();
-            let mut loader = BuiltinProgram::new_loader(config);
-
-            for (_key, (name, value)) in self
-                .program_runtime_environment
-                .get_function_registry()
-                .iter()
-            {
-                let name = std::str::from_utf8(name).unwrap();
-                loader.register_function(name, value).unwrap();
-            }
+    fn privilege_diagnostics(&self) -> bool {
+        self.privilege_diagnostics
+    }
 
-            Arc::new(loader)
-        };
-        self.replenish(
+    fn set_privilege_diagnostics(&mut self, enabled: bool) {
+        self.privilege_diagnostics = enabled;
+    }
+
+    fn coverage_collector(&self) -> Option<&CoverageCollector> {
+        self.coverage_collector.as_ref()
+    }
+
+    fn set_coverage_collector(&mut self, coverage_collector: CoverageCollector) {
+        self.coverage_collector = Some(coverage_collector);
+    }
+
+    fn activate_mock_programs(&self) -> ActiveMockProgramsGuard {
+        self.mock_programs.activate()
+    }
+
+    fn add_native_program(&mut self, program_id: &Pubkey, entrypoint: ProcessInstruction) {
+        native_program::install_syscall_stubs();
+        self.mock_programs.insert(
             *program_id,
-            Arc::new(
-                ProgramCacheEntry::new(
-                    loader_key,
-                    environment,
-                    0,
-                    0,
-                    elf,
-                    elf.len(),
-                    &mut LoadProgramMetrics::default(),
-                )
-                .unwrap(),
-            ),
+            Rc::new(move |context: &mut MockContext| {
+                native_program::invoke(context.invoke_context(), entrypoint)
+            }),
         );
+        self.add_builtin(Builtin::new(
+            *program_id,
+            "native_program",
+            mock_program::Entrypoint::vm,
+        ));
     }
 
-    /// Load a program from the cache.
-    pub fn load_program(&self, program_id: &Pubkey) -> Option<Arc<ProgramCacheEntry>> {
+    fn set_upgrade_authority(&mut self, program_id: &Pubkey, authority: Option<Pubkey>) {
+        self.entries_cache
+            .borrow_mut()
+            .get_mut(program_id)
+            .or_panic_with(MolluskError::ProgramNotCached(program_id))
+            .set_upgrade_authority(authority);
+    }
+
+    fn load_program(&self, program_id: &Pubkey) -> Option<Arc<ProgramCacheEntry>> {
         self.cache.borrow().find(program_id)
     }
 
-    // NOTE: These are only stubs. This will "just work", since Agave's SVM
-    // stubs out program accounts in transaction execution already, noting that
-    // the ELFs are already where they need to be: in the cache.
-    pub(crate) fn get_all_keyed_program_accounts(&self) -> Vec<(Pubkey, Account)> {
+    fn program_elf(&self, program_id: &Pubkey) -> Option<Arc<[u8]>> {
+        self.entries_cache.borrow().get(program_id)?.elf()
+    }
+
+    fn program_elf_hash(&self, program_id: &Pubkey) -> Option<Hash> {
+        self.entries_cache.borrow().get(program_id)?.elf_hash()
+    }
+
+    fn get_all_keyed_program_accounts(&self) -> Vec<(Pubkey, Account)> {
         self.entries_cache
             .borrow()
             .iter()
-            .map(|(program_id, loader_key)| match *loader_key {
-                loader_keys::NATIVE_LOADER => {
-                    create_keyed_account_for_builtin_program(program_id, "I'm a stub!")
-                }
-                loader_keys::LOADER_V1 => (*program_id, create_program_account_loader_v1(&[])),
-                loader_keys::LOADER_V2 => (*program_id, create_program_account_loader_v2(&[])),
-                loader_keys::LOADER_V3 => {
-                    (*program_id, create_program_account_loader_v3(program_id))
-                }
-                loader_keys::LOADER_V4 => (*program_id, create_program_account_loader_v4(&[])),
-                _ => panic!("Invalid loader key: {}", loader_key),
-            })
+            .flat_map(|(program_id, program)| program.keyed_accounts(program_id))
             .collect()
     }
 }
 
+/// A program tracked by a program cache, with the information required to
+/// create its account(s).
+pub(crate) struct CachedProgram {
+    loader_key: Pubkey,
+    elf: Arc<[u8]>,
+    elf_hash: Hash,
+    upgrade_authority: Option<Pubkey>,
+}
+
+impl CachedProgram {
+    pub(crate) fn new(loader_key: Pubkey, elf: &[u8]) -> Self {
+        Self {
+            loader_key,
+            elf: Arc::from(elf),
+            elf_hash: hash(elf),
+            upgrade_authority: None,
+        }
+    }
+
+    /// Update the program's loader and ELF, keeping its upgrade authority.
+    pub(crate) fn update(&mut self, loader_key: Pubkey, elf: &[u8]) {
+        self.loader_key = loader_key;
+        if *self.elf != *elf {
+            self.elf = Arc::from(elf);
+            self.elf_hash = hash(elf);
+        }
+    }
+
+    pub(crate) fn set_upgrade_authority(&mut self, authority: Option<Pubkey>) {
+        self.upgrade_authority = authority;
+    }
+
+    /// The program's ELF, or `None` for builtins.
+    pub(crate) fn elf(&self) -> Option<Arc<[u8]>> {
+        (!self.elf.is_empty()).then(|| Arc::clone(&self.elf))
+    }
+
+    /// The hash of the program's ELF, or `None` for builtins.
+    pub(crate) fn elf_hash(&self) -> Option<Hash> {
+        (!self.elf.is_empty()).then_some(self.elf_hash)
+    }
+
+    pub(crate) fn keyed_accounts(&self, program_id: &Pubkey) -> Vec<(Pubkey, Account)> {
+        match self.loader_key {
+            loader_keys::NATIVE_LOADER => {
+                vec![create_keyed_account_for_builtin_program(
+                    program_id,
+                    "I'm a stub!",
+                )]
+            }
+            loader_keys::LOADER_V1 => {
+                vec![(*program_id, create_program_account_loader_v1(&self.elf))]
+            }
+            loader_keys::LOADER_V2 => {
+                vec![(*program_id, create_program_account_loader_v2(&self.elf))]
+            }
+            loader_keys::LOADER_V3 => {
+                let programdata_address =
+                    Pubkey::find_program_address(&[program_id.as_ref()], &loader_keys::LOADER_V3).0;
+                vec![
+                    (*program_id, create_program_account_loader_v3(program_id)),
+                    (
+                        programdata_address,
+                        create_program_data_account_loader_v3_with_authority(
+                            &self.elf,
+                            self.upgrade_authority,
+                        ),
+                    ),
+                ]
+            }
+            loader_keys::LOADER_V4 => vec![(
+                *program_id,
+                create_program_account_loader_v4_with_authority(&self.elf, self.upgrade_authority),
+            )],
+            _ => panic!("Invalid loader key: {}", self.loader_key),
+        }
+    }
+}
+
 pub struct Builtin {
     pub program_id: Pubkey,
     name: &'static str,
@@ -173,11 +594,34 @@ pub struct Builtin {
 }
 
 impl Builtin {
+    pub fn new(
+        program_id: Pubkey,
+        name: &'static str,
+        entrypoint: BuiltinFunctionWithContext,
+    ) -> Self {
+        Self {
+            program_id,
+            name,
+            entrypoint,
+        }
+    }
+
     pub fn program_cache_entry(&self) -> Arc<ProgramCacheEntry> {
+        // The loaders' entrypoint is wrapped, so program invocations can be
+        // run under the debugger. See the `debugger` module.
+        #[cfg(feature = "sbpf-debugger")]
+        let entrypoint = match self.program_id {
+            loader_keys::LOADER_V2 | loader_keys::LOADER_V3 | loader_keys::LOADER_V4 => {
+                crate::debugger::Entrypoint::vm as BuiltinFunctionWithContext
+            }
+            _ => self.entrypoint,
+        };
+        #[cfg(not(feature = "sbpf-debugger"))]
+        let entrypoint = self.entrypoint;
         Arc::new(ProgramCacheEntry::new_builtin(
             0,
             self.name.len(),
-            self.entrypoint,
+            entrypoint,
         ))
     }
 }
@@ -198,6 +642,13 @@ pub static BUILTINS: &[Builtin] = &[
         name: "solana_bpf_loader_upgradeable_program",
         entrypoint: solana_bpf_loader_program::Entrypoint::vm,
     },
+    // Programs deployed with loader v4 are invoked through the BPF loader's
+    // entrypoint. Loader v4 management instructions are not supported.
+    Builtin {
+        program_id: loader_keys::LOADER_V4,
+        name: "solana_loader_v4_program",
+        entrypoint: solana_bpf_loader_program::Entrypoint::vm,
+    },
     #[cfg(feature = "all-builtins")]
     Builtin {
         program_id: solana_sdk_ids::stake::id(),
@@ -283,8 +734,18 @@ pub fn create_program_account_loader_v3(program_id: &Pubkey) -> Account {
     }
 }
 
-/// Create a BPF Loader v3 (Upgradeable) program data account.
+/// Create a BPF Loader v3 (Upgradeable) program data account, with no upgrade
+/// authority.
 pub fn create_program_data_account_loader_v3(elf: &[u8]) -> Account {
+    create_program_data_account_loader_v3_with_authority(elf, None)
+}
+
+/// Create a BPF Loader v3 (Upgradeable) program data account with the provided
+/// upgrade authority.
+pub fn create_program_data_account_loader_v3_with_authority(
+    elf: &[u8],
+    upgrade_authority_address: Option<Pubkey>,
+) -> Account {
     let data = {
         let elf_offset = UpgradeableLoaderState::size_of_programdata_metadata();
         let data_len = elf_offset + elf.len();
@@ -293,7 +754,7 @@ pub fn create_program_data_account_loader_v3(elf: &[u8]) -> Account {
             &mut data[0..elf_offset],
             &UpgradeableLoaderState::ProgramData {
                 slot: 0,
-                upgrade_authority_address: None,
+                upgrade_authority_address,
             },
         )
         .unwrap();
@@ -310,6 +771,22 @@ pub fn create_program_data_account_loader_v3(elf: &[u8]) -> Account {
     }
 }
 
+/// Get the ELF deployed in a BPF Loader v3 (Upgradeable) program data account,
+/// stripping the loader's metadata.
+///
+/// Returns `None` if the account is not a program data account.
+pub fn elf_from_program_data_account_loader_v3(account: &Account) -> Option<&[u8]> {
+    if account.owner != loader_keys::LOADER_V3 {
+        return None;
+    }
+    let Ok(UpgradeableLoaderState::ProgramData { .. }) = bincode::deserialize(&account.data) else {
+        return None;
+    };
+    account
+        .data
+        .get(UpgradeableLoaderState::size_of_programdata_metadata()..)
+}
+
 /// Create a BPF Loader v3 (Upgradeable) program and program data account.
 ///
 /// Returns a tuple, where the first element is the program account and the
@@ -324,28 +801,65 @@ pub fn create_program_account_pair_loader_v3(
     )
 }
 
-/// Create a BPF Loader 4 program account.
+/// Get the ELF deployed in a BPF Loader 4 program account, stripping the
+/// loader's metadata.
+///
+/// Returns `None` if the account is not a BPF Loader 4 program account, or if
+/// the program has been retracted.
+pub fn elf_from_program_account_loader_v4(account: &Account) -> Option<&[u8]> {
+    if account.owner != loader_keys::LOADER_V4 {
+        return None;
+    }
+    // The status is the last field of the state header.
+    let elf_offset = LoaderV4State::program_data_offset();
+    let status = account.data.get(elf_offset - 8..elf_offset)?;
+    if u64::from_le_bytes(status.try_into().unwrap()) == LoaderV4Status::Retracted as u64 {
+        return None;
+    }
+    account.data.get(elf_offset..)
+}
+
+/// Create a BPF Loader 4 program account, deployed with a placeholder
+/// authority (`[2; 32]`).
+///
+/// The account isn't marked executable. Use
+/// [`create_program_account_loader_v4_with_authority`] for an account matching
+/// the state the loader leaves a program in.
 pub fn create_program_account_loader_v4(elf: &[u8]) -> Account {
-    let data = unsafe {
-        let elf_offset = LoaderV4State::program_data_offset();
-        let data_len = elf_offset + elf.len();
-        let mut data = vec![0u8; data_len];
-        *std::mem::transmute::<&mut [u8; LoaderV4State::program_data_offset()], &mut LoaderV4State>(
-            (&mut data[0..elf_offset]).try_into().unwrap(),
-        ) = LoaderV4State {
-            slot: 0,
-            authority_address_or_next_version: Pubkey::new_from_array([2; 32]),
-            status: LoaderV4Status::Deployed,
-        };
-        data[elf_offset..].copy_from_slice(elf);
-        data
+    Account {
+        executable: false,
+        ..create_program_account_loader_v4_with_authority(
+            elf,
+            Some(Pubkey::new_from_array([2; 32])),
+        )
+    }
+}
+
+/// Create a BPF Loader 4 program account with the provided authority.
+///
+/// A program with an authority is deployed, while a program without one is
+/// finalized.
+pub fn create_program_account_loader_v4_with_authority(
+    elf: &[u8],
+    authority: Option<Pubkey>,
+) -> Account {
+    let (authority_address_or_next_version, status) = match authority {
+        Some(authority) => (authority, LoaderV4Status::Deployed),
+        None => (Pubkey::default(), LoaderV4Status::Finalized),
     };
+    // Serialize the state header by hand, since it's `repr(C)` and the
+    // account data isn't guaranteed to be aligned for it.
+    let mut data = Vec::with_capacity(LoaderV4State::program_data_offset() + elf.len());
+    data.extend_from_slice(&0u64.to_le_bytes()); // slot
+    data.extend_from_slice(authority_address_or_next_version.as_ref());
+    data.extend_from_slice(&(status as u64).to_le_bytes());
+    data.extend_from_slice(elf);
     let lamports = Rent::default().minimum_balance(data.len());
     Account {
         lamports,
         data,
-        owner: loader_keys::LOADER_V3,
-        executable: false,
+        owner: loader_keys::LOADER_V4,
+        executable: true,
         ..Default::default()
     }
 }
@@ -1,26 +1,33 @@
 //! Module for working with Solana programs.
 
+#[cfg(feature = "invocation-inspect-callback")]
+use crate::{EmptyInvocationInspectCallback, InvocationInspectCallback};
 use {
-    crate::program::*,
+    crate::{
+        account_store::AccountStore,
+        coverage::CoverageCollector,
+        mock_program::{self, ActiveMockProgramsGuard, MockContext, SyncMockPrograms},
+        native_program,
+        program::*,
+    },
     agave_feature_set::FeatureSet,
+    mollusk_svm_error::error::{MolluskError, MolluskPanic},
     solana_account::Account,
-    solana_bpf_loader_program::syscalls::create_program_runtime_environment_v1,
     solana_compute_budget::compute_budget::ComputeBudget,
-    solana_loader_v3_interface::state::UpgradeableLoaderState,
-    solana_loader_v4_interface::state::{LoaderV4State, LoaderV4Status},
+    solana_hash::Hash,
+    solana_instruction::error::InstructionError,
+    solana_program_entrypoint::ProcessInstruction,
     solana_program_runtime::{
-        invoke_context::{BuiltinFunctionWithContext, InvokeContext},
-        loaded_programs::{LoadProgramMetrics, ProgramCacheEntry, ProgramCacheForTxBatch},
+        invoke_context::InvokeContext,
+        loaded_programs::{ProgramCacheEntry, ProgramCacheForTxBatch},
         solana_sbpf::program::BuiltinProgram,
     },
     solana_pubkey::Pubkey,
-    solana_rent::Rent,
     std::{
-        cell::{RefCell, RefMut},
+        cell::Cell,
         collections::HashMap,
-        rc::Rc,
-        sync::Arc,
-        sync::RwLock,
+        ops::{Deref, DerefMut},
+        sync::{Arc, RwLock, RwLockWriteGuard},
     },
 };
 /*
@@ -57,129 +64,279 @@ pub struct MtProgramCache {
     // all program accounts they may use, when `Mollusk` has that information
     // already.
     //
-    // K: program ID, V: loader key
-    entries_cache: Arc<RwLock<HashMap<Pubkey, Pubkey>>>,
+    // K: program ID, V: loader key, ELF and upgrade authority
+    entries_cache: Arc<RwLock<HashMap<Pubkey, CachedProgram>>>,
     // The function registry (syscalls) to use for verifying and loading
     // program ELFs.
     pub program_runtime_environment: BuiltinProgram<InvokeContext<'static>>,
+    // Processors for any mock programs added to the cache.
+    mock_programs: SyncMockPrograms,
+    // Whether the program runtime environment's CPI syscalls explain
+    // privilege failures.
+    privilege_diagnostics: bool,
+    // Records the source lines executed by SBF programs, if set.
+    coverage_collector: Option<CoverageCollector>,
 }
 
 impl MtProgramCache {
     pub fn new(feature_set: &FeatureSet, compute_budget: &ComputeBudget) -> Self {
+        let program_runtime_environment = create_program_runtime_environment(
+            feature_set,
+            compute_budget,
+            /* debugging_features */ false,
+        );
+        let mut cache = ProgramCacheForTxBatch::default();
+        cache.environments.program_runtime_v1 =
+            clone_program_runtime_environment(&program_runtime_environment);
         let me = Self {
-            cache: Arc::new(RwLock::new(ProgramCacheForTxBatch::default())),
+            cache: Arc::new(RwLock::new(cache)),
             entries_cache: Arc::new(RwLock::new(HashMap::new())),
-            program_runtime_environment: create_program_runtime_environment_v1(
-                &feature_set.runtime_features(),
-                &compute_budget.to_budget(),
-                /* reject_deployment_of_broken_elfs */ false,
-                /* debugging_features */ false,
-            )
-            .unwrap(),
+            program_runtime_environment,
+            mock_programs: SyncMockPrograms::default(),
+            privilege_diagnostics: false,
+            coverage_collector: None,
         };
         BUILTINS.iter().for_each(|builtin| {
             let program_id = builtin.program_id;
             let entry = builtin.program_cache_entry();
-            me.replenish(program_id, entry);
+            me.replenish(program_id, entry, &[]);
         });
         me
     }
 
-    pub(crate) fn cache(&self) -> std::sync::RwLockWriteGuard<'_, ProgramCacheForTxBatch> {
-        let mut cache = self.cache.write().unwrap();
-        // Create a new environment based on the current program_runtime_environment
-        let config = self.program_runtime_environment.get_config().clone();
-        let mut loader = BuiltinProgram::new_loader(config);
-        
-        for (_key, (name, value)) in self
-            .program_runtime_environment
-            .get_function_registry()
-            .iter()
-        {
-            let name = std::str::from_utf8(name).unwrap();
-            loader.register_function(name, value).unwrap();
+    /// Add a builtin program to the cache.
+    pub fn add_builtin(&mut self, builtin: Builtin) {
+        ProgramCacheProvider::add_builtin(self, builtin)
+    }
+
+    /// Add a program to the cache.
+    pub fn add_program(&mut self, program_id: &Pubkey, loader_key: &Pubkey, elf: &[u8]) {
+        ProgramCacheProvider::add_program(self, program_id, loader_key, elf)
+    }
+
+    /// Load a program from the cache.
+    pub fn load_program(&self, program_id: &Pubkey) -> Option<Arc<ProgramCacheEntry>> {
+        ProgramCacheProvider::load_program(self, program_id)
+    }
+
+    /// Add a mock program to the cache, backed by the provided processor.
+    ///
+    /// See the `mock_program` module for more details.
+    pub fn add_mock_program<F>(&mut self, program_id: &Pubkey, processor: F)
+    where
+        F: Fn(&mut MockContext) -> Result<(), InstructionError> + Send + Sync + 'static,
+    {
+        self.mock_programs.insert(*program_id, Arc::new(processor));
+        self.add_builtin(Builtin::new(
+            *program_id,
+            "mock_program",
+            mock_program::Entrypoint::vm,
+        ));
+    }
+}
+
+impl ProgramCacheProvider for MtProgramCache {
+    type Cache<'a> = MtProgramCacheForTxBatch<'a>;
+    #[cfg(feature = "invocation-inspect-callback")]
+    type InvocationInspectCallback = dyn InvocationInspectCallback + Send + Sync;
+    type SharedAccountStore<AS: AccountStore> = Arc<RwLock<AS>>;
+
+    fn new(feature_set: &FeatureSet, compute_budget: &ComputeBudget) -> Self {
+        Self::new(feature_set, compute_budget)
+    }
+
+    #[cfg(feature = "invocation-inspect-callback")]
+    fn empty_invocation_inspect_callback() -> Box<Self::InvocationInspectCallback> {
+        Box::new(EmptyInvocationInspectCallback)
+    }
+
+    #[cfg(feature = "invocation-inspect-callback")]
+    fn dyn_invocation_inspect_callback(
+        callback: &Self::InvocationInspectCallback,
+    ) -> &(dyn InvocationInspectCallback + 'static) {
+        callback
+    }
+
+    fn cache(&self) -> Self::Cache<'_> {
+        if PARALLEL_INVOCATION.with(Cell::get) {
+            MtProgramCacheForTxBatch::Copied {
+                batch: self.cache.read().unwrap().clone(),
+                shared: &self.cache,
+            }
+        } else {
+            MtProgramCacheForTxBatch::Exclusive(self.cache.write().unwrap())
         }
-        
-        cache.environments.program_runtime_v1 = Arc::new(loader);
-        cache
     }
 
-    fn replenish(&self, program_id: Pubkey, entry: Arc<ProgramCacheEntry>) {
+    fn program_runtime_environment(&self) -> &BuiltinProgram<InvokeContext<'static>> {
+        &self.program_runtime_environment
+    }
+
+    fn replenish(&self, program_id: Pubkey, entry: Arc<ProgramCacheEntry>, elf: &[u8]) {
         self.entries_cache
             .write()
             .unwrap()
-            .insert(program_id, entry.account_owner());
+            .entry(program_id)
+            .and_modify(|program| program.update(entry.account_owner(), elf))
+            .or_insert_with(|| CachedProgram::new(entry.account_owner(), elf));
         self.cache.write().unwrap().replenish(program_id, entry);
     }
 
-    /// Add a builtin program to the cache.
-    pub fn add_builtin(&mut self, builtin: Builtin) {
-        let program_id = builtin.program_id;
-        let entry = builtin.program_cache_entry();
-        self.replenish(program_id, entry);
+    fn set_program_runtime_environment(
+        &mut self,
+        program_runtime_environment: BuiltinProgram<InvokeContext<'static>>,
+    ) {
+        let entries = reload_programs(
+            &self.entries_cache.read().unwrap(),
+            &program_runtime_environment,
+        );
+        let mut cache = self.cache.write().unwrap();
+        cache.environments.program_runtime_v1 =
+            clone_program_runtime_environment(&program_runtime_environment);
+        for (program_id, entry) in entries {
+            cache.replenish(program_id, entry);
+        }
+        self.program_runtime_environment = program_runtime_environment;
     }
 
-    /// Add a program to the cache.
-    pub fn add_program(&mut self, program_id: &Pubkey, loader_key: &Pubkey, elf: &[u8]) {
-        // This might look rough, but it's actually functionally the same as
-        // calling `create_program_runtime_environment_v1` on every addition.
-        let environment = {
-            let config = self.program_runtime_environment.get_config().clone();
-            let mut loader = BuiltinProgram::new_loader(config);
-
-            for (_key, (name, value)) in self
-                .program_runtime_environment
-                .get_function_registry()
-                .iter()
-            {
-                let name = std::str::from_utf8(name).unwrap();
-                loader.register_function(name, value).unwrap();
-            }
+    fn privilege_diagnostics(&self) -> bool {
+        self.privilege_diagnostics
+    }
 
-            Arc::new(loader)
-        };
-        self.replenish(
+    fn set_privilege_diagnostics(&mut self, enabled: bool) {
+        self.privilege_diagnostics = enabled;
+    }
+
+    fn coverage_collector(&self) -> Option<&CoverageCollector> {
+        self.coverage_collector.as_ref()
+    }
+
+    fn set_coverage_collector(&mut self, coverage_collector: CoverageCollector) {
+        self.coverage_collector = Some(coverage_collector);
+    }
+
+    fn activate_mock_programs(&self) -> ActiveMockProgramsGuard {
+        self.mock_programs.activate()
+    }
+
+    fn add_native_program(&mut self, program_id: &Pubkey, entrypoint: ProcessInstruction) {
+        native_program::install_syscall_stubs();
+        self.mock_programs.insert(
             *program_id,
-            Arc::new(
-                ProgramCacheEntry::new(
-                    loader_key,
-                    environment,
-                    0,
-                    0,
-                    elf,
-                    elf.len(),
-                    &mut LoadProgramMetrics::default(),
-                )
-                .unwrap(),
-            ),
+            Arc::new(move |context: &mut MockContext| {
+                native_program::invoke(context.invoke_context(), entrypoint)
+            }),
         );
+        self.add_builtin(Builtin::new(
+            *program_id,
+            "native_program",
+            mock_program::Entrypoint::vm,
+        ));
     }
 
-    /// Load a program from the cache.
-    pub fn load_program(&self, program_id: &Pubkey) -> Option<Arc<ProgramCacheEntry>> {
+    fn set_upgrade_authority(&mut self, program_id: &Pubkey, authority: Option<Pubkey>) {
+        self.entries_cache
+            .write()
+            .unwrap()
+            .get_mut(program_id)
+            .or_panic_with(MolluskError::ProgramNotCached(program_id))
+            .set_upgrade_authority(authority);
+    }
+
+    fn load_program(&self, program_id: &Pubkey) -> Option<Arc<ProgramCacheEntry>> {
         self.cache.read().unwrap().find(program_id)
     }
 
-    // NOTE: These are only stubs. This will "just work", since Agave's SVM
-    // stubs out program accounts in transaction execution already, noting that
-    // the ELFs are already where they need to be: in the cache.
-    pub(crate) fn get_all_keyed_program_accounts(&self) -> Vec<(Pubkey, Account)> {
+    fn program_elf(&self, program_id: &Pubkey) -> Option<Arc<[u8]>> {
+        self.entries_cache.read().unwrap().get(program_id)?.elf()
+    }
+
+    fn program_elf_hash(&self, program_id: &Pubkey) -> Option<Hash> {
+        self.entries_cache
+            .read()
+            .unwrap()
+            .get(program_id)?
+            .elf_hash()
+    }
+
+    fn get_all_keyed_program_accounts(&self) -> Vec<(Pubkey, Account)> {
         self.entries_cache
             .read()
             .unwrap()
             .iter()
-            .map(|(program_id, loader_key)| match *loader_key {
-                loader_keys::NATIVE_LOADER => {
-                    create_keyed_account_for_builtin_program(program_id, "I'm a stub!")
-                }
-                loader_keys::LOADER_V1 => (*program_id, create_program_account_loader_v1(&[])),
-                loader_keys::LOADER_V2 => (*program_id, create_program_account_loader_v2(&[])),
-                loader_keys::LOADER_V3 => {
-                    (*program_id, create_program_account_loader_v3(program_id))
-                }
-                loader_keys::LOADER_V4 => (*program_id, create_program_account_loader_v4(&[])),
-                _ => panic!("Invalid loader key: {}", loader_key),
-            })
+            .flat_map(|(program_id, program)| program.keyed_accounts(program_id))
             .collect()
     }
 }
+
+thread_local! {
+    static PARALLEL_INVOCATION: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Makes invocations on the current thread use a copy of the program cache,
+/// rather than locking it, until dropped.
+pub(crate) struct ParallelInvocationGuard {
+    previous: bool,
+}
+
+impl ParallelInvocationGuard {
+    pub(crate) fn new() -> Self {
+        let previous = PARALLEL_INVOCATION.with(|parallel| parallel.replace(true));
+        Self { previous }
+    }
+}
+
+impl Drop for ParallelInvocationGuard {
+    fn drop(&mut self) {
+        PARALLEL_INVOCATION.with(|parallel| parallel.set(self.previous));
+    }
+}
+
+/// The program cache entries of an `MtProgramCache`, for one invocation.
+///
+/// An invocation normally holds the write lock on the shared cache. Parallel
+/// invocations instead take a read lock only while copying its entries, which
+/// are reference counted, and any programs the invocation modified, ie.
+/// deployed or upgraded, are merged back into the shared cache when this is
+/// dropped.
+pub enum MtProgramCacheForTxBatch<'a> {
+    Exclusive(RwLockWriteGuard<'a, ProgramCacheForTxBatch>),
+    Copied {
+        batch: ProgramCacheForTxBatch,
+        shared: &'a RwLock<ProgramCacheForTxBatch>,
+    },
+}
+
+impl Deref for MtProgramCacheForTxBatch<'_> {
+    type Target = ProgramCacheForTxBatch;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Exclusive(batch) => batch,
+            Self::Copied { batch, .. } => batch,
+        }
+    }
+}
+
+impl DerefMut for MtProgramCacheForTxBatch<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Self::Exclusive(batch) => batch,
+            Self::Copied { batch, .. } => batch,
+        }
+    }
+}
+
+impl Drop for MtProgramCacheForTxBatch<'_> {
+    fn drop(&mut self) {
+        if let Self::Copied { batch, shared } = self {
+            let modified_entries = batch.drain_modified_entries();
+            if !modified_entries.is_empty() {
+                let mut shared = shared.write().unwrap();
+                for (program_id, entry) in modified_entries {
+                    shared.store_modified_entry(program_id, entry);
+                }
+            }
+        }
+    }
+}
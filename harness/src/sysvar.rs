@@ -107,6 +107,48 @@ impl Sysvars {
         self.sysvar_account(&self.stake_history)
     }
 
+    /// Get a sysvar by type.
+    ///
+    /// Panics if the sysvar isn't one of those tracked by `Sysvars`.
+    pub fn get<T: SysvarId + Sysvar>(&self) -> T {
+        let data = match T::id() {
+            id if id == Clock::id() => bincode::serialize(&self.clock),
+            id if id == EpochRewards::id() => bincode::serialize(&self.epoch_rewards),
+            id if id == EpochSchedule::id() => bincode::serialize(&self.epoch_schedule),
+            id if id == LastRestartSlot::id() => bincode::serialize(&self.last_restart_slot),
+            id if id == Rent::id() => bincode::serialize(&self.rent),
+            id if id == SlotHashes::id() => bincode::serialize(&self.slot_hashes),
+            id if id == StakeHistory::id() => bincode::serialize(&self.stake_history),
+            id => panic!("Unsupported sysvar type: {id}"),
+        };
+        bincode::deserialize(&data.unwrap()).unwrap()
+    }
+
+    /// Overwrite a sysvar by type.
+    ///
+    /// Panics if the sysvar isn't one of those tracked by `Sysvars`.
+    pub fn set<T: SysvarId + Sysvar>(&mut self, sysvar: &T) {
+        let data = bincode::serialize(sysvar).unwrap();
+        match T::id() {
+            id if id == Clock::id() => self.clock = bincode::deserialize(&data).unwrap(),
+            id if id == EpochRewards::id() => {
+                self.epoch_rewards = bincode::deserialize(&data).unwrap()
+            }
+            id if id == EpochSchedule::id() => {
+                self.epoch_schedule = bincode::deserialize(&data).unwrap()
+            }
+            id if id == LastRestartSlot::id() => {
+                self.last_restart_slot = bincode::deserialize(&data).unwrap()
+            }
+            id if id == Rent::id() => self.rent = bincode::deserialize(&data).unwrap(),
+            id if id == SlotHashes::id() => self.slot_hashes = bincode::deserialize(&data).unwrap(),
+            id if id == StakeHistory::id() => {
+                self.stake_history = bincode::deserialize(&data).unwrap()
+            }
+            id => panic!("Unsupported sysvar type: {id}"),
+        }
+    }
+
     /// Warp the test environment to a slot by updating sysvars.
     pub fn warp_to_slot(&mut self, slot: Slot) {
         let slot_delta = slot.saturating_sub(self.clock.slot);
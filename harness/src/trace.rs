@@ -0,0 +1,165 @@
+//! SBF instruction tracing.
+//!
+//! When tracing is enabled with
+//! [`MolluskCore::set_tracing`](crate::MolluskCore::set_tracing), the VM
+//! records the registers and program counter before every instruction it
+//! executes. The invoke context collects one such log per SBF program frame,
+//! in the order the frames complete. This module attributes each log to the
+//! program that produced it, and disassembles it against that program's
+//! executable.
+
+use {
+    crate::program::{loader_keys, ProgramCacheProvider},
+    mollusk_svm_result::{ProgramTrace, TraceEntry},
+    solana_program_runtime::{
+        loaded_programs::ProgramCacheEntryType,
+        solana_sbpf::{ebpf, static_analysis::Analysis},
+    },
+    solana_pubkey::Pubkey,
+    solana_transaction_context::TransactionContext,
+};
+
+/// An SBF program frame in the instruction trace.
+struct Frame {
+    index_in_trace: usize,
+    program_id: Pubkey,
+    stack_height: usize,
+}
+
+/// List the frames of SBF programs in the instruction trace, in the order
+/// they completed, which is the order the invoke context collects their
+/// trace logs in.
+fn sbf_frames_in_completion_order(transaction_context: &TransactionContext) -> Vec<Frame> {
+    let mut completed = Vec::new();
+    let mut stack: Vec<Frame> = Vec::new();
+
+    for index_in_trace in 0..transaction_context.get_instruction_trace_length() {
+        let Ok(instruction_context) =
+            transaction_context.get_instruction_context_at_index_in_trace(index_in_trace)
+        else {
+            continue;
+        };
+        let Ok(program_account) =
+            instruction_context.try_borrow_last_program_account(transaction_context)
+        else {
+            continue;
+        };
+        let frame = Frame {
+            index_in_trace,
+            program_id: *program_account.get_key(),
+            stack_height: instruction_context.get_stack_height(),
+        };
+        // Builtins, including mock programs, are owned by the native loader
+        // and don't run in the VM.
+        let is_sbf = [
+            loader_keys::LOADER_V1,
+            loader_keys::LOADER_V2,
+            loader_keys::LOADER_V3,
+            loader_keys::LOADER_V4,
+        ]
+        .contains(program_account.get_owner());
+
+        // Any frame at the same height or deeper has returned by now.
+        while stack
+            .last()
+            .is_some_and(|top| top.stack_height >= frame.stack_height)
+        {
+            completed.extend(stack.pop());
+        }
+        if is_sbf {
+            stack.push(frame);
+        }
+    }
+
+    completed.extend(stack.into_iter().rev());
+    completed
+}
+
+/// Render a trace log as text, with each executed instruction disassembled.
+fn disassemble<PC: ProgramCacheProvider>(
+    program_cache: &PC,
+    program_id: &Pubkey,
+    trace_log: &[[u64; 12]],
+) -> (Vec<u8>, String) {
+    let entry = program_cache.load_program(program_id);
+    let executable = match entry.as_ref().map(|entry| &entry.program) {
+        Some(ProgramCacheEntryType::Loaded(executable)) => executable,
+        _ => return (vec![0; trace_log.len()], String::new()),
+    };
+
+    let (_, text) = executable.get_text_bytes();
+    let opcodes = trace_log
+        .iter()
+        .map(|entry| {
+            text.get(entry[11] as usize * ebpf::INSN_SIZE)
+                .copied()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let mut disassembly = Vec::new();
+    if let Ok(analysis) = Analysis::from_executable(executable) {
+        let _ = analysis.disassemble_trace_log(&mut disassembly, trace_log);
+    }
+
+    (opcodes, String::from_utf8_lossy(&disassembly).into_owned())
+}
+
+/// Build the execution trace of each SBF program invoked by an instruction,
+/// in invocation order, from the trace logs collected by the invoke context.
+pub(crate) fn collect_traces<PC: ProgramCacheProvider>(
+    program_cache: &PC,
+    transaction_context: &TransactionContext,
+    trace_logs: &[Vec<[u64; 12]>],
+) -> Vec<ProgramTrace> {
+    // Frames are logged even when tracing is disabled, just empty.
+    if trace_logs.iter().all(Vec::is_empty) {
+        return Vec::new();
+    }
+
+    let mut frames = sbf_frames_in_completion_order(transaction_context);
+    // A frame that fails before its VM is created, such as a CPI to a
+    // program that isn't deployed, logs nothing. The failure aborts the
+    // instruction, so only the last frame in the instruction trace can have
+    // done so.
+    if frames.len() > trace_logs.len() {
+        if let Some(position) = frames
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, frame)| frame.index_in_trace)
+            .map(|(position, _)| position)
+        {
+            frames.remove(position);
+        }
+    }
+
+    let mut traces = frames
+        .into_iter()
+        .zip(trace_logs)
+        .filter(|(_, trace_log)| !trace_log.is_empty())
+        .map(|(frame, trace_log)| {
+            let (opcodes, disassembly) = disassemble(program_cache, &frame.program_id, trace_log);
+            let entries = trace_log
+                .iter()
+                .zip(opcodes)
+                .map(|(entry, opcode)| TraceEntry {
+                    pc: entry[11],
+                    registers: entry[..11].try_into().unwrap(),
+                    opcode,
+                })
+                .collect();
+            (
+                frame.index_in_trace,
+                ProgramTrace {
+                    program_id: frame.program_id,
+                    stack_height: frame.stack_height,
+                    entries,
+                    disassembly,
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+
+    traces.sort_by_key(|(index_in_trace, _)| *index_in_trace);
+    traces.into_iter().map(|(_, trace)| trace).collect()
+}
@@ -37,7 +37,7 @@ fn test_transfer_with_context_mt() {
         Account::new(base_lamports, 0, &solana_sdk_ids::system_program::id()),
     );
 
-    let mut context = mollusk.with_context(account_store);
+    let context = mollusk.with_context(account_store);
 
     // Process the transfer instruction
     /*  let result = context.process_and_validate_instruction(
@@ -106,7 +106,7 @@ fn test_multiple_transfers_with_persistent_state_mt() {
         Account::new(initial_lamports, 0, &solana_sdk_ids::system_program::id()),
     );
 
-    let mut context = mollusk.with_context(account_store);
+    let context = mollusk.with_context(account_store);
 
     let checks = vec![
         Check::success(),
@@ -175,7 +175,7 @@ fn test_multiple_transfers_with_persistent_state_mt_chain() {
         Account::new(initial_lamports, 0, &solana_sdk_ids::system_program::id()),
     );
 
-    let mut context = mollusk.with_context(account_store);
+    let context = mollusk.with_context(account_store);
 
     let checks = vec![
         Check::success(),
@@ -236,7 +236,7 @@ fn test_multiple_transfers_with_persistent_state_mt_chain() {
 #[test]
 fn test_account_store_default_account_mt() {
     let mollusk = MolluskMt::default();
-    let mut context = mollusk.with_context(HashMap::new());
+    let context = mollusk.with_context(HashMap::new());
 
     let non_existent_key = Pubkey::new_unique();
     let recipient = Pubkey::new_unique();
@@ -0,0 +1,158 @@
+#![cfg(all(feature = "fuzz", feature = "fuzz-fd"))]
+
+use {
+    mollusk_svm::{
+        fuzz::{
+            self,
+            convert::{firedancer_to_mollusk, mollusk_to_firedancer, Unrepresentable},
+        },
+        program::keyed_account_for_system_program,
+        Mollusk,
+    },
+    mollusk_svm_fuzz_fixture_firedancer::account::SeedAddress,
+    solana_account::Account,
+    solana_instruction::error::InstructionError,
+    solana_pubkey::Pubkey,
+    solana_rent::Rent,
+};
+
+const BASE_LAMPORTS: u64 = 100_000_000;
+const TRANSFER_AMOUNT: u64 = 42_000;
+
+fn transfer_fixture(mollusk: &Mollusk) -> mollusk_svm_fuzz_fixture::Fixture {
+    let sender = Pubkey::new_unique();
+    let recipient = Pubkey::new_unique();
+
+    let instruction =
+        solana_system_interface::instruction::transfer(&sender, &recipient, TRANSFER_AMOUNT);
+    // Include the program account, which the Firedancer layout requires, so
+    // round trips leave the accounts unchanged.
+    let accounts = vec![
+        (
+            sender,
+            Account::new(BASE_LAMPORTS, 0, &solana_sdk_ids::system_program::id()),
+        ),
+        (
+            recipient,
+            Account::new(BASE_LAMPORTS, 0, &solana_sdk_ids::system_program::id()),
+        ),
+        keyed_account_for_system_program(),
+    ];
+    let result = mollusk.process_instruction(&instruction, &accounts);
+    let mut fixture =
+        fuzz::mollusk::build_fixture_from_mollusk_test(mollusk, &instruction, &accounts, &result);
+    // Firedancer fixtures carry no execution time.
+    fixture.output.execution_time = 0;
+    fixture
+}
+
+#[test]
+fn test_round_trip_mollusk() {
+    let mut mollusk = Mollusk::default();
+    let fixture = transfer_fixture(&mollusk);
+
+    let converted = mollusk_to_firedancer(&fixture);
+    assert!(converted.unrepresentable.is_empty());
+    let fd_fixture = converted.fixture;
+    assert_eq!(fd_fixture.input.accounts.len(), 3);
+    mollusk.process_and_validate_firedancer_fixture(&fd_fixture);
+
+    let converted = firedancer_to_mollusk(&fd_fixture);
+    assert!(converted.unrepresentable.is_empty());
+    assert_eq!(converted.fixture, fixture);
+}
+
+#[test]
+fn test_round_trip_firedancer() {
+    let mut mollusk = Mollusk::default();
+    mollusk.warp_to_slot(1_000);
+    mollusk.slot = 1_000;
+    let fixture = transfer_fixture(&mollusk);
+    let (parsed, result) = fuzz::mollusk::load_fixture(&fixture);
+    let fd_fixture = fuzz::firedancer::build_fixture_from_mollusk_test(
+        &mollusk,
+        &parsed.instruction,
+        &parsed.accounts,
+        &result,
+    );
+
+    let converted = firedancer_to_mollusk(&fd_fixture);
+    assert!(converted.unrepresentable.is_empty());
+    // Sysvars are the defaults, warped to the slot.
+    assert_eq!(converted.fixture.input.sysvars, fixture.input.sysvars);
+    mollusk.process_and_validate_fixture(&converted.fixture);
+
+    let converted = mollusk_to_firedancer(&converted.fixture);
+    assert!(converted.unrepresentable.is_empty());
+    assert_eq!(converted.fixture, fd_fixture);
+}
+
+#[test]
+fn test_unrepresentable() {
+    let mut mollusk = Mollusk::default();
+    mollusk.sysvars.rent = Rent {
+        lamports_per_byte_year: 1,
+        ..Default::default()
+    };
+    mollusk.compute_budget.heap_size = 64 * 1024;
+    let mut fixture = transfer_fixture(&mollusk);
+    fixture.output.execution_time = 100;
+
+    let converted = mollusk_to_firedancer(&fixture);
+    assert_eq!(
+        converted.unrepresentable,
+        [
+            Unrepresentable::Sysvar("rent"),
+            Unrepresentable::ComputeBudget,
+            Unrepresentable::ExecutionTime(100),
+        ]
+    );
+
+    // Seed addresses, and errors other than program errors, can't be
+    // represented in the Mollusk layout.
+    let mut fd_fixture = converted.fixture;
+    let seed_address = SeedAddress {
+        base: vec![1; 32],
+        seed: b"seed".to_vec(),
+        owner: vec![2; 32],
+    };
+    fd_fixture.input.accounts[0].2 = Some(seed_address);
+    // Firedancer numbers errors by their variant index, plus one.
+    let error = bincode::serialize(&InstructionError::ExternalAccountLamportSpend).unwrap();
+    fd_fixture.output.program_result = i32::from_le_bytes(error[0..4].try_into().unwrap()) + 1;
+    let sender = fd_fixture.input.accounts[0].0;
+
+    let converted = firedancer_to_mollusk(&fd_fixture);
+    assert_eq!(
+        converted.unrepresentable,
+        [
+            Unrepresentable::SeedAddress(sender),
+            Unrepresentable::ProgramResult("ExternalAccountLamportSpend".to_string()),
+        ]
+    );
+    assert_eq!(converted.fixture.output.program_result, u64::MAX);
+}
+
+#[test]
+fn test_invalid_instruction_accounts() {
+    let mollusk = Mollusk::default();
+    let mut fixture = transfer_fixture(&mollusk);
+    let recipient = fixture.input.accounts.remove(1).0;
+
+    // The recipient is left out of the instruction, rather than panicking.
+    let converted = mollusk_to_firedancer(&fixture);
+    assert_eq!(
+        converted.unrepresentable,
+        [Unrepresentable::MissingInstructionAccount(recipient)]
+    );
+    let mut fd_fixture = converted.fixture;
+    assert_eq!(fd_fixture.input.instruction_accounts.len(), 1);
+
+    fd_fixture.input.instruction_accounts[0].index_in_transaction = 7;
+    let converted = firedancer_to_mollusk(&fd_fixture);
+    assert_eq!(
+        converted.unrepresentable,
+        [Unrepresentable::InvalidInstructionAccountIndex(7)]
+    );
+    assert!(converted.fixture.input.instruction_accounts.is_empty());
+}
@@ -0,0 +1,55 @@
+use {
+    mollusk_svm::{coverage::CoverageCollector, program::loader_keys, result::Check, Mollusk},
+    solana_instruction::Instruction,
+    solana_pubkey::Pubkey,
+};
+
+const MEMO_ID: Pubkey = solana_pubkey::pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+const MEMO_ELF: &[u8] = include_bytes!("../../programs/memo/src/elf/memo.so");
+
+fn memo_mollusk(coverage: &CoverageCollector) -> Mollusk {
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_with_elf_and_loader(&MEMO_ID, MEMO_ELF, &loader_keys::LOADER_V2);
+    mollusk.set_coverage_collector(coverage.clone());
+    mollusk
+}
+
+#[test]
+fn test_coverage_collector() {
+    let path = std::env::temp_dir()
+        .join(format!("mollusk-coverage-{}", Pubkey::new_unique()))
+        .join("lcov.info");
+
+    // Coverage recorded by an earlier run.
+    let existing = "TN:\nSF:src/lib.rs\nDA:1,1\nLF:1\nLH:1\nend_of_record\n";
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, existing).unwrap();
+
+    let coverage = CoverageCollector::new(&path);
+    let instruction = Instruction::new_with_bytes(MEMO_ID, b"covered", vec![]);
+
+    // Collectors are shared between instances, and enable tracing.
+    for _ in 0..2 {
+        let mollusk = memo_mollusk(&coverage);
+        let result =
+            mollusk.process_and_validate_instruction(&instruction, &[], &[Check::success()]);
+        assert_eq!(result.execution_trace.len(), 1);
+    }
+
+    // Tracing can't be disabled while a collector is attached.
+    let mut mollusk = memo_mollusk(&coverage);
+    assert!(mollusk.coverage_collector().is_some());
+    mollusk.set_tracing(false);
+    let result = mollusk.process_and_validate_instruction(&instruction, &[], &[Check::success()]);
+    assert_eq!(result.execution_trace.len(), 1);
+    drop(mollusk);
+
+    // The file is written when the last clone is dropped.
+    drop(coverage);
+
+    // The memo program is stripped of debug info, so it can't be mapped to
+    // source lines, and the earlier run's coverage is replaced.
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
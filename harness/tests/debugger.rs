@@ -0,0 +1,199 @@
+#![cfg(feature = "sbpf-debugger")]
+
+use {
+    mollusk_svm::{
+        debugger::DebuggerConfig, program::loader_keys, result::InstructionResult, Mollusk,
+    },
+    solana_instruction::{error::InstructionError, Instruction},
+    solana_log_collector::LogCollector,
+    solana_pubkey::Pubkey,
+    std::{
+        cell::RefCell,
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        rc::Rc,
+        thread,
+        time::Duration,
+    },
+};
+
+const MEMO_ID: Pubkey = solana_pubkey::pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+const MEMO_ELF: &[u8] = include_bytes!("../../programs/memo/src/elf/memo.so");
+
+/// A bare-bones GDB client.
+struct Client {
+    stream: TcpStream,
+    no_ack: bool,
+}
+
+impl Client {
+    fn connect(port: u16) -> Self {
+        for _ in 0..100 {
+            if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)) {
+                return Self {
+                    stream,
+                    no_ack: false,
+                };
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("Failed to connect to the debugger");
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn send(&mut self, packet: &str) -> String {
+        let checksum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", packet, checksum).unwrap();
+        if !self.no_ack {
+            assert_eq!(self.read_byte(), b'+');
+        }
+
+        while self.read_byte() != b'$' {}
+        let mut reply: Vec<u8> = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                // Run-length encoding: repeat the previous character.
+                b'*' => {
+                    let count = self.read_byte() - 29;
+                    let last = *reply.last().unwrap();
+                    reply.extend(std::iter::repeat(last).take(count as usize));
+                }
+                byte => reply.push(byte),
+            }
+        }
+        self.read_byte();
+        self.read_byte();
+        if !self.no_ack {
+            self.stream.write_all(b"+").unwrap();
+        }
+        String::from_utf8(reply).unwrap()
+    }
+
+    fn registers(&mut self) -> Vec<u64> {
+        let reply = self.send("g");
+        assert_eq!(reply.len(), 12 * 16);
+        (0..12)
+            .map(|i| {
+                let bytes = (0..8)
+                    .map(|j| {
+                        let start = i * 16 + j * 2;
+                        u8::from_str_radix(&reply[start..start + 2], 16).unwrap()
+                    })
+                    .collect::<Vec<_>>();
+                u64::from_le_bytes(bytes.try_into().unwrap())
+            })
+            .collect()
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn process_logged(
+    mollusk: &Mollusk,
+    instruction: &Instruction,
+) -> (InstructionResult, Vec<String>) {
+    let log = Rc::new(RefCell::new(LogCollector::default()));
+    let (result, _) = mollusk.process_instruction_log(instruction, &[], Some(log.clone()));
+    let messages = log.borrow().get_recorded_content().to_vec();
+    (result, messages)
+}
+
+#[test]
+fn test_debugger() {
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_with_elf_and_loader(&MEMO_ID, MEMO_ELF, &loader_keys::LOADER_V2);
+
+    let instruction = Instruction::new_with_bytes(MEMO_ID, b"debugged", vec![]);
+    let (expected, expected_logs) = process_logged(&mollusk, &instruction);
+    assert!(expected.raw_result.is_ok());
+
+    let port = free_port();
+    mollusk.debugger = Some(DebuggerConfig::new(port).with_program_id(MEMO_ID));
+
+    // Step through the first few instructions, inspecting the program, and
+    // then continue to the end of the program.
+    let client = thread::spawn(move || {
+        let mut client = Client::connect(port);
+        assert!(client
+            .send("qSupported:multiprocess+")
+            .contains("PacketSize"));
+        assert_eq!(client.send("QStartNoAckMode"), "OK");
+        client.no_ack = true;
+        assert!(client.send("?").starts_with("T05"));
+
+        let registers = client.registers();
+        // The input region is passed in `r1`, starting with the number of
+        // accounts.
+        assert_eq!(registers[1], 0x4_0000_0000);
+        assert_eq!(client.send("m400000000,8"), "0000000000000000");
+
+        let mut pcs = vec![registers[11]];
+        for _ in 0..5 {
+            assert_eq!(client.send("s"), "S05");
+            pcs.push(client.registers()[11]);
+        }
+        assert_eq!(client.send("c"), "W00");
+        pcs
+    });
+    let (result, logs) = process_logged(&mollusk, &instruction);
+    let pcs = client.join().unwrap();
+    assert_eq!(logs, expected_logs);
+    assert_eq!(result.program_result, expected.program_result);
+    assert_eq!(
+        result.compute_units_consumed,
+        expected.compute_units_consumed
+    );
+    assert!(pcs.windows(2).all(|pcs| pcs[0] != pcs[1]));
+
+    // Continue to a breakpoint, and then to the end of the program.
+    let client = thread::spawn(move || {
+        let mut client = Client::connect(port);
+        let breakpoint = format!("{:x}", pcs[5]);
+        assert_eq!(client.send(&format!("Z0,{},0", breakpoint)), "OK");
+        assert!(client.send("c").contains("swbreak"));
+        assert_eq!(client.registers()[11], pcs[5]);
+        assert_eq!(client.send(&format!("z0,{},0", breakpoint)), "OK");
+        assert_eq!(client.send("c"), "W00");
+    });
+    let (result, logs) = process_logged(&mollusk, &instruction);
+    client.join().unwrap();
+    assert_eq!(logs, expected_logs);
+    assert_eq!(result.program_result, expected.program_result);
+    assert_eq!(
+        result.compute_units_consumed,
+        expected.compute_units_consumed
+    );
+}
+
+#[test]
+fn test_debugger_port_in_use() {
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_with_elf_and_loader(&MEMO_ID, MEMO_ELF, &loader_keys::LOADER_V2);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    mollusk.debugger = Some(DebuggerConfig::new(port));
+
+    // The instruction fails instead of waiting for a client.
+    let instruction = Instruction::new_with_bytes(MEMO_ID, b"debugged", vec![]);
+    let (result, logs) = process_logged(&mollusk, &instruction);
+    assert_eq!(
+        result.raw_result,
+        Err(InstructionError::ProgramEnvironmentSetupFailure)
+    );
+    assert!(logs
+        .iter()
+        .any(|log| log.starts_with("Failed to listen for a GDB client")));
+}
@@ -25,6 +25,10 @@ impl FileType {
 }
 
 fn is_fixture_file(path: &Path, file_type: &FileType) -> bool {
+    #[cfg(feature = "fuzz")]
+    if path.ends_with(mollusk_svm_fuzz_fixture::index::INDEX_FILE_NAME) {
+        return false;
+    }
     if path.is_file() {
         let path = path.to_str().unwrap();
         if path.ends_with(file_type.extension()) {
@@ -165,7 +169,7 @@ impl<'a> TestSetup<'a> {
 #[test]
 #[serial]
 fn test_dump_mollusk() {
-    use mollusk_svm_fuzz_fixture::Fixture;
+    use mollusk_svm_fuzz_fixture::{chain::ChainFixture, index::FixtureIndex, Fixture};
 
     const EJECT_FUZZ_FIXTURES: &str = "./tests/mollusk-fixtures";
 
@@ -208,12 +212,24 @@ fn test_dump_mollusk() {
         &setup.accounts,
     );
 
-    // Ensure there are three of each fixture type in the target directory.
+    // Ensure there are three of each instruction fixture type in the target
+    // directory, along with one of each chain fixture type.
     let dir = std::fs::read_dir(EJECT_FUZZ_FIXTURES).unwrap();
     let mut count_blob = 0;
     let mut count_json = 0;
+    let mut chain_blob = None;
+    let mut chain_json = None;
     for entry in dir {
         let path = entry.unwrap().path();
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        if file_name.starts_with("chain-") {
+            if is_fixture_file(&path, &FileType::Blob) {
+                assert!(chain_blob.replace(path).is_none());
+            } else if is_fixture_file(&path, &FileType::Json) {
+                assert!(chain_json.replace(path).is_none());
+            }
+            continue;
+        }
         if is_fixture_file(&path, &FileType::Blob) {
             count_blob += 1;
         }
@@ -224,6 +240,44 @@ fn test_dump_mollusk() {
     assert_eq!(count_blob, 3);
     assert_eq!(count_json, 3);
 
+    // Each instruction fixture is indexed, along with the test it came from.
+    // The instructions only differ in the lamports of their accounts, so
+    // they're clustered together.
+    let index = FixtureIndex::load(EJECT_FUZZ_FIXTURES);
+    assert_eq!(index.fixtures.len(), 3);
+    assert!(index.fixtures.iter().all(|entry| {
+        entry.program_id == setup.instruction.program_id.to_string()
+            && entry.test.as_deref() == Some("test_dump_mollusk")
+            && entry.accounts.iter().all(|account| account.modified)
+    }));
+    assert_eq!(index.clusters(4).len(), 1);
+    assert_eq!(index, FixtureIndex::sync(EJECT_FUZZ_FIXTURES));
+
+    // Validate the chain fixtures, and replay them.
+    let chain_blob = chain_blob.unwrap();
+    let chain_json = chain_json.unwrap();
+    assert_filenames_match(chain_blob.to_str().unwrap(), chain_json.to_str().unwrap());
+
+    let chain_fixture = ChainFixture::load_from_blob_file(chain_blob.to_str().unwrap());
+    assert_eq!(
+        chain_fixture,
+        ChainFixture::load_from_json_file(chain_json.to_str().unwrap())
+    );
+    assert_eq!(
+        chain_fixture.input.instructions,
+        vec![setup.instruction.clone(); 3]
+    );
+    assert_eq!(chain_fixture.input.accounts, setup.accounts);
+    assert_eq!(chain_fixture.output.instructions.len(), 3);
+
+    let mut mollusk = Mollusk::default();
+    let results = mollusk.process_and_validate_chain_fixture(&chain_fixture);
+    assert_eq!(results.len(), 3);
+    assert_eq!(
+        results[2].get_account(&sender).unwrap().lamports,
+        BASE_LAMPORTS - 3 * TRANSFER_AMOUNT,
+    );
+
     std::env::remove_var("EJECT_FUZZ_FIXTURES");
     std::env::remove_var("EJECT_FUZZ_FIXTURES_JSON");
     clear(EJECT_FUZZ_FIXTURES);
@@ -296,3 +350,127 @@ fn test_dump_firedancer() {
     std::env::remove_var("EJECT_FUZZ_FIXTURES_JSON_FD");
     clear(EJECT_FUZZ_FIXTURES_FD);
 }
+
+#[cfg(feature = "fuzz")]
+#[test]
+#[serial]
+fn test_dump_programmatic_context() {
+    use {
+        mollusk_svm::{fuzz::FixtureEjection, mt::MolluskMt},
+        mollusk_svm_fuzz_fixture::{chain::ChainFixture, Fixture},
+        std::collections::HashMap,
+    };
+
+    const EJECT_DIR: &str = "./tests/context-fixtures";
+
+    let sender = Pubkey::new_unique();
+    let recipient = Pubkey::new_unique();
+    let setup = TestSetup::new(&sender, &recipient);
+    let account_store = setup.accounts.iter().cloned().collect::<HashMap<_, _>>();
+
+    // Eject Mollusk fixtures only, since Firedancer blobs share the extension.
+    // The Firedancer directories only exist with `fuzz-fd`.
+    #[allow(clippy::needless_update)]
+    let ejection = |json: bool| FixtureEjection {
+        blob_dir: Some(EJECT_DIR.to_string()),
+        json_dir: json.then(|| EJECT_DIR.to_string()),
+        ..Default::default()
+    };
+
+    // Count the blob fixtures in the directory, ensuring the chain fixture
+    // and every single-instruction fixture - unless the fixtures come from a
+    // chain - were ejected with the store's accounts as their input.
+    let count_fixtures = |from_chain: bool| {
+        let mut count = 0;
+        for entry in std::fs::read_dir(EJECT_DIR).unwrap() {
+            let path = entry.unwrap().path();
+            if !is_fixture_file(&path, &FileType::Blob) {
+                continue;
+            }
+            let file_name = path.file_name().unwrap().to_str().unwrap();
+            let path = path.to_str().unwrap();
+            let accounts = if file_name.starts_with("chain-") {
+                Some(ChainFixture::load_from_blob_file(path).input.accounts)
+            } else if !from_chain {
+                Some(Fixture::load_from_blob_file(path).input.accounts)
+            } else {
+                None
+            };
+            if let Some(accounts) = accounts {
+                for account in &setup.accounts {
+                    assert!(accounts.contains(account));
+                }
+            }
+            count += 1;
+        }
+        count
+    };
+
+    // `MolluskContext`.
+    clear(EJECT_DIR);
+    let mollusk = Mollusk {
+        fixture_ejection: ejection(false),
+        ..Default::default()
+    };
+    let context = mollusk.with_context(account_store.clone());
+    context.process_and_validate_instruction(&setup.instruction, &setup.checks);
+    assert_eq!(count_fixtures(false), 1);
+
+    clear(EJECT_DIR);
+    let mollusk = Mollusk {
+        fixture_ejection: ejection(true),
+        ..Default::default()
+    };
+    let context = mollusk.with_context(account_store.clone());
+    context.process_and_validate_instruction_chain(&[
+        (&setup.instruction, &setup.checks),
+        (&setup.instruction, &[]),
+    ]);
+    // One fixture per instruction, plus the chain fixture.
+    assert_eq!(count_fixtures(true), 3);
+    assert!(find_fixture(EJECT_DIR, &FileType::Json).is_some());
+
+    // `MolluskMt`, directly and through `MolluskContextMt`.
+    clear(EJECT_DIR);
+    let mollusk = MolluskMt {
+        fixture_ejection: ejection(false),
+        ..Default::default()
+    };
+    mollusk.process_and_validate_instruction(&setup.instruction, &setup.accounts, &setup.checks);
+    assert_eq!(count_fixtures(false), 1);
+
+    clear(EJECT_DIR);
+    let context = mollusk.with_context(account_store);
+    context.process_and_validate_instruction(&setup.instruction, &setup.checks);
+    assert_eq!(count_fixtures(false), 1);
+
+    // The store has been updated by the transfer, so the chain is ejected
+    // starting from its new state.
+    clear(EJECT_DIR);
+    context.process_and_validate_instruction_chain(&[(&setup.instruction, &[])]);
+    let chain_blob = std::fs::read_dir(EJECT_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| {
+            path.file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .starts_with("chain-")
+                && is_fixture_file(path, &FileType::Blob)
+        })
+        .unwrap();
+    let chain_fixture = ChainFixture::load_from_blob_file(chain_blob.to_str().unwrap());
+    let input = |key| {
+        chain_fixture
+            .input
+            .accounts
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, account)| account.lamports)
+    };
+    assert_eq!(input(&sender), Some(BASE_LAMPORTS - TRANSFER_AMOUNT));
+    assert_eq!(input(&recipient), Some(BASE_LAMPORTS + TRANSFER_AMOUNT));
+
+    clear(EJECT_DIR);
+}
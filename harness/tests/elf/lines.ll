@@ -0,0 +1,34 @@
+; A function with DWARF line info, for testing source line coverage.
+;
+; Build with:
+;
+;     llc -O0 -march=bpfel -filetype=obj lines.ll -o lines.o
+;
+; Each instruction maps to its own line of `/program/src/lib.c`:
+;
+;     pc 0: line 1
+;     pc 1: line 2
+;     pc 2: line 3
+;     pc 3: line 4
+
+target triple = "bpfel"
+
+define i64 @entrypoint(i64 %input) !dbg !5 {
+  %doubled = mul i64 %input, 2, !dbg !8
+  %sum = add i64 %doubled, 1, !dbg !9
+  ret i64 %sum, !dbg !10
+}
+
+!llvm.dbg.cu = !{!0}
+!llvm.module.flags = !{!3, !4}
+
+!0 = distinct !DICompileUnit(language: DW_LANG_C99, file: !1, producer: "llc", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug)
+!1 = !DIFile(filename: "src/lib.c", directory: "/program")
+!3 = !{i32 7, !"Dwarf Version", i32 4}
+!4 = !{i32 2, !"Debug Info Version", i32 3}
+!5 = distinct !DISubprogram(name: "entrypoint", scope: !1, file: !1, line: 1, type: !6, scopeLine: 1, spFlags: DISPFlagDefinition, unit: !0)
+!6 = !DISubroutineType(types: !7)
+!7 = !{null}
+!8 = !DILocation(line: 2, column: 3, scope: !5)
+!9 = !DILocation(line: 3, column: 3, scope: !5)
+!10 = !DILocation(line: 4, column: 3, scope: !5)
@@ -0,0 +1,135 @@
+#![cfg(feature = "fuzz-fd")]
+
+use {
+    mollusk_svm::{
+        fuzz::backend::{BackendError, ExecutionBackend, ProcessBackend},
+        program::keyed_account_for_system_program,
+        result::{Compare, Config},
+        Mollusk,
+    },
+    mollusk_svm_fuzz_fixture_firedancer::context::{Context, EpochContext},
+    solana_account::Account,
+    solana_pubkey::Pubkey,
+    solana_transaction_context::InstructionAccount,
+};
+
+fn transfer_context(mollusk: &Mollusk) -> Context {
+    let sender = Pubkey::new_unique();
+    let recipient = Pubkey::new_unique();
+    let (system_program, system_program_account) = keyed_account_for_system_program();
+
+    let instruction_account = |index_in_transaction, is_signer, is_writable| InstructionAccount {
+        index_in_transaction,
+        index_in_caller: index_in_transaction,
+        index_in_callee: index_in_transaction,
+        is_signer,
+        is_writable,
+    };
+
+    Context {
+        program_id: system_program,
+        accounts: vec![
+            (sender, Account::new(1_000_000, 0, &system_program), None),
+            (recipient, Account::new(0, 0, &system_program), None),
+            (system_program, system_program_account, None),
+        ],
+        instruction_accounts: vec![
+            instruction_account(0, true, true),
+            instruction_account(1, false, true),
+        ],
+        instruction_data: solana_system_interface::instruction::transfer(
+            &sender, &recipient, 400_000,
+        )
+        .data,
+        compute_units_available: 200_000,
+        epoch_context: EpochContext {
+            feature_set: mollusk.feature_set.clone(),
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_mollusk_backend() {
+    let mut mollusk = Mollusk::default();
+    let context = transfer_context(&mollusk);
+
+    let result = mollusk.execute(&context).unwrap();
+    assert!(!result.program_result.is_err());
+    assert_eq!(
+        result.get_account(&context.accounts[1].0).unwrap().lamports,
+        400_000,
+    );
+}
+
+#[test]
+fn test_mollusk_backend_program_not_cached() {
+    let mut mollusk = Mollusk::default();
+    let mut context = transfer_context(&mollusk);
+    context.program_id = Pubkey::new_unique();
+
+    assert!(matches!(
+        mollusk.execute(&context),
+        Err(BackendError::ProgramNotCached(program_id)) if program_id == context.program_id,
+    ));
+}
+
+#[test]
+fn test_process_backend() {
+    let mollusk = Mollusk::default();
+    let context = transfer_context(&mollusk);
+
+    // A backend which writes empty effects: success, with no accounts
+    // modified and no compute units left.
+    let mut backend = ProcessBackend::new("sh").arg("-c").arg("cat > /dev/null");
+    let result = backend.execute(&context).unwrap();
+    assert!(!result.program_result.is_err());
+    assert_eq!(result.compute_units_consumed, 200_000);
+    assert_eq!(
+        result.get_account(&context.accounts[1].0).unwrap().lamports,
+        0,
+    );
+
+    // Mollusk diverges from it on the recipient's lamports.
+    let mut mollusk = Mollusk::default();
+    let mollusk_result = mollusk.execute(&context).unwrap();
+    let config = Config {
+        panic: false,
+        verbose: false,
+    };
+    assert!(mollusk_result.compare_with_config(&result, &[Compare::ProgramResult], &config));
+    assert!(!mollusk_result.compare_with_config(
+        &result,
+        &[Compare::all_resulting_accounts()],
+        &config,
+    ));
+}
+
+#[test]
+fn test_process_backend_errors() {
+    let mollusk = Mollusk::default();
+    let context = transfer_context(&mollusk);
+
+    let mut backend = ProcessBackend::new("sh")
+        .arg("-c")
+        .arg("echo 'no shim' >&2; exit 3");
+    match backend.execute(&context) {
+        Err(BackendError::Failed { status, stderr }) => {
+            assert_eq!(status.code(), Some(3));
+            assert_eq!(stderr, "no shim\n");
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let mut backend = ProcessBackend::new("sh").arg("-c").arg("printf '\\377'");
+    assert!(matches!(
+        backend.execute(&context),
+        Err(BackendError::InvalidEffects(_)),
+    ));
+
+    let mut backend = ProcessBackend::new("/nonexistent/backend");
+    assert!(matches!(
+        backend.execute(&context),
+        Err(BackendError::Io(_)),
+    ));
+}
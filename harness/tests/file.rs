@@ -0,0 +1,126 @@
+use {
+    mollusk_svm::{file, Mollusk},
+    mollusk_svm_error::error::MolluskError,
+    std::path::PathBuf,
+};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mollusk-file-test-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_try_load_program_elf_from() {
+    let first = temp_dir("first");
+    let second = temp_dir("second");
+    std::fs::write(second.join("my_program.so"), [1, 2, 3]).unwrap();
+    std::fs::write(first.join("my_program.so"), [4, 5, 6]).unwrap();
+
+    // Search paths are checked in order.
+    assert_eq!(
+        file::try_load_program_elf_from("my_program", &[first.clone(), second.clone()]).unwrap(),
+        vec![4, 5, 6],
+    );
+    assert_eq!(
+        file::try_load_program_elf_from("my_program", &[second.clone(), first.clone()]).unwrap(),
+        vec![1, 2, 3],
+    );
+
+    std::fs::remove_dir_all(first).unwrap();
+    std::fs::remove_dir_all(second).unwrap();
+}
+
+#[test]
+fn test_file_not_found() {
+    let dir = temp_dir("not-found");
+    let missing = dir.join("missing");
+    std::fs::write(dir.join("my_progam.so"), []).unwrap();
+    std::fs::write(dir.join("notes.txt"), []).unwrap();
+
+    let search_paths = vec![missing.clone(), dir.clone()];
+    let err = file::try_load_program_elf_from("my_program", &search_paths).unwrap_err();
+
+    match &err {
+        MolluskError::FileNotFound {
+            file_name,
+            searched,
+            nearby,
+        } => {
+            assert_eq!(file_name, "my_program.so");
+            assert_eq!(searched, &search_paths);
+            assert_eq!(nearby, &vec!["my_progam.so".to_string()]);
+        }
+        _ => panic!("unexpected error: {err}"),
+    }
+
+    let message = err.to_string();
+    assert!(message.contains(&missing.display().to_string()));
+    assert!(message.contains(&dir.display().to_string()));
+    assert!(message.contains("my_progam.so"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_unreadable_file() {
+    let dir = temp_dir("unreadable");
+    let path = dir.join("my_program.so");
+    std::fs::create_dir(&path).unwrap();
+
+    // A directory with the program's file name is found, but can't be read.
+    let err = file::try_load_program_elf_from("my_program", &[dir.clone()]).unwrap_err();
+    match &err {
+        MolluskError::FileReadError(read_path) => assert_eq!(read_path, &path),
+        _ => panic!("unexpected error: {err}"),
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_default_search_paths_include_workspace_deploy_dir() {
+    let workspace_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let expected = std::env::var("CARGO_TARGET_DIR")
+        .map(|target_dir| PathBuf::from(target_dir).join("deploy"))
+        .unwrap_or_else(|_| workspace_root.join("target/deploy"));
+
+    assert!(file::default_shared_object_dirs().contains(&expected));
+}
+
+#[test]
+#[should_panic(expected = "Program file not found: does_not_exist.so")]
+fn test_with_program_search_path() {
+    let dir = temp_dir("with-search-path");
+
+    let mut mollusk = Mollusk::default().with_program_search_path(&dir);
+    assert_eq!(mollusk.program_search_paths, vec![dir]);
+
+    mollusk.add_program(
+        &solana_pubkey::Pubkey::new_unique(),
+        "does_not_exist",
+        &mollusk_svm::program::loader_keys::LOADER_V3,
+    );
+}
+
+#[test]
+fn test_new_with_search_paths() {
+    let dir = temp_dir("new-with-search-paths");
+    let elf = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../programs/memo/src/elf/memo.so");
+    std::fs::copy(elf, dir.join("search_path_memo.so")).unwrap();
+
+    // The program is loaded from the search path, which is kept for programs
+    // added later.
+    let mollusk = Mollusk::new_with_search_paths(
+        &solana_pubkey::Pubkey::new_unique(),
+        "search_path_memo",
+        [&dir],
+    );
+    assert_eq!(mollusk.program_search_paths, vec![dir.clone()]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,312 @@
+#![cfg(feature = "fuzz-fd")]
+
+use {
+    mollusk_svm::{
+        fuzz::firedancer_syscall::SyscallCompare, program::keyed_account_for_system_program,
+        Mollusk,
+    },
+    mollusk_svm_fuzz_fixture_firedancer::{
+        context::{Context as InstrContext, EpochContext, SlotContext},
+        syscall::{
+            Context as SyscallContext, Effects as SyscallEffects, ErrorKind,
+            Fixture as SyscallFixture, SyscallInvocation, VmContext,
+        },
+        txn::{
+            CompiledInstruction, Context as TxnContext, Effects as TxnEffects, FeeDetails,
+            Fixture as TxnFixture, Message, MessageHeader, Transaction,
+        },
+    },
+    solana_account::Account,
+    solana_program_runtime::solana_sbpf::ebpf::MM_HEAP_START,
+    solana_pubkey::Pubkey,
+    solana_system_interface::error::SystemError,
+};
+
+const FEE: u64 = 5_000;
+const SYSTEM_TRANSFER_UNITS: u64 = 150;
+// The variant index of `InstructionError::Custom`, plus one.
+const CUSTOM_ERROR: u32 = 26;
+
+fn transfer_fixture(
+    mollusk: &Mollusk,
+    payer_lamports: u64,
+    amounts: &[u64],
+) -> (TxnFixture, Pubkey, Pubkey) {
+    let payer = Pubkey::new_unique();
+    let recipient = Pubkey::new_unique();
+    let (system_program, system_program_account) = keyed_account_for_system_program();
+
+    let instructions = amounts
+        .iter()
+        .map(|amount| CompiledInstruction {
+            program_id_index: 2,
+            accounts: vec![0, 1],
+            data: solana_system_interface::instruction::transfer(&payer, &recipient, *amount).data,
+        })
+        .collect();
+
+    let input = TxnContext {
+        transaction: Transaction {
+            message: Message {
+                is_legacy: true,
+                header: MessageHeader {
+                    num_required_signatures: 1,
+                    num_readonly_signed_accounts: 0,
+                    num_readonly_unsigned_accounts: 1,
+                },
+                account_keys: vec![payer, recipient, system_program],
+                instructions,
+                ..Default::default()
+            },
+            signatures: vec![vec![0; 64]],
+            ..Default::default()
+        },
+        accounts: vec![
+            (
+                payer,
+                Account::new(payer_lamports, 0, &system_program),
+                None,
+            ),
+            (recipient, Account::new(0, 0, &system_program), None),
+            (system_program, system_program_account, None),
+        ],
+        epoch_context: EpochContext {
+            feature_set: mollusk.feature_set.clone(),
+        },
+        ..Default::default()
+    };
+
+    let fixture = TxnFixture {
+        metadata: None,
+        input,
+        output: TxnEffects::default(),
+    };
+    (fixture, payer, recipient)
+}
+
+#[test]
+fn test_transaction_fixture_transfer() {
+    let mut mollusk = Mollusk::default();
+
+    let payer_lamports = 1_000_000_000;
+    let amount = 100_000_000;
+    let (mut fixture, payer, recipient) = transfer_fixture(&mollusk, payer_lamports, &[amount]);
+    let system_program = solana_sdk_ids::system_program::id();
+
+    fixture.output = TxnEffects {
+        executed: true,
+        is_ok: true,
+        resulting_accounts: vec![
+            (
+                payer,
+                Account::new(payer_lamports - FEE - amount, 0, &system_program),
+                None,
+            ),
+            (recipient, Account::new(amount, 0, &system_program), None),
+        ],
+        executed_units: SYSTEM_TRANSFER_UNITS,
+        fee_details: FeeDetails {
+            transaction_fee: FEE,
+            prioritization_fee: 0,
+        },
+        ..Default::default()
+    };
+
+    let result = mollusk.process_and_validate_firedancer_transaction_fixture(&fixture);
+    assert!(result.executed);
+    assert_eq!(result.fee_details, fixture.output.fee_details);
+}
+
+#[test]
+fn test_transaction_fixture_failed_instruction() {
+    let mut mollusk = Mollusk::default();
+
+    let payer_lamports = 1_000_000_000;
+    let (mut fixture, payer, _) = transfer_fixture(&mollusk, payer_lamports, &[1, payer_lamports]);
+    let system_program = solana_sdk_ids::system_program::id();
+
+    // The fee is charged, but the first transfer is rolled back.
+    fixture.output = TxnEffects {
+        executed: true,
+        resulting_accounts: vec![(
+            payer,
+            Account::new(payer_lamports - FEE, 0, &system_program),
+            None,
+        )],
+        instruction_error: CUSTOM_ERROR,
+        instruction_error_index: 1,
+        custom_error: SystemError::ResultWithNegativeLamports as u32,
+        executed_units: 2 * SYSTEM_TRANSFER_UNITS,
+        fee_details: FeeDetails {
+            transaction_fee: FEE,
+            prioritization_fee: 0,
+        },
+        ..Default::default()
+    };
+
+    let result = mollusk.process_and_validate_firedancer_transaction_fixture(&fixture);
+    assert_eq!(result.instruction_error_index, Some(1));
+}
+
+#[test]
+fn test_transaction_fixture_fee_payer_cannot_pay() {
+    let mut mollusk = Mollusk::default();
+
+    let (mut fixture, _, _) = transfer_fixture(&mollusk, FEE - 1, &[1]);
+
+    // Nothing is charged or executed.
+    fixture.output = TxnEffects::default();
+
+    let result = mollusk.process_and_validate_firedancer_transaction_fixture(&fixture);
+    assert!(!result.executed);
+}
+
+#[test]
+fn test_transaction_fixture_account_index_out_of_range() {
+    let mut mollusk = Mollusk::default();
+
+    let (mut fixture, _, _) = transfer_fixture(&mollusk, 1_000_000_000, &[1]);
+    fixture.input.transaction.message.instructions[0].accounts[1] = 7;
+
+    // The transaction fails sanitization, rather than panicking.
+    fixture.output = TxnEffects {
+        sanitization_error: true,
+        ..Default::default()
+    };
+
+    let result = mollusk.process_and_validate_firedancer_transaction_fixture(&fixture);
+    assert!(result.sanitization_error);
+    assert!(!result.executed);
+}
+
+fn memset_fixture(mollusk: &Mollusk, registers: [u64; 12]) -> SyscallFixture {
+    let program_id = Pubkey::new_unique();
+    let mut program_account = Account::new(0, 0, &solana_sdk_ids::bpf_loader_upgradeable::id());
+    program_account.executable = true;
+
+    SyscallFixture {
+        metadata: None,
+        input: SyscallContext {
+            vm_context: VmContext {
+                heap_max: 64,
+                registers,
+                ..Default::default()
+            },
+            instruction_context: InstrContext {
+                program_id,
+                accounts: vec![(program_id, program_account, None)],
+                instruction_accounts: vec![],
+                instruction_data: vec![],
+                compute_units_available: 1_000,
+                slot_context: SlotContext::default(),
+                epoch_context: EpochContext {
+                    feature_set: mollusk.feature_set.clone(),
+                },
+            },
+            invocation: SyscallInvocation {
+                function_name: b"sol_memset_".to_vec(),
+                heap_prefix: vec![1; 8],
+                stack_prefix: vec![],
+            },
+        },
+        output: SyscallEffects::default(),
+    }
+}
+
+#[test]
+fn test_syscall_fixture_memset() {
+    let mut mollusk = Mollusk::default();
+
+    let mut registers = [0; 12];
+    registers[1] = MM_HEAP_START + 4;
+    registers[2] = 0xab;
+    registers[3] = 16;
+    let mut fixture = memset_fixture(&mollusk, registers);
+
+    let mut heap = vec![0; 64];
+    heap[..8].fill(1);
+    heap[4..20].fill(0xab);
+    fixture.output = SyscallEffects {
+        heap,
+        // The syscall's base cost.
+        compute_units_available: 1_000 - 10,
+        ..Default::default()
+    };
+
+    let result = mollusk.process_and_partially_validate_firedancer_syscall_fixture(
+        &fixture,
+        &[
+            SyscallCompare::ComputeUnits,
+            SyscallCompare::Result,
+            SyscallCompare::Heap,
+        ],
+    );
+    assert_eq!(result.error, 0);
+    // The stack is zeroed, beyond any prefix.
+    assert!(result.stack.iter().all(|byte| *byte == 0));
+}
+
+#[test]
+fn test_syscall_fixture_access_violation() {
+    let mut mollusk = Mollusk::default();
+
+    // Write past the end of the heap. Memory is written up to the end of the
+    // heap before the access violation.
+    let mut registers = [0; 12];
+    registers[1] = MM_HEAP_START + 60;
+    registers[2] = 0xab;
+    registers[3] = 16;
+    let mut fixture = memset_fixture(&mollusk, registers);
+
+    let mut heap = vec![0; 64];
+    heap[..8].fill(1);
+    heap[60..].fill(0xab);
+    fixture.output = SyscallEffects {
+        // The variant index of `EbpfError::AccessViolation`, plus one.
+        error: 12,
+        error_kind: ErrorKind::Ebpf,
+        heap,
+        compute_units_available: 1_000 - 10,
+        ..Default::default()
+    };
+
+    mollusk.process_and_partially_validate_firedancer_syscall_fixture(
+        &fixture,
+        &[
+            SyscallCompare::ComputeUnits,
+            SyscallCompare::Result,
+            SyscallCompare::Heap,
+        ],
+    );
+}
+
+#[test]
+fn test_syscall_fixture_not_invoked() {
+    let mut mollusk = Mollusk::default();
+
+    let unspecified = SyscallEffects {
+        error: -1,
+        error_kind: ErrorKind::Unspecified,
+        ..Default::default()
+    };
+
+    // An unregistered syscall.
+    let mut fixture = memset_fixture(&mollusk, [0; 12]);
+    fixture.input.invocation.function_name = b"sol_unknown_".to_vec();
+    fixture.output = unspecified.clone();
+    let result = mollusk.process_and_partially_validate_firedancer_syscall_fixture(
+        &fixture,
+        &[SyscallCompare::Result],
+    );
+    assert_eq!(result.error, -1);
+
+    // A heap larger than the maximum heap frame.
+    let mut fixture = memset_fixture(&mollusk, [0; 12]);
+    fixture.input.vm_context.heap_max = u64::MAX;
+    fixture.output = unspecified;
+    let result = mollusk.process_and_partially_validate_firedancer_syscall_fixture(
+        &fixture,
+        &[SyscallCompare::Result, SyscallCompare::Heap],
+    );
+    assert!(result.heap.is_empty());
+}
@@ -0,0 +1,367 @@
+#![cfg(feature = "invocation-inspect-callback")]
+
+use {
+    mollusk_svm::{program::loader_keys, result::Check, InvocationInspectCallback, Mollusk},
+    solana_account::Account,
+    solana_instruction::{error::InstructionError, AccountMeta, Instruction},
+    solana_program_error::ProgramError,
+    solana_program_runtime::invoke_context::InvokeContext,
+    solana_pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    },
+};
+
+const ASSOCIATED_TOKEN_ID: Pubkey =
+    solana_pubkey::pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+const ASSOCIATED_TOKEN_ELF: &[u8] =
+    include_bytes!("../../programs/token/src/elf/associated_token.so");
+const TOKEN_ID: Pubkey = solana_pubkey::pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+const TOKEN_ELF: &[u8] = include_bytes!("../../programs/token/src/elf/token.so");
+
+fn mint_account(mollusk: &Mollusk) -> Account {
+    // Packed `Mint`: no mint authority, zero supply, zero decimals,
+    // initialized, no freeze authority.
+    let mut data = vec![0; 82];
+    data[45] = 1;
+    Account {
+        lamports: mollusk.minimum_balance_for_rent_exemption(data.len()),
+        data,
+        owner: TOKEN_ID,
+        ..Default::default()
+    }
+}
+
+/// Set up an instruction creating an associated token account, which invokes
+/// the token and system programs via CPI.
+fn create_associated_token_account() -> (Mollusk, Instruction, Vec<(Pubkey, Account)>) {
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_with_elf_and_loader(
+        &ASSOCIATED_TOKEN_ID,
+        ASSOCIATED_TOKEN_ELF,
+        &loader_keys::LOADER_V2,
+    );
+    mollusk.add_program_with_elf_and_loader(&TOKEN_ID, TOKEN_ELF, &loader_keys::LOADER_V2);
+
+    let payer = Pubkey::new_unique();
+    let wallet = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let (associated_token_address, _) = Pubkey::find_program_address(
+        &[wallet.as_ref(), TOKEN_ID.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_ID,
+    );
+
+    let instruction = Instruction::new_with_bytes(
+        ASSOCIATED_TOKEN_ID,
+        &[0],
+        vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new(associated_token_address, false),
+            AccountMeta::new_readonly(wallet, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(solana_sdk_ids::system_program::id(), false),
+            AccountMeta::new_readonly(TOKEN_ID, false),
+        ],
+    );
+    let accounts = vec![
+        (
+            payer,
+            Account::new(1_000_000_000, 0, &solana_sdk_ids::system_program::id()),
+        ),
+        (associated_token_address, Account::default()),
+        (wallet, Account::default()),
+        (mint, mint_account(&mollusk)),
+        mollusk_svm::program::keyed_account_for_system_program(),
+        (
+            TOKEN_ID,
+            mollusk_svm::program::create_program_account_loader_v2(TOKEN_ELF),
+        ),
+    ];
+
+    (mollusk, instruction, accounts)
+}
+
+type CpiResult = (Pubkey, Result<(), InstructionError>);
+
+#[derive(Clone, Default)]
+struct CpiRecorder {
+    before: Arc<Mutex<Vec<(Pubkey, usize)>>>,
+    after: Arc<Mutex<Vec<CpiResult>>>,
+}
+
+impl InvocationInspectCallback for CpiRecorder {
+    fn before_cpi(
+        &self,
+        instruction: &Instruction,
+        invoke_context: &InvokeContext,
+    ) -> Result<(), InstructionError> {
+        self.before
+            .lock()
+            .unwrap()
+            .push((instruction.program_id, invoke_context.get_stack_height()));
+        Ok(())
+    }
+
+    fn after_cpi(
+        &self,
+        instruction: &Instruction,
+        _invoke_context: &InvokeContext,
+        result: Result<(), InstructionError>,
+    ) -> Result<(), InstructionError> {
+        self.after
+            .lock()
+            .unwrap()
+            .push((instruction.program_id, result.clone()));
+        result
+    }
+}
+
+#[test]
+fn test_cpi_hooks() {
+    let (mut mollusk, instruction, accounts) = create_associated_token_account();
+
+    let recorder = CpiRecorder::default();
+    mollusk.invocation_inspect_callback = Box::new(recorder.clone());
+
+    mollusk.process_and_validate_instruction(&instruction, &accounts, &[Check::success()]);
+
+    let before = recorder.before.lock().unwrap().clone();
+    let after = recorder.after.lock().unwrap().clone();
+
+    // Every CPI is made by the associated token program, at stack height 1.
+    assert!(!before.is_empty());
+    assert!(before.iter().all(|(_, stack_height)| *stack_height == 1));
+    let invoked = before.iter().map(|(program_id, _)| *program_id);
+    assert!(invoked.clone().any(|id| id == TOKEN_ID));
+    assert!(invoked
+        .clone()
+        .any(|id| id == solana_sdk_ids::system_program::id()));
+
+    assert_eq!(
+        after,
+        before
+            .iter()
+            .map(|(program_id, _)| (*program_id, Ok(())))
+            .collect::<Vec<_>>(),
+    );
+}
+
+struct AbortSystemProgramCpi;
+
+impl InvocationInspectCallback for AbortSystemProgramCpi {
+    fn before_cpi(
+        &self,
+        instruction: &Instruction,
+        _invoke_context: &InvokeContext,
+    ) -> Result<(), InstructionError> {
+        if instruction.program_id == solana_sdk_ids::system_program::id() {
+            return Err(InstructionError::Custom(42));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_before_cpi_abort() {
+    let (mut mollusk, instruction, accounts) = create_associated_token_account();
+    mollusk.invocation_inspect_callback = Box::new(AbortSystemProgramCpi);
+
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &accounts,
+        &[
+            Check::err(ProgramError::Custom(42)),
+            Check::account(&accounts[1].0).lamports(0).build(),
+        ],
+    );
+}
+
+struct FailTokenProgramCpi;
+
+impl InvocationInspectCallback for FailTokenProgramCpi {
+    fn after_cpi(
+        &self,
+        instruction: &Instruction,
+        _invoke_context: &InvokeContext,
+        result: Result<(), InstructionError>,
+    ) -> Result<(), InstructionError> {
+        result?;
+        if instruction.program_id == TOKEN_ID {
+            return Err(InstructionError::Custom(7));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_after_cpi_inject_failure() {
+    let (mut mollusk, instruction, accounts) = create_associated_token_account();
+    mollusk.invocation_inspect_callback = Box::new(FailTokenProgramCpi);
+
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &accounts,
+        &[Check::err(ProgramError::Custom(7))],
+    );
+}
+
+struct RewriteInstruction;
+
+impl InvocationInspectCallback for RewriteInstruction {
+    fn before_instruction(
+        &self,
+        instruction: &mut Instruction,
+        accounts: &mut Vec<(Pubkey, Account)>,
+    ) -> Result<(), InstructionError> {
+        match instruction.data.first() {
+            Some(0) => Err(InstructionError::Custom(0)),
+            _ => {
+                instruction.data.reverse();
+                accounts[0].1.lamports += 1;
+                Ok(())
+            }
+        }
+    }
+
+    fn after_instruction(
+        &self,
+        _instruction: &Instruction,
+        result: &mollusk_svm::result::InstructionResult,
+    ) -> Result<(), InstructionError> {
+        if result.return_data == [9, 9] {
+            return Err(InstructionError::Custom(9));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_instruction_hooks() {
+    let program_id = Pubkey::new_unique();
+    let key = Pubkey::new_unique();
+
+    let mut mollusk = Mollusk::default();
+    mollusk.add_mock_program(&program_id, |ctx| {
+        let data = ctx.instruction_data().to_vec();
+        ctx.borrow_account(0)?.set_data_from_slice(&data)?;
+        ctx.set_return_data(data)
+    });
+    mollusk.invocation_inspect_callback = Box::new(RewriteInstruction);
+
+    let accounts = [(key, Account::new(1_000_000, 2, &program_id))];
+    let instruction = |data: &[u8]| {
+        Instruction::new_with_bytes(program_id, data, vec![AccountMeta::new(key, false)])
+    };
+
+    // The instruction data and accounts are rewritten before processing.
+    mollusk.process_and_validate_instruction(
+        &instruction(&[1, 2]),
+        &accounts,
+        &[
+            Check::success(),
+            Check::return_data(&[2, 1]),
+            Check::account(&key)
+                .data(&[2, 1])
+                .lamports(1_000_001)
+                .build(),
+        ],
+    );
+
+    // The instruction is aborted before processing.
+    mollusk.process_and_validate_instruction(
+        &instruction(&[0, 1]),
+        &accounts,
+        &[
+            Check::err(ProgramError::Custom(0)),
+            Check::compute_units(0),
+            Check::account(&key).data(&[0, 0]).build(),
+        ],
+    );
+
+    // The result is turned into a failure after processing, discarding the
+    // changes made to the accounts.
+    mollusk.process_and_validate_instruction(
+        &instruction(&[9, 9]),
+        &accounts,
+        &[
+            Check::err(ProgramError::Custom(9)),
+            Check::account(&key)
+                .data(&[0, 0])
+                .lamports(1_000_000)
+                .build(),
+        ],
+    );
+}
+
+struct ModifyThenAbort;
+
+impl InvocationInspectCallback for ModifyThenAbort {
+    fn before_instruction(
+        &self,
+        _instruction: &mut Instruction,
+        accounts: &mut Vec<(Pubkey, Account)>,
+    ) -> Result<(), InstructionError> {
+        accounts[0].1.lamports = 0;
+        accounts[0].1.data = vec![1, 1];
+        Err(InstructionError::Custom(3))
+    }
+}
+
+#[test]
+fn test_before_instruction_abort_keeps_accounts() {
+    let program_id = Pubkey::new_unique();
+    let key = Pubkey::new_unique();
+
+    let mut mollusk = Mollusk::default();
+    mollusk.add_mock_program(&program_id, |_| Ok(()));
+    mollusk.invocation_inspect_callback = Box::new(ModifyThenAbort);
+
+    let account = Account::new(1_000_000, 2, &program_id);
+    let instruction =
+        Instruction::new_with_bytes(program_id, &[], vec![AccountMeta::new(key, false)]);
+
+    // The changes the callback made before aborting are discarded.
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[(key, account.clone())],
+        &[
+            Check::err(ProgramError::Custom(3)),
+            Check::account(&key)
+                .data(&[0, 0])
+                .lamports(1_000_000)
+                .build(),
+        ],
+    );
+
+    // Including from a context's account store.
+    let context = mollusk.with_context(HashMap::from([(key, account.clone())]));
+    context.process_and_validate_instruction(&instruction, &[Check::err(ProgramError::Custom(3))]);
+    assert_eq!(context.account_store.borrow()[&key], account);
+}
+
+/// Counts processed instructions. Only `MolluskMt` requires callbacks to be
+/// `Send + Sync`, so `Mollusk`'s can hold an `Rc`.
+struct InstructionCounter(std::rc::Rc<std::cell::Cell<usize>>);
+
+impl InvocationInspectCallback for InstructionCounter {
+    fn after_instruction(
+        &self,
+        _instruction: &Instruction,
+        _result: &mollusk_svm::result::InstructionResult,
+    ) -> Result<(), InstructionError> {
+        self.0.set(self.0.get() + 1);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_single_threaded_callback() {
+    let (mut mollusk, instruction, accounts) = create_associated_token_account();
+
+    let count = std::rc::Rc::new(std::cell::Cell::new(0));
+    mollusk.invocation_inspect_callback = Box::new(InstructionCounter(count.clone()));
+
+    mollusk.process_and_validate_instruction(&instruction, &accounts, &[Check::success()]);
+    assert_eq!(count.get(), 1);
+}
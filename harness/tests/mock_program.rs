@@ -0,0 +1,121 @@
+use {
+    mollusk_svm::{
+        mock_program::DEFAULT_COMPUTE_UNITS, program::create_keyed_account_for_builtin_program,
+        result::Check, Mollusk,
+    },
+    solana_account::Account,
+    solana_instruction::{error::InstructionError, AccountMeta, Instruction},
+    solana_pubkey::Pubkey,
+    std::{cell::RefCell, rc::Rc},
+};
+
+#[test]
+fn test_mock_program() {
+    let mock_program_id = Pubkey::new_unique();
+    let key = Pubkey::new_unique();
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+
+    let mut mollusk = Mollusk::default();
+    mollusk.add_mock_program(&mock_program_id, {
+        let seen = seen.clone();
+        move |ctx| {
+            seen.borrow_mut().push((
+                ctx.instruction_data().to_vec(),
+                ctx.accounts()?,
+                ctx.stack_height(),
+            ));
+            ctx.borrow_account(0)?.set_data_from_slice(&[7; 4])?;
+            ctx.set_return_data(vec![4, 2])
+        }
+    });
+
+    let instruction = Instruction::new_with_bytes(
+        mock_program_id,
+        &[1, 2, 3],
+        vec![AccountMeta::new(key, false)],
+    );
+    let account = Account::new(1_000_000, 4, &mock_program_id);
+
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[(key, account.clone())],
+        &[
+            Check::success(),
+            Check::compute_units(DEFAULT_COMPUTE_UNITS),
+            Check::return_data(&[4, 2]),
+            Check::account(&key).data(&[7; 4]).build(),
+        ],
+    );
+
+    let seen = seen.borrow();
+    assert_eq!(seen.len(), 1);
+    let (data, accounts, stack_height) = &seen[0];
+    assert_eq!(data, &[1, 2, 3]);
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts[0].key, key);
+    assert_eq!(accounts[0].account, account);
+    assert!(!accounts[0].is_signer);
+    assert!(accounts[0].is_writable);
+    assert_eq!(*stack_height, 1);
+}
+
+#[test]
+fn test_mock_program_error() {
+    let mock_program_id = Pubkey::new_unique();
+
+    let mut mollusk = Mollusk::default();
+    mollusk.add_mock_program(&mock_program_id, |ctx| {
+        ctx.consume_compute_units(100)?;
+        Err(InstructionError::Custom(42))
+    });
+
+    mollusk.process_and_validate_instruction(
+        &Instruction::new_with_bytes(mock_program_id, &[], vec![]),
+        &[],
+        &[
+            Check::err(solana_program_error::ProgramError::Custom(42)),
+            Check::compute_units(DEFAULT_COMPUTE_UNITS + 100),
+        ],
+    );
+}
+
+#[test]
+fn test_mock_program_cpi() {
+    let caller_id = Pubkey::new_unique();
+    let callee_id = Pubkey::new_unique();
+
+    let callee_calls = Rc::new(RefCell::new(0));
+
+    let mut mollusk = Mollusk::default();
+    mollusk.add_mock_program(&caller_id, move |ctx| {
+        let instruction = Instruction::new_with_bytes(callee_id, &[9], vec![]);
+        ctx.invoke_context()
+            .native_invoke(instruction.into(), &[])
+            .map_err(|_| InstructionError::ProgramFailedToComplete)
+    });
+    mollusk.add_mock_program(&callee_id, {
+        let callee_calls = callee_calls.clone();
+        move |ctx| {
+            assert_eq!(ctx.instruction_data(), &[9]);
+            assert_eq!(ctx.stack_height(), 2);
+            *callee_calls.borrow_mut() += 1;
+            ctx.set_return_data(vec![1])
+        }
+    });
+
+    mollusk.process_and_validate_instruction(
+        &Instruction::new_with_bytes(
+            caller_id,
+            &[],
+            vec![AccountMeta::new_readonly(callee_id, false)],
+        ),
+        &[create_keyed_account_for_builtin_program(
+            &callee_id,
+            "mock_program",
+        )],
+        &[Check::success(), Check::return_data(&[1])],
+    );
+
+    assert_eq!(*callee_calls.borrow(), 1);
+}
@@ -0,0 +1,199 @@
+use {
+    mollusk_svm::{mt::MolluskMt, result::Check, Mollusk},
+    solana_account::Account,
+    solana_clock::Clock,
+    solana_instruction::{error::InstructionError, AccountMeta, Instruction},
+    solana_log_collector::{ic_logger_msg, LogCollector},
+    solana_program_error::ProgramError,
+    solana_pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    },
+};
+
+fn transfer_accounts(sender: &Pubkey, recipient: &Pubkey) -> [(Pubkey, Account); 2] {
+    [
+        (
+            *sender,
+            Account::new(100_000_000, 0, &solana_sdk_ids::system_program::id()),
+        ),
+        (
+            *recipient,
+            Account::new(100_000_000, 0, &solana_sdk_ids::system_program::id()),
+        ),
+    ]
+}
+
+#[test]
+#[should_panic]
+fn test_checks() {
+    let sender = Pubkey::new_unique();
+    let recipient = Pubkey::new_unique();
+
+    MolluskMt::default().process_and_validate_instruction(
+        &solana_system_interface::instruction::transfer(&sender, &recipient, 42_000),
+        &transfer_accounts(&sender, &recipient),
+        &[Check::account(&recipient).lamports(0).build()],
+    );
+}
+
+#[test]
+fn test_mock_program() {
+    let mock_program_id = Pubkey::new_unique();
+    let key = Pubkey::new_unique();
+
+    let mut mollusk = MolluskMt::default();
+    mollusk.add_mock_program(&mock_program_id, |ctx| {
+        let data = ctx.instruction_data().to_vec();
+        ctx.borrow_account(0)?.set_data_from_slice(&data)?;
+        if data.is_empty() {
+            return Err(InstructionError::InvalidInstructionData);
+        }
+        Ok(())
+    });
+
+    let context = mollusk.with_context(HashMap::new());
+    context
+        .account_store
+        .write()
+        .unwrap()
+        .insert(key, Account::new(1_000_000, 2, &mock_program_id));
+
+    context.process_and_validate_instruction(
+        &Instruction::new_with_bytes(mock_program_id, &[4, 2], vec![AccountMeta::new(key, false)]),
+        &[Check::success()],
+    );
+    context.process_and_validate_instruction(
+        &Instruction::new_with_bytes(mock_program_id, &[], vec![AccountMeta::new(key, false)]),
+        &[Check::err(ProgramError::InvalidInstructionData)],
+    );
+
+    assert_eq!(context.account_store.read().unwrap()[&key].data, vec![4, 2]);
+}
+
+#[test]
+fn test_logger() {
+    let mock_program_id = Pubkey::new_unique();
+
+    let mut mollusk = MolluskMt::default();
+    mollusk.add_mock_program(&mock_program_id, |ctx| {
+        let message = format!("data: {:?}", ctx.instruction_data());
+        ic_logger_msg!(ctx.invoke_context().get_log_collector(), &message);
+        Ok(())
+    });
+
+    let logger = Arc::new(Mutex::new(LogCollector::default()));
+    mollusk.logger = Some(logger.clone());
+
+    mollusk.process_instruction_chain(
+        &[
+            Instruction::new_with_bytes(mock_program_id, &[1], vec![]),
+            Instruction::new_with_bytes(mock_program_id, &[2], vec![]),
+        ],
+        &[],
+    );
+
+    let messages = logger.lock().unwrap().get_recorded_content().to_vec();
+    assert!(messages.contains(&"data: [1]".to_string()));
+    assert!(messages.contains(&"data: [2]".to_string()));
+}
+
+#[test]
+fn test_sysvars() {
+    let mut mollusk = Mollusk::default();
+
+    let mut clock: Clock = mollusk.get_sysvar();
+    clock.unix_timestamp = 1_234_567_890;
+    mollusk.set_sysvar(&clock);
+    assert_eq!(mollusk.sysvars.clock, clock);
+
+    mollusk.expire_blockhash();
+    assert_eq!(mollusk.get_sysvar::<Clock>().slot, clock.slot + 1);
+}
+
+#[test]
+fn test_process_instructions_parallel() {
+    let mock_program_id = Pubkey::new_unique();
+
+    let mut mollusk = MolluskMt::default();
+    mollusk.add_mock_program(&mock_program_id, |ctx| {
+        let data = ctx.instruction_data().to_vec();
+        ctx.set_return_data(data)
+    });
+
+    let batch = (0..64u8)
+        .map(|i| {
+            let sender = Pubkey::new_unique();
+            let recipient = Pubkey::new_unique();
+            let instruction = if i % 2 == 0 {
+                solana_system_interface::instruction::transfer(&sender, &recipient, i as u64)
+            } else {
+                Instruction::new_with_bytes(mock_program_id, &[i], vec![])
+            };
+            (instruction, transfer_accounts(&sender, &recipient).to_vec())
+        })
+        .collect::<Vec<_>>();
+
+    let results = mollusk.process_instructions_parallel(&batch);
+
+    assert_eq!(results.len(), batch.len());
+    for ((instruction, accounts), result) in batch.iter().zip(results) {
+        let expected = mollusk.process_instruction(instruction, accounts);
+        assert_eq!(result.program_result, expected.program_result);
+        assert_eq!(result.return_data, expected.return_data);
+        assert_eq!(result.resulting_accounts, expected.resulting_accounts);
+        assert_eq!(
+            result.compute_units_consumed,
+            expected.compute_units_consumed
+        );
+    }
+}
+
+#[test]
+fn test_process_instruction_chains_parallel() {
+    let keys = (0..8).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+    let store = keys
+        .iter()
+        .map(|key| {
+            (
+                *key,
+                Account::new(100_000_000, 0, &solana_sdk_ids::system_program::id()),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    // A mix of independent and conflicting transfers, some of which fail
+    // depending on the order in which they're processed.
+    let chains = (0..64u64)
+        .map(|i| {
+            let sender = keys[(i % 8) as usize];
+            let recipient = keys[((i * 3 + 1) % 8) as usize];
+            let lamports = if i % 5 == 0 { 150_000_000 } else { i * 1_000 };
+            vec![
+                solana_system_interface::instruction::transfer(&sender, &recipient, lamports),
+                solana_system_interface::instruction::transfer(&recipient, &sender, lamports / 2),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let parallel = MolluskMt::default().with_context(store.clone());
+    let results = parallel.process_instruction_chains_parallel(&chains);
+
+    let sequential = MolluskMt::default().with_context(store);
+    assert_eq!(results.len(), chains.len());
+    for (chain, result) in chains.iter().zip(results) {
+        let expected = sequential.process_instruction_chain(chain);
+        assert_eq!(result.program_result, expected.program_result);
+        assert_eq!(result.return_data, expected.return_data);
+        assert_eq!(
+            result.compute_units_consumed,
+            expected.compute_units_consumed
+        );
+    }
+
+    assert_eq!(
+        *parallel.account_store.read().unwrap(),
+        *sequential.account_store.read().unwrap(),
+    );
+}
@@ -0,0 +1,185 @@
+use {
+    mollusk_svm::{result::Check, Mollusk},
+    solana_account::Account,
+    solana_account_info::AccountInfo,
+    solana_clock::Clock,
+    solana_instruction::{AccountMeta, Instruction},
+    solana_program_error::{ProgramError, ProgramResult},
+    solana_pubkey::Pubkey,
+    solana_system_interface::error::SystemError,
+    solana_sysvar::{program_stubs, Sysvar},
+};
+
+fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    match instruction_data.split_first() {
+        // Write data.
+        Some((0, data)) => {
+            let account = &accounts[0];
+            if account.owner != program_id {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            account.try_borrow_mut_data()?.copy_from_slice(data);
+            program_stubs::sol_set_return_data(data);
+            Ok(())
+        }
+        // Resize data.
+        Some((1, data)) => {
+            let len = u64::from_le_bytes(data.try_into().unwrap()) as usize;
+            accounts[0].realloc(len, true)
+        }
+        // Transfer via CPI, then read the result.
+        Some((2, data)) => {
+            let (from, to) = (&accounts[0], &accounts[1]);
+            let lamports = u64::from_le_bytes(data.try_into().unwrap());
+            let to_before = to.lamports();
+            program_stubs::sol_invoke_signed(
+                &solana_system_interface::instruction::transfer(from.key, to.key, lamports),
+                accounts,
+                &[],
+            )?;
+            assert_eq!(to.lamports(), to_before + lamports);
+            Ok(())
+        }
+        // Write the current slot.
+        Some((3, _)) => {
+            let clock = Clock::get()?;
+            accounts[0]
+                .try_borrow_mut_data()?
+                .copy_from_slice(&clock.slot.to_le_bytes());
+            Ok(())
+        }
+        _ => Err(ProgramError::Custom(7)),
+    }
+}
+
+fn setup() -> (Mollusk, Pubkey) {
+    let program_id = Pubkey::new_unique();
+    let mut mollusk = Mollusk::default();
+    mollusk.add_native_program(&program_id, process_instruction);
+    (mollusk, program_id)
+}
+
+#[test]
+fn test_write_data() {
+    let (mollusk, program_id) = setup();
+    let key = Pubkey::new_unique();
+
+    mollusk.process_and_validate_instruction(
+        &Instruction::new_with_bytes(
+            program_id,
+            &[0, 1, 2, 3, 4],
+            vec![AccountMeta::new(key, false)],
+        ),
+        &[(key, Account::new(1_000_000, 4, &program_id))],
+        &[
+            Check::success(),
+            Check::return_data(&[1, 2, 3, 4]),
+            Check::account(&key).data(&[1, 2, 3, 4]).build(),
+        ],
+    );
+
+    // Accounts owned by another program can't be modified.
+    mollusk.process_and_validate_instruction(
+        &Instruction::new_with_bytes(
+            program_id,
+            &[0, 1, 2, 3, 4],
+            vec![AccountMeta::new(key, false)],
+        ),
+        &[(key, Account::new(1_000_000, 4, &Pubkey::new_unique()))],
+        &[Check::err(ProgramError::IncorrectProgramId)],
+    );
+}
+
+#[test]
+fn test_realloc() {
+    let (mollusk, program_id) = setup();
+    let key = Pubkey::new_unique();
+
+    mollusk.process_and_validate_instruction(
+        &Instruction::new_with_bytes(
+            program_id,
+            &[&[1], 16u64.to_le_bytes().as_slice()].concat(),
+            vec![AccountMeta::new(key, false)],
+        ),
+        &[(key, Account::new(1_000_000, 4, &program_id))],
+        &[
+            Check::success(),
+            Check::account(&key).data(&[0; 16]).build(),
+        ],
+    );
+}
+
+#[test]
+fn test_cpi() {
+    let (mollusk, program_id) = setup();
+    let from = Pubkey::new_unique();
+    let to = Pubkey::new_unique();
+
+    let instruction = |lamports: u64| {
+        Instruction::new_with_bytes(
+            program_id,
+            &[&[2], lamports.to_le_bytes().as_slice()].concat(),
+            vec![
+                AccountMeta::new(from, true),
+                AccountMeta::new(to, false),
+                AccountMeta::new_readonly(solana_sdk_ids::system_program::id(), false),
+            ],
+        )
+    };
+    let accounts = [
+        (from, Account::new(1_000_000, 0, &Pubkey::default())),
+        (to, Account::new(1_000_000, 0, &Pubkey::default())),
+        mollusk_svm::program::keyed_account_for_system_program(),
+    ];
+
+    mollusk.process_and_validate_instruction(
+        &instruction(400_000),
+        &accounts,
+        &[
+            Check::success(),
+            Check::account(&from).lamports(600_000).build(),
+            Check::account(&to).lamports(1_400_000).build(),
+        ],
+    );
+
+    // A failed CPI aborts the caller with the callee's error.
+    mollusk.process_and_validate_instruction(
+        &instruction(2_000_000),
+        &accounts,
+        &[Check::err(ProgramError::Custom(
+            SystemError::ResultWithNegativeLamports as u32,
+        ))],
+    );
+}
+
+#[test]
+fn test_sysvar() {
+    let (mut mollusk, program_id) = setup();
+    let key = Pubkey::new_unique();
+
+    mollusk.warp_to_slot(42);
+
+    mollusk.process_and_validate_instruction(
+        &Instruction::new_with_bytes(program_id, &[3], vec![AccountMeta::new(key, false)]),
+        &[(key, Account::new(1_000_000, 8, &program_id))],
+        &[
+            Check::success(),
+            Check::account(&key).data(&42u64.to_le_bytes()).build(),
+        ],
+    );
+}
+
+#[test]
+fn test_error() {
+    let (mollusk, program_id) = setup();
+
+    mollusk.process_and_validate_instruction(
+        &Instruction::new_with_bytes(program_id, &[9], vec![]),
+        &[],
+        &[Check::err(ProgramError::Custom(7))],
+    );
+}
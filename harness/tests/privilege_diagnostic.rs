@@ -0,0 +1,194 @@
+use {
+    mollusk_svm::{
+        program::{
+            create_program_account_loader_v2, keyed_account_for_system_program, loader_keys,
+        },
+        result::{Check, Config, Privilege, PrivilegeDiagnostic},
+        Mollusk,
+    },
+    solana_account::Account,
+    solana_instruction::{error::InstructionError, AccountMeta, Instruction},
+    solana_program_error::ProgramError,
+    solana_pubkey::Pubkey,
+};
+
+const TOKEN_ID: Pubkey = solana_pubkey::pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+const TOKEN_ELF: &[u8] = include_bytes!("../../programs/token/src/elf/token.so");
+const ATA_ID: Pubkey = solana_pubkey::pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+const ATA_ELF: &[u8] = include_bytes!("../../programs/token/src/elf/associated_token.so");
+
+const SYSTEM_PROGRAM_ID: Pubkey = solana_sdk_ids::system_program::id();
+
+fn mint_account() -> Account {
+    // An initialized mint, with no authorities.
+    let mut data = vec![0; 82];
+    data[44] = 6; // Decimals.
+    data[45] = 1; // Initialized.
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: TOKEN_ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+#[test]
+fn test_cpi_privilege_escalation() {
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_with_elf_and_loader(&TOKEN_ID, TOKEN_ELF, &loader_keys::LOADER_V2);
+    mollusk.add_program_with_elf_and_loader(&ATA_ID, ATA_ELF, &loader_keys::LOADER_V2);
+
+    let funder = Pubkey::new_unique();
+    let wallet = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let (ata, _) = Pubkey::find_program_address(
+        &[wallet.as_ref(), TOKEN_ID.as_ref(), mint.as_ref()],
+        &ATA_ID,
+    );
+
+    let create = |funder_meta: AccountMeta| {
+        Instruction::new_with_bytes(
+            ATA_ID,
+            &[0],
+            vec![
+                funder_meta,
+                AccountMeta::new(ata, false),
+                AccountMeta::new_readonly(wallet, false),
+                AccountMeta::new_readonly(mint, false),
+                AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+                AccountMeta::new_readonly(TOKEN_ID, false),
+            ],
+        )
+    };
+    let accounts = [
+        (funder, Account::new(1_000_000_000, 0, &SYSTEM_PROGRAM_ID)),
+        (ata, Account::default()),
+        (wallet, Account::default()),
+        (mint, mint_account()),
+        keyed_account_for_system_program(),
+        (TOKEN_ID, create_program_account_loader_v2(TOKEN_ELF)),
+    ];
+
+    let result = mollusk.process_instruction(&create(AccountMeta::new(funder, true)), &accounts);
+    assert!(result.raw_result.is_ok());
+    assert_eq!(result.privilege_diagnostic, None);
+
+    // CPIs are only explained with privilege diagnostics enabled.
+    let result = mollusk.process_instruction(&create(AccountMeta::new(funder, false)), &accounts);
+    assert_eq!(
+        result.raw_result,
+        Err(InstructionError::PrivilegeEscalation)
+    );
+    assert_eq!(result.privilege_diagnostic, None);
+
+    mollusk.set_privilege_diagnostics(true);
+
+    // The funder doesn't sign, but the associated token program asks the
+    // system program for its signature. The associated token account itself
+    // is signed for by the program, so isn't to blame.
+    let result = mollusk.process_instruction(&create(AccountMeta::new(funder, false)), &accounts);
+    assert_eq!(
+        result.privilege_diagnostic,
+        Some(PrivilegeDiagnostic {
+            error: InstructionError::PrivilegeEscalation,
+            privilege: Privilege::Signer,
+            stack_height: 2,
+            program_id: SYSTEM_PROGRAM_ID,
+            caller_program_id: Some(ATA_ID),
+            accounts: vec![funder],
+        })
+    );
+
+    let result =
+        mollusk.process_instruction(&create(AccountMeta::new_readonly(funder, true)), &accounts);
+    let diagnostic = result.privilege_diagnostic.unwrap();
+    assert_eq!(diagnostic.privilege, Privilege::Writable);
+    assert_eq!(diagnostic.accounts, vec![funder]);
+    assert_eq!(
+        diagnostic.to_string(),
+        format!(
+            "The CPI from program {} to program {} (stack height 2) marks account {} as writable, \
+             but the caller does not grant that privilege",
+            ATA_ID, SYSTEM_PROGRAM_ID, funder,
+        )
+    );
+}
+
+#[test]
+fn test_missing_signature() {
+    let mollusk = Mollusk::default();
+
+    let sender = Pubkey::new_unique();
+    let recipient = Pubkey::new_unique();
+
+    let mut instruction = solana_system_interface::instruction::transfer(&sender, &recipient, 42);
+    instruction.accounts[0].is_signer = false;
+    let accounts = [
+        (sender, Account::new(1_000_000, 0, &SYSTEM_PROGRAM_ID)),
+        (recipient, Account::new(1_000_000, 0, &SYSTEM_PROGRAM_ID)),
+    ];
+
+    let result = mollusk.process_instruction(&instruction, &accounts);
+    assert_eq!(
+        result.privilege_diagnostic,
+        Some(PrivilegeDiagnostic {
+            error: InstructionError::MissingRequiredSignature,
+            privilege: Privilege::Signer,
+            stack_height: 1,
+            program_id: SYSTEM_PROGRAM_ID,
+            caller_program_id: None,
+            accounts: vec![sender, recipient],
+        })
+    );
+
+    // The explanation is part of the check failure output.
+    let config = Config {
+        panic: false,
+        verbose: true,
+    };
+    assert!(!result.run_checks(&[Check::success()], &config, &mollusk));
+    assert!(result.run_checks(
+        &[Check::err(ProgramError::MissingRequiredSignature)],
+        &config,
+        &mollusk,
+    ));
+}
+
+#[test]
+fn test_missing_signature_in_cpi() {
+    let program_id = Pubkey::new_unique();
+    let sender = Pubkey::new_unique();
+    let recipient = Pubkey::new_unique();
+
+    let mut mollusk = Mollusk::default();
+    mollusk.add_mock_program(&program_id, move |ctx| {
+        let mut transfer = solana_system_interface::instruction::transfer(&sender, &recipient, 42);
+        transfer.accounts[0].is_signer = false;
+        ctx.invoke_context().native_invoke(transfer.into(), &[])
+    });
+
+    let instruction = Instruction::new_with_bytes(
+        program_id,
+        &[],
+        vec![
+            AccountMeta::new(sender, false),
+            AccountMeta::new(recipient, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+        ],
+    );
+    let accounts = [
+        (sender, Account::new(1_000_000, 0, &SYSTEM_PROGRAM_ID)),
+        (recipient, Account::new(1_000_000, 0, &SYSTEM_PROGRAM_ID)),
+        keyed_account_for_system_program(),
+    ];
+
+    // The system program raised the error, so it isn't blamed on the
+    // instruction itself.
+    let result = mollusk.process_instruction(&instruction, &accounts);
+    assert_eq!(
+        result.raw_result,
+        Err(InstructionError::MissingRequiredSignature)
+    );
+    assert_eq!(result.privilege_diagnostic, None);
+}
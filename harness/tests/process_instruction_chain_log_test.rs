@@ -33,7 +33,7 @@ fn test_process_instruction_chain_log_basic() {
     };
     
     // Create a context
-    let mut context = mollusk.with_context(HashMap::new());
+    let context = mollusk.with_context(HashMap::new());
     
     // Store accounts in the context's account store
     {
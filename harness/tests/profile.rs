@@ -0,0 +1,195 @@
+use {
+    mollusk_svm::{profile::RUNTIME_FRAME, program::loader_keys, Mollusk},
+    solana_account::Account,
+    solana_instruction::{AccountMeta, Instruction},
+    solana_pubkey::Pubkey,
+};
+
+const ASSOCIATED_TOKEN_ID: Pubkey =
+    solana_pubkey::pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+const ASSOCIATED_TOKEN_ELF: &[u8] =
+    include_bytes!("../../programs/token/src/elf/associated_token.so");
+const TOKEN_ID: Pubkey = solana_pubkey::pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+const TOKEN_ELF: &[u8] = include_bytes!("../../programs/token/src/elf/token.so");
+
+fn mint_account(mollusk: &Mollusk) -> Account {
+    // Packed `Mint`: no mint authority, zero supply, zero decimals,
+    // initialized, no freeze authority.
+    let mut data = vec![0; 82];
+    data[45] = 1;
+    Account {
+        lamports: mollusk.minimum_balance_for_rent_exemption(data.len()),
+        data,
+        owner: TOKEN_ID,
+        ..Default::default()
+    }
+}
+
+/// Set up an instruction creating an associated token account, which invokes
+/// the token and system programs via CPI.
+fn create_associated_token_account() -> (Mollusk, Instruction, Vec<(Pubkey, Account)>) {
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_with_elf_and_loader(
+        &ASSOCIATED_TOKEN_ID,
+        ASSOCIATED_TOKEN_ELF,
+        &loader_keys::LOADER_V2,
+    );
+    mollusk.add_program_with_elf_and_loader(&TOKEN_ID, TOKEN_ELF, &loader_keys::LOADER_V2);
+
+    let payer = Pubkey::new_unique();
+    let wallet = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let (associated_token_address, _) = Pubkey::find_program_address(
+        &[wallet.as_ref(), TOKEN_ID.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_ID,
+    );
+
+    let instruction = Instruction::new_with_bytes(
+        ASSOCIATED_TOKEN_ID,
+        &[0],
+        vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new(associated_token_address, false),
+            AccountMeta::new_readonly(wallet, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(solana_sdk_ids::system_program::id(), false),
+            AccountMeta::new_readonly(TOKEN_ID, false),
+        ],
+    );
+    let accounts = vec![
+        (
+            payer,
+            Account::new(1_000_000_000, 0, &solana_sdk_ids::system_program::id()),
+        ),
+        (associated_token_address, Account::default()),
+        (wallet, Account::default()),
+        (mint, mint_account(&mollusk)),
+        mollusk_svm::program::keyed_account_for_system_program(),
+        (
+            TOKEN_ID,
+            mollusk_svm::program::create_program_account_loader_v2(TOKEN_ELF),
+        ),
+    ];
+
+    (mollusk, instruction, accounts)
+}
+
+#[test]
+fn test_profile_instruction() {
+    let (mut mollusk, instruction, accounts) = create_associated_token_account();
+
+    let expected = mollusk.process_instruction(&instruction, &accounts);
+    let (result, profile) = mollusk.profile_instruction(&instruction, &accounts);
+
+    // Profiling doesn't change the result, and tracing is disabled again
+    // afterwards.
+    assert_eq!(result.program_result, expected.program_result);
+    assert_eq!(
+        result.compute_units_consumed,
+        expected.compute_units_consumed
+    );
+    assert!(mollusk
+        .process_instruction(&instruction, &accounts)
+        .execution_trace
+        .is_empty());
+
+    // Every compute unit is attributed to a stack, rooted at the invoked
+    // program.
+    assert_eq!(
+        profile.compute_units_consumed,
+        result.compute_units_consumed
+    );
+    assert_eq!(
+        profile
+            .stacks
+            .iter()
+            .map(|stack| stack.compute_units)
+            .sum::<u64>(),
+        result.compute_units_consumed,
+    );
+    let associated_token_id = ASSOCIATED_TOKEN_ID.to_string();
+    let token_id = TOKEN_ID.to_string();
+    assert!(profile
+        .stacks
+        .iter()
+        .all(|stack| stack.frames[0] == associated_token_id));
+    assert!(profile
+        .stacks
+        .iter()
+        .any(|stack| stack.frames == [associated_token_id.clone(), RUNTIME_FRAME.to_string()]));
+
+    // The token program's stacks are nested under the function of the
+    // associated token program which invoked it.
+    let cpi_stack = profile
+        .stacks
+        .iter()
+        .find(|stack| stack.frames.contains(&token_id))
+        .unwrap();
+    let cpi_frame = cpi_stack
+        .frames
+        .iter()
+        .position(|frame| *frame == token_id)
+        .unwrap();
+    assert!(cpi_frame > 2);
+    assert_eq!(cpi_stack.frames[1], "entrypoint");
+    assert_eq!(cpi_stack.frames[cpi_frame + 1], "entrypoint");
+
+    // Functions are ordered by self compute units, which add up to the total.
+    assert!(profile
+        .functions
+        .windows(2)
+        .all(|pair| pair[0].self_compute_units >= pair[1].self_compute_units));
+    assert_eq!(
+        profile
+            .functions
+            .iter()
+            .map(|function| function.self_compute_units)
+            .sum::<u64>(),
+        result.compute_units_consumed,
+    );
+    let entrypoint = profile
+        .functions
+        .iter()
+        .find(|function| {
+            function.program_id == ASSOCIATED_TOKEN_ID && function.name == "entrypoint"
+        })
+        .unwrap();
+    let runtime = profile
+        .functions
+        .iter()
+        .find(|function| function.name == RUNTIME_FRAME)
+        .unwrap();
+    assert_eq!(
+        entrypoint.inclusive_compute_units + runtime.inclusive_compute_units,
+        result.compute_units_consumed,
+    );
+
+    let folded = profile.folded();
+    assert_eq!(folded.lines().count(), profile.stacks.len());
+    assert!(folded.lines().zip(&profile.stacks).all(|(line, stack)| {
+        *line == format!("{} {}", stack.frames.join(";"), stack.compute_units)
+    }));
+
+    let top = profile.top(5);
+    assert_eq!(top.lines().count(), 2 + 5);
+    assert!(top
+        .lines()
+        .nth(2)
+        .unwrap()
+        .starts_with(&format!("| {} |", profile.functions[0].name)));
+}
+
+#[test]
+fn test_profile_instruction_keeps_tracing() {
+    let (mut mollusk, instruction, accounts) = create_associated_token_account();
+    mollusk.set_tracing(true);
+
+    let (result, _) = mollusk.profile_instruction(&instruction, &accounts);
+    assert!(!result.execution_trace.is_empty());
+
+    // Tracing was enabled before profiling, so it stays enabled.
+    assert!(!mollusk
+        .process_instruction(&instruction, &accounts)
+        .execution_trace
+        .is_empty());
+}
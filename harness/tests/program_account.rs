@@ -0,0 +1,174 @@
+use {
+    mollusk_svm::{
+        program::{
+            create_program_account_loader_v3, create_program_account_loader_v4,
+            create_program_data_account_loader_v3, elf_from_program_account_loader_v4,
+            elf_from_program_data_account_loader_v3, loader_keys,
+        },
+        result::Check,
+        Mollusk,
+    },
+    solana_account::Account,
+    solana_instruction::Instruction,
+    solana_loader_v3_interface::state::UpgradeableLoaderState,
+    solana_loader_v4_interface::state::{LoaderV4State, LoaderV4Status},
+    solana_pubkey::Pubkey,
+    std::collections::HashMap,
+};
+
+const MEMO_ELF: &[u8] = include_bytes!("../../programs/memo/src/elf/memo.so");
+
+fn memo_instruction(program_id: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(*program_id, b"hello", vec![])
+}
+
+fn retracted_loader_v4_program_account(elf: &[u8]) -> Account {
+    let mut data = vec![0; LoaderV4State::program_data_offset()];
+    data[40..48].copy_from_slice(&(LoaderV4Status::Retracted as u64).to_le_bytes());
+    data.extend_from_slice(elf);
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: loader_keys::LOADER_V4,
+        executable: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_add_program_from_programdata_account() {
+    let program_id = Pubkey::new_unique();
+
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_from_programdata_account(
+        &program_id,
+        &create_program_data_account_loader_v3(MEMO_ELF),
+    );
+
+    mollusk.process_and_validate_instruction(
+        &memo_instruction(&program_id),
+        &[],
+        &[Check::success()],
+    );
+}
+
+#[test]
+#[should_panic(expected = "Account does not contain a deployed program")]
+fn test_add_program_from_programdata_account_invalid() {
+    let program_id = Pubkey::new_unique();
+    Mollusk::default().add_program_from_programdata_account(
+        &program_id,
+        &create_program_account_loader_v3(&program_id),
+    );
+}
+
+#[test]
+fn test_add_program_from_loader_v4_account() {
+    let program_id = Pubkey::new_unique();
+
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_from_loader_v4_account(
+        &program_id,
+        &create_program_account_loader_v4(MEMO_ELF),
+    );
+    assert!(mollusk.program_cache.load_program(&program_id).is_some());
+
+    mollusk.process_and_validate_instruction(
+        &memo_instruction(&program_id),
+        &[],
+        &[Check::success()],
+    );
+}
+
+#[test]
+#[should_panic(expected = "Account does not contain a deployed program")]
+fn test_add_program_from_loader_v4_account_retracted() {
+    Mollusk::default().add_program_from_loader_v4_account(
+        &Pubkey::new_unique(),
+        &retracted_loader_v4_program_account(MEMO_ELF),
+    );
+}
+
+#[test]
+fn test_context_detects_program_accounts() {
+    let v3_program_id = Pubkey::new_unique();
+    let v3_programdata_address =
+        Pubkey::find_program_address(&[v3_program_id.as_ref()], &loader_keys::LOADER_V3).0;
+    let v4_program_id = Pubkey::new_unique();
+
+    let context = Mollusk::default().with_context(HashMap::new());
+    {
+        let mut store = context.account_store.borrow_mut();
+        store.insert(
+            v3_program_id,
+            create_program_account_loader_v3(&v3_program_id),
+        );
+        store.insert(
+            v3_programdata_address,
+            create_program_data_account_loader_v3(MEMO_ELF),
+        );
+        store.insert(v4_program_id, create_program_account_loader_v4(MEMO_ELF));
+    }
+
+    context
+        .process_and_validate_instruction(&memo_instruction(&v3_program_id), &[Check::success()]);
+    context
+        .process_and_validate_instruction(&memo_instruction(&v4_program_id), &[Check::success()]);
+}
+
+#[test]
+fn test_context_program_accounts() {
+    let v2_program_id = Pubkey::new_unique();
+    let v3_program_id = Pubkey::new_unique();
+    let v3_programdata_address =
+        Pubkey::find_program_address(&[v3_program_id.as_ref()], &loader_keys::LOADER_V3).0;
+    let v4_program_id = Pubkey::new_unique();
+    let authority = Pubkey::new_unique();
+
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_with_elf_and_loader(&v2_program_id, MEMO_ELF, &loader_keys::LOADER_V2);
+    mollusk.add_program_with_elf_and_loader(&v3_program_id, MEMO_ELF, &loader_keys::LOADER_V3);
+    mollusk.add_program_with_elf_and_loader(&v4_program_id, MEMO_ELF, &loader_keys::LOADER_V4);
+    mollusk.set_program_upgrade_authority(&v3_program_id, Some(authority));
+    mollusk.set_program_upgrade_authority(&v4_program_id, Some(authority));
+
+    let context = mollusk.with_context(HashMap::new());
+    let store = context.account_store.borrow();
+
+    let v2_program_account = &store[&v2_program_id];
+    assert_eq!(v2_program_account.owner, loader_keys::LOADER_V2);
+    assert!(v2_program_account.executable);
+    assert_eq!(v2_program_account.data, MEMO_ELF);
+
+    let v3_programdata_account = &store[&v3_programdata_address];
+    assert_eq!(
+        elf_from_program_data_account_loader_v3(v3_programdata_account),
+        Some(MEMO_ELF),
+    );
+    assert_eq!(
+        bincode::deserialize::<UpgradeableLoaderState>(&v3_programdata_account.data).unwrap(),
+        UpgradeableLoaderState::ProgramData {
+            slot: 0,
+            upgrade_authority_address: Some(authority),
+        },
+    );
+
+    let v4_program_account = &store[&v4_program_id];
+    assert_eq!(v4_program_account.owner, loader_keys::LOADER_V4);
+    assert!(v4_program_account.executable);
+    assert_eq!(
+        elf_from_program_account_loader_v4(v4_program_account),
+        Some(MEMO_ELF),
+    );
+    assert_eq!(&v4_program_account.data[8..40], authority.as_ref());
+    assert_eq!(
+        v4_program_account.data[40..48],
+        (LoaderV4Status::Deployed as u64).to_le_bytes(),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Program targeted by the instruction is missing from the cache")]
+fn test_set_program_upgrade_authority_not_cached() {
+    Mollusk::default().set_program_upgrade_authority(&Pubkey::new_unique(), None);
+}
@@ -83,7 +83,7 @@ fn test_deploy_solana_program_with_sbf_out_dir() {
     println!("Created test accounts");
     
     // Create a context
-    let mut context = mollusk.with_context(HashMap::new());
+    let context = mollusk.with_context(HashMap::new());
     println!("Created MolluskContextMt");
     
     // Store payer account in the context's account store
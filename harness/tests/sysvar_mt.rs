@@ -1,6 +1,9 @@
 use mollusk_svm::mt::MolluskMt;
+use solana_account::Account;
 use solana_clock::Clock;
 use solana_epoch_schedule::EpochSchedule;
+use solana_instruction::{AccountMeta, Instruction};
+use solana_pubkey::Pubkey;
 use solana_rent::Rent;
 use solana_slot_hashes::SlotHashes;
 
@@ -161,6 +164,44 @@ fn test_minimum_balance_for_rent_exemption() {
     println!("✅ minimum_balance_for_rent_exemption tests passed!");
 }
 
+#[test]
+fn test_context_sysvars_not_synced_from_accounts() {
+    let program_id = Pubkey::new_unique();
+    let mut mollusk = MolluskMt::default();
+    mollusk.add_mock_program(&program_id, |_| Ok(()));
+
+    // A clock account in the store, with a different slot than the
+    // environment's clock.
+    let clock_id = solana_sdk_ids::sysvar::clock::id();
+    let clock = Clock {
+        slot: 42,
+        ..Default::default()
+    };
+    let clock_account = Account {
+        lamports: 1,
+        data: bincode::serialize(&clock).unwrap(),
+        owner: solana_sdk_ids::sysvar::id(),
+        ..Default::default()
+    };
+    let mut context =
+        mollusk.with_context(std::collections::HashMap::from([(clock_id, clock_account)]));
+
+    // Sysvar accounts an instruction returns are stored like any other
+    // account, but don't change the environment's sysvars, as with
+    // `MolluskContext`.
+    let instruction = Instruction::new_with_bytes(
+        program_id,
+        &[],
+        vec![AccountMeta::new_readonly(clock_id, false)],
+    );
+    assert!(context.process_instruction(&instruction).raw_result.is_ok());
+    assert_eq!(context.mollusk.get_sysvar::<Clock>().slot, 0);
+
+    // They're set on the Mollusk instance instead.
+    context.mollusk.set_sysvar(&clock);
+    assert_eq!(context.mollusk.get_sysvar::<Clock>().slot, 42);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,156 @@
+use {
+    mollusk_svm::{program::loader_keys, result::Check, Mollusk},
+    solana_account::Account,
+    solana_instruction::{error::InstructionError, AccountMeta, Instruction},
+    solana_program_runtime::solana_sbpf::ebpf,
+    solana_pubkey::Pubkey,
+};
+
+const ASSOCIATED_TOKEN_ID: Pubkey =
+    solana_pubkey::pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+const ASSOCIATED_TOKEN_ELF: &[u8] =
+    include_bytes!("../../programs/token/src/elf/associated_token.so");
+const TOKEN_ID: Pubkey = solana_pubkey::pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+const TOKEN_ELF: &[u8] = include_bytes!("../../programs/token/src/elf/token.so");
+
+fn mint_account(mollusk: &Mollusk) -> Account {
+    // Packed `Mint`: no mint authority, zero supply, zero decimals,
+    // initialized, no freeze authority.
+    let mut data = vec![0; 82];
+    data[45] = 1;
+    Account {
+        lamports: mollusk.minimum_balance_for_rent_exemption(data.len()),
+        data,
+        owner: TOKEN_ID,
+        ..Default::default()
+    }
+}
+
+/// Set up an instruction creating an associated token account, which invokes
+/// the token and system programs via CPI.
+fn create_associated_token_account() -> (Mollusk, Instruction, Vec<(Pubkey, Account)>) {
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_with_elf_and_loader(
+        &ASSOCIATED_TOKEN_ID,
+        ASSOCIATED_TOKEN_ELF,
+        &loader_keys::LOADER_V2,
+    );
+    mollusk.add_program_with_elf_and_loader(&TOKEN_ID, TOKEN_ELF, &loader_keys::LOADER_V2);
+
+    let payer = Pubkey::new_unique();
+    let wallet = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let (associated_token_address, _) = Pubkey::find_program_address(
+        &[wallet.as_ref(), TOKEN_ID.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_ID,
+    );
+
+    let instruction = Instruction::new_with_bytes(
+        ASSOCIATED_TOKEN_ID,
+        &[0],
+        vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new(associated_token_address, false),
+            AccountMeta::new_readonly(wallet, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(solana_sdk_ids::system_program::id(), false),
+            AccountMeta::new_readonly(TOKEN_ID, false),
+        ],
+    );
+    let accounts = vec![
+        (
+            payer,
+            Account::new(1_000_000_000, 0, &solana_sdk_ids::system_program::id()),
+        ),
+        (associated_token_address, Account::default()),
+        (wallet, Account::default()),
+        (mint, mint_account(&mollusk)),
+        mollusk_svm::program::keyed_account_for_system_program(),
+        (
+            TOKEN_ID,
+            mollusk_svm::program::create_program_account_loader_v2(TOKEN_ELF),
+        ),
+    ];
+
+    (mollusk, instruction, accounts)
+}
+
+#[test]
+fn test_tracing_disabled() {
+    let (mollusk, instruction, accounts) = create_associated_token_account();
+
+    let result =
+        mollusk.process_and_validate_instruction(&instruction, &accounts, &[Check::success()]);
+
+    assert!(result.execution_trace.is_empty());
+    assert!(result.trace_dump().is_empty());
+}
+
+#[test]
+fn test_tracing() {
+    let (mut mollusk, instruction, accounts) = create_associated_token_account();
+    mollusk.set_tracing(true);
+
+    let result =
+        mollusk.process_and_validate_instruction(&instruction, &accounts, &[Check::success()]);
+
+    // The associated token program is traced first, followed by each of its
+    // CPIs into the token program. The system program is a builtin, so it
+    // isn't traced.
+    let (outer, inner) = result.execution_trace.split_first().unwrap();
+    assert_eq!(outer.program_id, ASSOCIATED_TOKEN_ID);
+    assert_eq!(outer.stack_height, 1);
+    assert!(!inner.is_empty());
+    for trace in inner {
+        assert_eq!(trace.program_id, TOKEN_ID);
+        assert_eq!(trace.stack_height, 2);
+    }
+
+    for trace in &result.execution_trace {
+        // Every program returns successfully.
+        assert_eq!(trace.entries.last().unwrap().opcode, ebpf::EXIT);
+        // Each entry is disassembled on its own line.
+        assert_eq!(trace.disassembly.lines().count(), trace.entries.len());
+        assert!(trace.disassembly.lines().last().unwrap().ends_with("exit"));
+    }
+
+    let dump = result.trace_dump();
+    assert!(dump.starts_with(&format!(
+        "Program {} (stack height 1):\n",
+        ASSOCIATED_TOKEN_ID
+    )));
+    assert!(dump.contains(&format!("Program {} (stack height 2):\n", TOKEN_ID)));
+
+    // Tracing can be turned off again.
+    mollusk.set_tracing(false);
+    let result = mollusk.process_instruction(&instruction, &accounts);
+    assert!(result.execution_trace.is_empty());
+}
+
+#[test]
+fn test_tracing_cpi_to_missing_program() {
+    let (_, instruction, accounts) = create_associated_token_account();
+
+    // The token program account exists, but the program isn't deployed, so
+    // the CPI into it fails before it runs.
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_with_elf_and_loader(
+        &ASSOCIATED_TOKEN_ID,
+        ASSOCIATED_TOKEN_ELF,
+        &loader_keys::LOADER_V2,
+    );
+    mollusk.set_tracing(true);
+
+    let result = mollusk.process_instruction(&instruction, &accounts);
+    assert_eq!(
+        result.raw_result,
+        Err(InstructionError::UnsupportedProgramId)
+    );
+
+    // Only the associated token program ran, and its trace is attributed to
+    // it rather than to the token program.
+    assert_eq!(result.execution_trace.len(), 1);
+    let trace = &result.execution_trace[0];
+    assert_eq!(trace.program_id, ASSOCIATED_TOKEN_ID);
+    assert_eq!(trace.stack_height, 1);
+}
@@ -16,16 +16,76 @@
 //! standalone or within the other transaction helpers in this library to build
 //! custom transactions for the SVM API with the required structure and roles.
 //!
+//! A key map can also be compiled into the keys and header of a legacy or v0
+//! message, and validated against the keys signing it, so tests can check that
+//! instruction builders produce valid, compact messages.
+//!
 //! This implementation closely follows the implementation in the Anza SDK
 //! for `Message::new_with_blockhash`. For more information, see:
 //! <https://github.com/anza-xyz/agave/blob/c6e8239843af8e6301cd198e39d0a44add427bef/sdk/program/src/message/legacy.rs#L357>.
 
 use {
     solana_instruction::{AccountMeta, Instruction},
+    solana_message::{
+        v0::{LoadedAddresses, MessageAddressTableLookup},
+        AddressLookupTableAccount, MessageHeader,
+    },
     solana_pubkey::Pubkey,
-    std::collections::{HashMap, HashSet},
+    std::collections::{BTreeMap, HashMap, HashSet},
+    thiserror::Error,
 };
 
+/// Errors compiling or validating the keys of a message.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum KeyMapError {
+    /// The message references more accounts than an account index can
+    /// address.
+    #[error("Account index overflowed during compilation")]
+    AccountIndexOverflow,
+    /// A key was found in an address lookup table past the last index a
+    /// lookup can address.
+    #[error("Address lookup table index overflowed during compilation")]
+    AddressTableLookupIndexOverflow,
+    /// A key was provided more than once to sign the message.
+    #[error("Duplicate signer: {0}")]
+    DuplicateSigner(Pubkey),
+    /// A key required to sign the message was not provided.
+    #[error("Missing signer: {0}")]
+    MissingSigner(Pubkey),
+    /// An invoked program is also used as a signer or writable account.
+    ///
+    /// The runtime demotes invoked programs to readonly non-signers, so an
+    /// instruction relying on either privilege would fail.
+    #[error("Privilege escalation of invoked program: {0}")]
+    PrivilegeEscalation(Pubkey),
+}
+
+/// The keys and header of a message compiled from a [`KeyMap`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CompiledMessageKeys {
+    /// The message header, describing the static keys.
+    pub header: MessageHeader,
+    /// The keys stored in the message, ordered as the runtime expects:
+    /// writable signers, starting with the payer, then readonly signers,
+    /// writable non-signers and readonly non-signers. Keys within each group
+    /// are sorted.
+    pub static_account_keys: Vec<Pubkey>,
+    /// The lookups of keys loaded from address lookup tables. Always empty
+    /// for legacy messages.
+    pub address_table_lookups: Vec<MessageAddressTableLookup>,
+    /// The keys loaded from address lookup tables, which the runtime appends
+    /// to the static keys.
+    pub loaded_addresses: LoadedAddresses,
+}
+
+impl CompiledMessageKeys {
+    /// Get the number of accounts the message references, including those
+    /// loaded from address lookup tables.
+    pub fn num_accounts(&self) -> usize {
+        self.static_account_keys.len() + self.loaded_addresses.len()
+    }
+}
+
 /// Wrapper around a hashmap of account keys and their corresponding roles
 /// (`is_signer`, `is_writable`).
 ///
@@ -37,6 +97,9 @@ use {
 pub struct KeyMap {
     map: HashMap<Pubkey, (bool, bool)>,
     program_ids: HashSet<Pubkey>,
+    payer: Option<Pubkey>,
+    /// Program IDs also requested as signers or writable accounts.
+    escalated_program_ids: HashSet<Pubkey>,
 }
 
 impl KeyMap {
@@ -45,6 +108,9 @@ impl KeyMap {
         let entry = self.map.entry(meta.pubkey).or_default();
         entry.0 |= meta.is_signer;
         entry.1 |= meta.is_writable;
+        if (meta.is_signer || meta.is_writable) && self.program_ids.contains(&meta.pubkey) {
+            self.escalated_program_ids.insert(meta.pubkey);
+        }
     }
 
     /// Add a list of account metas to the key map.
@@ -69,10 +135,22 @@ impl KeyMap {
 
     /// Add a single program ID to the key map.
     pub fn add_program(&mut self, program_id: Pubkey) {
-        self.map.insert(program_id, (false, false));
+        if let Some((is_signer, is_writable)) = self.map.insert(program_id, (false, false)) {
+            if is_signer || is_writable {
+                self.escalated_program_ids.insert(program_id);
+            }
+        }
         self.program_ids.insert(program_id);
     }
 
+    /// Add the fee payer to the key map, as a writable signer.
+    ///
+    /// The payer is the first key of a compiled message.
+    pub fn add_payer(&mut self, payer: Pubkey) {
+        self.add_account(&AccountMeta::new(payer, true));
+        self.payer = Some(payer);
+    }
+
     /// Add a list of program IDs to the key map.
     pub fn add_programs<'a>(&mut self, program_ids: impl Iterator<Item = &'a Pubkey>) {
         for program_id in program_ids {
@@ -106,6 +184,129 @@ impl KeyMap {
         map
     }
 
+    /// Compile the keys and header of a legacy message, where every key is
+    /// stored in the message.
+    pub fn compile_message_keys(&self) -> Result<CompiledMessageKeys, KeyMapError> {
+        self.compile_v0_message_keys(&[])
+    }
+
+    /// Compile the keys and header of a v0 message, loading keys from the
+    /// provided address lookup tables where possible.
+    ///
+    /// Signers, invoked programs and the payer are always stored in the
+    /// message. Any other key found in a table is loaded from the first table
+    /// containing it, and tables without any such keys are skipped, as done
+    /// by the Anza SDK's `v0::Message::try_compile`.
+    pub fn compile_v0_message_keys(
+        &self,
+        lookup_tables: &[AddressLookupTableAccount],
+    ) -> Result<CompiledMessageKeys, KeyMapError> {
+        let mut static_keys: BTreeMap<Pubkey, (bool, bool)> = self
+            .map
+            .iter()
+            .filter(|(key, _)| Some(*key) != self.payer.as_ref())
+            .map(|(key, roles)| (*key, *roles))
+            .collect();
+
+        let mut address_table_lookups = Vec::new();
+        let mut loaded_addresses = LoadedAddresses::default();
+        for lookup_table in lookup_tables {
+            let mut lookup = MessageAddressTableLookup {
+                account_key: lookup_table.key,
+                ..Default::default()
+            };
+            for (key, &(is_signer, is_writable)) in &static_keys {
+                if is_signer || self.is_invoked(key) {
+                    continue;
+                }
+                let Some(index) = lookup_table.addresses.iter().position(|k| k == key) else {
+                    continue;
+                };
+                let index = u8::try_from(index)
+                    .map_err(|_| KeyMapError::AddressTableLookupIndexOverflow)?;
+                if is_writable {
+                    lookup.writable_indexes.push(index);
+                    loaded_addresses.writable.push(*key);
+                } else {
+                    lookup.readonly_indexes.push(index);
+                    loaded_addresses.readonly.push(*key);
+                }
+            }
+            if lookup.writable_indexes.is_empty() && lookup.readonly_indexes.is_empty() {
+                continue;
+            }
+            static_keys.retain(|key, _| {
+                !loaded_addresses.writable.contains(key) && !loaded_addresses.readonly.contains(key)
+            });
+            address_table_lookups.push(lookup);
+        }
+
+        let group = |signer: bool, writable: bool| {
+            static_keys
+                .iter()
+                .filter(move |(_, roles)| **roles == (signer, writable))
+                .map(|(key, _)| *key)
+        };
+        let static_account_keys: Vec<Pubkey> = self
+            .payer
+            .into_iter()
+            .chain(group(true, true))
+            .chain(group(true, false))
+            .chain(group(false, true))
+            .chain(group(false, false))
+            .collect();
+
+        let try_into_u8 = |count: usize| -> Result<u8, KeyMapError> {
+            u8::try_from(count).map_err(|_| KeyMapError::AccountIndexOverflow)
+        };
+        let compiled = CompiledMessageKeys {
+            header: MessageHeader {
+                num_required_signatures: try_into_u8(
+                    self.payer.iter().count()
+                        + group(true, true).count()
+                        + group(true, false).count(),
+                )?,
+                num_readonly_signed_accounts: try_into_u8(group(true, false).count())?,
+                num_readonly_unsigned_accounts: try_into_u8(group(false, false).count())?,
+            },
+            static_account_keys,
+            address_table_lookups,
+            loaded_addresses,
+        };
+        // Every account must be addressable by a `u8` index.
+        if compiled.num_accounts() > u8::MAX as usize + 1 {
+            return Err(KeyMapError::AccountIndexOverflow);
+        }
+        Ok(compiled)
+    }
+
+    /// Validate that a message compiled from the key map can be signed by the
+    /// provided keys, and executed as its instructions intend.
+    ///
+    /// Every key required to sign must be provided exactly once, and no
+    /// invoked program can be used as a signer or writable account.
+    pub fn validate(&self, signers: &[Pubkey]) -> Result<(), KeyMapError> {
+        let mut provided = HashSet::new();
+        for signer in signers {
+            if !provided.insert(signer) {
+                return Err(KeyMapError::DuplicateSigner(*signer));
+            }
+        }
+        if let Some(program_id) = self.escalated_program_ids.iter().min() {
+            return Err(KeyMapError::PrivilegeEscalation(*program_id));
+        }
+        let missing_signer = self
+            .map
+            .iter()
+            .filter(|(key, (is_signer, _))| *is_signer && !provided.contains(key))
+            .map(|(key, _)| key)
+            .min();
+        match missing_signer {
+            Some(key) => Err(KeyMapError::MissingSigner(*key)),
+            None => Ok(()),
+        }
+    }
+
     /// Query the key map for the `is_invoked` role of a key.
     ///
     /// This role is only for program IDs designated in an instruction.
@@ -185,7 +386,7 @@ impl KeyMap {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use {super::*, solana_hash::Hash};
 
     #[test]
     fn test_compile() {
@@ -263,4 +464,155 @@ mod tests {
         );
         run_checks(&key_map);
     }
+
+    #[test]
+    fn test_compile_message_keys() {
+        let payer = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let signer = Pubkey::new_unique();
+        let readonly_signer = Pubkey::new_unique();
+        let writable = Pubkey::new_unique();
+        let readonly = Pubkey::new_unique();
+        let unlisted = Pubkey::new_unique();
+
+        let instructions = [
+            Instruction::new_with_bytes(
+                program_id,
+                &[],
+                vec![
+                    AccountMeta::new(signer, true),
+                    AccountMeta::new_readonly(readonly_signer, true),
+                    AccountMeta::new(writable, false),
+                    AccountMeta::new_readonly(readonly, false),
+                ],
+            ),
+            Instruction::new_with_bytes(
+                program_id,
+                &[],
+                vec![
+                    AccountMeta::new_readonly(writable, false),
+                    AccountMeta::new(unlisted, false),
+                ],
+            ),
+        ];
+        let mut key_map = KeyMap::compile_from_instructions(instructions.iter());
+        key_map.add_payer(payer);
+
+        // Legacy messages store every key.
+        let legacy = solana_message::Message::new(&instructions, Some(&payer));
+        let compiled = key_map.compile_message_keys().unwrap();
+        assert_eq!(compiled.header, legacy.header);
+        assert_eq!(compiled.static_account_keys, legacy.account_keys);
+        assert!(compiled.address_table_lookups.is_empty());
+        assert_eq!(compiled.num_accounts(), 7);
+
+        // v0 messages load non-signers from lookup tables. Signers and
+        // invoked programs are always stored in the message, and tables
+        // without any loaded keys are skipped.
+        let lookup_tables = [
+            AddressLookupTableAccount {
+                key: Pubkey::new_unique(),
+                addresses: vec![Pubkey::new_unique()],
+            },
+            AddressLookupTableAccount {
+                key: Pubkey::new_unique(),
+                addresses: vec![signer, readonly, program_id, writable],
+            },
+        ];
+        let v0 = solana_message::v0::Message::try_compile(
+            &payer,
+            &instructions,
+            &lookup_tables,
+            Hash::default(),
+        )
+        .unwrap();
+        let compiled = key_map.compile_v0_message_keys(&lookup_tables).unwrap();
+        assert_eq!(compiled.header, v0.header);
+        assert_eq!(compiled.static_account_keys, v0.account_keys);
+        assert_eq!(compiled.address_table_lookups, v0.address_table_lookups);
+        assert_eq!(
+            compiled.address_table_lookups,
+            vec![MessageAddressTableLookup {
+                account_key: lookup_tables[1].key,
+                writable_indexes: vec![3],
+                readonly_indexes: vec![1],
+            }],
+        );
+        assert_eq!(compiled.loaded_addresses.writable, vec![writable]);
+        assert_eq!(compiled.loaded_addresses.readonly, vec![readonly]);
+        assert_eq!(compiled.num_accounts(), 7);
+
+        // Lookups can only address the first 256 keys of a table.
+        let mut addresses = vec![Pubkey::default(); 256];
+        addresses.push(readonly);
+        let lookup_tables = [AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses,
+        }];
+        assert_eq!(
+            key_map.compile_v0_message_keys(&lookup_tables),
+            Err(KeyMapError::AddressTableLookupIndexOverflow),
+        );
+
+        // Every account must be addressable.
+        let mut key_map = KeyMap::default();
+        key_map.add_accounts(
+            (0..257)
+                .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+                .collect::<Vec<_>>()
+                .iter(),
+        );
+        assert_eq!(
+            key_map.compile_message_keys(),
+            Err(KeyMapError::AccountIndexOverflow),
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let payer = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let signer = Pubkey::new_unique();
+
+        let instruction = Instruction::new_with_bytes(
+            program_id,
+            &[],
+            vec![
+                AccountMeta::new_readonly(signer, true),
+                AccountMeta::new(Pubkey::new_unique(), false),
+            ],
+        );
+        let mut key_map = KeyMap::compile_from_instruction(&instruction);
+        key_map.add_payer(payer);
+
+        assert_eq!(key_map.validate(&[payer, signer]), Ok(()));
+        assert_eq!(key_map.validate(&[signer, payer]), Ok(()));
+        assert_eq!(
+            key_map.validate(&[payer, signer, payer]),
+            Err(KeyMapError::DuplicateSigner(payer)),
+        );
+        assert_eq!(
+            key_map.validate(&[payer]),
+            Err(KeyMapError::MissingSigner(signer)),
+        );
+
+        // Invoked programs can't be signers or writable, whether they're
+        // invoked before or after being used as an account.
+        let escalating = Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[],
+            vec![AccountMeta::new(program_id, false)],
+        );
+        for instructions in [
+            [instruction.clone(), escalating.clone()],
+            [escalating, instruction],
+        ] {
+            let mut key_map = KeyMap::compile_from_instructions(instructions.iter());
+            key_map.add_payer(payer);
+            assert_eq!(
+                key_map.validate(&[payer, signer]),
+                Err(KeyMapError::PrivilegeEscalation(program_id)),
+            );
+        }
+    }
 }
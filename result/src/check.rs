@@ -197,7 +197,17 @@ impl InstructionResult {
                 CheckType::ProgramResult(result) => {
                     let check_result = result;
                     let actual_result = &self.program_result;
-                    pass &= compare!(c, "program_result", check_result, actual_result);
+                    pass &= match &self.privilege_diagnostic {
+                        Some(diagnostic) if check_result != actual_result => throw!(
+                            c,
+                            "CHECK FAILED: program_result\n  Expected: `{:?}`,\n Got: `{:?}`,\n \
+                             Explanation: {}",
+                            check_result,
+                            actual_result,
+                            diagnostic,
+                        ),
+                        _ => compare!(c, "program_result", check_result, actual_result),
+                    };
                 }
                 CheckType::ReturnData(return_data) => {
                     let check_return_data = return_data;
@@ -41,5 +41,8 @@ pub use {
     check::{AccountCheckBuilder, Check},
     compare::Compare,
     config::{CheckContext, Config},
-    types::{ContextResult, InstructionResult, ProgramResult},
+    types::{
+        ContextResult, InstructionResult, Privilege, PrivilegeDiagnostic, ProgramResult,
+        ProgramTrace, TraceEntry,
+    },
 };
@@ -2,7 +2,7 @@
 
 use {
     solana_account::Account, solana_instruction::error::InstructionError,
-    solana_program_error::ProgramError, solana_pubkey::Pubkey,
+    solana_program_error::ProgramError, solana_pubkey::Pubkey, std::fmt,
 };
 
 /// The result code of the program's execution.
@@ -57,6 +57,15 @@ pub struct InstructionResult {
     /// they were provided. Any accounts that were modified will maintain
     /// their original position in this list, but with updated state.
     pub resulting_accounts: Vec<(Pubkey, Account)>,
+    /// The execution trace of each SBF program invoked by the instruction,
+    /// in invocation order.
+    ///
+    /// Empty unless tracing is enabled.
+    pub execution_trace: Vec<ProgramTrace>,
+    /// An explanation of which account and privilege caused the instruction
+    /// to fail, if it failed with `PrivilegeEscalation` or
+    /// `MissingRequiredSignature`.
+    pub privilege_diagnostic: Option<PrivilegeDiagnostic>,
 }
 
 impl Default for InstructionResult {
@@ -68,6 +77,8 @@ impl Default for InstructionResult {
             raw_result: Ok(()),
             return_data: vec![],
             resulting_accounts: vec![],
+            execution_trace: vec![],
+            privilege_diagnostic: None,
         }
     }
 }
@@ -88,6 +99,128 @@ impl InstructionResult {
         self.raw_result = other.raw_result;
         self.return_data = other.return_data;
         self.resulting_accounts = other.resulting_accounts;
+        self.execution_trace.extend(other.execution_trace);
+        self.privilege_diagnostic = other.privilege_diagnostic;
+    }
+
+    /// Render the execution trace of every invoked SBF program as text, with
+    /// each executed instruction disassembled.
+    pub fn trace_dump(&self) -> String {
+        self.execution_trace
+            .iter()
+            .map(|trace| {
+                format!(
+                    "Program {} (stack height {}):\n{}",
+                    trace.program_id, trace.stack_height, trace.disassembly,
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// A single instruction executed by an SBF program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// The program counter, in instructions.
+    pub pc: u64,
+    /// The registers `r0` through `r10`, before the instruction executed.
+    pub registers: [u64; 11],
+    /// The opcode of the instruction.
+    pub opcode: u8,
+}
+
+/// The execution trace of a single invocation of an SBF program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProgramTrace {
+    /// The ID of the invoked program.
+    pub program_id: Pubkey,
+    /// The invocation stack height. `1` means the program was invoked
+    /// directly by the instruction, while anything higher means it was
+    /// invoked via CPI.
+    pub stack_height: usize,
+    /// Every instruction executed by the program, in execution order.
+    pub entries: Vec<TraceEntry>,
+    /// A text dump of the trace, with one line per entry containing its
+    /// index, registers, program counter and disassembled instruction.
+    pub disassembly: String,
+}
+
+/// An account privilege.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Privilege {
+    Signer,
+    Writable,
+}
+
+impl fmt::Display for Privilege {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Privilege::Signer => write!(f, "signer"),
+            Privilege::Writable => write!(f, "writable"),
+        }
+    }
+}
+
+/// An explanation of an instruction failing with `PrivilegeEscalation` or
+/// `MissingRequiredSignature`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrivilegeDiagnostic {
+    /// The error the instruction failed with.
+    pub error: InstructionError,
+    /// The privilege which was escalated or missing.
+    pub privilege: Privilege,
+    /// The stack height of the offending instruction. `1` means the
+    /// instruction itself, while anything higher means a CPI.
+    pub stack_height: usize,
+    /// The program invoked by the offending instruction.
+    pub program_id: Pubkey,
+    /// The program which made the offending CPI, if any.
+    pub caller_program_id: Option<Pubkey>,
+    /// The accounts responsible for the failure.
+    ///
+    /// For `PrivilegeEscalation`, this is the single account whose privilege
+    /// was escalated. Since a program decides for itself which accounts must
+    /// sign, for `MissingRequiredSignature` this is every account of the
+    /// offending instruction which wasn't a signer.
+    pub accounts: Vec<Pubkey>,
+}
+
+impl fmt::Display for PrivilegeDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let instruction = match self.caller_program_id {
+            Some(caller) => format!(
+                "CPI from program {} to program {} (stack height {})",
+                caller, self.program_id, self.stack_height
+            ),
+            None => format!(
+                "instruction to program {} (stack height {})",
+                self.program_id, self.stack_height
+            ),
+        };
+        let accounts = self
+            .accounts
+            .iter()
+            .map(|pubkey| pubkey.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        match self.error {
+            InstructionError::PrivilegeEscalation => write!(
+                f,
+                "The {} marks account {} as {}, but the caller does not grant that privilege",
+                instruction, accounts, self.privilege,
+            ),
+            _ if self.accounts.is_empty() => write!(
+                f,
+                "The {} is missing a required signature, and has no accounts which aren't signers",
+                instruction,
+            ),
+            _ => write!(
+                f,
+                "The {} is missing a required signature. Accounts not marked as {}: {}",
+                instruction, self.privilege, accounts,
+            ),
+        }
     }
 }
 
@@ -105,4 +238,13 @@ pub struct ContextResult {
     pub raw_result: Result<(), InstructionError>,
     /// The return data produced by the instruction, if any.
     pub return_data: Vec<u8>,
+    /// The execution trace of each SBF program invoked by the instruction,
+    /// in invocation order.
+    ///
+    /// Empty unless tracing is enabled.
+    pub execution_trace: Vec<ProgramTrace>,
+    /// An explanation of which account and privilege caused the instruction
+    /// to fail, if it failed with `PrivilegeEscalation` or
+    /// `MissingRequiredSignature`.
+    pub privilege_diagnostic: Option<PrivilegeDiagnostic>,
 }